@@ -2,12 +2,16 @@ use std::{
     error::Error,
     fs::File,
     io::{self, ErrorKind, Read, Seek, Write},
+    mem,
     path::PathBuf,
     process::ExitCode,
     str::{self, FromStr},
 };
 
 use clap::Parser;
+use possum2_media::{DISK_SIZE, ROM_SIZE};
+use possum2_ops::*;
+use possum2_sym::{Section, Symbol};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -26,6 +30,64 @@ struct Args {
     /// Pre-defined symbols (repeatable)
     #[arg(short = 'D', value_name="KEY1=val", value_parser = parse_defines::<String, i32>)]
     defines: Vec<(String, i32)>,
+
+    /// Directories searched for `inf` files not found as given (repeatable)
+    #[arg(short = 'I', long = "include", value_name = "DIR")]
+    include_dirs: Vec<PathBuf>,
+
+    /// Output format: `raw`, `possum2-rom` (checked to be exactly the
+    /// 3840 bytes from $F100 to $FFFF), or `possum2-disk` (a 640KiB
+    /// floppy image with the program from its first sector on)
+    #[arg(long, default_value = "raw")]
+    target: Target,
+}
+
+/// What the output is for
+#[derive(Clone, Copy, Debug)]
+enum Target {
+    Raw,
+    Rom,
+    Disk,
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(Target::Raw),
+            "possum2-rom" => Ok(Target::Rom),
+            "possum2-disk" => Ok(Target::Disk),
+            _ => Err(format!(
+                "expected `raw`, `possum2-rom`, or `possum2-disk`: `{s}`"
+            )),
+        }
+    }
+}
+
+impl Target {
+    /// Check the assembled bytes fit the target, and lay them out as the
+    /// emulator loads it
+    fn image(self, mut bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+        match self {
+            Target::Raw => Ok(bytes),
+            Target::Rom if bytes.len() != ROM_SIZE => Err(format!(
+                "ROM is {} bytes, but it must be exactly {ROM_SIZE} bytes (from $F100 to $FFFF)",
+                bytes.len()
+            )),
+            Target::Rom => Ok(bytes),
+            Target::Disk if bytes.len() > DISK_SIZE => Err(format!(
+                "disk is {} bytes, but it must fit in {DISK_SIZE} bytes (640KiB)",
+                bytes.len()
+            )),
+            Target::Disk => {
+                // the boot sector is the first, so the program goes there
+                // and the rest of the image is blank
+                bytes.resize(DISK_SIZE, 0);
+                Ok(bytes)
+            }
+        }
+    }
 }
 
 fn parse_defines<T, U>(s: &str) -> Result<(T, U), Box<dyn Error + Send + Sync + 'static>>
@@ -55,21 +117,10 @@ fn main_real() -> Result<(), Box<dyn Error>> {
     let file = File::open(args.input).map_err(|e| format!("cannot open file: {e}"))?;
     let reader = Reader::new(file);
     let lexer = Lexer::new(reader);
-    let output: Box<dyn Write> = match args.output {
-        Some(path) => Box::new(
-            File::options()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(path)
-                .map_err(|e| format!("cannot open file: {e}"))?,
-        ),
-        None => Box::new(io::stdout()),
-    };
-
-    let mut asm = Asm::new(lexer, output);
+    let mut asm = Asm::new(lexer);
+    asm.include_dirs = args.include_dirs;
     for (k, v) in args.defines {
-        asm.syms.push((k.clone(), v));
+        asm.syms.push((k.clone(), v, None));
     }
 
     eprint!("pass1: ");
@@ -82,6 +133,18 @@ fn main_real() -> Result<(), Box<dyn Error>> {
     pass(&mut asm)?;
     eprintln!("ok");
 
+    let image = args.target.image(mem::take(&mut asm.output))?;
+    match args.output {
+        Some(path) => File::options()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(&image))
+            .map_err(|e| format!("cannot write file: {e}"))?,
+        None => io::stdout().write_all(&image)?,
+    }
+
     if let Some(path) = args.sym {
         let mut file = File::options()
             .write(true)
@@ -89,9 +152,15 @@ fn main_real() -> Result<(), Box<dyn Error>> {
             .truncate(true)
             .open(path)
             .map_err(|e| format!("cannot open file: {e}"))?;
-        for sym in asm.syms {
-            writeln!(&mut file, "{}:{:04X}", sym.0, sym.1)?;
-        }
+        let symbols = asm
+            .syms
+            .into_iter()
+            .map(|(name, value, section)| Symbol {
+                section,
+                ..Symbol::new(name, value as u16)
+            })
+            .collect::<Vec<_>>();
+        possum2_sym::write(&mut file, &symbols)?;
     }
 
     Ok(())
@@ -133,6 +202,10 @@ fn pass(asm: &mut Asm) -> Result<(), Box<dyn Error>> {
             && !POPS
                 .iter()
                 .any(|op| asm.lexer().string().eq_ignore_ascii_case(op.0))
+            && !asm
+                .macros
+                .iter()
+                .any(|mac| mac.name == asm.lexer().string())
         {
             // apply outer label
             if asm.lexer().string().starts_with(".") {
@@ -153,7 +226,7 @@ fn pass(asm: &mut Asm) -> Result<(), Box<dyn Error>> {
                 if asm.macros.iter().any(|mac| mac.name == name) {
                     // todo: it shouldnt even be possible for this to happen
                     // if we try to define the macro again, it would immediately invoke it
-                    return Err(asm.lexer().err("macro already defined"))?;
+                    Err(asm.lexer().err("macro already defined"))?;
                 }
                 mac(asm, name)?;
                 continue;
@@ -171,7 +244,7 @@ fn pass(asm: &mut Asm) -> Result<(), Box<dyn Error>> {
                 } else {
                     // save the label in the symbol table
                     let index = asm.syms.len();
-                    asm.syms.push((name, 0));
+                    asm.syms.push((name, 0, None));
                     index
                 };
 
@@ -194,6 +267,11 @@ fn pass(asm: &mut Asm) -> Result<(), Box<dyn Error>> {
 
             // otherwise it is a pointer to the current PC
             asm.syms[sym_index].1 = asm.pc() as u32 as i32;
+            asm.syms[sym_index].2 = Some(if asm.bss_mode {
+                Section::Bss
+            } else {
+                Section::Txt
+            });
         }
 
         // macro?
@@ -330,121 +408,6 @@ fn pass(asm: &mut Asm) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-const IMM: u8 = 0;
-const ABS: u8 = 1;
-const B: u8 = 2;
-const ACCUM: u8 = 3;
-const IMPL: u8 = 4;
-const IND_X: u8 = 5; // (B,X)
-const IND_Y: u8 = 6; // (B),Y
-const IND_Z: u8 = 7; // (B),Z
-const IND_SP: u8 = 8; // (d,SP),Y
-const B_X: u8 = 9;
-const B_Y: u8 = 10;
-const ABS_X: u8 = 11;
-const ABS_Y: u8 = 12;
-const REL: u8 = 13;
-const WREL: u8 = 14;
-const IND_ABS: u8 = 15; // (ABS)
-const B_REL: u8 = 16;
-const IND_ABS_X: u8 = 17; // (ABS,X)
-
-type Op = (&'static str, &'static [(u8, u8)]);
-
-#[rustfmt::skip]
-const OPS: &[Op] = &[
-    ("AUG", &[(IMPL, 0x5C)]), // special
-    ("BRK", &[(IMPL, 0x00)]), // special
-    ("CLC", &[(IMPL, 0x18)]),
-    ("CLD", &[(IMPL, 0xD8)]),
-    ("CLE", &[(IMPL, 0x02)]),
-    ("CLI", &[(IMPL, 0x58)]),
-    ("CLV", &[(IMPL, 0xB8)]),
-    ("DEX", &[(IMPL, 0xCA)]),
-    ("DEY", &[(IMPL, 0x88)]),
-    ("DEZ", &[(IMPL, 0x3B)]),
-    ("INX", &[(IMPL, 0xE8)]),
-    ("INY", &[(IMPL, 0xC8)]),
-    ("INZ", &[(IMPL, 0x1B)]),
-    ("NOP", &[(IMPL, 0xEA)]),
-    ("PHA", &[(IMPL, 0x48)]),
-    ("PHP", &[(IMPL, 0x08)]),
-    ("PHX", &[(IMPL, 0xDA)]),
-    ("PHY", &[(IMPL, 0x5A)]),
-    ("PHZ", &[(IMPL, 0xDB)]),
-    ("PLA", &[(IMPL, 0x68)]),
-    ("PLP", &[(IMPL, 0x28)]),
-    ("PLX", &[(IMPL, 0xFA)]),
-    ("PLY", &[(IMPL, 0x7A)]),
-    ("PLZ", &[(IMPL, 0xFB)]),
-    ("RTI", &[(IMPL, 0x40)]),
-    ("RTN", &[(IMPL, 0x62)]), // special
-    ("RTS", &[(IMPL, 0x60)]),
-    ("SEC", &[(IMPL, 0x38)]),
-    ("SED", &[(IMPL, 0xF8)]),
-    ("SEE", &[(IMPL, 0x03)]),
-    ("SEI", &[(IMPL, 0x78)]),
-    ("TAB", &[(IMPL, 0x5B)]),
-    ("TAX", &[(IMPL, 0xAA)]),
-    ("TAY", &[(IMPL, 0xA8)]),
-    ("TBA", &[(IMPL, 0x7B)]),
-    ("TSX", &[(IMPL, 0xBA)]),
-    ("TSY", &[(IMPL, 0x0B)]),
-    ("TXA", &[(IMPL, 0x8A)]),
-    ("TXS", &[(IMPL, 0x9A)]),
-    ("TYA", &[(IMPL, 0x98)]),
-    ("TYS", &[(IMPL, 0x2B)]),
-    ("TZA", &[(IMPL, 0x6B)]),
-
-    ("ADC", &[(IMM, 0x69), (ABS, 0x6D), (B, 0x65), (IND_X, 0x61), (IND_Y, 0x71), (IND_Z, 0x72), (B_X, 0x75), (ABS_X, 0x7D), (ABS_Y, 0x79)]),
-    ("AND", &[(IMM, 0x29), (ABS, 0x2D), (B, 0x25), (IND_X, 0x21), (IND_Y, 0x31), (IND_Z, 0x32), (B_X, 0x35), (ABS_X, 0x3D), (ABS_Y, 0x39)]),
-    ("ASL", &[(ABS, 0x0E), (B, 0x06), (ACCUM, 0x0A), (B_X, 0x16), (ABS_X, 0x1E)]),
-    ("ASR", &[(B, 0x44), (ACCUM, 0x43), (B_X, 0x54)]),
-    ("ASW", &[(ABS, 0xCB)]),
-    ("BIT", &[(IMM, 0x89), (ABS, 0x2C), (B, 0x24), (B_X, 0x34), (ABS_X, 0x3C)]),
-    ("BBR", &[(B_REL, 0x0F), (B_REL, 0x1F), (B_REL, 0x2F), (B_REL, 0x3F), (B_REL, 0x4F), (B_REL, 0x5F), (B_REL, 0x6F), (B_REL, 0x7F)]), // special
-    ("BBS", &[(B_REL, 0x8F), (B_REL, 0x9F), (B_REL, 0xAF), (B_REL, 0xBF), (B_REL, 0xCF), (B_REL, 0xDF), (B_REL, 0xEF), (B_REL, 0xFF)]), // special
-    ("BCC", &[(REL, 0x90), (WREL, 0x93)]),
-    ("BCS", &[(REL, 0xB0), (WREL, 0xB3)]),
-    ("BEQ", &[(REL, 0xF0), (WREL, 0xF3)]),
-    ("BMI", &[(REL, 0x30), (WREL, 0x33)]),
-    ("BNE", &[(REL, 0xD0), (WREL, 0xD3)]),
-    ("BPL", &[(REL, 0x10), (WREL, 0x13)]),
-    ("BRU", &[(REL, 0x80), (WREL, 0x83)]),
-    ("BSR", &[(WREL, 0x63)]),
-    ("BVC", &[(REL, 0x50), (WREL, 0x53)]),
-    ("BVS", &[(REL, 0x70), (WREL, 0x73)]),
-    ("CMP", &[(IMM, 0xC9), (ABS, 0xCD), (B, 0xC5), (IND_X, 0xC1), (IND_Y, 0xD1), (IND_Z, 0xD2), (B_X, 0xD5), (ABS_X, 0xDD), (ABS_Y, 0xD9)]),
-    ("CPX", &[(IMM, 0xE0), (ABS, 0xEC), (B, 0xE4)]),
-    ("CPY", &[(IMM, 0xC0), (ABS, 0xCC), (B, 0xC4)]),
-    ("CPZ", &[(IMM, 0xC2), (ABS, 0xDC), (B, 0xD4)]),
-    ("DEC", &[(ABS, 0xCE), (B, 0xC6), (ACCUM, 0x3A), (B_X, 0xD6), (ABS_X, 0xDE)]),
-    ("EOR", &[(IMM, 0x49), (ABS, 0x4D), (B, 0x45), (IND_X, 0x41), (IND_Y, 0x51), (IND_Z, 0x52), (B_X, 0x55), (ABS_X, 0x5D), (ABS_Y, 0x59)]),
-    ("INC", &[(ABS, 0xEE), (B, 0xE6), (ACCUM, 0x1A), (B_X, 0xF6), (ABS_X, 0xFE)]),
-    ("INW", &[(B, 0xE3)]),
-    ("JMP", &[(ABS, 0x4C), (IND_ABS, 0x6C), (IND_ABS_X, 0x7C)]),
-    ("JSR", &[(ABS, 0x20), (IND_ABS, 0x22), (IND_ABS_X, 0x23)]),
-    ("LDA", &[(IMM, 0xA9), (ABS, 0xAD), (B, 0xA5), (IND_X, 0xA1), (IND_Y, 0xB1), (IND_Z, 0xB2), (IND_SP, 0xE2), (B_X, 0xB5), (ABS_X, 0xBD), (ABS_Y, 0xB9)]),
-    ("LDX", &[(IMM, 0xA2), (ABS, 0xAE), (B, 0xA6), (B_Y, 0xB6), (ABS_Y, 0xBE)]),
-    ("LDY", &[(IMM, 0xA0), (ABS, 0xAC), (B, 0xA4), (B_X, 0xB4), (ABS_X, 0xBC)]),
-    ("LDZ", &[(IMM, 0xA3), (ABS, 0xAB), (ABS_X, 0xBB)]),
-    ("LSR", &[(ABS, 0x4E), (B, 0x46), (ACCUM, 0x4A), (B_X, 0x56), (ABS_X, 0x5E)]),
-    ("NEG", &[(ACCUM, 0x42)]),
-    ("ORA", &[(IMM, 0x09), (ABS, 0x0D), (B, 0x05), (IND_X, 0x01), (IND_Y, 0x11), (IND_Z, 0x12), (B_X, 0x15), (ABS_X, 0x1D), (ABS_Y, 0x19)]),
-    ("RMB", &[(B, 0x07), (B, 0x17), (B, 0x27), (B, 0x37), (B, 0x47), (B, 0x57), (B, 0x67), (B, 0x77)]), // special
-    ("ROL", &[(ABS, 0x2E), (B, 0x26), (ACCUM, 0x2A), (B_X, 0x36), (ABS_X, 0x3E)]),
-    ("ROR", &[(ABS, 0x6E), (B, 0x66), (ACCUM, 0x6A), (B_X, 0x76), (ABS_X, 0x7E)]),
-    ("ROW", &[(ABS, 0xEB)]),
-    ("SBC", &[(IMM, 0xE9), (ABS, 0xED), (B, 0xE5), (IND_X, 0xE1), (IND_Y, 0xF1), (IND_Z, 0xF2), (B_X, 0xF5), (ABS_X, 0xFD), (ABS_Y, 0xF9)]),
-    ("SMB", &[(B, 0x87), (B, 0x97), (B, 0xA7), (B, 0xB7), (B, 0xC7), (B, 0xD7), (B, 0xE7), (B, 0xF7)]), // special
-    ("STA", &[(ABS, 0x8D), (B, 0x85), (IND_X, 0x81), (IND_Y, 0x91), (IND_Z, 0x92), (IND_SP, 0x82), (B_X, 0x95), (ABS_X, 0x9D), (ABS_Y, 0x99)]),
-    ("STX", &[(ABS, 0x8E), (B, 0x86), (ABS_Y, 0x96), (ABS_Y, 0x9B)]),
-    ("STY", &[(ABS, 0x8C), (B, 0x84), (ABS_X, 0x94), (ABS_X, 0x8B)]),
-    ("STZ", &[(ABS, 0x9C), (B, 0x64), (ABS_X, 0x74), (ABS_X, 0x9E)]),
-    ("TRB", &[(ABS, 0x1C), (B, 0x14)]), // xfer reset bits, M[addr] &= ~A
-    ("TSB", &[(ABS, 0x0C), (B, 0x04)]), // xfer set bits, M[addr] |= A
-];
-
 fn operand(asm: &mut Asm, op: &Op) -> io::Result<()> {
     // implied?
     if (op.1.len() == 1) && (op.1[0].0 == IMPL) {
@@ -515,7 +478,7 @@ fn operand(asm: &mut Asm, op: &Op) -> io::Result<()> {
             asm.add_pc(1)?;
             return Ok(());
         }
-        return Err(asm.lexer().err("illegal addressing mode"))?;
+        Err(asm.lexer().err("illegal addressing mode"))?;
     }
 
     // some indirect thing?
@@ -619,7 +582,7 @@ fn operand(asm: &mut Asm, op: &Op) -> io::Result<()> {
     if op.0.eq_ignore_ascii_case("BBS") || op.0.eq_ignore_ascii_case("BBR") {
         let bit = expr(asm)?;
         let bit = const_expr(asm, bit)?;
-        if (bit < 0) || (bit > 7) {
+        if !(0..=7).contains(&bit) {
             return Err(asm.lexer().err("invalid bit"));
         }
         expect(asm, COMMA)?;
@@ -659,7 +622,7 @@ fn operand(asm: &mut Asm, op: &Op) -> io::Result<()> {
     if op.0.eq_ignore_ascii_case("RMB") || op.0.eq_ignore_ascii_case("SMB") {
         let bit = expr(asm)?;
         let bit = const_expr(asm, bit)?;
-        if (bit < 0) || (bit > 7) {
+        if !(0..=7).contains(&bit) {
             return Err(asm.lexer().err("invalid bit"));
         }
         expect(asm, COMMA)?;
@@ -870,24 +833,27 @@ fn operand(asm: &mut Asm, op: &Op) -> io::Result<()> {
 
 struct Asm {
     lexers: Vec<Box<dyn TokenSrc>>,
-    output: Box<dyn Write>,
+    /// Everything assembled so far, written out once both passes succeed
+    output: Vec<u8>,
     pc: u16,
     pc_end: bool,
     bss: u16,
     bss_end: bool,
-    syms: Vec<(String, i32)>,
+    /// Name, value, and the section labels were defined in
+    syms: Vec<(String, i32, Option<Section>)>,
     outer_label: String,
     emit: bool,
     bss_mode: bool,
     macros: Vec<Macro>,
     if_level: usize,
+    include_dirs: Vec<PathBuf>,
 }
 
 impl Asm {
-    fn new<R: Read + Seek + 'static>(lexer: Lexer<R>, output: Box<dyn Write>) -> Self {
+    fn new<R: Read + Seek + 'static>(lexer: Lexer<R>) -> Self {
         Self {
             lexers: vec![Box::new(lexer)],
-            output,
+            output: Vec::new(),
             pc: 0,
             pc_end: false,
             bss: 0,
@@ -898,6 +864,7 @@ impl Asm {
             bss_mode: false,
             macros: Vec::new(),
             if_level: 0,
+            include_dirs: Vec::new(),
         }
     }
 
@@ -916,7 +883,8 @@ impl Asm {
     }
 
     fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
-        self.output.write_all(bytes)
+        self.output.extend_from_slice(bytes);
+        Ok(())
     }
 
     fn lexer(&self) -> &dyn TokenSrc {
@@ -1147,7 +1115,7 @@ fn apply(values: &mut Vec<i32>, op: &'static str) {
 
 fn push_and_apply(values: &mut Vec<i32>, operators: &mut Vec<&'static str>, op: &'static str) {
     while let Some(top) = operators.last() {
-        if precedence(&top) > precedence(op) {
+        if precedence(top) > precedence(op) {
             break;
         }
         apply(values, top);
@@ -1330,11 +1298,13 @@ fn expr(asm: &mut Asm) -> io::Result<Option<i32>> {
             continue;
         }
         if asm.lexer_mut().peek()? == NUMBER {
+            // macro expansions only know the number of the current token
+            let number = asm.lexer().number();
             asm.lexer_mut().eat();
             if seen_value {
                 return Err(asm.lexer().err("expected operator"));
             }
-            values.push(asm.lexer().number());
+            values.push(number);
             seen_value = true;
             continue;
         }
@@ -1382,7 +1352,7 @@ fn expr(asm: &mut Asm) -> io::Result<Option<i32>> {
             if let Some(sym) = asm
                 .syms
                 .iter()
-                .find(|sym| sym.0.eq_ignore_ascii_case(&asm.lexer().string()))
+                .find(|sym| sym.0.eq_ignore_ascii_case(asm.lexer().string()))
                 .cloned()
             {
                 asm.lexer_mut().eat();
@@ -1523,7 +1493,15 @@ fn inf(asm: &mut Asm) -> io::Result<()> {
     if asm.lexer_mut().peek()? != STRING {
         return Err(asm.lexer().err("expected file name"));
     }
-    let file = File::open(&asm.lexer().string())?;
+    let name = asm.lexer().string().to_string();
+    let file = match File::open(&name) {
+        Err(e) if e.kind() == ErrorKind::NotFound => asm
+            .include_dirs
+            .iter()
+            .find_map(|dir| File::open(dir.join(&name)).ok())
+            .ok_or(e)?,
+        result => result?,
+    };
     asm.lexer_mut().eat();
     let reader = Reader::new(file);
     let lexer = Lexer::new(reader);
@@ -1579,7 +1557,7 @@ fn mac(asm: &mut Asm, name: String) -> io::Result<()> {
             ARGUMENT => {
                 let index = asm.lexer().number();
                 if index < 1 {
-                    return Err(asm
+                    Err(asm
                         .lexer()
                         .err("macro argument index must be greater than 0"))?;
                 }
@@ -1817,8 +1795,10 @@ impl<R: Read + Seek> TokenSrc for Lexer<R> {
                     self.string.push(c as char);
                     self.inner.eat();
                 }
-                self.number =
-                    i32::from_str_radix(&self.string, 10).map_err(|e| self.err(&e.to_string()))?;
+                self.number = self
+                    .string
+                    .parse::<i32>()
+                    .map_err(|e| self.err(&e.to_string()))?;
                 self.stash = Some(ARGUMENT);
                 return Ok(ARGUMENT);
             }
@@ -1900,7 +1880,7 @@ impl<R: Read + Seek> TokenSrc for Lexer<R> {
                 return Ok(IDENT);
             }
             // the char wasn't an ident, so wasnt eaten
-            if self.string.len() == 0 {
+            if self.string.is_empty() {
                 self.inner.eat();
             }
             // check for big symbol
@@ -0,0 +1,258 @@
+//! Disassembler
+//!
+//! Turns a binary (and optionally the SYM file `pasm` wrote for it) back
+//! into source `pasm` assembles to the same bytes. Instructions the
+//! assembler would encode differently (it picks branch widths itself, for
+//! one) are written out with `byt` and commented with their disassembly.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::{self, File},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use possum2_ops::{dasm::Instruction, *};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Input binary
+    input: PathBuf,
+
+    /// Output file (default: stdout)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Symbol file
+    #[arg(short, long)]
+    sym: Option<PathBuf>,
+
+    /// Load address in hex (default: the binary ends at $FFFF, like a ROM)
+    #[arg(long, value_parser = parse_hex)]
+    org: Option<u16>,
+}
+
+fn parse_hex(s: &str) -> Result<u16, String> {
+    u16::from_str_radix(s.trim_start_matches('$'), 16).map_err(|e| e.to_string())
+}
+
+/// Disassemble as `args` says
+pub fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+    let bin = fs::read(&args.input).map_err(|e| format!("cannot open file: {e}"))?;
+    let org = match args.org {
+        Some(org) => org as usize,
+        None => 0x10000usize
+            .checked_sub(bin.len())
+            .ok_or("binary is larger than 64KiB")?,
+    };
+    if org + bin.len() > 0x10000 {
+        Err("binary does not fit above the load address")?;
+    }
+
+    let mut symbols = HashMap::<u16, Vec<String>>::new();
+    if let Some(path) = &args.sym {
+        for symbol in possum2_sym::read(path)? {
+            symbols.entry(symbol.value).or_default().push(symbol.name);
+        }
+    }
+
+    let mut output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(
+            File::options()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)
+                .map_err(|e| format!("cannot open file: {e}"))?,
+        ),
+        None => Box::new(io::stdout()),
+    };
+    Dasm::new(&bin, org as u16, symbols).write(&mut output)?;
+    Ok(())
+}
+
+enum Line {
+    Inst(Instruction),
+    Byte,
+}
+
+struct Dasm<'a> {
+    bin: &'a [u8],
+    org: u16,
+    symbols: HashMap<u16, Vec<String>>,
+    lines: Vec<(u16, Line)>,
+    /// Symbols that land on a line, sorted
+    labels: Vec<u16>,
+}
+
+impl<'a> Dasm<'a> {
+    fn new(bin: &'a [u8], org: u16, symbols: HashMap<u16, Vec<String>>) -> Self {
+        let mut dasm = Self {
+            bin,
+            org,
+            symbols,
+            lines: Vec::new(),
+            labels: Vec::new(),
+        };
+        dasm.lines = dasm.decode();
+        dasm.labels = dasm
+            .symbols
+            .keys()
+            .filter(|addr| {
+                dasm.lines
+                    .binary_search_by_key(*addr, |(addr, _)| *addr)
+                    .is_ok()
+            })
+            .copied()
+            .collect();
+        dasm.labels.sort();
+        dasm
+    }
+
+    fn read(&self, addr: u16) -> u8 {
+        self.bin
+            .get(addr.wrapping_sub(self.org) as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn end(&self) -> usize {
+        self.org as usize + self.bin.len()
+    }
+
+    fn decode(&self) -> Vec<(u16, Line)> {
+        let mut lines = Vec::new();
+        let mut addr = self.org as usize;
+        while addr < self.end() {
+            let line = match Instruction::decode(addr as u16, |addr| self.read(addr)) {
+                Some(inst) if addr + inst.len as usize <= self.end() => Line::Inst(inst),
+                _ => Line::Byte,
+            };
+            let len = match &line {
+                Line::Inst(inst) => inst.len as usize,
+                Line::Byte => 1,
+            };
+            lines.push((addr as u16, line));
+            addr += len;
+        }
+        lines
+    }
+
+    fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        // symbols that don't land on a line become constants up front
+        let mut consts = self
+            .symbols
+            .iter()
+            .filter(|(addr, _)| self.labels.binary_search(addr).is_err())
+            .flat_map(|(addr, names)| names.iter().map(move |name| (*addr, name)))
+            .collect::<Vec<(u16, &String)>>();
+        consts.sort();
+        for (addr, name) in &consts {
+            writeln!(out, "{name}\tequ ${addr:04X}")?;
+        }
+        if !consts.is_empty() {
+            writeln!(out)?;
+        }
+        writeln!(out, "*\tequ ${:04X}", self.org)?;
+
+        let mut bytes = Vec::new();
+        for (addr, line) in &self.lines {
+            let label = self
+                .symbols
+                .get(addr)
+                .filter(|_| self.labels.binary_search(addr).is_ok());
+            // runs of data bytes share a line until a label splits them
+            if let Line::Byte = line {
+                if label.is_none() && bytes.len() < 8 {
+                    bytes.push(self.read(*addr));
+                    continue;
+                }
+            }
+            write_bytes(out, &mut bytes, None)?;
+
+            let names = label.map(Vec::as_slice).unwrap_or_default();
+            if let Some((last, rest)) = names.split_last() {
+                for name in rest {
+                    writeln!(out, "{name}")?;
+                }
+                write!(out, "{last}")?;
+            }
+            match line {
+                Line::Byte => bytes.push(self.read(*addr)),
+                Line::Inst(inst) => self.write_inst(out, inst)?,
+            }
+        }
+        write_bytes(out, &mut bytes, None)
+    }
+
+    fn write_inst(&self, out: &mut dyn Write, inst: &Instruction) -> io::Result<()> {
+        // branches and base-page operands may only name symbols already
+        // defined, otherwise the assembler can't size them in its first pass
+        let sized_by_value = matches!(inst.mode, REL | WREL | B | B_X | B_Y);
+        let operand = inst.operand_string(|addr| {
+            self.symbols
+                .get(&addr)
+                .filter(|_| !sized_by_value || self.defined_before(addr, inst.addr))
+                .map(|names| names[0].clone())
+        });
+        let text = format!("{} {operand}", inst.mnemonic.to_ascii_lowercase());
+        if self.assembles_to(inst) {
+            writeln!(out, "\t{}", text.trim_end())
+        } else {
+            let mut bytes = (0..inst.len)
+                .map(|i| self.read(inst.addr.wrapping_add(i)))
+                .collect();
+            write_bytes(out, &mut bytes, Some(text.trim_end()))
+        }
+    }
+
+    fn defined_before(&self, addr: u16, at: u16) -> bool {
+        self.labels.binary_search(&addr).is_err() || addr <= at
+    }
+
+    /// Whether `pasm` would produce the same bytes from the disassembly
+    fn assembles_to(&self, inst: &Instruction) -> bool {
+        let operand = inst.operand;
+        match (inst.mnemonic, inst.mode) {
+            // these always assemble with NOP padding
+            ("AUG", _) => operand == 0xEAEAEA,
+            ("BRK", _) => operand == 0xEA,
+            // the assembler wants a branch target for these
+            ("RMB" | "SMB", _) => false,
+            // the assembler's PC wraps before it computes the branch
+            (_, REL | WREL | B_REL) if inst.next() < inst.addr => false,
+            (mnemonic, REL | WREL) => {
+                let target = inst.target().unwrap() as i32;
+                let pc = inst.addr as i32;
+                let dist = target - pc;
+                let short = if dist > 0 { dist - 3 } else { dist - 2 };
+                if (i8::MIN as i32..=i8::MAX as i32).contains(&short) && mnemonic != "BSR" {
+                    inst.mode == REL && short as i8 as u8 == operand as u8
+                } else {
+                    inst.mode == WREL && (i16::MIN as i32..=i16::MAX as i32).contains(&(dist - 3))
+                }
+            }
+            (_, B_REL) => {
+                let target = inst.target().unwrap() as i32;
+                (i8::MIN as i32..=i8::MAX as i32).contains(&(target - inst.next() as i32))
+            }
+            _ => true,
+        }
+    }
+}
+
+fn write_bytes(out: &mut dyn Write, bytes: &mut Vec<u8>, comment: Option<&str>) -> io::Result<()> {
+    if bytes.is_empty() {
+        return Ok(());
+    }
+    let list = bytes
+        .drain(..)
+        .map(|byte| format!("${byte:02X}"))
+        .collect::<Vec<String>>()
+        .join(",");
+    match comment {
+        Some(comment) => writeln!(out, "\tbyt {list}\t; {comment}"),
+        None => writeln!(out, "\tbyt {list}"),
+    }
+}
@@ -0,0 +1,19 @@
+use std::process::ExitCode;
+
+use clap::Parser;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Cli {
+    #[command(flatten)]
+    args: dasm::Args,
+}
+
+fn main() -> ExitCode {
+    if let Err(e) = dasm::run(&Cli::parse().args) {
+        eprintln!("{e}");
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,90 @@
+//! CPU Core Benchmarks
+//!
+//! `Cpu::tick` over a flat 64K RAM bus, running:
+//! * a tight arithmetic loop
+//! * a 256-byte memory copy
+//! * an interrupt storm (an IRQ held asserted, so every instruction after
+//!   RTI is interrupted again)
+//!
+//! Run with `cargo bench -p possum2-emu`.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use possum2_emu::{bus::Bus, cpu::Cpu};
+
+const TICKS: u64 = 10_000;
+
+// the unit tests' buses, which the library only builds for tests
+#[allow(dead_code)]
+#[path = "../src/bus/test.rs"]
+mod test;
+
+use test::Ram;
+
+/// A program at F100, with the IRQ handler at F110
+fn ram(program: &[u8], handler: &[u8]) -> Ram {
+    let mut ram = Ram::new(0xEA);
+    ram.data[0xF100..0xF100 + program.len()].copy_from_slice(program);
+    ram.data[0xF110..0xF110 + handler.len()].copy_from_slice(handler);
+    ram.data[0xFFFC..].copy_from_slice(&[0x00, 0xF1, 0x10, 0xF1]);
+    ram
+}
+
+fn run(c: &mut Criterion, name: &str, program: &[u8], handler: &[u8], irq: bool) {
+    let mut ram = ram(program, handler);
+    let mut cpu = Cpu::new();
+    cpu.reset(&mut ram);
+    cpu.set_irq(irq);
+
+    let mut group = c.benchmark_group("cpu");
+    group.throughput(Throughput::Elements(TICKS));
+    group.bench_function(name, |b| {
+        b.iter(|| {
+            for _ in 0..TICKS {
+                cpu.tick(black_box(&mut ram));
+            }
+        })
+    });
+    group.finish();
+}
+
+fn arithmetic(c: &mut Criterion) {
+    #[rustfmt::skip]
+    let program = [
+        0xA2, 0x00,       // F100 LDX #$00
+        0x18,             // F102 CLC
+        0x69, 0x03,       // F103 ADC #$03
+        0xE8,             // F105 INX
+        0xD0, 0xFB,       // F106 BNE $F103
+        0x4C, 0x00, 0xF1, // F108 JMP $F100
+    ];
+    run(c, "arithmetic", &program, &[0x40], false);
+}
+
+fn memory_copy(c: &mut Criterion) {
+    #[rustfmt::skip]
+    let program = [
+        0xA2, 0x00,       // F100 LDX #$00
+        0xBD, 0x00, 0x10, // F102 LDA $1000,X
+        0x9D, 0x00, 0x20, // F105 STA $2000,X
+        0xE8,             // F108 INX
+        0xD0, 0xF7,       // F109 BNE $F102
+        0x4C, 0x00, 0xF1, // F10B JMP $F100
+    ];
+    run(c, "memory_copy", &program, &[0x40], false);
+}
+
+fn interrupt_storm(c: &mut Criterion) {
+    #[rustfmt::skip]
+    let program = [
+        0x58,             // F100 CLI
+        0xEA,             // F101 NOP
+        0x4C, 0x01, 0xF1, // F102 JMP $F101
+    ];
+    // F110 RTI
+    run(c, "interrupt_storm", &program, &[0x40], true);
+}
+
+criterion_group!(benches, arithmetic, memory_copy, interrupt_storm);
+criterion_main!(benches);
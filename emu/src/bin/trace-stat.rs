@@ -0,0 +1,163 @@
+//! Trace Summarizer
+//!
+//! Reads a trace written by `possum2-emu --trace-out` (in either format)
+//! and prints what the guest spent its time on: the busiest opcodes and
+//! addresses, the IO registers it touched, and how often it was
+//! interrupted.
+
+use std::{collections::HashMap, error::Error, path::PathBuf, process::ExitCode};
+
+use clap::Parser;
+use possum2_emu::trace::format::{Event, Reader};
+use possum2_ops::DECODE;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Trace file
+    input: PathBuf,
+
+    /// How many opcodes and addresses to list
+    #[arg(short = 'n', long, default_value_t = 10)]
+    top: usize,
+}
+
+#[derive(Default)]
+struct Stats {
+    events: u64,
+    first_cycle: Option<u64>,
+    last_cycle: u64,
+    instructions: u64,
+    opcodes: HashMap<u8, u64>,
+    pcs: HashMap<u16, u64>,
+    /// Reads and writes of each IO address
+    io: HashMap<u16, (u64, u64)>,
+    irqs: u64,
+    nmis: u64,
+    /// The cycles of the interrupts
+    interrupts: Vec<u64>,
+}
+
+impl Stats {
+    fn add(&mut self, event: &Event) {
+        let cycles = match *event {
+            Event::Insn {
+                cycles, pc, opcode, ..
+            } => {
+                self.instructions += 1;
+                *self.opcodes.entry(opcode).or_default() += 1;
+                *self.pcs.entry(pc).or_default() += 1;
+                cycles
+            }
+            Event::Io {
+                cycles,
+                addr,
+                write,
+                ..
+            } => {
+                let (reads, writes) = self.io.entry(addr).or_default();
+                *if write { writes } else { reads } += 1;
+                cycles
+            }
+            Event::Irq { cycles, nmi, .. } => {
+                if nmi {
+                    self.nmis += 1;
+                } else {
+                    self.irqs += 1;
+                }
+                self.interrupts.push(cycles);
+                cycles
+            }
+        };
+        self.events += 1;
+        self.first_cycle.get_or_insert(cycles);
+        self.last_cycle = cycles;
+    }
+
+    fn print(&self, top: usize) {
+        let first = self.first_cycle.unwrap_or(0);
+        println!("events        {}", self.events);
+        println!(
+            "cycles        {first}..{} ({})",
+            self.last_cycle,
+            self.last_cycle - first
+        );
+        println!("instructions  {}", self.instructions);
+        println!("interrupts    {} IRQ, {} NMI", self.irqs, self.nmis);
+        if self.interrupts.len() > 1 {
+            let span = self.interrupts[self.interrupts.len() - 1] - self.interrupts[0];
+            println!(
+                "              every {} cycles on average",
+                span / (self.interrupts.len() as u64 - 1)
+            );
+        }
+
+        println!();
+        println!("top opcodes:");
+        for (opcode, count) in hottest(&self.opcodes, top) {
+            let mnemonic = DECODE[opcode as usize].map_or("???", |decode| decode.mnemonic);
+            println!(
+                "  {opcode:02X} {mnemonic:4} {count:12} {:6.2}%",
+                percent(count, self.instructions)
+            );
+        }
+
+        println!();
+        println!("top addresses:");
+        for (pc, count) in hottest(&self.pcs, top) {
+            println!(
+                "  {pc:04X}     {count:12} {:6.2}%",
+                percent(count, self.instructions)
+            );
+        }
+
+        if !self.io.is_empty() {
+            println!();
+            println!("IO registers:        reads       writes");
+            let mut io = self.io.iter().collect::<Vec<_>>();
+            io.sort();
+            for (addr, (reads, writes)) in io {
+                println!("  {addr:04X}     {reads:12} {writes:12}");
+            }
+        }
+    }
+}
+
+/// The `top` biggest counts, biggest first
+fn hottest<K: Copy + Ord>(counts: &HashMap<K, u64>, top: usize) -> Vec<(K, u64)> {
+    let mut counts = counts
+        .iter()
+        .map(|(&key, &count)| (key, count))
+        .collect::<Vec<_>>();
+    counts.sort_by(|(a_key, a), (b_key, b)| b.cmp(a).then(a_key.cmp(b_key)));
+    counts.truncate(top);
+    counts
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    count as f64 * 100.0 / total as f64
+}
+
+fn main() -> ExitCode {
+    if let Err(e) = main_real() {
+        eprintln!("{e}");
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn main_real() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let reader = Reader::open(&args.input).map_err(|e| format!("{}: {e}", args.input.display()))?;
+    let mut stats = Stats::default();
+    for (i, event) in reader.enumerate() {
+        let event = event.map_err(|e| format!("{}: event {i}: {e}", args.input.display()))?;
+        stats.add(&event);
+    }
+    stats.print(args.top);
+    Ok(())
+}
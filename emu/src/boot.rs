@@ -0,0 +1,49 @@
+//! Floppy Boot
+//!
+//! `--boot-fd0` runs the machine without a ROM file, for iterating on
+//! software that boots from FD0. The first sectors of FD0 are copied into
+//! RAM at [`LOAD_ADDR`] after reset, and a built-in ROM stub jumps into
+//! them. The loaded image starts with a header for the interrupts, since
+//! the stub owns the vectors:
+//!
+//! ```text
+//! *           equ $0200
+//!             wrd Nmi,Irq     ; the stub jumps through these
+//! Boot        ...             ; and starts here, at $0204
+//! ```
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use possum2_media::{ROM_SIZE, ROM_START, SECTOR_SIZE};
+
+/// Where the boot sectors are loaded (bank 0)
+pub const LOAD_ADDR: u16 = 0x0200;
+pub const ENTRY: u16 = LOAD_ADDR + 4;
+
+/// The stub ROM image
+pub fn rom() -> Vec<u8> {
+    let [entry_lo, entry_hi] = ENTRY.to_le_bytes();
+    let [nmi_lo, nmi_hi] = LOAD_ADDR.to_le_bytes();
+    let [irq_lo, irq_hi] = (LOAD_ADDR + 2).to_le_bytes();
+    let code = [
+        0x4C, entry_lo, entry_hi, // Reset: jmp ENTRY
+        0x6C, nmi_lo, nmi_hi, // Nmi: jmp (LOAD_ADDR)
+        0x6C, irq_lo, irq_hi, // Irq: jmp (LOAD_ADDR+2)
+    ];
+    let mut rom = vec![0; ROM_SIZE];
+    rom[..code.len()].copy_from_slice(&code);
+    // NMI, reset, and IRQ vectors
+    for (i, vector) in [ROM_START + 3, ROM_START, ROM_START + 6].iter().enumerate() {
+        rom[ROM_SIZE - 6 + i * 2..][..2].copy_from_slice(&vector.to_le_bytes());
+    }
+    rom
+}
+
+/// Read the boot sectors from the start of a disk
+pub fn read_sectors(disk: &mut (impl Read + Seek), sectors: usize) -> io::Result<Vec<u8>> {
+    let mut data = vec![0; sectors * SECTOR_SIZE];
+    disk.seek(SeekFrom::Start(0))?;
+    disk.read_exact(&mut data)?;
+    disk.seek(SeekFrom::Start(0))?;
+    Ok(data)
+}
@@ -1,13 +1,65 @@
+use crate::xmodem::Xmodem;
+
+#[cfg(test)]
+pub mod test;
+
 pub trait Bus {
     fn read(&mut self, addr: u16) -> u8;
 
     fn write(&mut self, addr: u16, data: u8);
+
+    /// Handle an AUG-prefixed extended opcode, see [`crate::trap`].
+    /// Returns false if nothing did, leaving the AUG a 4-byte NOP.
+    #[allow(unused_variables)]
+    fn aug(&mut self, trap: &mut Trap) -> bool {
+        false
+    }
+}
+
+/// An AUG's operand bytes, and the registers it takes arguments from and
+/// leaves results in
+pub struct Trap {
+    pub operands: [u8; 3],
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub z: u8,
+    /// Set when the call failed
+    pub carry: bool,
+}
+
+/// A picture a device is putting out, as 0x00RRGGBB pixels row by row
+pub struct Frame<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: &'a [u32],
+    /// How many frames have been finished since power on
+    pub number: u64,
+    /// The scanline the beam is on, in the blanking interval past `height`
+    pub line: u16,
+    /// Frames per second, as a fraction
+    pub rate: (u32, u32),
+}
+
+/// What a disk drive is up to
+pub struct DiskActivity {
+    pub track: u8,
+    pub side: u8,
+    pub sector: u8,
+    /// A command is running
+    pub busy: bool,
+    /// Sectors transferred since power on
+    pub sectors_read: u64,
+    pub sectors_written: u64,
 }
 
 pub trait BusDevice {
-    fn reset<B: Bus>(&mut self, bus: &mut B);
+    /// Back to the power-on state, less anything the host end owns. Called
+    /// for every device on a system reset, and for one alone when the guest
+    /// writes the Device Reset register, so it mustn't rely on the others.
+    fn reset(&mut self, bus: &mut dyn Bus);
 
-    fn tick<B: Bus>(&mut self, bus: &mut B);
+    fn tick(&mut self, bus: &mut dyn Bus);
 
     #[allow(unused_variables)]
     fn read(&mut self, addr: u16) -> u8 {
@@ -16,4 +68,63 @@ pub trait BusDevice {
 
     #[allow(unused_variables)]
     fn write(&mut self, addr: u16, data: u8) {}
+
+    /// The register at `addr` as a read would return it, but without the
+    /// read's side effects. None for registers that can't be looked at
+    /// that way.
+    #[allow(unused_variables)]
+    fn peek(&self, addr: u16) -> Option<u8> {
+        None
+    }
+
+    /// The name of the register at `addr`, for IO traces
+    #[allow(unused_variables)]
+    fn register_name(&self, addr: u16) -> Option<&'static str> {
+        None
+    }
+
+    /// Queue host text as input, at `rate` bytes per second of emulated
+    /// time. Returns false if the device takes no input.
+    #[allow(unused_variables)]
+    fn paste(&mut self, data: &[u8], rate: u32) -> bool {
+        false
+    }
+
+    /// Hand the host end over to an XMODEM transfer until it finishes.
+    /// Returns false if the device has no host end.
+    #[allow(unused_variables)]
+    fn xmodem(&mut self, transfer: Xmodem) -> bool {
+        false
+    }
+
+    /// The current picture, for devices with a video output
+    fn frame(&self) -> Option<Frame<'_>> {
+        None
+    }
+
+    /// Video memory, for devices that have their own
+    fn vram(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Write a disk drive's overlay back to its base image, see
+    /// [`crate::overlay`]. None if the device isn't a disk drive.
+    fn commit(&mut self) -> Option<Result<usize, String>> {
+        None
+    }
+
+    /// Head position and transfer counts, for disk drives
+    fn disk(&self) -> Option<DiskActivity> {
+        None
+    }
+
+    /// State of the device's interrupt request output
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// State of the device's DMA request output
+    fn drq(&self) -> bool {
+        false
+    }
 }
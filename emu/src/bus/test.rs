@@ -0,0 +1,49 @@
+//! Buses for testing devices and the CPU on their own. The benchmarks
+//! include this file too, so it only leans on [`Bus`] being in scope.
+
+use super::Bus;
+
+/// Nothing attached: reads are 0 and writes go nowhere
+pub struct NoBus;
+
+impl Bus for NoBus {
+    fn read(&mut self, _addr: u16) -> u8 {
+        0
+    }
+
+    fn write(&mut self, _addr: u16, _data: u8) {}
+}
+
+/// 64KiB of RAM and nothing else, counting the accesses
+pub struct Ram {
+    pub data: Vec<u8>,
+    pub accesses: usize,
+}
+
+impl Ram {
+    /// Every byte set to `fill`
+    pub fn new(fill: u8) -> Self {
+        Self::from(vec![fill; 0x10000])
+    }
+}
+
+impl From<Vec<u8>> for Ram {
+    fn from(data: Vec<u8>) -> Self {
+        assert_eq!(data.len(), 0x10000);
+        Self { data, accesses: 0 }
+    }
+}
+
+impl Bus for Ram {
+    #[inline]
+    fn read(&mut self, addr: u16) -> u8 {
+        self.accesses += 1;
+        self.data[addr as usize]
+    }
+
+    #[inline]
+    fn write(&mut self, addr: u16, data: u8) {
+        self.accesses += 1;
+        self.data[addr as usize] = data;
+    }
+}
@@ -0,0 +1,49 @@
+//! Code Coverage Tracking
+
+pub enum CoverageFlags {}
+
+impl CoverageFlags {
+    pub const EXECUTED: u8 = 1 << 0;
+    pub const READ: u8 = 1 << 1;
+    pub const WRITTEN: u8 = 1 << 2;
+}
+
+pub struct Coverage {
+    enabled: bool,
+    flags: Vec<u8>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            flags: vec![0; 0x10000],
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.enabled = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.enabled = false;
+    }
+
+    pub fn clear(&mut self) {
+        self.flags.fill(0);
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn flags(&self, addr: u16) -> u8 {
+        self.flags[addr as usize]
+    }
+
+    pub fn mark(&mut self, addr: u16, flags: u8) {
+        if self.enabled {
+            self.flags[addr as usize] |= flags;
+        }
+    }
+}
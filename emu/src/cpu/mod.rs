@@ -1,6 +1,29 @@
 //! CSG65CE02 Emulation
-
-use crate::bus::{Bus, BusDevice};
+//!
+//! The B flag is where the 65CE02 documentation is vaguest. Like the 6502
+//! family it came from, B only exists on the stack: BRK pushes P with B
+//! set, IRQs and NMIs push it clear, and PLP and RTI leave the live B (and
+//! E) alone, so B never shows in P and a handler can tell a BRK from an
+//! IRQ by the flags alone. By default PHP pushes P as it is, the way this
+//! emulator always has. In strict mode it pushes B set, like the 6502.
+//!
+//! IRQ is a level, sampled between instructions against the I flag as it
+//! was before the last instruction ran, like the 6502: an IRQ waiting on
+//! CLI gets in one instruction late, and one arriving just as SEI runs
+//! still gets in. RTI is the exception and takes effect at once.
+//!
+//! `AUG $CB` (the 65C02's WAI opcode in AUG's first operand byte) halts
+//! the CPU until an IRQ or NMI arrives, with the devices running on. A
+//! masked IRQ wakes it too, carrying on after the AUG without taking the
+//! interrupt.
+//!
+//! Any other AUG whose operands aren't all NOPs is offered to the bus as
+//! an extended opcode (see [`crate::trap`]), and is a 4-byte NOP if the
+//! bus doesn't take it.
+
+use possum2_ops::DECODE;
+
+use crate::bus::{Bus, Trap};
 
 #[cfg(test)]
 mod tests;
@@ -18,6 +41,18 @@ impl Flags {
     pub const NEGATIVE: u8 = 1 << 7;
 }
 
+/// Nominal cost of taking an IRQ or NMI
+const INTERRUPT_CYCLES: u64 = 7;
+
+/// AUG operand that halts until an interrupt
+const AUG_WAI: u8 = 0xCB;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interrupt {
+    Nmi,
+    Irq,
+}
+
 #[derive(Debug, Default)]
 pub struct Cpu {
     a: u8,
@@ -31,7 +66,17 @@ pub struct Cpu {
 
     irq: bool,
     nmi: bool,
+    /// The I flag as of the last interrupt poll
+    irq_masked: bool,
     stack_xfer_wait: bool, // delay interrupt handling during stack transfers
+    waiting: bool,
+    strict: bool,
+    /// The interrupt the last tick took instead of running an instruction
+    taken: Option<Interrupt>,
+
+    // performance counters, kept across resets
+    instructions: u64,
+    cycles: u64,
 }
 
 impl Cpu {
@@ -71,14 +116,77 @@ impl Cpu {
         u16::from_le_bytes(self.pc)
     }
 
-    pub fn irq(&mut self) {
-        self.irq = true;
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc.to_le_bytes();
+    }
+
+    /// Instructions executed since power on
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Nominal cycles (from the opcode tables) spent since power on,
+    /// including cycles stolen by DMA
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Hold the CPU off the bus while a device uses it
+    pub fn stall(&mut self, cycles: u64) {
+        self.cycles += cycles;
+    }
+
+    /// Drive the IRQ line
+    pub fn set_irq(&mut self, asserted: bool) {
+        self.irq = asserted;
+    }
+
+    /// Whether the CPU is halted waiting for an interrupt
+    pub fn waiting(&self) -> bool {
+        self.waiting
+    }
+
+    /// The interrupt the last tick took, if it took one
+    pub fn interrupt_taken(&self) -> Option<Interrupt> {
+        self.taken
+    }
+
+    /// Push B set on PHP the 6502 way, see the module docs
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// P as pushed by PHP
+    fn p_php(&self) -> u8 {
+        if self.strict {
+            self.p | Flags::BREAK
+        } else {
+            self.p
+        }
     }
 
     pub fn nmi(&mut self) {
         self.nmi = true;
     }
 
+    fn trap<B: Bus>(&mut self, bus: &mut B, operands: [u8; 3]) {
+        let mut trap = Trap {
+            operands,
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            z: self.z,
+            carry: (self.p & Flags::CARRY) != 0,
+        };
+        if bus.aug(&mut trap) {
+            self.a = trap.a;
+            self.x = trap.x;
+            self.y = trap.y;
+            self.z = trap.z;
+            self.set_flag(Flags::CARRY, trap.carry);
+        }
+    }
+
     fn push<B: Bus>(&mut self, bus: &mut B, data: u8) {
         let addr = if (self.p & Flags::EXTEND_STACK_DISABLE) != 0 {
             self.sp[0] = self.sp[0].wrapping_sub(1);
@@ -124,6 +232,61 @@ impl Cpu {
         self.p = preserve_be | value;
     }
 
+    // The shifts move the bit shifted out into carry
+    fn shift(&mut self, result: u8, carry: bool) -> u8 {
+        self.set_flag(Flags::CARRY, carry);
+        self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
+        self.set_flag(Flags::ZERO, result == 0);
+        result
+    }
+
+    fn asl(&mut self, data: u8) -> u8 {
+        self.shift(data << 1, (data & 0x80) != 0)
+    }
+
+    fn rol(&mut self, data: u8) -> u8 {
+        self.shift((data << 1) | (self.p & Flags::CARRY), (data & 0x80) != 0)
+    }
+
+    fn lsr(&mut self, data: u8) -> u8 {
+        self.shift(data >> 1, (data & 0x01) != 0)
+    }
+
+    fn ror(&mut self, data: u8) -> u8 {
+        self.shift(
+            (data >> 1) | ((self.p & Flags::CARRY) << 7),
+            (data & 0x01) != 0,
+        )
+    }
+
+    fn asr(&mut self, data: u8) -> u8 {
+        self.shift(((data as i8) >> 1) as u8, (data & 0x01) != 0)
+    }
+
+    // Carry is set when there is no borrow
+    fn compare(&mut self, reg: u8, data: u8) {
+        let result = reg.wrapping_sub(data);
+        self.set_flag(Flags::CARRY, reg >= data);
+        self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
+        self.set_flag(Flags::ZERO, result == 0);
+    }
+
+    fn adc(&mut self, data: u8) {
+        let (result, carry1) = self.a.overflowing_add(data);
+        let (result, carry2) = result.overflowing_add(self.p & Flags::CARRY);
+        let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
+        self.a = result;
+        self.set_flag(Flags::OVERFLOW, overflow);
+        self.set_flag(Flags::CARRY, carry1 || carry2);
+        self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
+        self.set_flag(Flags::ZERO, self.a == 0);
+    }
+
+    fn sbc(&mut self, data: u8) {
+        // subtracting is adding the inverse, the carry being an inverted borrow
+        self.adc(!data);
+    }
+
     // (B,X)
     fn addr_b_indirect_x<B: Bus>(&mut self, bus: &mut B) -> u16 {
         let ptr = self.fetch(bus).wrapping_add(self.x);
@@ -216,8 +379,43 @@ impl Cpu {
     }
 }
 
-impl BusDevice for Cpu {
-    fn reset<B: Bus>(&mut self, bus: &mut B) {
+/// Register writes from outside the CPU, for script hooks
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+impl Cpu {
+    pub fn set_a(&mut self, a: u8) {
+        self.a = a;
+    }
+
+    pub fn set_b(&mut self, b: u8) {
+        self.b = b;
+    }
+
+    pub fn set_x(&mut self, x: u8) {
+        self.x = x;
+    }
+
+    pub fn set_y(&mut self, y: u8) {
+        self.y = y;
+    }
+
+    pub fn set_z(&mut self, z: u8) {
+        self.z = z;
+    }
+
+    /// Overwrite P, B and E included (unlike PLP)
+    pub fn set_flags(&mut self, p: u8) {
+        self.p = p;
+    }
+
+    pub fn set_sp(&mut self, sp: u16) {
+        self.sp = sp.to_le_bytes();
+    }
+}
+
+// The CPU drives the bus rather than sitting on it, so it stays generic
+// over the bus instead of being a `BusDevice`.
+impl Cpu {
+    pub fn reset<B: Bus>(&mut self, bus: &mut B) {
         let lo = bus.read(0xFFFC);
         let hi = bus.read(0xFFFD);
         *self = Self {
@@ -232,46 +430,75 @@ impl BusDevice for Cpu {
 
             irq: false,
             nmi: false,
+            irq_masked: true,
             stack_xfer_wait: false,
+            waiting: false,
+            strict: self.strict,
+            taken: None,
+
+            instructions: self.instructions,
+            cycles: self.cycles,
         };
     }
 
-    fn tick<B: Bus>(&mut self, bus: &mut B) {
+    pub fn tick<B: Bus>(&mut self, bus: &mut B) {
         // TXS and TYS instructions require delaying interrupt handling
         // for an extra tick because they need to be ran twice
         // in succession in either order.
+        self.taken = None;
         if !self.stack_xfer_wait {
             if self.nmi {
                 self.nmi = false;
+                self.waiting = false;
                 let [lo, hi] = self.pc;
                 self.push(bus, hi);
                 self.push(bus, lo);
-                self.push(bus, self.p);
+                self.push(bus, self.p & !Flags::BREAK);
                 self.p &= !Flags::DECIMAL_MODE;
                 self.p |= Flags::INTERRUPT_DISABLE;
                 let lo = bus.read(0xFFFA);
                 let hi = bus.read(0xFFFB);
                 self.pc = [lo, hi];
+                self.cycles += INTERRUPT_CYCLES;
+                self.taken = Some(Interrupt::Nmi);
                 return;
             }
 
-            if self.irq && ((self.p & Flags::INTERRUPT_DISABLE) == 0) {
-                self.irq = false;
+            if self.irq && !self.irq_masked {
+                self.waiting = false;
                 let [lo, hi] = self.pc;
                 self.push(bus, hi);
                 self.push(bus, lo);
-                self.push(bus, self.p);
+                self.push(bus, self.p & !Flags::BREAK);
                 self.p &= !Flags::DECIMAL_MODE;
                 self.p |= Flags::INTERRUPT_DISABLE;
                 let lo = bus.read(0xFFFE);
                 let hi = bus.read(0xFFFF);
                 self.pc = [lo, hi];
+                self.cycles += INTERRUPT_CYCLES;
+                self.irq_masked = true;
+                self.taken = Some(Interrupt::Irq);
                 return;
             }
         }
         self.stack_xfer_wait = false;
 
-        match self.fetch(bus) {
+        if self.waiting {
+            if !self.irq {
+                self.cycles += 1;
+                return;
+            }
+            // a masked IRQ still wakes us, but isn't taken
+            self.waiting = false;
+        }
+
+        let masked = (self.p & Flags::INTERRUPT_DISABLE) != 0;
+        let opcode = self.fetch(bus);
+        self.instructions += 1;
+        if let Some(decode) = &DECODE[opcode as usize] {
+            self.cycles += decode.cycles as u64;
+        }
+        match opcode {
             // BRK
             0x00 => {
                 // the intent of the extra byte following BRK is to store the BRK reason?
@@ -279,9 +506,9 @@ impl BusDevice for Cpu {
                 let [lo, hi] = self.pc;
                 self.push(bus, hi);
                 self.push(bus, lo);
-                self.push(bus, self.p);
+                self.push(bus, self.p | Flags::BREAK);
                 self.p &= !Flags::DECIMAL_MODE;
-                self.p |= Flags::BREAK | Flags::INTERRUPT_DISABLE;
+                self.p |= Flags::INTERRUPT_DISABLE;
                 let lo = bus.read(0xFFFE);
                 let hi = bus.read(0xFFFF);
                 self.pc = [lo, hi];
@@ -327,11 +554,8 @@ impl BusDevice for Cpu {
             0x06 => {
                 let addr = self.addr_b(bus);
                 let data = bus.read(addr);
-                let (data, carry) = data.overflowing_shl(1);
+                let data = self.asl(data);
                 bus.write(addr, data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (data & 0x80) != 0);
-                self.set_flag(Flags::ZERO, data == 0);
             }
 
             // RMB 0,B
@@ -344,7 +568,7 @@ impl BusDevice for Cpu {
 
             // PHP
             0x08 => {
-                self.push(bus, self.p);
+                self.push(bus, self.p_php());
             }
 
             // ORA IMM
@@ -357,11 +581,7 @@ impl BusDevice for Cpu {
 
             // ASL A
             0x0A => {
-                let (result, carry) = self.a.overflowing_shl(1);
-                self.a = result;
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                self.a = self.asl(self.a);
             }
 
             // TSY
@@ -392,11 +612,8 @@ impl BusDevice for Cpu {
             0x0E => {
                 let addr = self.addr_abs(bus);
                 let data = bus.read(addr);
-                let (data, carry) = data.overflowing_shl(1);
+                let data = self.asl(data);
                 bus.write(addr, data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (data & 0x80) != 0);
-                self.set_flag(Flags::ZERO, data == 0);
             }
 
             // BBR 0,B
@@ -472,11 +689,8 @@ impl BusDevice for Cpu {
             0x16 => {
                 let addr = self.addr_b_x(bus);
                 let data = bus.read(addr);
-                let (data, carry) = data.overflowing_shl(1);
+                let data = self.asl(data);
                 bus.write(addr, data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (data & 0x80) != 0);
-                self.set_flag(Flags::ZERO, data == 0);
             }
 
             // RMB 1,B
@@ -536,11 +750,8 @@ impl BusDevice for Cpu {
             0x1E => {
                 let addr = self.addr_abs_x(bus);
                 let data = bus.read(addr);
-                let (data, carry) = data.overflowing_shl(1);
+                let data = self.asl(data);
                 bus.write(addr, data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (data & 0x80) != 0);
-                self.set_flag(Flags::ZERO, data == 0);
             }
 
             // BBR 1,B
@@ -610,12 +821,8 @@ impl BusDevice for Cpu {
             0x26 => {
                 let addr = self.addr_b(bus);
                 let data = bus.read(addr);
-                let (result, carry) = data.overflowing_shl(1);
-                let result = result | (self.p & Flags::CARRY);
-                bus.write(addr, result);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                let data = self.rol(data);
+                bus.write(addr, data);
             }
 
             // RMB 2,B
@@ -642,11 +849,7 @@ impl BusDevice for Cpu {
 
             // ROL A
             0x2A => {
-                let (result, carry) = self.a.overflowing_shl(1);
-                self.a = result | (self.p & Flags::CARRY);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                self.a = self.rol(self.a);
             }
 
             // TYS
@@ -677,12 +880,8 @@ impl BusDevice for Cpu {
             0x2E => {
                 let addr = self.addr_abs(bus);
                 let data = bus.read(addr);
-                let (result, carry) = data.overflowing_shl(1);
-                let result = result | (self.p & Flags::CARRY);
-                bus.write(addr, result);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                let data = self.rol(data);
+                bus.write(addr, data);
             }
 
             // BBR 2,B
@@ -759,12 +958,8 @@ impl BusDevice for Cpu {
             0x36 => {
                 let addr = self.addr_b_x(bus);
                 let data = bus.read(addr);
-                let (result, carry) = data.overflowing_shl(1);
-                let result = result | (self.p & Flags::CARRY);
-                bus.write(addr, result);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                let data = self.rol(data);
+                bus.write(addr, data);
             }
 
             // RMB 3,B
@@ -825,12 +1020,8 @@ impl BusDevice for Cpu {
             0x3E => {
                 let addr = self.addr_abs_x(bus);
                 let data = bus.read(addr);
-                let (result, carry) = data.overflowing_shl(1);
-                let result = result | (self.p & Flags::CARRY);
-                bus.write(addr, result);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                let data = self.rol(data);
+                bus.write(addr, data);
             }
 
             // BBR 3,B
@@ -865,30 +1056,22 @@ impl BusDevice for Cpu {
 
             // NEG A
             0x42 => {
-                self.a = (-(self.a as i8)) as u8;
+                self.a = self.a.wrapping_neg();
                 self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
                 self.set_flag(Flags::ZERO, self.a == 0);
             }
 
             // ASR A
             0x43 => {
-                let (result, carry) = (self.a as i8).overflowing_shr(1);
-                self.a = result as u8;
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                self.a = self.asr(self.a);
             }
 
             // ASR B
             0x44 => {
                 let addr = self.addr_b(bus);
                 let data = bus.read(addr);
-                let (data, carry) = (data as i8).overflowing_shr(1);
-                let data = data as u8;
+                let data = self.asr(data);
                 bus.write(addr, data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (data & 0x80) != 0);
-                self.set_flag(Flags::ZERO, data == 0);
             }
 
             // EOR B
@@ -904,11 +1087,8 @@ impl BusDevice for Cpu {
             0x46 => {
                 let addr = self.addr_b(bus);
                 let data = bus.read(addr);
-                let (data, carry) = data.overflowing_shr(1);
+                let data = self.lsr(data);
                 bus.write(addr, data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (data & 0x80) != 0);
-                self.set_flag(Flags::ZERO, data == 0);
             }
 
             // RMB 4,B
@@ -934,11 +1114,7 @@ impl BusDevice for Cpu {
 
             // LSR A
             0x4A => {
-                let (result, carry) = self.a.overflowing_shr(1);
-                self.a = result;
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                self.a = self.lsr(self.a);
             }
 
             // TAZ
@@ -967,11 +1143,8 @@ impl BusDevice for Cpu {
             0x4E => {
                 let addr = self.addr_abs(bus);
                 let data = bus.read(addr);
-                let (data, carry) = data.overflowing_shr(1);
+                let data = self.lsr(data);
                 bus.write(addr, data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (data & 0x80) != 0);
-                self.set_flag(Flags::ZERO, data == 0);
             }
 
             // BBR 4,B
@@ -1030,12 +1203,8 @@ impl BusDevice for Cpu {
             0x54 => {
                 let addr = self.addr_b_x(bus);
                 let data = bus.read(addr);
-                let (data, carry) = (data as i8).overflowing_shr(1);
-                let data = data as u8;
+                let data = self.asr(data);
                 bus.write(addr, data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (data & 0x80) != 0);
-                self.set_flag(Flags::ZERO, data == 0);
             }
 
             // EOR B,X
@@ -1051,11 +1220,8 @@ impl BusDevice for Cpu {
             0x56 => {
                 let addr = self.addr_b_x(bus);
                 let data = bus.read(addr);
-                let (data, carry) = data.overflowing_shr(1);
+                let data = self.lsr(data);
                 bus.write(addr, data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (data & 0x80) != 0);
-                self.set_flag(Flags::ZERO, data == 0);
             }
 
             // RMB 5,B
@@ -1092,9 +1258,12 @@ impl BusDevice for Cpu {
 
             // AUG
             0x5C => {
-                self.fetch(bus);
-                self.fetch(bus);
-                self.fetch(bus);
+                let operands = [self.fetch(bus), self.fetch(bus), self.fetch(bus)];
+                if operands[0] == AUG_WAI {
+                    self.waiting = true;
+                } else if operands != [0xEA; 3] {
+                    self.trap(bus, operands);
+                }
             }
 
             // EOR ABS,X
@@ -1110,11 +1279,8 @@ impl BusDevice for Cpu {
             0x5E => {
                 let addr = self.addr_abs_x(bus);
                 let data = bus.read(addr);
-                let (data, carry) = data.overflowing_shr(1);
+                let data = self.lsr(data);
                 bus.write(addr, data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (data & 0x80) != 0);
-                self.set_flag(Flags::ZERO, data == 0);
             }
 
             // BBR 5,B
@@ -1140,15 +1306,7 @@ impl BusDevice for Cpu {
             0x61 => {
                 let addr = self.addr_b_indirect_x(bus);
                 let data = bus.read(addr);
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                self.adc(data);
             }
 
             // RTN IMM
@@ -1187,27 +1345,15 @@ impl BusDevice for Cpu {
             0x65 => {
                 let addr = self.addr_b(bus);
                 let data = bus.read(addr);
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                self.adc(data);
             }
 
             // ROR B
             0x66 => {
                 let addr = self.addr_b(bus);
                 let data = bus.read(addr);
-                let (result, carry) = data.overflowing_shr(1);
-                let result = result | ((self.p & Flags::CARRY) << 7);
-                bus.write(addr, result);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                let data = self.ror(data);
+                bus.write(addr, data);
             }
 
             // RMB 6,B
@@ -1228,24 +1374,12 @@ impl BusDevice for Cpu {
             // ADC IMM
             0x69 => {
                 let data = self.fetch(bus);
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                self.adc(data);
             }
 
             // ROR A
             0x6A => {
-                let (result, carry) = self.a.overflowing_shr(1);
-                self.a = result | ((self.p & Flags::CARRY) << 7);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                self.a = self.ror(self.a);
             }
 
             // TZA
@@ -1265,27 +1399,15 @@ impl BusDevice for Cpu {
             0x6D => {
                 let addr = self.addr_abs(bus);
                 let data = bus.read(addr);
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                self.adc(data);
             }
 
             // ROR ABS
             0x6E => {
                 let addr = self.addr_abs(bus);
                 let data = bus.read(addr);
-                let (result, carry) = data.overflowing_shr(1);
-                let result = result | ((self.p & Flags::CARRY) << 7);
-                bus.write(addr, result);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                let data = self.ror(data);
+                bus.write(addr, data);
             }
 
             // BBR 6,B
@@ -1314,30 +1436,14 @@ impl BusDevice for Cpu {
             0x71 => {
                 let addr = self.addr_b_indirect_y(bus);
                 let data = bus.read(addr);
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                self.adc(data);
             }
 
             // ADC (B),Z
             0x72 => {
                 let addr = self.addr_b_indirect_z(bus);
                 let data = bus.read(addr);
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                self.adc(data);
             }
 
             // BVS WREL
@@ -1362,27 +1468,15 @@ impl BusDevice for Cpu {
             0x75 => {
                 let addr = self.addr_b_x(bus);
                 let data = bus.read(addr);
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                self.adc(data);
             }
 
             // ROR B,X
             0x76 => {
                 let addr = self.addr_b_x(bus);
                 let data = bus.read(addr);
-                let (result, carry) = data.overflowing_shr(1);
-                let result = result | ((self.p & Flags::CARRY) << 7);
-                bus.write(addr, result);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                let data = self.ror(data);
+                bus.write(addr, data);
             }
 
             // RMB 7,B
@@ -1402,15 +1496,7 @@ impl BusDevice for Cpu {
             0x79 => {
                 let addr = self.addr_abs_y(bus);
                 let data = bus.read(addr);
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                self.adc(data);
             }
 
             // PLY
@@ -1437,27 +1523,15 @@ impl BusDevice for Cpu {
             0x7D => {
                 let addr = self.addr_abs_x(bus);
                 let data = bus.read(addr);
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                self.adc(data);
             }
 
             // ROR ABS,X
             0x7E => {
                 let addr = self.addr_abs_x(bus);
                 let data = bus.read(addr);
-                let (result, carry) = data.overflowing_shr(1);
-                let result = result | ((self.p & Flags::CARRY) << 7);
-                bus.write(addr, result);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                let data = self.ror(data);
+                bus.write(addr, data);
             }
 
             // BBR 7,B
@@ -1635,7 +1709,7 @@ impl BusDevice for Cpu {
             // STX B,Y
             0x96 => {
                 let addr = self.addr_b_y(bus);
-                bus.write(addr, self.a);
+                bus.write(addr, self.x);
             }
 
             // SMB 1,B
@@ -1883,7 +1957,7 @@ impl BusDevice for Cpu {
 
             // LDX B,Y
             0xB6 => {
-                let addr = self.addr_b_x(bus);
+                let addr = self.addr_b_y(bus);
                 self.x = bus.read(addr);
                 self.set_flag(Flags::NEGATIVE, (self.x & 0x80) != 0);
                 self.set_flag(Flags::ZERO, self.x == 0);
@@ -1964,29 +2038,20 @@ impl BusDevice for Cpu {
             // CPY IMM
             0xC0 => {
                 let data = self.fetch(bus);
-                let (result, carry) = self.y.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.y, data);
             }
 
             // CMP (B,X)
             0xC1 => {
                 let addr = self.addr_b_indirect_x(bus);
                 let data = bus.read(addr);
-                let (result, carry) = self.a.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.a, data);
             }
 
             // CPZ IMM
             0xC2 => {
                 let data = self.fetch(bus);
-                let (result, carry) = self.z.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.z, data);
             }
 
             // DEW B
@@ -2006,20 +2071,14 @@ impl BusDevice for Cpu {
             0xC4 => {
                 let addr = self.addr_b(bus);
                 let data = bus.read(addr);
-                let (result, carry) = self.y.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.y, data);
             }
 
             // CMP B
             0xC5 => {
                 let addr = self.addr_b(bus);
                 let data = bus.read(addr);
-                let (result, carry) = self.a.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.a, data);
             }
 
             // DEC B
@@ -2050,10 +2109,7 @@ impl BusDevice for Cpu {
             // CMP IMM
             0xC9 => {
                 let data = self.fetch(bus);
-                let (result, carry) = self.a.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.a, data);
             }
 
             // DEX
@@ -2065,14 +2121,15 @@ impl BusDevice for Cpu {
 
             // ASW ABS
             0xCB => {
-                let addr = self.addr_b(bus);
+                let addr = self.addr_abs(bus);
                 let lo = bus.read(addr);
                 let hi = bus.read(addr.wrapping_add(1));
-                let (result, carry) = u16::from_le_bytes([lo, hi]).overflowing_shl(1);
+                let data = u16::from_le_bytes([lo, hi]);
+                let result = data << 1;
                 let [lo, hi] = result.to_le_bytes();
                 bus.write(addr, lo);
                 bus.write(addr.wrapping_add(1), hi);
-                self.set_flag(Flags::CARRY, carry);
+                self.set_flag(Flags::CARRY, (data & 0x8000) != 0);
                 self.set_flag(Flags::NEGATIVE, (result & 0x8000) != 0);
                 self.set_flag(Flags::ZERO, result == 0);
             }
@@ -2081,20 +2138,14 @@ impl BusDevice for Cpu {
             0xCC => {
                 let addr = self.addr_abs(bus);
                 let data = bus.read(addr);
-                let (result, carry) = self.y.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.y, data);
             }
 
             // CMP ABS
             0xCD => {
                 let addr = self.addr_abs(bus);
                 let data = bus.read(addr);
-                let (result, carry) = self.a.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.a, data);
             }
 
             // DEC ABS
@@ -2133,20 +2184,14 @@ impl BusDevice for Cpu {
             0xD1 => {
                 let addr = self.addr_b_indirect_y(bus);
                 let data = bus.read(addr);
-                let (result, carry) = self.a.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.a, data);
             }
 
             // CMP (B),Z
             0xD2 => {
                 let addr = self.addr_b_indirect_z(bus);
                 let data = bus.read(addr);
-                let (result, carry) = self.a.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.a, data);
             }
 
             // BNE WREL
@@ -2165,20 +2210,14 @@ impl BusDevice for Cpu {
             0xD4 => {
                 let addr = self.addr_b(bus);
                 let data = bus.read(addr);
-                let (result, carry) = self.z.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.z, data);
             }
 
             // CMP B,X
             0xD5 => {
                 let addr = self.addr_b_x(bus);
                 let data = bus.read(addr);
-                let (result, carry) = self.a.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.a, data);
             }
 
             // DEC B,X
@@ -2208,10 +2247,7 @@ impl BusDevice for Cpu {
             0xD9 => {
                 let addr = self.addr_abs_y(bus);
                 let data = bus.read(addr);
-                let (result, carry) = self.a.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.a, data);
             }
 
             // PHX
@@ -2228,20 +2264,14 @@ impl BusDevice for Cpu {
             0xDC => {
                 let addr = self.addr_abs(bus);
                 let data = bus.read(addr);
-                let (result, carry) = self.z.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.z, data);
             }
 
             // CMP ABS,X
             0xDD => {
                 let addr = self.addr_abs_x(bus);
                 let data = bus.read(addr);
-                let (result, carry) = self.a.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.a, data);
             }
 
             // DEC ABS,X
@@ -2269,25 +2299,14 @@ impl BusDevice for Cpu {
             // CPX IMM
             0xE0 => {
                 let data = self.fetch(bus);
-                let (result, carry) = self.x.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.x, data);
             }
 
             // SBC (B,X)
             0xE1 => {
                 let addr = self.addr_b_indirect_x(bus);
-                let data = !bus.read(addr); // invert arg and adc
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                let data = bus.read(addr);
+                self.sbc(data);
             }
 
             // LDA (d,SP),Y
@@ -2315,25 +2334,14 @@ impl BusDevice for Cpu {
             0xE4 => {
                 let addr = self.addr_b(bus);
                 let data = bus.read(addr);
-                let (result, carry) = self.x.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.x, data);
             }
 
             // SBC B
             0xE5 => {
                 let addr = self.addr_b(bus);
-                let data = !bus.read(addr); // invert arg and adc
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                let data = bus.read(addr);
+                self.sbc(data);
             }
 
             // INC B
@@ -2363,31 +2371,24 @@ impl BusDevice for Cpu {
 
             // SBC IMM
             0xE9 => {
-                let data = !self.fetch(bus); // invert arg and adc
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                let data = self.fetch(bus);
+                self.sbc(data);
             }
 
             // NOP
             0xEA => {}
 
-            // ROW
+            // ROW ABS
             0xEB => {
-                let addr = self.addr_b(bus);
+                let addr = self.addr_abs(bus);
                 let lo = bus.read(addr);
                 let hi = bus.read(addr.wrapping_add(1));
-                let (result, carry) = u16::from_le_bytes([lo, hi]).overflowing_shl(1);
+                let data = u16::from_le_bytes([lo, hi]);
+                let result = (data << 1) | (self.p & Flags::CARRY) as u16;
                 let [lo, hi] = result.to_le_bytes();
                 bus.write(addr, lo);
                 bus.write(addr.wrapping_add(1), hi);
-                self.set_flag(Flags::CARRY, carry);
+                self.set_flag(Flags::CARRY, (data & 0x8000) != 0);
                 self.set_flag(Flags::NEGATIVE, (result & 0x8000) != 0);
                 self.set_flag(Flags::ZERO, result == 0);
             }
@@ -2396,25 +2397,14 @@ impl BusDevice for Cpu {
             0xEC => {
                 let addr = self.addr_abs(bus);
                 let data = bus.read(addr);
-                let (result, carry) = self.x.overflowing_sub(data);
-                self.set_flag(Flags::CARRY, carry);
-                self.set_flag(Flags::NEGATIVE, (result & 0x80) != 0);
-                self.set_flag(Flags::ZERO, result == 0);
+                self.compare(self.x, data);
             }
 
             // SBC ABS
             0xED => {
                 let addr = self.addr_abs(bus);
-                let data = !bus.read(addr); // invert arg and adc
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                let data = bus.read(addr);
+                self.sbc(data);
             }
 
             // INC ABS
@@ -2452,31 +2442,15 @@ impl BusDevice for Cpu {
             // SBC (B),Y
             0xF1 => {
                 let addr = self.addr_b_indirect_y(bus);
-                let data = !bus.read(addr); // invert arg and adc
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                let data = bus.read(addr);
+                self.sbc(data);
             }
 
             // SBC (B),Z
             0xF2 => {
                 let addr = self.addr_b_indirect_z(bus);
-                let data = !bus.read(addr); // invert arg and adc
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                let data = bus.read(addr);
+                self.sbc(data);
             }
 
             // BEQ WREL
@@ -2502,16 +2476,8 @@ impl BusDevice for Cpu {
             // SBC B,X
             0xF5 => {
                 let addr = self.addr_b_x(bus);
-                let data = !bus.read(addr); // invert arg and adc
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                let data = bus.read(addr);
+                self.sbc(data);
             }
 
             // INC B,X
@@ -2540,16 +2506,8 @@ impl BusDevice for Cpu {
             // SBC ABS,Y
             0xF9 => {
                 let addr = self.addr_abs_y(bus);
-                let data = !bus.read(addr); // invert arg and adc
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                let data = bus.read(addr);
+                self.sbc(data);
             }
 
             // PLX
@@ -2568,7 +2526,7 @@ impl BusDevice for Cpu {
 
             // PHW WABS
             0xFC => {
-                let addr = self.addr_abs_indirect(bus);
+                let addr = self.addr_abs(bus);
                 let lo = bus.read(addr);
                 let hi = bus.read(addr.wrapping_add(1));
                 self.push(bus, hi);
@@ -2578,16 +2536,8 @@ impl BusDevice for Cpu {
             // SBC ABS,X
             0xFD => {
                 let addr = self.addr_abs_x(bus);
-                let data = !bus.read(addr); // invert arg and adc
-                let (result, carry1) = self.a.overflowing_add(data);
-                let (result, carry2) =
-                    result.overflowing_add(if (self.p & Flags::CARRY) != 0 { 1 } else { 0 });
-                let overflow = ((!(self.a ^ data)) & (self.a ^ result) & 0x80) != 0;
-                self.a = result;
-                self.set_flag(Flags::OVERFLOW, overflow);
-                self.set_flag(Flags::CARRY, carry1 || carry2);
-                self.set_flag(Flags::NEGATIVE, (self.a & 0x80) != 0);
-                self.set_flag(Flags::ZERO, self.a == 0);
+                let data = bus.read(addr);
+                self.sbc(data);
             }
 
             // INC ABS,X
@@ -2612,5 +2562,13 @@ impl BusDevice for Cpu {
                 }
             }
         }
+
+        // interrupts are polled before the instruction's last cycle, so
+        // only RTI's change to I counts straight away
+        self.irq_masked = if opcode == 0x40 {
+            (self.p & Flags::INTERRUPT_DISABLE) != 0
+        } else {
+            masked
+        };
     }
 }
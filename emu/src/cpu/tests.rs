@@ -1,6 +1,883 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use possum2_ops::{ABS, ABS_X, ABS_Y, ACCUM, B, B_X, IMM, IND_X, IND_Y, IND_Z};
+
 use super::*;
+use crate::bus::test::Ram;
 
 #[test]
 fn foo() {
-    let cpu = Cpu::new();
+    let _cpu = Cpu::new();
+}
+
+#[test]
+fn counters_survive_reset() {
+    let mut bus = Ram::new(0xEA); // NOP everywhere, vectors at $EAEA
+    let mut cpu = Cpu::new();
+    cpu.reset(&mut bus);
+    for _ in 0..3 {
+        cpu.tick(&mut bus);
+    }
+    cpu.reset(&mut bus);
+    cpu.tick(&mut bus);
+    assert_eq!(cpu.instructions(), 4);
+    assert_eq!(
+        cpu.cycles(),
+        4 * possum2_ops::DECODE[0xEA].unwrap().cycles as u64
+    );
+}
+
+/// What an ALU op should leave in its destination and P, worked out with
+/// wider arithmetic rather than the CPU's carries. `reg` is the register
+/// an add, subtract, or compare works on.
+fn alu_oracle(mnemonic: &str, reg: u8, data: u8, p: u8) -> (u8, u8) {
+    let carry = (p & Flags::CARRY) as i16;
+    // (result, the value N and Z come from, carry, overflow)
+    let (result, value, carry, overflow) = match mnemonic {
+        "ADC" => {
+            let sum = reg as i16 + data as i16 + carry;
+            let signed = reg as i8 as i16 + data as i8 as i16 + carry;
+            let overflow = !(-128..=127).contains(&signed);
+            (sum as u8, sum as u8, sum > 0xFF, Some(overflow))
+        }
+        "SBC" => {
+            let diff = reg as i16 - data as i16 - (1 - carry);
+            let signed = reg as i8 as i16 - data as i8 as i16 - (1 - carry);
+            let overflow = !(-128..=127).contains(&signed);
+            (diff as u8, diff as u8, diff >= 0, Some(overflow))
+        }
+        "CMP" | "CPX" | "CPY" | "CPZ" => {
+            let diff = reg as i16 - data as i16;
+            (reg, diff as u8, diff >= 0, None)
+        }
+        "ASL" => {
+            let result = (data as i16 * 2) as u8;
+            (result, result, data >= 0x80, None)
+        }
+        "ROL" => {
+            let result = (data as i16 * 2 + carry) as u8;
+            (result, result, data >= 0x80, None)
+        }
+        "LSR" => (data / 2, data / 2, data % 2 == 1, None),
+        "ROR" => {
+            let result = data / 2 + carry as u8 * 0x80;
+            (result, result, data % 2 == 1, None)
+        }
+        "ASR" => {
+            let result = data / 2 + (data & 0x80);
+            (result, result, data % 2 == 1, None)
+        }
+        _ => unreachable!(),
+    };
+    let mut p = p & !(Flags::NEGATIVE | Flags::ZERO | Flags::CARRY);
+    if value >= 0x80 {
+        p |= Flags::NEGATIVE;
+    }
+    if value == 0 {
+        p |= Flags::ZERO;
+    }
+    if carry {
+        p |= Flags::CARRY;
+    }
+    if let Some(overflow) = overflow {
+        p &= !Flags::OVERFLOW;
+        if overflow {
+            p |= Flags::OVERFLOW;
+        }
+    }
+    (result, p)
+}
+
+/// Every form of every ALU op, for every operand (and register) value with
+/// the carry both ways, against [`alu_oracle`]
+#[test]
+fn alu_flags_match_oracle() {
+    const DATA: u16 = 0x0300;
+    let mut bus = Ram::new(0);
+    // (B) modes point at the data
+    bus.data[0x20..=0x21].copy_from_slice(&DATA.to_le_bytes());
+    let mut failures = Vec::new();
+    for opcode in 0..=0xFF {
+        let Some(decode) = DECODE[opcode as usize] else {
+            continue;
+        };
+        let mnemonic = decode.mnemonic;
+        if !matches!(
+            mnemonic,
+            "ADC" | "SBC" | "CMP" | "CPX" | "CPY" | "CPZ" | "ASL" | "ROL" | "LSR" | "ROR" | "ASR"
+        ) {
+            continue;
+        }
+        // where the operand lives; indexes are all 0
+        let (operand, data_addr) = match decode.mode {
+            IMM => (vec![0], Some(0x0201)),
+            ACCUM => (vec![], None),
+            B | B_X => (vec![0x10], Some(0x0010)),
+            ABS | ABS_X | ABS_Y => (DATA.to_le_bytes().to_vec(), Some(DATA)),
+            IND_X | IND_Y | IND_Z => (vec![0x20], Some(DATA)),
+            _ => unreachable!("{mnemonic} has an untested mode"),
+        };
+        bus.data[0x0200] = opcode;
+        bus.data[0x0201..][..operand.len()].copy_from_slice(&operand);
+        let regs = match mnemonic {
+            "ADC" | "SBC" | "CMP" | "CPX" | "CPY" | "CPZ" => 0..=0xFF,
+            _ => 0..=0,
+        };
+        'op: for reg in regs {
+            for data in 0..=0xFF {
+                for p in [0, Flags::CARRY, Flags::OVERFLOW] {
+                    let p = p | Flags::EXTEND_STACK_DISABLE;
+                    let mut cpu = Cpu::new();
+                    cpu.pc = 0x0200u16.to_le_bytes();
+                    cpu.p = p;
+                    match mnemonic {
+                        "CPX" => cpu.x = reg,
+                        "CPY" => cpu.y = reg,
+                        "CPZ" => cpu.z = reg,
+                        _ => cpu.a = reg,
+                    }
+                    match data_addr {
+                        Some(addr) => bus.data[addr as usize] = data,
+                        None => cpu.a = data,
+                    }
+                    cpu.tick(&mut bus);
+
+                    let result = match (mnemonic, data_addr) {
+                        ("CPX", _) => cpu.x,
+                        ("CPY", _) => cpu.y,
+                        ("CPZ", _) => cpu.z,
+                        ("ADC" | "SBC" | "CMP", _) | (_, None) => cpu.a,
+                        (_, Some(addr)) => bus.data[addr as usize],
+                    };
+                    let expected = alu_oracle(mnemonic, reg, data, p);
+                    if (result, cpu.p) != expected {
+                        failures.push(format!(
+                            "{mnemonic} ({opcode:02X}) reg={reg:02X} data={data:02X} p={p:02X}: \
+                             expected {:02X} p={:02X}, got {result:02X} p={:02X}",
+                            expected.0, expected.1, cpu.p
+                        ));
+                        break 'op;
+                    }
+                }
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn word_shifts() {
+    // (opcode, word, carry in, result, carry out)
+    let cases = [
+        (0xCB, 0x4001, false, 0x8002, false),
+        (0xCB, 0x8001, true, 0x0002, true),
+        (0xCB, 0x8000, false, 0x0000, true),
+        (0xEB, 0x4001, true, 0x8003, false),
+        (0xEB, 0x8000, false, 0x0000, true),
+        (0xEB, 0xFFFF, true, 0xFFFF, true),
+    ];
+    for (opcode, word, carry, result, carry_out) in cases {
+        let mut bus = Ram::new(0);
+        bus.data[0x0200..0x0203].copy_from_slice(&[opcode, 0x00, 0x03]);
+        bus.data[0x0300..0x0302].copy_from_slice(&u16::to_le_bytes(word));
+        let mut cpu = Cpu::new();
+        cpu.pc = 0x0200u16.to_le_bytes();
+        cpu.p = if carry { Flags::CARRY } else { 0 };
+        cpu.tick(&mut bus);
+        assert_eq!(cpu.pc(), 0x0203, "{opcode:02X} {word:04X}");
+        assert_eq!(
+            u16::from_le_bytes([bus.data[0x0300], bus.data[0x0301]]),
+            result,
+            "{opcode:02X} {word:04X}"
+        );
+        assert_eq!(
+            cpu.p & Flags::CARRY != 0,
+            carry_out,
+            "{opcode:02X} {word:04X}"
+        );
+        assert_eq!(
+            cpu.p & Flags::ZERO != 0,
+            result == 0,
+            "{opcode:02X} {word:04X}"
+        );
+        assert_eq!(
+            cpu.p & Flags::NEGATIVE != 0,
+            result >= 0x8000,
+            "{opcode:02X} {word:04X}"
+        );
+    }
+}
+
+/// Run `program` from $0200 for `instructions` ticks, with the stack at
+/// the top of page 1
+fn run_program(cpu: &mut Cpu, bus: &mut Ram, program: &[u8], instructions: usize) {
+    bus.data[0x0200..][..program.len()].copy_from_slice(program);
+    cpu.pc = 0x0200u16.to_le_bytes();
+    cpu.sp = 0x01FFu16.to_le_bytes();
+    for _ in 0..instructions {
+        cpu.tick(bus);
+    }
+}
+
+#[test]
+fn base_page_y_transfers() {
+    let mut bus = Ram::new(0);
+    bus.data[0x0013] = 0x5A;
+    let mut cpu = Cpu::new();
+    cpu.x = 0xA5;
+    cpu.y = 0x03;
+    // STX $20,Y; LDX $10,Y
+    run_program(&mut cpu, &mut bus, &[0x96, 0x20, 0xB6, 0x10], 2);
+    assert_eq!(bus.data[0x0023], 0xA5);
+    assert_eq!(cpu.x(), 0x5A);
+}
+
+#[test]
+fn push_words() {
+    let mut bus = Ram::new(0);
+    bus.data[0x0300..0x0302].copy_from_slice(&[0x34, 0x12]);
+    let mut cpu = Cpu::new();
+    // PHW #$BEEF; PHW $0300
+    run_program(&mut cpu, &mut bus, &[0xF4, 0xEF, 0xBE, 0xFC, 0x00, 0x03], 2);
+    let top = cpu.sp() as usize;
+    assert_eq!(top, 0x01FF - 4);
+    // words are pushed high byte first, leaving them little-endian in memory
+    assert_eq!(bus.data[top..(top + 4)], [0x34, 0x12, 0xEF, 0xBE]);
+}
+
+#[test]
+fn break_flag_on_the_stack() {
+    for strict in [false, true] {
+        let mut bus = Ram::new(0);
+        bus.data[0xFFFE..].copy_from_slice(&[0x00, 0x04]);
+        let mut cpu = Cpu::new();
+        cpu.set_strict(strict);
+        // PHP; BRK
+        run_program(&mut cpu, &mut bus, &[0x08, 0x00, 0x00], 2);
+        let top = cpu.sp() as usize;
+        let (brk, php) = (bus.data[top], bus.data[top + 3]);
+        assert_eq!(php & Flags::BREAK != 0, strict, "strict {strict}");
+        assert_ne!(brk & Flags::BREAK, 0, "strict {strict}");
+        assert_eq!(cpu.p() & Flags::BREAK, 0, "strict {strict}");
+
+        cpu.p &= !Flags::INTERRUPT_DISABLE;
+        cpu.set_irq(true);
+        cpu.tick(&mut bus);
+        assert_eq!(cpu.pc(), 0x0400, "strict {strict}");
+        let irq = bus.data[cpu.sp() as usize];
+        assert_eq!(irq & Flags::BREAK, 0, "strict {strict}");
+    }
+}
+
+#[test]
+fn break_flag_stays_off_after_rti() {
+    let mut bus = Ram::new(0);
+    // the BRK handler at 0400 is just RTI
+    bus.data[0x0400] = 0x40;
+    bus.data[0xFFFE..].copy_from_slice(&[0x00, 0x04]);
+    let mut cpu = Cpu::new();
+    // BRK; RTI; PHP
+    run_program(&mut cpu, &mut bus, &[0x00, 0x00, 0x08], 3);
+    assert_eq!(cpu.p() & Flags::BREAK, 0);
+    let php = bus.data[cpu.sp() as usize];
+    assert_eq!(php & Flags::BREAK, 0);
+}
+
+#[test]
+fn irq_waits_for_cli_to_finish() {
+    let mut bus = Ram::new(0xEA);
+    bus.data[0xFFFE..].copy_from_slice(&[0x00, 0x04]);
+    let mut cpu = Cpu::new();
+    cpu.p = Flags::INTERRUPT_DISABLE;
+    cpu.irq_masked = true;
+    cpu.set_irq(true);
+    // CLI; NOP; NOP
+    run_program(&mut cpu, &mut bus, &[0x58, 0xEA, 0xEA], 2);
+    assert_eq!(cpu.pc(), 0x0202);
+    cpu.tick(&mut bus);
+    assert_eq!(cpu.pc(), 0x0400);
+
+    // and one arriving as SEI runs still gets in
+    cpu.p = 0;
+    cpu.set_irq(false);
+    run_program(&mut cpu, &mut bus, &[0xEA, 0x78], 2);
+    cpu.set_irq(true);
+    cpu.tick(&mut bus);
+    assert_eq!(cpu.pc(), 0x0400);
+    assert_eq!(bus.data[0x01FD..0x01FF], [0x02, 0x02]);
+}
+
+#[test]
+fn wai_halts_until_an_interrupt() {
+    for masked in [false, true] {
+        let mut bus = Ram::new(0xEA);
+        bus.data[0xFFFE..].copy_from_slice(&[0x00, 0x04]);
+        let mut cpu = Cpu::new();
+        cpu.p = if masked { Flags::INTERRUPT_DISABLE } else { 0 };
+        // AUG $CB $EA $EA
+        run_program(&mut cpu, &mut bus, &[0x5C, 0xCB, 0xEA, 0xEA], 100);
+        assert!(cpu.waiting());
+        assert_eq!(cpu.pc(), 0x0204);
+        assert_eq!(cpu.instructions(), 1);
+
+        cpu.set_irq(true);
+        cpu.tick(&mut bus);
+        assert!(!cpu.waiting());
+        assert_eq!(cpu.pc(), if masked { 0x0205 } else { 0x0400 });
+    }
+}
+
+#[test]
+fn aug_is_offered_to_the_bus() {
+    /// Takes AUG $01 and doubles A
+    struct TrapBus(Ram);
+
+    impl Bus for TrapBus {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.0.read(addr)
+        }
+
+        fn write(&mut self, addr: u16, data: u8) {
+            self.0.write(addr, data)
+        }
+
+        fn aug(&mut self, trap: &mut Trap) -> bool {
+            if trap.operands != [0x01, 0x02, 0x03] {
+                return false;
+            }
+            trap.a *= 2;
+            trap.carry = true;
+            true
+        }
+    }
+
+    let mut bus = TrapBus(Ram::new(0));
+    let program = [0x5C, 0x01, 0x02, 0x03, 0x5C, 0x01, 0x02, 0x04];
+    bus.0.data[0x0200..][..program.len()].copy_from_slice(&program);
+    let mut cpu = Cpu::new();
+    cpu.pc = 0x0200u16.to_le_bytes();
+    cpu.a = 21;
+    cpu.tick(&mut bus);
+    assert_eq!(cpu.a(), 42);
+    assert_ne!(cpu.p() & Flags::CARRY, 0);
+    // anything the bus doesn't take is a NOP
+    cpu.tick(&mut bus);
+    assert_eq!(cpu.pc(), 0x0208);
+    assert_eq!(cpu.a(), 42);
+}
+
+/// Published functional test binaries as (file, load address, start
+/// address, success trap address).
+///
+/// The binaries aren't checked in. Grab them from
+/// https://github.com/Klaus2m5/6502_65C02_functional_tests and drop them in
+/// `tests/functional` (or point `POSSUM2_FUNCTIONAL_TESTS` at them), then
+/// run `cargo test -p possum2-emu functional_tests -- --ignored`. A binary
+/// that isn't there fails the test.
+///
+/// `$3469` is the success trap of the prebuilt `6502_functional_test.bin`,
+/// which is assembled with the default options. That means
+/// `disable_decimal = 0`, so it checks decimal mode ADC and SBC, which this
+/// CPU doesn't do yet: expect it to trap in the decimal tests. Assembled
+/// with `disable_decimal = 1` it skips them and the success trap moves, so
+/// take the new address from the listing.
+///
+/// The 65C02 extended opcodes test can't pass on the 65CE02, which uses
+/// the 65C02's single-byte `$x3` and `$xB` NOPs for its own instructions.
+const FUNCTIONAL_TESTS: &[(&str, u16, u16, u16)] =
+    &[("6502_functional_test.bin", 0x0000, 0x0400, 0x3469)];
+
+/// Far more instructions than any of the suites need to finish
+const FUNCTIONAL_TEST_TICKS: u64 = 200_000_000;
+
+/// Run a test binary until it traps (jumps or branches to itself),
+/// returning the trap address
+fn run_functional_test(image: &[u8], load: u16, start: u16) -> Option<u16> {
+    let mut bus = Ram::new(0);
+    bus.data[(load as usize)..][..image.len()].copy_from_slice(image);
+    bus.data[0xFFFC..=0xFFFD].copy_from_slice(&start.to_le_bytes());
+
+    let mut cpu = Cpu::new();
+    cpu.reset(&mut bus);
+    for _ in 0..FUNCTIONAL_TEST_TICKS {
+        let pc = cpu.pc();
+        cpu.tick(&mut bus);
+        if cpu.pc() == pc {
+            return Some(pc);
+        }
+    }
+    None
+}
+
+#[test]
+#[ignore = "needs the functional test binaries, see FUNCTIONAL_TESTS"]
+fn functional_tests() {
+    let dir = env::var_os("POSSUM2_FUNCTIONAL_TESTS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/functional"));
+    let mut report = Vec::new();
+    let mut failed = false;
+    for &(file, load, start, success) in FUNCTIONAL_TESTS {
+        let result = match fs::read(dir.join(file)) {
+            Err(e) => format!("FAIL (can't read it from {}: {e})", dir.display()),
+            Ok(image) => match run_functional_test(&image, load, start) {
+                Some(pc) if pc == success => "pass".to_string(),
+                Some(pc) => format!("FAIL (trapped at {pc:04X}, expected {success:04X})"),
+                None => "FAIL (never trapped)".to_string(),
+            },
+        };
+        failed |= result != "pass";
+        report.push(format!("{file}: {result}"));
+    }
+    let report = report.join("\n");
+    assert!(!failed, "functional tests failed:\n{report}");
+    println!("{report}");
+}
+
+/// TomHarte-style single-step vectors: one JSON file per opcode, each an
+/// array of tests giving the state before and after a single instruction.
+///
+/// The vectors aren't checked in. Put the files for the opcodes to check in
+/// `tests/single-step` (or point `POSSUM2_SINGLE_STEP_TESTS` at them) and
+/// run `cargo test --features single-step-tests`. Bus cycles are ignored
+/// since the CPU only counts nominal cycles; only registers and RAM are
+/// compared.
+#[cfg(feature = "single-step-tests")]
+mod single_step {
+    use std::{fs::File, io::BufReader};
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Vector {
+        name: String,
+        initial: State,
+        #[serde(rename = "final")]
+        expected: State,
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct State {
+        pc: u16,
+        s: u8,
+        a: u8,
+        x: u8,
+        y: u8,
+        p: u8,
+        ram: Vec<(u16, u8)>,
+    }
+
+    fn run_vector(vector: &Vector) -> State {
+        let mut bus = Ram::new(0);
+        for &(addr, data) in &vector.initial.ram {
+            bus.data[addr as usize] = data;
+        }
+        let mut cpu = Cpu::new();
+        cpu.pc = vector.initial.pc.to_le_bytes();
+        cpu.sp = [vector.initial.s, 1];
+        cpu.a = vector.initial.a;
+        cpu.x = vector.initial.x;
+        cpu.y = vector.initial.y;
+        cpu.p = vector.initial.p;
+
+        cpu.tick(&mut bus);
+
+        State {
+            pc: cpu.pc(),
+            s: cpu.sp[0],
+            a: cpu.a,
+            x: cpu.x,
+            y: cpu.y,
+            p: cpu.p,
+            ram: vector
+                .expected
+                .ram
+                .iter()
+                .map(|&(addr, _)| (addr, bus.data[addr as usize]))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn single_step_tests() {
+        let dir = env::var_os("POSSUM2_SINGLE_STEP_TESTS")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/single-step"));
+        let Ok(entries) = fs::read_dir(&dir) else {
+            println!("skipped (no vectors in {})", dir.display());
+            return;
+        };
+        let mut paths = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect::<Vec<PathBuf>>();
+        paths.sort();
+
+        let mut failures = Vec::new();
+        for path in paths {
+            let file = File::open(&path).unwrap();
+            let vectors: Vec<Vector> = serde_json::from_reader(BufReader::new(file)).unwrap();
+            let mut passed = 0;
+            for vector in &vectors {
+                let actual = run_vector(vector);
+                if actual == vector.expected {
+                    passed += 1;
+                } else if !failures.contains(&path) {
+                    // only the first failure per file, the rest are usually the same bug
+                    println!(
+                        "{}: `{}` expected {:?}, got {actual:?}",
+                        path.display(),
+                        vector.name,
+                        vector.expected
+                    );
+                    failures.push(path.clone());
+                }
+            }
+            println!("{}: {passed}/{} passed", path.display(), vectors.len());
+        }
+        assert!(
+            failures.is_empty(),
+            "single-step tests failed: {failures:?}"
+        );
+    }
+}
+
+/// Differential fuzzing against a reference 65CE02.
+///
+/// A case is a random instruction stream: the starting state (registers
+/// plus every byte the stream touches) and a trace of the state after each
+/// instruction, where `ram` holds the bytes that instruction wrote. Run
+/// `cargo test --features diff-fuzz` to replay the cases in
+/// `tests/diff-fuzz` (or `POSSUM2_DIFF_FUZZ`). Mismatches are reported at
+/// the first instruction that disagrees and tallied by opcode, which is
+/// usually enough to pin down a flag bug.
+///
+/// To record new cases, set `POSSUM2_DIFF_FUZZ_EXPORT` to a file. That
+/// writes random cases traced by this core (`POSSUM2_DIFF_FUZZ_SEED` and
+/// `POSSUM2_DIFF_FUZZ_CASES` pick which and how many); run each `initial`
+/// state through the reference for as many instructions as the trace is
+/// long, and replace the trace with what it did.
+#[cfg(feature = "diff-fuzz")]
+mod diff_fuzz {
+    use std::{
+        collections::BTreeMap,
+        fs::File,
+        io::{BufReader, BufWriter},
+    };
+
+    use possum2_ops::dasm::Instruction;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    /// Instructions per generated case
+    const STEPS: usize = 16;
+
+    #[derive(Serialize, Deserialize)]
+    struct Case {
+        name: String,
+        initial: State,
+        trace: Vec<State>,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+    struct State {
+        pc: u16,
+        sp: u16,
+        a: u8,
+        b: u8,
+        x: u8,
+        y: u8,
+        z: u8,
+        p: u8,
+        ram: Vec<(u16, u8)>,
+    }
+
+    /// xorshift64*, so cases are reproducible from a seed
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+        }
+
+        fn byte(&mut self) -> u8 {
+            (self.next() >> 32) as u8
+        }
+    }
+
+    /// RAM that remembers the first value of every byte touched and what
+    /// each instruction writes. With `fill`, bytes nobody set read as
+    /// random values.
+    struct TraceBus {
+        ram: Vec<u8>,
+        known: Vec<bool>,
+        initial: BTreeMap<u16, u8>,
+        writes: BTreeMap<u16, u8>,
+        fill: Option<Rng>,
+    }
+
+    impl TraceBus {
+        fn new(fill: Option<Rng>) -> Self {
+            Self {
+                ram: vec![0; 0x10000],
+                known: vec![false; 0x10000],
+                initial: BTreeMap::new(),
+                writes: BTreeMap::new(),
+                fill,
+            }
+        }
+
+        fn set(&mut self, addr: u16, data: u8) {
+            self.ram[addr as usize] = data;
+            self.known[addr as usize] = true;
+            self.initial.insert(addr, data);
+        }
+
+        fn touch(&mut self, addr: u16) {
+            if !self.known[addr as usize] {
+                let data = match &mut self.fill {
+                    Some(rng) => rng.byte(),
+                    None => self.ram[addr as usize],
+                };
+                self.set(addr, data);
+            }
+        }
+    }
+
+    impl Bus for TraceBus {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.touch(addr);
+            self.ram[addr as usize]
+        }
+
+        fn write(&mut self, addr: u16, data: u8) {
+            self.touch(addr);
+            self.ram[addr as usize] = data;
+            self.writes.insert(addr, data);
+        }
+    }
+
+    fn load(state: &State, bus: &mut TraceBus) -> Cpu {
+        for &(addr, data) in &state.ram {
+            bus.set(addr, data);
+        }
+        let mut cpu = Cpu::new();
+        cpu.pc = state.pc.to_le_bytes();
+        cpu.sp = state.sp.to_le_bytes();
+        cpu.a = state.a;
+        cpu.b = state.b;
+        cpu.x = state.x;
+        cpu.y = state.y;
+        cpu.z = state.z;
+        cpu.p = state.p;
+        cpu
+    }
+
+    fn save(cpu: &Cpu, ram: Vec<(u16, u8)>) -> State {
+        State {
+            pc: cpu.pc(),
+            sp: cpu.sp(),
+            a: cpu.a,
+            b: cpu.b,
+            x: cpu.x,
+            y: cpu.y,
+            z: cpu.z,
+            p: cpu.p,
+            ram,
+        }
+    }
+
+    fn generate(rng: &mut Rng, name: String) -> Case {
+        let opcodes = (0..=0xFF)
+            .filter(|&op| DECODE[op as usize].is_some())
+            .collect::<Vec<u8>>();
+        let mut bus = TraceBus::new(Some(Rng(rng.next() | 1)));
+        let mut cpu = Cpu::new();
+        cpu.pc = (rng.next() as u16).to_le_bytes();
+        cpu.sp = (rng.next() as u16).to_le_bytes();
+        cpu.a = rng.byte();
+        cpu.b = rng.byte();
+        cpu.x = rng.byte();
+        cpu.y = rng.byte();
+        cpu.z = rng.byte();
+        cpu.p = rng.byte();
+
+        // lay the stream down up front, random bytes fill in wherever
+        // branches take it
+        let mut addr = cpu.pc();
+        for _ in 0..STEPS {
+            let opcode = opcodes[rng.next() as usize % opcodes.len()];
+            bus.set(addr, opcode);
+            for i in 1..DECODE[opcode as usize].unwrap().len {
+                bus.set(addr.wrapping_add(i), rng.byte());
+            }
+            addr = addr.wrapping_add(DECODE[opcode as usize].unwrap().len);
+        }
+
+        let registers = save(&cpu, Vec::new());
+        let mut trace = Vec::new();
+        for _ in 0..STEPS {
+            cpu.tick(&mut bus);
+            let writes = bus.writes.iter().map(|(&addr, &data)| (addr, data));
+            trace.push(save(&cpu, writes.collect()));
+            bus.writes.clear();
+        }
+        Case {
+            name,
+            initial: State {
+                ram: bus.initial.into_iter().collect(),
+                ..registers
+            },
+            trace,
+        }
+    }
+
+    struct Mismatch {
+        step: usize,
+        opcode: u8,
+        /// The disassembly of the instruction that disagreed
+        text: String,
+        actual: State,
+    }
+
+    /// Replay a case, stopping at the first step that disagrees
+    fn replay(case: &Case) -> Option<Mismatch> {
+        let mut bus = TraceBus::new(None);
+        let mut cpu = load(&case.initial, &mut bus);
+        for (step, expected) in case.trace.iter().enumerate() {
+            let pc = cpu.pc();
+            let opcode = bus.ram[pc as usize];
+            let text = match Instruction::decode(pc, |addr| bus.ram[addr as usize]) {
+                Some(inst) => format!(
+                    "{pc:04X} {} {}",
+                    inst.mnemonic,
+                    inst.operand_string(|_| None)
+                ),
+                None => format!("{pc:04X} ???"),
+            };
+            cpu.tick(&mut bus);
+            let ram = expected
+                .ram
+                .iter()
+                .map(|&(addr, _)| (addr, bus.ram[addr as usize]))
+                .collect();
+            let actual = save(&cpu, ram);
+            if actual != *expected {
+                return Some(Mismatch {
+                    step,
+                    opcode,
+                    text,
+                    actual,
+                });
+            }
+        }
+        None
+    }
+
+    fn flags(p: u8) -> String {
+        "NVEBDIZC"
+            .chars()
+            .enumerate()
+            .map(|(i, name)| if p & (0x80 >> i) != 0 { name } else { '-' })
+            .collect()
+    }
+
+    fn describe(expected: &State, actual: &State) -> String {
+        let mut diffs = Vec::new();
+        let mut reg = |name: &str, expected: u16, actual: u16| {
+            if expected != actual {
+                diffs.push(format!("{name} {expected:02X}!={actual:02X}"));
+            }
+        };
+        reg("PC", expected.pc, actual.pc);
+        reg("SP", expected.sp, actual.sp);
+        reg("A", expected.a as u16, actual.a as u16);
+        reg("B", expected.b as u16, actual.b as u16);
+        reg("X", expected.x as u16, actual.x as u16);
+        reg("Y", expected.y as u16, actual.y as u16);
+        reg("Z", expected.z as u16, actual.z as u16);
+        if expected.p != actual.p {
+            diffs.push(format!("P {}!={}", flags(expected.p), flags(actual.p)));
+        }
+        for (&(addr, expected), &(_, actual)) in expected.ram.iter().zip(&actual.ram) {
+            if expected != actual {
+                diffs.push(format!("{addr:04X} {expected:02X}!={actual:02X}"));
+            }
+        }
+        diffs.join(", ")
+    }
+
+    fn env_u64(name: &str, default: u64) -> u64 {
+        env::var(name).map_or(default, |value| value.parse().unwrap())
+    }
+
+    #[test]
+    fn diff_fuzz_export() {
+        let Some(path) = env::var_os("POSSUM2_DIFF_FUZZ_EXPORT") else {
+            println!("skipped (POSSUM2_DIFF_FUZZ_EXPORT not set)");
+            return;
+        };
+        let seed = env_u64("POSSUM2_DIFF_FUZZ_SEED", 1);
+        let count = env_u64("POSSUM2_DIFF_FUZZ_CASES", 1000);
+        let mut rng = Rng(seed | 1);
+        let cases = (0..count)
+            .map(|i| generate(&mut rng, format!("{seed}-{i}")))
+            .collect::<Vec<Case>>();
+        let file = BufWriter::new(File::create(&path).unwrap());
+        serde_json::to_writer(file, &cases).unwrap();
+        println!("wrote {count} cases to {}", Path::new(&path).display());
+    }
+
+    #[test]
+    fn diff_fuzz() {
+        let dir = env::var_os("POSSUM2_DIFF_FUZZ")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/diff-fuzz"));
+        let Ok(entries) = fs::read_dir(&dir) else {
+            println!("skipped (no cases in {})", dir.display());
+            return;
+        };
+        let mut paths = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect::<Vec<PathBuf>>();
+        paths.sort();
+
+        // opcode -> (failures, first example)
+        let mut failures = BTreeMap::<u8, (usize, String)>::new();
+        let mut total = 0;
+        for path in paths {
+            let file = File::open(&path).unwrap();
+            let cases: Vec<Case> = serde_json::from_reader(BufReader::new(file)).unwrap();
+            total += cases.len();
+            for case in &cases {
+                let Some(mismatch) = replay(case) else {
+                    continue;
+                };
+                let example = format!(
+                    "{} step {}: {}: {}",
+                    case.name,
+                    mismatch.step,
+                    mismatch.text.trim_end(),
+                    describe(&case.trace[mismatch.step], &mismatch.actual)
+                );
+                failures.entry(mismatch.opcode).or_insert((0, example)).0 += 1;
+            }
+        }
+        for (opcode, (count, example)) in &failures {
+            println!("{opcode:02X}: {count} failures, first: {example}");
+        }
+        let failed = failures.values().map(|(count, _)| count).sum::<usize>();
+        println!("{}/{total} cases passed", total - failed);
+        assert!(failures.is_empty(), "differential fuzzing found mismatches");
+    }
 }
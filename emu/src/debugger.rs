@@ -0,0 +1,2669 @@
+//! Debugger
+//!
+//! The commands behind the `dbg>` prompt. Output goes to whatever writer
+//! the front-end hands in, so the same commands serve the console and
+//! remote clients.
+//!
+//! TODO: Reverse stepping (`rs`: restore the nearest earlier snapshot and
+//!   run forward to the instruction before the PC) needs rewind snapshots
+//!   first. `state` isn't one: it only writes out the registers and the IO
+//!   registers that peek without side effects, leaves out RAM and the
+//!   devices' insides (FDC buffers and disk position, PPU VRAM and DMA,
+//!   UART FIFOs, timer counts), and nothing reads it back in. Replaying
+//!   from a CPU and RAM copy alone would tick the devices on from where
+//!   they are now, not from where they were, so every device needs a
+//!   saveable state first.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    num::ParseIntError,
+    ops::Range,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use possum2_ops::{asm, dasm::Instruction, op_len, B_REL, REL, WREL};
+use serde_json::{json, Value};
+use termion::color::{Fg, LightBlue, LightMagenta, LightRed, LightYellow, Reset};
+
+use crate::{
+    cov::{Coverage, CoverageFlags},
+    cpu::{Cpu, Flags},
+    filter::{self, Filter},
+    hooks::Hooks,
+    idle::Idle,
+    irq::{IrqSource, IrqStats},
+    mem::{check::MemCheck, Mem, IO_START, RAM_CHAPTERS, ROM_START},
+    png,
+    ppu::inspect,
+    profile::Profiler,
+    record::Recorder,
+    stack::StackGuard,
+    stats::Stats,
+    sys::{IoBreakFlags, System},
+    trace::{TraceFilter, Tracer},
+    watchdog::Watchdog,
+    xmodem::{self, Xmodem},
+};
+
+pub struct Debugger {
+    pub symbols: HashMap<u16, Vec<String>>,
+    pub breakpoints: Breakpoints,
+    pub watches: Vec<u16>,
+    pub profiler: Profiler,
+    pub stats: Stats,
+    pub idle: Idle,
+    pub hooks: Hooks,
+    pub recorder: Option<Recorder>,
+    /// Post-processing for screenshots and recordings
+    pub filters: Vec<Filter>,
+    pub tracer: Option<Tracer>,
+    /// What the instruction log and `tracer` cover
+    pub trace_filter: TraceFilter,
+    pub stack_guard: StackGuard,
+    pub watchdog: Watchdog,
+    /// Bytes per second that `paste` feeds SER0
+    pub paste_rate: u32,
+    /// Where the last `d` listing stopped
+    pub listing_end: Option<u16>,
+    /// Where the last `a` instruction ended
+    pub assemble_end: Option<u16>,
+}
+
+impl Debugger {
+    pub fn new(symbols: HashMap<u16, Vec<String>>) -> Self {
+        Self {
+            symbols,
+            breakpoints: Breakpoints::new(),
+            watches: Vec::new(),
+            profiler: Profiler::new(),
+            stats: Stats::new(),
+            idle: Idle::new(),
+            hooks: Hooks::new(),
+            recorder: None,
+            filters: Vec::new(),
+            tracer: None,
+            trace_filter: TraceFilter::new(),
+            stack_guard: StackGuard::new(),
+            watchdog: Watchdog::new(),
+            paste_rate: 100,
+            listing_end: None,
+            assemble_end: None,
+        }
+    }
+}
+
+/// A breakpoint, numbered in the order it was set
+pub struct Breakpoint {
+    pub id: usize,
+    pub addr: u16,
+    pub enabled: bool,
+    /// Deleted the first time it stops the emulator
+    pub temporary: bool,
+    /// Times the PC reached it while enabled, ignored or not
+    pub hits: u64,
+    /// Hits left to pass over before it stops the emulator
+    pub ignore: u64,
+}
+
+/// Breakpoints, in the order they were set, plus a bitmap of the enabled
+/// ones so the run loop can check the PC without searching
+pub struct Breakpoints {
+    list: Vec<Breakpoint>,
+    map: Box<[u64; 0x400]>,
+    next_id: usize,
+}
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Self {
+            list: Vec::new(),
+            map: Box::new([0; 0x400]),
+            next_id: 1,
+        }
+    }
+
+    /// Whether an enabled breakpoint is at `addr`
+    #[inline]
+    pub fn contains(&self, addr: u16) -> bool {
+        (self.map[(addr >> 6) as usize] & (1 << (addr & 0x3F))) != 0
+    }
+
+    fn mark(&mut self, addr: u16, enabled: bool) {
+        if enabled {
+            self.map[(addr >> 6) as usize] |= 1 << (addr & 0x3F);
+        } else {
+            self.map[(addr >> 6) as usize] &= !(1 << (addr & 0x3F));
+        }
+    }
+
+    /// Returns the new breakpoint's number, or `None` if there already is
+    /// one at `addr`
+    pub fn add(&mut self, addr: u16, temporary: bool) -> Option<usize> {
+        if self.list.iter().any(|bp| bp.addr == addr) {
+            return None;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.list.push(Breakpoint {
+            id,
+            addr,
+            enabled: true,
+            temporary,
+            hits: 0,
+            ignore: 0,
+        });
+        self.mark(addr, true);
+        Some(id)
+    }
+
+    /// Returns false if there was no breakpoint
+    pub fn remove(&mut self, addr: u16) -> bool {
+        let len = self.list.len();
+        self.list.retain(|bp| bp.addr != addr);
+        self.mark(addr, false);
+        self.list.len() != len
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Breakpoint> {
+        self.list.iter().find(|bp| bp.id == id)
+    }
+
+    /// Returns false if there is no breakpoint `id`
+    pub fn set_enabled(&mut self, id: usize, enabled: bool) -> bool {
+        let Some(bp) = self.list.iter_mut().find(|bp| bp.id == id) else {
+            return false;
+        };
+        bp.enabled = enabled;
+        let addr = bp.addr;
+        self.mark(addr, enabled);
+        true
+    }
+
+    /// Returns false if there is no breakpoint `id`
+    pub fn set_ignore(&mut self, id: usize, count: u64) -> bool {
+        let Some(bp) = self.list.iter_mut().find(|bp| bp.id == id) else {
+            return false;
+        };
+        bp.ignore = count;
+        true
+    }
+
+    /// Count a hit on the PC reaching `addr`, returning whether the
+    /// emulator should stop. Temporary breakpoints are deleted as they stop
+    /// it. Call once each time the PC arrives.
+    pub fn hit(&mut self, addr: u16) -> bool {
+        if !self.contains(addr) {
+            return false;
+        }
+        let Some(i) = self.list.iter().position(|bp| bp.addr == addr) else {
+            return false;
+        };
+        let bp = &mut self.list[i];
+        bp.hits += 1;
+        if bp.ignore > 0 {
+            bp.ignore -= 1;
+            return false;
+        }
+        if bp.temporary {
+            self.list.remove(i);
+            self.mark(addr, false);
+        }
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> + '_ {
+        self.list.iter()
+    }
+}
+
+impl Default for Breakpoints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum DebugAction {
+    Prompt,
+    Continue,
+    Quit,
+}
+
+pub fn debug_command(
+    out: &mut dyn Write,
+    sys: &mut System,
+    dbg: &mut Debugger,
+    parts: &[String],
+) -> io::Result<DebugAction> {
+    let Some((name, args)) = parts.split_first() else {
+        return Ok(DebugAction::Prompt);
+    };
+    let Some(command) = find_command(name) else {
+        write!(out, "unknown command: `{name}`.")?;
+        if let Some(suggestion) = suggest_command(name) {
+            write!(out, " did you mean `{suggestion}`?")?;
+        }
+        writeln!(out, " type `?` for help")?;
+        return Ok(DebugAction::Prompt);
+    };
+    let (min, max) = command.args;
+    if args.len() < min || args.len() > max {
+        let usage = command.help.iter().map(|(usage, _)| *usage);
+        writeln!(out, "usage: {}", usage.collect::<Vec<_>>().join(" or "))?;
+        return Ok(DebugAction::Prompt);
+    }
+    (command.run)(out, sys, dbg, args)?;
+    Ok(command.action)
+}
+
+type Run = fn(&mut dyn Write, &mut System, &mut Debugger, &[String]) -> io::Result<()>;
+
+/// A debugger command, known by its first name in help and the rest as
+/// aliases
+struct DebugCommand {
+    names: &'static [&'static str],
+    /// Usage and description of each form, for `?`
+    help: &'static [(&'static str, &'static str)],
+    /// Smallest and largest number of arguments, checked before running
+    args: (usize, usize),
+    /// What the front-end does after running it
+    action: DebugAction,
+    run: Run,
+}
+
+impl DebugCommand {
+    const fn new(
+        names: &'static [&'static str],
+        help: &'static [(&'static str, &'static str)],
+        args: (usize, usize),
+        run: Run,
+    ) -> Self {
+        Self {
+            names,
+            help,
+            args,
+            action: DebugAction::Prompt,
+            run,
+        }
+    }
+
+    const fn then(mut self, action: DebugAction) -> Self {
+        self.action = action;
+        self
+    }
+}
+
+/// Any number of arguments
+const ANY: usize = usize::MAX;
+
+fn arg(args: &[String], i: usize) -> Option<&str> {
+    args.get(i).map(String::as_str)
+}
+
+static COMMANDS: &[DebugCommand] = &[
+    DebugCommand::new(
+        &["c", "continue"],
+        &[("c", "continue emulator (exiting debugger)")],
+        (0, 0),
+        |_, _, dbg, _| {
+            dbg.listing_end = None;
+            Ok(())
+        },
+    )
+    .then(DebugAction::Continue),
+    DebugCommand::new(
+        &["q", "quit"],
+        &[("q", "quit emulator")],
+        (0, 0),
+        |_, _, _, _| Ok(()),
+    )
+    .then(DebugAction::Quit),
+    DebugCommand::new(
+        &["s", "n", "step"],
+        &[("s", "single step cpu")],
+        (0, 0),
+        |out, sys, dbg, _| {
+            sys.tick();
+            dbg.listing_end = None;
+            dissasemble(out, sys.mem(), &dbg.symbols, sys.cpu().pc(), 1)?;
+            Ok(())
+        },
+    ),
+    DebugCommand::new(
+        &["fs"],
+        &[("fs", "run to the next vblank, when a frame finishes")],
+        (0, 0),
+        |out, sys, dbg, _| step_video(out, sys, dbg, |(frame, _), (now, _)| now != frame),
+    ),
+    DebugCommand::new(
+        &["ls"],
+        &[("ls", "run to the next scanline")],
+        (0, 0),
+        |out, sys, dbg, _| step_video(out, sys, dbg, |(_, line), (_, now)| now != line),
+    ),
+    DebugCommand::new(
+        &["halt"],
+        &[("halt", "stop a running emulator (remote debugger)")],
+        (0, 0),
+        |out, sys, dbg, _| {
+            // only meaningful to remote clients, the console is already stopped
+            dbg.listing_end = None;
+            dissasemble(out, sys.mem(), &dbg.symbols, sys.cpu().pc(), 1)?;
+            Ok(())
+        },
+    ),
+    DebugCommand::new(
+        &["reset"],
+        &[("reset", "reset the system")],
+        (0, 0),
+        |out, sys, dbg, _| {
+            sys.reset();
+            dbg.listing_end = None;
+            dissasemble(out, sys.mem(), &dbg.symbols, sys.cpu().pc(), 1)?;
+            Ok(())
+        },
+    ),
+    DebugCommand::new(
+        &["nmi"],
+        &[("nmi", "raise a non-maskable interrupt")],
+        (0, 0),
+        |out, sys, _, _| {
+            // taken on the next tick
+            sys.nmi();
+            writeln!(out, "nmi pending")
+        },
+    ),
+    DebugCommand::new(
+        &["r", "regs"],
+        &[("r", "print cpu registers")],
+        (0, 0),
+        |out, sys, _, _| print_cpu_regs(out, sys.cpu()),
+    ),
+    DebugCommand::new(
+        &["R"],
+        &[("R", "print cpu registers (base 10)")],
+        (0, 0),
+        |out, sys, _, _| print_cpu_regs_base10(out, sys.cpu()),
+    ),
+    DebugCommand::new(
+        &["RR"],
+        &[("RR", "print cpu registers (signed base 10)")],
+        (0, 0),
+        |out, sys, _, _| print_cpu_regs_signed_base10(out, sys.cpu()),
+    ),
+    DebugCommand::new(
+        &["sp"],
+        &[
+            (
+                "sp [count]",
+                "dump the stack with return addresses (16 bytes by default)",
+            ),
+            (
+                "sp guard [addr|off]",
+                "warn when SP drops below an address or wraps",
+            ),
+        ],
+        (0, 2),
+        |out, sys, dbg, args| match arg(args, 0) {
+            Some("guard") => match arg(args, 1) {
+                None => match dbg.stack_guard.low() {
+                    Some(low) => writeln!(out, "stack guard at {low:04X}"),
+                    None => writeln!(out, "stack guard off"),
+                },
+                Some("off") => {
+                    dbg.stack_guard.set(None, sys.cpu());
+                    writeln!(out, "stack guard off")
+                }
+                Some(addr) => match parse_addr(&dbg.symbols, addr) {
+                    Ok(low) => {
+                        dbg.stack_guard.set(Some(low), sys.cpu());
+                        writeln!(out, "stack guard at {low:04X}")
+                    }
+                    Err(e) => writeln!(out, "error parsing address: {e}"),
+                },
+            },
+            count => print_stack(out, sys.mem(), sys.cpu(), &dbg.symbols, count),
+        },
+    ),
+    DebugCommand::new(
+        &["b", "break"],
+        &[
+            ("b [addr]", "add breakpoint"),
+            ("b list", "list breakpoints with their hit counts"),
+            ("b en <n> or b dis <n>", "enable or disable breakpoint n"),
+            (
+                "b ign <n> <count>",
+                "pass over breakpoint n the next count hits",
+            ),
+        ],
+        (0, 3),
+        |out, sys, dbg, args| match arg(args, 0) {
+            Some("list") => list_breakpoints(out, &dbg.breakpoints, &dbg.symbols),
+            Some("en" | "dis" | "ign") => change_breakpoint(out, &mut dbg.breakpoints, args),
+            addr => add_breakpoint(
+                out,
+                sys.cpu(),
+                &mut dbg.breakpoints,
+                &dbg.symbols,
+                addr,
+                false,
+            ),
+        },
+    ),
+    DebugCommand::new(
+        &["tb"],
+        &[(
+            "tb [addr]",
+            "add temporary breakpoint (deleted when it stops)",
+        )],
+        (0, 1),
+        |out, sys, dbg, args| {
+            add_breakpoint(
+                out,
+                sys.cpu(),
+                &mut dbg.breakpoints,
+                &dbg.symbols,
+                arg(args, 0),
+                true,
+            )
+        },
+    ),
+    DebugCommand::new(
+        &["B"],
+        &[("B [addr]", "delete breakpoint")],
+        (0, 1),
+        |out, sys, dbg, args| {
+            remove_breakpoint(
+                out,
+                sys.cpu(),
+                &mut dbg.breakpoints,
+                &dbg.symbols,
+                arg(args, 0),
+            )
+        },
+    ),
+    DebugCommand::new(
+        &["bio"],
+        &[(
+            "bio [addr|device] [r|w]",
+            "stop on IO register reads and/or writes (lists without an address)",
+        )],
+        (0, 2),
+        |out, sys, dbg, args| io_breakpoint(out, sys, &dbg.symbols, args),
+    ),
+    DebugCommand::new(
+        &["BIO"],
+        &[("BIO <addr|device>", "delete IO breakpoint")],
+        (0, 1),
+        |out, sys, dbg, args| remove_io_breakpoint(out, sys, &dbg.symbols, arg(args, 0)),
+    ),
+    DebugCommand::new(
+        &["w"],
+        &[("w [addr]", "add watch (lists watches without an address)")],
+        (0, 1),
+        |out, sys, dbg, args| {
+            add_watch(out, sys.mem(), &mut dbg.watches, &dbg.symbols, arg(args, 0))
+        },
+    ),
+    DebugCommand::new(
+        &["W"],
+        &[("W <addr>", "delete watch")],
+        (0, 1),
+        |out, _, dbg, args| remove_watch(out, &mut dbg.watches, &dbg.symbols, arg(args, 0)),
+    ),
+    DebugCommand::new(
+        &["save-breakpoints"],
+        &[(
+            "save-breakpoints <file>",
+            "save breakpoints as a debugger script",
+        )],
+        (0, 1),
+        |out, _, dbg, args| save_breakpoints(out, &dbg.breakpoints, &dbg.symbols, arg(args, 0)),
+    ),
+    DebugCommand::new(
+        &["sym"],
+        &[
+            (
+                "sym load <file>",
+                "load (or reload) a SYM file alongside the others",
+            ),
+            ("sym clear", "forget all symbols"),
+            ("sym find [prefix]", "list symbols starting with a prefix"),
+        ],
+        (1, 2),
+        |out, _, dbg, args| match (arg(args, 0), arg(args, 1)) {
+            (Some("load"), Some(path)) => match load_symbols(&mut dbg.symbols, Path::new(path)) {
+                Ok(count) => writeln!(out, "loaded {count} symbols"),
+                Err(e) => writeln!(out, "failed to load symbols: {e}"),
+            },
+            (Some("load"), None) => writeln!(out, "missing file path"),
+            (Some("clear"), None) => {
+                dbg.symbols.clear();
+                writeln!(out, "symbols cleared")
+            }
+            (Some("find"), prefix) => find_symbols(out, &dbg.symbols, prefix.unwrap_or("")),
+            _ => writeln!(out, "usage: sym load <file>|clear|find [prefix]"),
+        },
+    ),
+    DebugCommand::new(
+        &["paste"],
+        &[(
+            "paste [file]",
+            "type the host clipboard (or a file) into SER0",
+        )],
+        (0, 1),
+        |out, sys, dbg, args| {
+            let text = match arg(args, 0) {
+                Some(path) => fs::read(path),
+                None => read_clipboard(),
+            };
+            match text {
+                Ok(text) => {
+                    // terminals send CR for the enter key
+                    let text = String::from_utf8_lossy(&text)
+                        .replace("\r\n", "\r")
+                        .replace('\n', "\r");
+                    match sys.paste("ser0", text.as_bytes(), dbg.paste_rate) {
+                        Ok(()) => writeln!(out, "pasting {} bytes into ser0", text.len()),
+                        Err(e) => writeln!(out, "error pasting: {e}"),
+                    }
+                }
+                Err(e) => writeln!(out, "error reading paste: {e}"),
+            }
+        },
+    ),
+    DebugCommand::new(
+        &["send"],
+        &[(
+            "send <file> [port]",
+            "send a file to the guest with XMODEM (over SER0 by default)",
+        )],
+        (1, 2),
+        |out, sys, _, args| {
+            let path = &args[0];
+            let port = arg(args, 1).unwrap_or("ser0");
+            match fs::read(path) {
+                Ok(data) => {
+                    let len = data.len();
+                    match sys.xmodem(port, Xmodem::send(data)) {
+                        Ok(()) => writeln!(
+                            out,
+                            "sending {len} bytes over {port}, start an XMODEM receive on the guest"
+                        ),
+                        Err(e) => writeln!(out, "error sending: {e}"),
+                    }
+                }
+                Err(e) => writeln!(out, "error reading {path}: {e}"),
+            }
+        },
+    ),
+    DebugCommand::new(
+        &["recv"],
+        &[(
+            "recv <file> [port]",
+            "receive a file from the guest with XMODEM into a file",
+        )],
+        (1, 2),
+        |out, sys, _, args| {
+            let path = &args[0];
+            let port = arg(args, 1).unwrap_or("ser0");
+            match sys.xmodem(port, Xmodem::receive(PathBuf::from(path))) {
+                Ok(()) => writeln!(
+                    out,
+                    "receiving into {path} over {port}, start an XMODEM send on the guest"
+                ),
+                Err(e) => writeln!(out, "error receiving: {e}"),
+            }
+        },
+    ),
+    DebugCommand::new(
+        &["screenshot"],
+        &[("screenshot <file>", "save the current frame as a PNG")],
+        (1, 1),
+        |out, sys, dbg, args| {
+            let path = &args[0];
+            match save_frame(sys, &dbg.filters, Path::new(path)) {
+                Ok(()) => writeln!(out, "saved frame to {path}"),
+                Err(e) => writeln!(out, "error saving frame: {e}"),
+            }
+        },
+    ),
+    DebugCommand::new(
+        &["ppu"],
+        &[
+            ("ppu bg|fg <file>", "save a whole BG or FG plane as a PNG"),
+            (
+                "ppu tiles <bank> <file> [palette]",
+                "save a tile bank as a PNG, colored with a BG/FG palette",
+            ),
+            (
+                "ppu sprites [all]",
+                "list the sprites that aren't hidden, or all",
+            ),
+            ("ppu palettes", "list the BG/FG and sprite palettes"),
+        ],
+        (1, 4),
+        |out, sys, _, args| inspect_ppu(out, sys, args),
+    ),
+    DebugCommand::new(
+        &["filter"],
+        &[(
+            "filter [none|scanlines|ntsc...]",
+            "set the CRT-ish filters for screenshots and recordings",
+        )],
+        (0, ANY),
+        |out, _, dbg, args| {
+            if let Some(first) = arg(args, 0) {
+                let parsed = match first {
+                    "none" => Ok(Vec::new()),
+                    _ => args.iter().map(|arg| arg.parse()).collect(),
+                };
+                match parsed {
+                    Ok(parsed) => dbg.filters = parsed,
+                    Err(e) => writeln!(out, "{e}")?,
+                }
+            }
+            if dbg.filters.is_empty() {
+                writeln!(out, "no display filters")
+            } else {
+                let names = dbg
+                    .filters
+                    .iter()
+                    .map(Filter::to_string)
+                    .collect::<Vec<_>>();
+                writeln!(out, "display filters: {}", names.join(" "))
+            }
+        },
+    ),
+    DebugCommand::new(
+        &["disk"],
+        &[(
+            "disk",
+            "show each drive's head position and sectors transferred",
+        )],
+        (0, 0),
+        |out, sys, _, _| {
+            let mut any = false;
+            for (name, disk) in sys.disks() {
+                any = true;
+                writeln!(
+                    out,
+                    "{name}: {} track {:2} side {} sector {:2}, {} sectors read, {} written",
+                    if disk.busy { "busy" } else { "idle" },
+                    disk.track,
+                    disk.side,
+                    disk.sector,
+                    disk.sectors_read,
+                    disk.sectors_written
+                )?;
+            }
+            if !any {
+                writeln!(out, "the machine has no disk drives")?;
+            }
+            Ok(())
+        },
+    ),
+    DebugCommand::new(
+        &["commit"],
+        &[(
+            "commit [drive]",
+            "write a drive's overlay back to its base image (FDC0 by default)",
+        )],
+        (0, 1),
+        |out, sys, _, args| {
+            let drive = arg(args, 0).unwrap_or("fdc0");
+            match sys.commit(drive) {
+                Ok(count) => writeln!(out, "committed {count} sectors to the {drive} base image"),
+                Err(e) => writeln!(out, "error committing: {e}"),
+            }
+        },
+    ),
+    DebugCommand::new(
+        &["cov"],
+        &[
+            ("cov start|stop|clear|report", "track code coverage"),
+            ("cov export <file>", "export coverage as `ADDR:XRW` lines"),
+        ],
+        (1, 2),
+        |out, sys, dbg, args| match arg(args, 0) {
+            Some("start") => {
+                sys.cov_mut().start();
+                writeln!(out, "coverage tracking started")
+            }
+            Some("stop") => {
+                sys.cov_mut().stop();
+                writeln!(out, "coverage tracking stopped")
+            }
+            Some("clear") => {
+                sys.cov_mut().clear();
+                writeln!(out, "coverage cleared")
+            }
+            Some("report") => print_coverage(out, sys.cov(), &dbg.symbols),
+            Some("export") => export_coverage(out, sys.cov(), arg(args, 1)),
+            _ => writeln!(out, "usage: cov start|stop|clear|report|export <file>"),
+        },
+    ),
+    DebugCommand::new(
+        &["profile"],
+        &[("profile start|stop|report [count]", "profile executed code")],
+        (1, 2),
+        |out, _, dbg, args| match arg(args, 0) {
+            Some("start") => {
+                dbg.profiler.start();
+                writeln!(out, "profiler started")
+            }
+            Some("stop") => {
+                dbg.profiler.stop();
+                writeln!(out, "profiler stopped")
+            }
+            Some("report") => print_profile(out, &dbg.profiler, &dbg.symbols, arg(args, 1)),
+            _ => writeln!(out, "usage: profile start|stop|report [count]"),
+        },
+    ),
+    DebugCommand::new(
+        &["stats"],
+        &[("stats", "show instruction counts and emulation speed")],
+        (0, 0),
+        |out, sys, dbg, _| dbg.stats.print(out, sys.cpu()),
+    ),
+    DebugCommand::new(
+        &["irqs"],
+        &[(
+            "irqs [clear]",
+            "show interrupts taken per source, their latency in cycles, and time masked",
+        )],
+        (0, 1),
+        |out, sys, _, args| match arg(args, 0) {
+            Some("clear") => {
+                sys.irq_stats_mut().clear();
+                writeln!(out, "interrupt stats cleared")
+            }
+            None => print_irq_stats(out, sys.irq_stats()),
+            _ => writeln!(out, "usage: irqs [clear]"),
+        },
+    ),
+    DebugCommand::new(
+        &["io-trace"],
+        &[("io-trace [on|off]", "log every access to the IO window")],
+        (0, 1),
+        |out, sys, _, args| match arg(args, 0) {
+            Some("on") => {
+                sys.set_io_trace(true);
+                writeln!(out, "io trace on")
+            }
+            Some("off") => {
+                sys.set_io_trace(false);
+                writeln!(out, "io trace off")
+            }
+            None => writeln!(
+                out,
+                "io trace {}",
+                if sys.io_trace() { "on" } else { "off" }
+            ),
+            _ => writeln!(out, "usage: io-trace [on|off]"),
+        },
+    ),
+    DebugCommand::new(
+        &["trace"],
+        &[
+            (
+                "trace",
+                "show what the instruction log and --trace-out are limited to",
+            ),
+            (
+                "trace only <start>..<end>|<sym>",
+                "trace only this code (again to add more), end excluded or up to the next symbol",
+            ),
+            ("trace skip <start>..<end>|<sym>", "don't trace this code"),
+            ("trace clear", "trace everything again"),
+        ],
+        (0, 2),
+        |out, _, dbg, args| {
+            let range = args.get(1).map(|arg| parse_trace_range(&dbg.symbols, arg));
+            match (arg(args, 0), range) {
+                (None, None) => print_trace_filter(out, &dbg.trace_filter),
+                (Some("clear"), None) => {
+                    dbg.trace_filter.clear();
+                    writeln!(out, "tracing everything")
+                }
+                (Some("only"), Some(Ok(range))) => {
+                    dbg.trace_filter.only(range);
+                    print_trace_filter(out, &dbg.trace_filter)
+                }
+                (Some("skip"), Some(Ok(range))) => {
+                    dbg.trace_filter.skip(range);
+                    print_trace_filter(out, &dbg.trace_filter)
+                }
+                (Some("only" | "skip"), Some(Err(e))) => writeln!(out, "{e}"),
+                _ => writeln!(
+                    out,
+                    "usage: trace [only|skip <start>..<end>|<sym>] or trace clear"
+                ),
+            }
+        },
+    ),
+    DebugCommand::new(
+        &["mem-check"],
+        &[(
+            "mem-check [off|warn|break]",
+            "check for uninitialized reads and read-only writes",
+        )],
+        (0, 1),
+        |out, sys, _, args| match arg(args, 0).map(str::parse::<MemCheck>) {
+            Some(Ok(mode)) => {
+                sys.set_mem_check(mode);
+                writeln!(out, "memory checks {mode}")
+            }
+            Some(Err(e)) => writeln!(out, "{e}"),
+            None => writeln!(out, "memory checks {}", sys.mem_check()),
+        },
+    ),
+    DebugCommand::new(
+        &["ro"],
+        &[(
+            "ro [start [end|+len]]",
+            "mark memory read-only (lists without an address)",
+        )],
+        (0, 2),
+        |out, sys, dbg, args| {
+            if args.is_empty() {
+                let ranges = sys.read_only();
+                if ranges.is_empty() {
+                    writeln!(out, "no read-only ranges")?;
+                }
+                for (start, end) in ranges {
+                    writeln!(out, "{start:04X}-{end:04X}")?;
+                }
+                return Ok(());
+            }
+            set_read_only(out, sys, &dbg.symbols, args, true)
+        },
+    ),
+    DebugCommand::new(
+        &["RO"],
+        &[("RO <start [end|+len]>", "make memory writable again")],
+        (1, 2),
+        |out, sys, dbg, args| set_read_only(out, sys, &dbg.symbols, args, false),
+    ),
+    DebugCommand::new(
+        &["exec-check"],
+        &[(
+            "exec-check [off|warn|break] [cycles]",
+            "check for running IO or freshly written code",
+        )],
+        (0, 2),
+        |out, sys, _, args| {
+            let (_, window) = sys.exec_check();
+            match (
+                arg(args, 0).map(str::parse::<MemCheck>),
+                arg(args, 1).map(str::parse),
+            ) {
+                (None, _) => {}
+                (Some(Ok(mode)), None) => sys.set_exec_check(mode, window),
+                (Some(Ok(mode)), Some(Ok(window))) => sys.set_exec_check(mode, window),
+                (Some(Err(e)), _) => writeln!(out, "{e}")?,
+                (_, Some(Err(e))) => writeln!(out, "error parsing window: {e}")?,
+            }
+            let (mode, window) = sys.exec_check();
+            writeln!(out, "execution checks {mode}, window {window} cycles")
+        },
+    ),
+    DebugCommand::new(
+        &["x"],
+        &[(
+            "x [start [end|+len]]",
+            "examine memory (16 bytes by default)",
+        )],
+        (0, 2),
+        |out, sys, dbg, args| examine(out, sys, &dbg.symbols, args, Examine::Hex),
+    ),
+    DebugCommand::new(
+        &["X"],
+        &[("X [start [end|+len]]", "examine memory (base 10)")],
+        (0, 2),
+        |out, sys, dbg, args| examine(out, sys, &dbg.symbols, args, Examine::Base10),
+    ),
+    DebugCommand::new(
+        &["XX"],
+        &[("XX [start [end|+len]]", "examine memory (signed base 10)")],
+        (0, 2),
+        |out, sys, dbg, args| examine(out, sys, &dbg.symbols, args, Examine::SignedBase10),
+    ),
+    DebugCommand::new(
+        &["xw"],
+        &[("xw [start [end|+len]]", "examine memory as 16-bit words")],
+        (0, 2),
+        |out, sys, dbg, args| examine(out, sys, &dbg.symbols, args, Examine::Word),
+    ),
+    DebugCommand::new(
+        &["xd"],
+        &[("xd [start [end|+len]]", "examine memory as 32-bit words")],
+        (0, 2),
+        |out, sys, dbg, args| examine(out, sys, &dbg.symbols, args, Examine::DoubleWord),
+    ),
+    DebugCommand::new(
+        &["find"],
+        &[(
+            "find <bytes|\"string\"> [start end]",
+            "search memory, every RAM bank included (bytes in hex, e.g. `A9FF`)",
+        )],
+        (1, ANY),
+        |out, sys, dbg, args| find_bytes(out, sys.mem(), &dbg.symbols, args),
+    ),
+    DebugCommand::new(
+        &["crc"],
+        &[(
+            "crc <start> <end|+len>",
+            "print the CRC-16/XMODEM and CRC-32 of memory",
+        )],
+        (2, 2),
+        |out, sys, dbg, args| match parse_range(&dbg.symbols, sys.cpu(), args) {
+            Ok((start, len)) => {
+                let data = (0..len)
+                    .map(|i| sys.mem().read(start.wrapping_add(i as u16)))
+                    .collect::<Vec<u8>>();
+                let crc16 = xmodem::crc16(&data);
+                let crc32 = png::crc32(0xFFFF_FFFF, &data) ^ 0xFFFF_FFFF;
+                let end = (start as u32 + len.max(1) - 1) as u16;
+                writeln!(
+                    out,
+                    "{start:04X}-{end:04X}  {len} bytes  crc16 {crc16:04X}  crc32 {crc32:08X}"
+                )
+            }
+            Err(e) => writeln!(out, "{e}"),
+        },
+    ),
+    DebugCommand::new(
+        &["cmp"],
+        &[(
+            "cmp <addr1> <addr2> <len>",
+            "compare memory, listing the bytes that differ (len in hex)",
+        )],
+        (3, 3),
+        |out, sys, dbg, args| compare(out, sys.mem(), &dbg.symbols, args),
+    ),
+    DebugCommand::new(
+        &["dump"],
+        &[
+            (
+                "dump <file> <start> <end|+len>",
+                "save raw memory to a file",
+            ),
+            ("dump <file> vram", "save video memory to a file"),
+        ],
+        (2, 3),
+        |out, sys, dbg, args| {
+            let path = &args[0];
+            if arg(args, 1) == Some("vram") {
+                return match sys.vram() {
+                    Some(vram) => match fs::write(path, vram) {
+                        Ok(()) => {
+                            writeln!(out, "saved {} bytes of video memory to {path}", vram.len())
+                        }
+                        Err(e) => writeln!(out, "error writing {path}: {e}"),
+                    },
+                    None => writeln!(out, "the machine has no video memory"),
+                };
+            }
+            if args.len() != 3 {
+                return writeln!(
+                    out,
+                    "usage: dump <file> <start> <end|+len> or dump <file> vram"
+                );
+            }
+            match parse_range(&dbg.symbols, sys.cpu(), &args[1..]) {
+                Ok((start, len)) => {
+                    // the IO window reads as zeros
+                    let data = (0..len)
+                        .map(|i| sys.mem().read(start.wrapping_add(i as u16)))
+                        .collect::<Vec<u8>>();
+                    match fs::write(path, &data) {
+                        Ok(()) => writeln!(out, "saved {len} bytes from {start:04X} to {path}"),
+                        Err(e) => writeln!(out, "error writing {path}: {e}"),
+                    }
+                }
+                Err(e) => writeln!(out, "{e}"),
+            }
+        },
+    ),
+    DebugCommand::new(
+        &["restore"],
+        &[(
+            "restore <file> <addr>",
+            "load a raw file into memory, ROM included",
+        )],
+        (2, 2),
+        |out, sys, dbg, args| {
+            let path = &args[0];
+            match (fs::read(path), parse_addr(&dbg.symbols, &args[1])) {
+                (Ok(data), Ok(addr)) => match sys.patch(addr, &data) {
+                    Ok(()) => writeln!(out, "restored {} bytes to {addr:04X}", data.len()),
+                    Err(e) => writeln!(out, "{e}"),
+                },
+                (Err(e), _) => writeln!(out, "error reading {path}: {e}"),
+                (_, Err(e)) => writeln!(out, "error parsing address: {e}"),
+            }
+        },
+    ),
+    DebugCommand::new(
+        &["state"],
+        &[(
+            "state [file]",
+            "print registers, bank selects, and IO registers as JSON, or save them",
+        )],
+        (0, 1),
+        |out, sys, _, args| {
+            let state = serde_json::to_string_pretty(&machine_state(sys)).unwrap();
+            match arg(args, 0) {
+                Some(path) => match fs::write(path, state + "\n") {
+                    Ok(()) => writeln!(out, "saved machine state to {path}"),
+                    Err(e) => writeln!(out, "error writing {path}: {e}"),
+                },
+                None => writeln!(out, "{state}"),
+            }
+        },
+    ),
+    DebugCommand::new(
+        &["d", "disasm"],
+        &[(
+            "d [start]",
+            "disassemble memory (again to continue the listing)",
+        )],
+        (0, 1),
+        |out, sys, dbg, args| {
+            // a bare `d` carries on from the end of the last listing
+            let start = match arg(args, 0).map(|arg| parse_addr(&dbg.symbols, arg)) {
+                Some(Ok(addr)) => addr,
+                Some(Err(e)) => return writeln!(out, "error parsing start address: {e}"),
+                None => dbg.listing_end.unwrap_or(sys.cpu().pc()),
+            };
+            let notes = Some(SpaceNotes::new(sys));
+            let end = dissasemble_noted(out, sys.mem(), &dbg.symbols, start, 24, notes)?;
+            dbg.listing_end = Some(end);
+            Ok(())
+        },
+    ),
+    DebugCommand::new(
+        &["a"],
+        &[(
+            "a <addr|+> <instruction>",
+            "assemble into memory, ROM included (`+` continues after the last one)",
+        )],
+        (2, ANY),
+        |out, sys, dbg, args| {
+            // `+` carries on after the last assembled instruction
+            let addr = match (args[0].as_str(), dbg.assemble_end) {
+                ("+", Some(addr)) => addr,
+                ("+", None) => return writeln!(out, "nothing assembled yet"),
+                (arg, _) => match parse_addr(&dbg.symbols, arg) {
+                    Ok(addr) => addr,
+                    Err(e) => return writeln!(out, "error parsing address: {e}"),
+                },
+            };
+            let line = args[1..].join(" ");
+            // bare operands are hex or symbols, like everywhere else here
+            let symbols = &dbg.symbols;
+            let bytes = asm::assemble(addr, &line, |arg| parse_addr(symbols, arg).ok());
+            match bytes.and_then(|bytes| sys.patch(addr, &bytes).map(|()| bytes)) {
+                Ok(bytes) => {
+                    dbg.assemble_end = Some(addr.wrapping_add(bytes.len() as u16));
+                    dissasemble(out, sys.mem(), &dbg.symbols, addr, 1)?;
+                    Ok(())
+                }
+                Err(e) => writeln!(out, "{e}"),
+            }
+        },
+    ),
+    DebugCommand::new(
+        &["?", "help"],
+        &[("? [command]", "show this help info, or a command's")],
+        (0, 1),
+        |out, _, _, args| print_help(out, arg(args, 0)),
+    ),
+];
+
+fn find_command(name: &str) -> Option<&'static DebugCommand> {
+    COMMANDS
+        .iter()
+        .find(|command| command.names.contains(&name))
+}
+
+/// The closest command name to a mistyped one, if any is close. Case is
+/// ignored since it's the easiest thing to get wrong (`xx` for `XX`).
+fn suggest_command(name: &str) -> Option<&'static str> {
+    let name = name.to_ascii_lowercase();
+    // short names are a typo or two away from everything
+    let max_distance = if name.len() <= 3 { 1 } else { 2 };
+    COMMANDS
+        .iter()
+        .flat_map(|command| command.names.iter().copied())
+        .map(|candidate| {
+            let distance = edit_distance(&name, &candidate.to_ascii_lowercase());
+            (distance, candidate)
+        })
+        .filter(|&(distance, candidate)| distance <= max_distance && distance < candidate.len())
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance, counted in chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut row = (0..=b.len()).collect::<Vec<usize>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitute = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Most instructions `fs` and `ls` run before giving up, a few frames'
+/// worth at any clock rate the machine might have
+const MAX_VIDEO_STEPS: u64 = 10_000_000;
+
+/// Run until `done` says the beam has gone far enough, from the frame
+/// number and scanline it started at and the ones it's at now.
+/// Breakpoints, the guest exiting, an access that should break, and the
+/// watchdog firing stop it early.
+fn step_video(
+    out: &mut dyn Write,
+    sys: &mut System,
+    dbg: &mut Debugger,
+    done: fn((u64, u16), (u64, u16)) -> bool,
+) -> io::Result<()> {
+    let beam = |sys: &System| sys.frame().map(|frame| (frame.number, frame.line));
+    let Some(start) = beam(sys) else {
+        return writeln!(out, "the machine has no video output");
+    };
+    dbg.listing_end = None;
+    for _ in 0..MAX_VIDEO_STEPS {
+        let pc = sys.cpu().pc();
+        sys.tick();
+        dbg.watchdog.check(sys.cpu(), pc);
+        let now = beam(sys).expect("video output went away");
+        if let Some(status) = sys.exit_status() {
+            write!(out, "exited with status {status}: ")?;
+        } else if let Some(addr) = sys.take_fault() {
+            write!(out, "stopped by an access to {addr:04X}: ")?;
+        } else if dbg.watchdog.take_fired() {
+            write!(out, "watchdog: ")?;
+        } else if dbg.breakpoints.hit(sys.cpu().pc()) {
+            write!(out, "breakpoint: ")?;
+        } else if !done(start, now) {
+            continue;
+        }
+        writeln!(out, "frame {} line {}", now.0, now.1)?;
+        dissasemble(out, sys.mem(), &dbg.symbols, sys.cpu().pc(), 1)?;
+        return Ok(());
+    }
+    writeln!(out, "gave up after {MAX_VIDEO_STEPS} instructions")
+}
+
+fn set_read_only(
+    out: &mut dyn Write,
+    sys: &mut System,
+    symbols: &HashMap<u16, Vec<String>>,
+    args: &[String],
+    read_only: bool,
+) -> io::Result<()> {
+    match parse_range(symbols, sys.cpu(), args) {
+        Ok((start, len)) => {
+            let end = (start as u32 + len.max(1) - 1) as u16;
+            sys.set_read_only(start, end, read_only);
+            let state = if read_only { "read-only" } else { "writable" };
+            writeln!(out, "{start:04X}-{end:04X} {state}")
+        }
+        Err(e) => writeln!(out, "{e}"),
+    }
+}
+
+enum Examine {
+    Hex,
+    Base10,
+    SignedBase10,
+    Word,
+    DoubleWord,
+}
+
+impl Examine {
+    /// Size in bytes of each value
+    fn width(&self) -> u32 {
+        match self {
+            Examine::Word => 2,
+            Examine::DoubleWord => 4,
+            _ => 1,
+        }
+    }
+}
+
+/// Parse `[start [end|+len]]` into a start address and a length in bytes.
+/// Without arguments, 16 bytes from the PC.
+fn parse_range(
+    symbols: &HashMap<u16, Vec<String>>,
+    cpu: &Cpu,
+    args: &[String],
+) -> Result<(u16, u32), String> {
+    let start = match args.first() {
+        Some(arg) => {
+            parse_addr(symbols, arg).map_err(|e| format!("error parsing start address: {e}"))?
+        }
+        None => cpu.pc(),
+    };
+    let len = match args.get(1) {
+        Some(arg) => match arg.strip_prefix('+') {
+            Some(len) => {
+                u32::from_str_radix(len, 16).map_err(|e| format!("error parsing length: {e}"))?
+            }
+            None => {
+                let end = parse_addr(symbols, arg)
+                    .map_err(|e| format!("error parsing end address: {e}"))?;
+                if end < start {
+                    return Err("end address is before start address".to_string());
+                }
+                (end - start) as u32 + 1
+            }
+        },
+        None => 16,
+    };
+    Ok((start, len.min(0x10000 - start as u32)))
+}
+
+/// Most matches `find` and differences `cmp` print
+const MAX_MATCHES: usize = 64;
+
+/// Search for bytes (`A9FF`) or a quoted string, as the CPU sees memory and
+/// then in the RAM banks that aren't selected. The IO window is skipped.
+fn find_bytes(
+    out: &mut dyn Write,
+    mem: &Mem,
+    symbols: &HashMap<u16, Vec<String>>,
+    args: &[String],
+) -> io::Result<()> {
+    let line = args.join(" ");
+    let (pattern, rest) = if let Some(quoted) = line.strip_prefix('"') {
+        match quoted.split_once('"') {
+            Some((string, rest)) => (string.as_bytes().to_vec(), rest),
+            None => {
+                writeln!(out, "missing closing quote")?;
+                return Ok(());
+            }
+        }
+    } else {
+        let (hex, rest) = line.split_once(' ').unwrap_or((&line, ""));
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|b| u8::from_str_radix(b, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>();
+        match bytes {
+            Some(bytes) => (bytes, rest),
+            None => {
+                writeln!(out, "error parsing bytes: `{hex}`")?;
+                return Ok(());
+            }
+        }
+    };
+    if pattern.is_empty() {
+        writeln!(out, "usage: find <bytes|\"string\"> [start end]")?;
+        return Ok(());
+    }
+    let range = rest.split_whitespace().collect::<Vec<&str>>();
+    let (start, end) = match range[..] {
+        [] => (0, 0xFFFF),
+        [start, end] => match (parse_addr(symbols, start), parse_addr(symbols, end)) {
+            (Ok(start), Ok(end)) if start <= end => (start as u32, end as u32),
+            (Ok(_), Ok(_)) => {
+                writeln!(out, "end address is before start address")?;
+                return Ok(());
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                writeln!(out, "error parsing address: {e}")?;
+                return Ok(());
+            }
+        },
+        _ => {
+            writeln!(out, "usage: find <bytes|\"string\"> [start end]")?;
+            return Ok(());
+        }
+    };
+
+    let len = pattern.len() as u32;
+    let io = IO_START as u32..ROM_START as u32;
+    let mut found = Vec::new();
+    for addr in start..(end + 2).saturating_sub(len) {
+        let span = addr..addr + len;
+        if !span.clone().any(|a| io.contains(&a))
+            && span.zip(&pattern).all(|(a, &b)| mem.read(a as u16) == b)
+        {
+            found.push((mem.bank(addr as u16), addr as u16));
+        }
+    }
+    let ram_end = end.min(IO_START as u32 - 1);
+    for bank in 0..mem.banks() {
+        for addr in start..(ram_end + 2).saturating_sub(len) {
+            let span = addr..addr + len;
+            // already searched where the bank is selected
+            if span.clone().all(|a| mem.bank(a as u16) == bank) {
+                continue;
+            }
+            if span
+                .zip(&pattern)
+                .all(|(a, &b)| mem.read_bank(a as u16, bank) == b)
+            {
+                found.push((bank, addr as u16));
+            }
+        }
+    }
+
+    if found.is_empty() {
+        writeln!(out, "not found")?;
+        return Ok(());
+    }
+    for (bank, addr) in found.iter().take(MAX_MATCHES) {
+        let line = format!("{bank}:{addr:04X}  {}", symbolize(symbols, *addr));
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    if found.len() > MAX_MATCHES {
+        writeln!(out, "... and {} more", found.len() - MAX_MATCHES)?;
+    }
+    Ok(())
+}
+
+/// Compare two ranges of memory as the CPU sees it, listing the bytes
+/// that differ
+fn compare(
+    out: &mut dyn Write,
+    mem: &Mem,
+    symbols: &HashMap<u16, Vec<String>>,
+    args: &[String],
+) -> io::Result<()> {
+    let [a, b, len] = args else {
+        writeln!(out, "usage: cmp <addr1> <addr2> <len>")?;
+        return Ok(());
+    };
+    let (a, b) = match (parse_addr(symbols, a), parse_addr(symbols, b)) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(e), _) | (_, Err(e)) => {
+            writeln!(out, "error parsing address: {e}")?;
+            return Ok(());
+        }
+    };
+    let len = match u32::from_str_radix(len, 16) {
+        Ok(len) => len.min(0x10000 - a.max(b) as u32),
+        Err(e) => {
+            writeln!(out, "error parsing length: {e}")?;
+            return Ok(());
+        }
+    };
+
+    let differ = (0..len as u16)
+        .map(|i| (a + i, b + i))
+        .filter(|&(a, b)| mem.read(a) != mem.read(b))
+        .collect::<Vec<(u16, u16)>>();
+    for &(a, b) in differ.iter().take(MAX_MATCHES) {
+        writeln!(
+            out,
+            "{a:04X} {:02X}  {b:04X} {:02X}",
+            mem.read(a),
+            mem.read(b)
+        )?;
+    }
+    if differ.len() > MAX_MATCHES {
+        writeln!(out, "... and {} more", differ.len() - MAX_MATCHES)?;
+    }
+    match differ.len() {
+        0 => writeln!(out, "{len} bytes identical")?,
+        n => writeln!(out, "{n} of {len} bytes differ")?,
+    }
+    Ok(())
+}
+
+fn examine(
+    out: &mut dyn Write,
+    sys: &System,
+    symbols: &HashMap<u16, Vec<String>>,
+    args: &[String],
+    kind: Examine,
+) -> io::Result<()> {
+    let mem = sys.mem();
+    let (start, len) = match parse_range(symbols, sys.cpu(), args) {
+        Ok(range) => range,
+        Err(e) => {
+            writeln!(out, "{e}")?;
+            return Ok(());
+        }
+    };
+    let width = kind.width();
+    let end = start as u32 + len;
+    let mut notes = SpaceNotes::new(sys);
+    let mut row = start as u32;
+    while row < end {
+        // rows stop at chapter boundaries since each chapter has its own bank,
+        // and where the IO window ends and ROM starts
+        let mut row_end = (row + 16).min(end).min((row | 0xFFF) + 1);
+        if row < ROM_START as u32 {
+            row_end = row_end.min(ROM_START as u32);
+        }
+        // round up to whole values
+        let row_end = row + (row_end - row).div_ceil(width) * width;
+        let addr = row as u16;
+        let bank = mem.bank(addr);
+        match kind {
+            Examine::Base10 | Examine::SignedBase10 => write!(out, "{bank}:{addr:05}  ")?,
+            _ => write!(out, "{bank}:{addr:04X}  ")?,
+        }
+        for value in (row..row_end).step_by(width as usize) {
+            let addr = value as u16;
+            let read = |i: u16| mem.read(addr.wrapping_add(i));
+            match kind {
+                Examine::Hex => write!(out, "{:02X} ", read(0))?,
+                Examine::Base10 => write!(out, "{:03} ", read(0))?,
+                Examine::SignedBase10 => write!(out, "{:+04} ", read(0) as i8)?,
+                Examine::Word => write!(out, "{:04X} ", u16::from_le_bytes([read(0), read(1)]))?,
+                Examine::DoubleWord => write!(
+                    out,
+                    "{:08X} ",
+                    u32::from_le_bytes([read(0), read(1), read(2), read(3)])
+                )?,
+            }
+        }
+        if width == 1 {
+            // line up the text of a short last row
+            let cell = match kind {
+                Examine::Hex => 3,
+                Examine::Base10 => 4,
+                _ => 5,
+            };
+            write!(out, "{:1$}", "", (16 - (row_end - row) as usize) * cell)?;
+            write!(out, " |")?;
+            for addr in row..row_end {
+                let c = mem.read(addr as u16);
+                if c.is_ascii_graphic() {
+                    write!(out, "{}", c as char)?;
+                } else {
+                    write!(out, ".")?;
+                }
+            }
+            write!(out, "|")?;
+        }
+        if let Some(note) = notes.note(row as u16, (row_end - row) as u16) {
+            write!(out, "  {note}")?;
+        }
+        writeln!(out)?;
+        row = row_end;
+    }
+    Ok(())
+}
+
+/// Dump the stack from SP up, picking out the words that look like return
+/// addresses (they follow a JSR or BSR)
+fn print_stack(
+    out: &mut dyn Write,
+    mem: &Mem,
+    cpu: &Cpu,
+    symbols: &HashMap<u16, Vec<String>>,
+    count: Option<&str>,
+) -> io::Result<()> {
+    let count = match count.map(|count| count.parse::<u32>()) {
+        None => 16,
+        Some(Ok(count)) => count,
+        Some(Err(e)) => {
+            writeln!(out, "error parsing count: {e}")?;
+            return Ok(());
+        }
+    };
+    let sp = cpu.sp() as u32;
+    // the 8-bit stack ends with its page
+    let top = if (cpu.p() & Flags::EXTEND_STACK_DISABLE) != 0 {
+        sp | 0xFF
+    } else {
+        0xFFFF
+    };
+    let end = (sp + count).min(top + 1);
+    let mut addr = sp;
+    while addr < end {
+        let lo = mem.read(addr as u16);
+        if addr + 1 < end {
+            let hi = mem.read((addr + 1) as u16);
+            let ret = u16::from_le_bytes([lo, hi]);
+            if matches!(mem.read(ret.wrapping_sub(3)), 0x20 | 0x22 | 0x23 | 0x63) {
+                writeln!(
+                    out,
+                    "{addr:04X}  {lo:02X} {hi:02X}  return to {ret:04X} {}",
+                    symbolize(symbols, ret)
+                )?;
+                addr += 2;
+                continue;
+            }
+        }
+        writeln!(out, "{addr:04X}  {lo:02X}")?;
+        addr += 1;
+    }
+    Ok(())
+}
+
+fn add_breakpoint(
+    out: &mut dyn Write,
+    cpu: &Cpu,
+    breakpoints: &mut Breakpoints,
+    symbols: &HashMap<u16, Vec<String>>,
+    arg: Option<&str>,
+    temporary: bool,
+) -> io::Result<()> {
+    let addr = if let Some(arg) = arg {
+        match parse_addr(symbols, arg) {
+            Ok(addr) => addr,
+            Err(e) => {
+                writeln!(out, "error parsing address: {e}")?;
+                return Ok(());
+            }
+        }
+    } else {
+        cpu.pc()
+    };
+    match breakpoints.add(addr, temporary) {
+        Some(id) if temporary => writeln!(out, "temporary breakpoint {id} added at {addr:04X}")?,
+        Some(id) => writeln!(out, "breakpoint {id} added at {addr:04X}")?,
+        None => writeln!(out, "breakpoint already exists")?,
+    }
+    Ok(())
+}
+
+fn list_breakpoints(
+    out: &mut dyn Write,
+    breakpoints: &Breakpoints,
+    symbols: &HashMap<u16, Vec<String>>,
+) -> io::Result<()> {
+    if breakpoints.is_empty() {
+        writeln!(out, "no breakpoints")?;
+        return Ok(());
+    }
+    for bp in breakpoints.iter() {
+        let label = symbols
+            .get(&bp.addr)
+            .map_or("", |labels| labels[0].as_str());
+        write!(
+            out,
+            "{:3} {:04X} {label:20} {} hits {}",
+            bp.id,
+            bp.addr,
+            if bp.enabled { "enabled " } else { "disabled" },
+            bp.hits
+        )?;
+        if bp.ignore > 0 {
+            write!(out, ", ignoring {}", bp.ignore)?;
+        }
+        if bp.temporary {
+            write!(out, ", temporary")?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// `b en N`, `b dis N`, and `b ign N COUNT`
+fn change_breakpoint(
+    out: &mut dyn Write,
+    breakpoints: &mut Breakpoints,
+    parts: &[String],
+) -> io::Result<()> {
+    let Some(id) = parts.get(1) else {
+        writeln!(out, "missing breakpoint number")?;
+        return Ok(());
+    };
+    let Ok(id) = id.parse::<usize>() else {
+        writeln!(out, "invalid breakpoint number: {id}")?;
+        return Ok(());
+    };
+    let found = match parts[0].as_str() {
+        "en" => breakpoints.set_enabled(id, true),
+        "dis" => breakpoints.set_enabled(id, false),
+        _ => {
+            let Some(Ok(count)) = parts.get(2).map(|count| count.parse::<u64>()) else {
+                writeln!(out, "missing or invalid ignore count")?;
+                return Ok(());
+            };
+            breakpoints.set_ignore(id, count)
+        }
+    };
+    match breakpoints.get(id) {
+        Some(bp) if found => {
+            let state = if bp.enabled { "enabled" } else { "disabled" };
+            write!(out, "breakpoint {id} at {:04X} {state}", bp.addr)?;
+            if bp.ignore > 0 {
+                write!(out, ", ignoring the next {} hits", bp.ignore)?;
+            }
+            writeln!(out)?;
+        }
+        _ => writeln!(out, "breakpoint {id} does not exist")?,
+    }
+    Ok(())
+}
+
+fn remove_breakpoint(
+    out: &mut dyn Write,
+    cpu: &Cpu,
+    breakpoints: &mut Breakpoints,
+    symbols: &HashMap<u16, Vec<String>>,
+    arg: Option<&str>,
+) -> io::Result<()> {
+    let addr = if let Some(arg) = arg {
+        match parse_addr(symbols, arg) {
+            Ok(addr) => addr,
+            Err(e) => {
+                writeln!(out, "error parsing address: {e}")?;
+                return Ok(());
+            }
+        }
+    } else {
+        cpu.pc()
+    };
+    if breakpoints.remove(addr) {
+        writeln!(out, "breakpoint removed at {addr:04X}")?;
+    } else {
+        writeln!(out, "breakpoint does not exist")?;
+    }
+    Ok(())
+}
+
+/// `bio` lists, `bio <addr|device> [r|w]` stops on reads and/or writes
+fn io_breakpoint(
+    out: &mut dyn Write,
+    sys: &mut System,
+    symbols: &HashMap<u16, Vec<String>>,
+    args: &[String],
+) -> io::Result<()> {
+    let Some(target) = args.first() else {
+        let mut any = false;
+        for (addr, flags) in sys.io_breaks() {
+            any = true;
+            let access = match flags {
+                IoBreakFlags::READ => "r ",
+                IoBreakFlags::WRITE => " w",
+                _ => "rw",
+            };
+            writeln!(out, "{addr:04X} {access} {}", sys.register_name(addr))?;
+        }
+        if !any {
+            writeln!(out, "no io breakpoints")?;
+        }
+        return Ok(());
+    };
+    let flags = match args.get(1).map(String::as_str) {
+        None => IoBreakFlags::READ | IoBreakFlags::WRITE,
+        Some("r") => IoBreakFlags::READ,
+        Some("w") => IoBreakFlags::WRITE,
+        Some(access) => {
+            writeln!(out, "expected `r` or `w`: {access}")?;
+            return Ok(());
+        }
+    };
+    match io_registers(sys, symbols, target) {
+        Ok((base, size)) => {
+            for addr in base..base + size {
+                sys.set_io_break(addr, flags);
+            }
+            writeln!(
+                out,
+                "io breakpoint added at {base:04X}-{:04X}",
+                base + size - 1
+            )?;
+        }
+        Err(e) => writeln!(out, "{e}")?,
+    }
+    Ok(())
+}
+
+fn remove_io_breakpoint(
+    out: &mut dyn Write,
+    sys: &mut System,
+    symbols: &HashMap<u16, Vec<String>>,
+    arg: Option<&str>,
+) -> io::Result<()> {
+    let Some(target) = arg else {
+        writeln!(out, "missing address or device")?;
+        return Ok(());
+    };
+    match io_registers(sys, symbols, target) {
+        Ok((base, size)) => {
+            for addr in base..base + size {
+                sys.set_io_break(addr, 0);
+            }
+            writeln!(
+                out,
+                "io breakpoint removed at {base:04X}-{:04X}",
+                base + size - 1
+            )?;
+        }
+        Err(e) => writeln!(out, "{e}")?,
+    }
+    Ok(())
+}
+
+/// The registers of a device (by name), or a single register in the IO
+/// window, as the first and the number of them
+fn io_registers(
+    sys: &System,
+    symbols: &HashMap<u16, Vec<String>>,
+    target: &str,
+) -> Result<(u16, u16), String> {
+    // device names look like hex (`fdc0`), so they go first
+    if let Some(registers) = sys.device_registers(target) {
+        return Ok(registers);
+    }
+    let addr = parse_addr(symbols, target).map_err(|e| format!("error parsing address: {e}"))?;
+    if !(0xF000..=0xF0FF).contains(&addr) {
+        return Err(format!("{addr:04X} is outside the IO window"));
+    }
+    Ok((addr, 1))
+}
+
+fn add_watch(
+    out: &mut dyn Write,
+    mem: &Mem,
+    watches: &mut Vec<u16>,
+    symbols: &HashMap<u16, Vec<String>>,
+    arg: Option<&str>,
+) -> io::Result<()> {
+    let Some(arg) = arg else {
+        for addr in watches.iter() {
+            let value = mem.read(*addr);
+            writeln!(
+                out,
+                "{addr:04X}  {value:02X} {value:03} {:+04}  {}",
+                value as i8,
+                symbolize(symbols, *addr)
+            )?;
+        }
+        return Ok(());
+    };
+    let addr = match parse_addr(symbols, arg) {
+        Ok(addr) => addr,
+        Err(e) => {
+            writeln!(out, "error parsing address: {e}")?;
+            return Ok(());
+        }
+    };
+    if watches.contains(&addr) {
+        writeln!(out, "watch already exists")?;
+    } else {
+        watches.push(addr);
+        writeln!(out, "watch added at {addr:04X}")?;
+    }
+    Ok(())
+}
+
+fn remove_watch(
+    out: &mut dyn Write,
+    watches: &mut Vec<u16>,
+    symbols: &HashMap<u16, Vec<String>>,
+    arg: Option<&str>,
+) -> io::Result<()> {
+    let Some(arg) = arg else {
+        writeln!(out, "missing address")?;
+        return Ok(());
+    };
+    let addr = match parse_addr(symbols, arg) {
+        Ok(addr) => addr,
+        Err(e) => {
+            writeln!(out, "error parsing address: {e}")?;
+            return Ok(());
+        }
+    };
+    if let Some(index) = watches.iter().position(|&a| a == addr) {
+        watches.remove(index);
+        writeln!(out, "watch removed at {addr:04X}")?;
+    } else {
+        writeln!(out, "watch does not exist")?;
+    }
+    Ok(())
+}
+
+/// The host clipboard, from whichever clipboard tool is installed
+fn read_clipboard() -> io::Result<Vec<u8>> {
+    const TOOLS: [&[&str]; 4] = [
+        &["wl-paste", "--no-newline"],
+        &["xclip", "-selection", "clipboard", "-out"],
+        &["xsel", "--clipboard", "--output"],
+        &["pbpaste"],
+    ];
+    for tool in TOOLS {
+        if let Ok(output) = Command::new(tool[0]).args(&tool[1..]).output() {
+            if output.status.success() {
+                return Ok(output.stdout);
+            }
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "no clipboard tool (wl-paste, xclip, xsel, or pbpaste) worked",
+    ))
+}
+
+/// Save the current video frame as a PNG, through the display filters
+pub fn save_frame(sys: &System, filters: &[Filter], path: &Path) -> io::Result<()> {
+    let Some(frame) = sys.frame() else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "the machine has no video device",
+        ));
+    };
+    let pixels = filter::apply(filters, &frame);
+    save_png(path, frame.width, frame.height, &pixels)
+}
+
+fn save_png(path: &Path, width: usize, height: usize, pixels: &[u32]) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    png::write(&mut file, width, height, pixels)?;
+    file.flush()
+}
+
+fn inspect_ppu(out: &mut dyn Write, sys: &System, args: &[String]) -> io::Result<()> {
+    let Some(vram) = sys.vram() else {
+        return writeln!(out, "the machine has no video memory");
+    };
+    let save = |out: &mut dyn Write, path: &str, size: usize, pixels: Vec<u32>| match save_png(
+        Path::new(path),
+        size,
+        size,
+        &pixels,
+    ) {
+        Ok(()) => writeln!(out, "saved {size}x{size} image to {path}"),
+        Err(e) => writeln!(out, "error writing {path}: {e}"),
+    };
+    match (arg(args, 0), args.len()) {
+        (Some(layer @ ("bg" | "fg")), 2) => save(
+            out,
+            &args[1],
+            inspect::PLANE_SIZE,
+            inspect::plane(vram, layer == "fg"),
+        ),
+        (Some("tiles"), 3 | 4) => {
+            let Some(bank) = arg(args, 1)
+                .and_then(|bank| bank.parse::<usize>().ok())
+                .filter(|&bank| bank < 2)
+            else {
+                return writeln!(out, "tile bank must be 0 or 1");
+            };
+            let Some(palette) = arg(args, 3)
+                .map_or(Some(0), |palette| palette.parse::<usize>().ok())
+                .filter(|&palette| palette < inspect::PALETTE_COUNT)
+            else {
+                return writeln!(out, "palette must be 0 to {}", inspect::PALETTE_COUNT - 1);
+            };
+            save(
+                out,
+                &args[2],
+                inspect::BANK_SIZE,
+                inspect::tile_bank(vram, bank, palette),
+            )
+        }
+        (Some("sprites"), 1 | 2) => {
+            let all = match arg(args, 1) {
+                None => false,
+                Some("all") => true,
+                Some(_) => return writeln!(out, "usage: ppu sprites [all]"),
+            };
+            writeln!(out, "  #  TILE  BANK  PAL  FLIP  PRIORITY      X    Y")?;
+            for (index, sprite) in inspect::sprites(vram).iter().enumerate() {
+                if sprite.priority == 0 && !all {
+                    continue;
+                }
+                let flip = match (sprite.flip_x, sprite.flip_y) {
+                    (false, false) => "-",
+                    (true, false) => "x",
+                    (false, true) => "y",
+                    (true, true) => "xy",
+                };
+                let priority = ["hidden", "behind bg", "behind fg", "front"];
+                writeln!(
+                    out,
+                    "{index:3}  {:02X}    {}     {}    {flip:4}  {:12}  {:4} {:4}",
+                    sprite.tile,
+                    sprite.bank,
+                    sprite.palette,
+                    priority[sprite.priority as usize],
+                    sprite.x,
+                    sprite.y
+                )?;
+            }
+            Ok(())
+        }
+        (Some("palettes"), 1) => {
+            for (name, sprites) in [("bg/fg", false), ("sprite", true)] {
+                for (i, colors) in inspect::palettes(vram, sprites).iter().enumerate() {
+                    write!(out, "{name:6} {i}")?;
+                    for rgb in colors {
+                        write!(out, " {rgb:06X}")?;
+                    }
+                    writeln!(out)?;
+                }
+            }
+            Ok(())
+        }
+        _ => writeln!(out, "usage: ppu bg|fg <file>, ppu tiles <bank> <file> [palette], ppu sprites [all], or ppu palettes"),
+    }
+}
+
+fn save_breakpoints(
+    out: &mut dyn Write,
+    breakpoints: &Breakpoints,
+    symbols: &HashMap<u16, Vec<String>>,
+    path: Option<&str>,
+) -> io::Result<()> {
+    let Some(path) = path else {
+        writeln!(out, "missing file path")?;
+        return Ok(());
+    };
+    let mut script = String::new();
+    for bp in breakpoints.iter() {
+        let command = if bp.temporary { "tb" } else { "b" };
+        if let Some(labels) = symbols.get(&bp.addr) {
+            script.push_str(&format!("{command} {}\n", labels[0]));
+        } else {
+            script.push_str(&format!("{command} {:04X}\n", bp.addr));
+        }
+    }
+    match File::create(path).and_then(|mut file| file.write_all(script.as_bytes())) {
+        Ok(()) => writeln!(out, "saved {} breakpoints to {path}", breakpoints.len())?,
+        Err(e) => writeln!(out, "error saving breakpoints: {e}")?,
+    }
+    Ok(())
+}
+
+/// A snapshot of everything the CPU can see short of memory, for `state`.
+/// IO registers that can't be read without side effects are null.
+fn machine_state(sys: &System) -> Value {
+    let cpu = sys.cpu();
+    let p = cpu.p();
+    let flag = |flag: u8| (p & flag) != 0;
+    let devices = sys
+        .slots()
+        .map(|slot| {
+            let registers = (0..slot.registers())
+                .map(|offset| {
+                    let name = match slot.device.register_name(offset) {
+                        Some(name) => name.to_string(),
+                        None => format!("{offset}"),
+                    };
+                    (name, json!(slot.device.peek(offset)))
+                })
+                .collect::<serde_json::Map<_, _>>();
+            let device = json!({ "base": slot.base, "registers": registers });
+            (slot.name.to_string(), device)
+        })
+        .collect::<serde_json::Map<_, _>>();
+    json!({
+        "cycles": cpu.cycles(),
+        "cpu": {
+            "a": cpu.a(),
+            "b": cpu.b(),
+            "x": cpu.x(),
+            "y": cpu.y(),
+            "z": cpu.z(),
+            "pc": cpu.pc(),
+            "sp": cpu.sp(),
+            "p": p,
+            "flags": {
+                "negative": flag(Flags::NEGATIVE),
+                "overflow": flag(Flags::OVERFLOW),
+                "extend_stack_disable": flag(Flags::EXTEND_STACK_DISABLE),
+                "break": flag(Flags::BREAK),
+                "decimal_mode": flag(Flags::DECIMAL_MODE),
+                "interrupt_disable": flag(Flags::INTERRUPT_DISABLE),
+                "zero": flag(Flags::ZERO),
+                "carry": flag(Flags::CARRY),
+            },
+            "waiting": cpu.waiting(),
+        },
+        "banks": {
+            "ram": (0..RAM_CHAPTERS).map(|chapter| sys.mem().bank_select(chapter)).collect::<Vec<_>>(),
+            "rom": sys.mem().rom_bank_select(),
+        },
+        "irq": {
+            "enable_mask": sys.peek_io(0xF0F8),
+            "pending": sys.peek_io(0xF0F9),
+            "trigger_mode": sys.peek_io(0xF0FA),
+            "routing": (0xF0FB..=0xF0FE).map(|addr| sys.peek_io(addr)).collect::<Vec<_>>(),
+            "latch": sys.peek_io(0xF0FF),
+        },
+        "drq_routing": sys.peek_io(0xF038),
+        "devices": devices,
+    })
+}
+
+fn print_irq_stats(out: &mut dyn Write, stats: &IrqStats) -> io::Result<()> {
+    writeln!(out, "source          taken  max latency  avg latency")?;
+    for (source, name) in IrqSource::NAMES.iter().enumerate() {
+        let taken = stats.taken[source];
+        if taken == 0 {
+            continue;
+        }
+        writeln!(
+            out,
+            "{name:10} {taken:10} {:12} {:12}",
+            stats.max_latency[source],
+            stats.total_latency[source] / taken
+        )?;
+    }
+    writeln!(out, "NMI        {:10}", stats.nmis)?;
+    let percent = if stats.cycles == 0 {
+        0.0
+    } else {
+        stats.masked_cycles as f64 * 100.0 / stats.cycles as f64
+    };
+    writeln!(
+        out,
+        "I flag set for {} of {} cycles ({percent:.2}%)",
+        stats.masked_cycles, stats.cycles
+    )?;
+    Ok(())
+}
+
+fn print_profile(
+    out: &mut dyn Write,
+    profiler: &Profiler,
+    symbols: &HashMap<u16, Vec<String>>,
+    count: Option<&str>,
+) -> io::Result<()> {
+    let count = match count.map(str::parse::<usize>) {
+        Some(Ok(count)) => count,
+        Some(Err(e)) => {
+            writeln!(out, "error parsing count: {e}")?;
+            return Ok(());
+        }
+        None => 20,
+    };
+    let total = profiler.total();
+    if total == 0 {
+        writeln!(out, "no profile data")?;
+        return Ok(());
+    }
+    if profiler.running() {
+        writeln!(out, "(profiler still running)")?;
+    }
+    let percent = |cycles: u64| (cycles as f64) * 100.0 / (total as f64);
+
+    let spots = profiler.hot_spots();
+    writeln!(out, "{total} cycles over {} addresses", spots.len())?;
+    writeln!(out, "    CYCLES       %  ADDR  SYMBOL")?;
+    for (addr, cycles) in spots.iter().take(count) {
+        writeln!(
+            out,
+            "{cycles:>10} {:>6.2}%  {addr:04X}  {}",
+            percent(*cycles),
+            symbolize(symbols, *addr)
+        )?;
+    }
+
+    let mut by_symbol = HashMap::<&str, u64>::new();
+    for (addr, cycles) in &spots {
+        let label = nearest_symbol(symbols, *addr).map_or("?", |(_, label)| label);
+        *by_symbol.entry(label).or_default() += cycles;
+    }
+    let mut by_symbol = by_symbol.into_iter().collect::<Vec<_>>();
+    by_symbol.sort_by(|(a_label, a), (b_label, b)| b.cmp(a).then(a_label.cmp(b_label)));
+    writeln!(out)?;
+    writeln!(out, "    CYCLES       %  SYMBOL")?;
+    for (label, cycles) in by_symbol.iter().take(count) {
+        writeln!(out, "{cycles:>10} {:>6.2}%  {label}", percent(*cycles))?;
+    }
+    Ok(())
+}
+
+pub fn mark_executed(sys: &mut System) {
+    if !sys.cov().enabled() {
+        return;
+    }
+    let pc = sys.cpu().pc();
+    let len = op_len(sys.mem().read(pc));
+    for i in 0..len {
+        sys.cov_mut()
+            .mark(pc.wrapping_add(i), CoverageFlags::EXECUTED);
+    }
+}
+
+/// Log the instruction about to execute (at trace level)
+/// `<start>..<end>`, or a symbol (or address) up to the next symbol
+fn parse_trace_range(symbols: &HashMap<u16, Vec<String>>, arg: &str) -> Result<Range<u32>, String> {
+    let parse = |arg| {
+        parse_addr(symbols, arg)
+            .map(u32::from)
+            .map_err(|e| format!("error parsing address {arg}: {e}"))
+    };
+    let (start, end) = match arg.split_once("..") {
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        None => {
+            let start = parse(arg)?;
+            let next = symbols
+                .keys()
+                .map(|&addr| addr as u32)
+                .filter(|&addr| addr > start)
+                .min();
+            (start, next.unwrap_or(0x1_0000))
+        }
+    };
+    if end <= start {
+        return Err(format!("{arg} is empty"));
+    }
+    Ok(start..end)
+}
+
+fn print_trace_filter(out: &mut dyn Write, filter: &TraceFilter) -> io::Result<()> {
+    let list = |ranges: &[Range<u32>]| {
+        ranges
+            .iter()
+            .map(|range| format!("{:04X}..{:04X}", range.start, range.end))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    match (filter.only_ranges(), filter.skip_ranges()) {
+        ([], []) => writeln!(out, "tracing everything"),
+        ([], skip) => writeln!(out, "tracing everything but {}", list(skip)),
+        (only, []) => writeln!(out, "tracing only {}", list(only)),
+        (only, skip) => writeln!(out, "tracing only {}, but not {}", list(only), list(skip)),
+    }
+}
+
+pub fn trace_instruction(sys: &System, symbols: &HashMap<u16, Vec<String>>) {
+    if !tracing::enabled!(tracing::Level::TRACE) {
+        return;
+    }
+    let pc = sys.cpu().pc();
+    let mem = sys.mem();
+    let location = symbolize(symbols, pc);
+    match Instruction::decode(pc, |addr| mem.read(addr)) {
+        Some(inst) => tracing::trace!(
+            "{pc:04X} {location:16} {} {:20} ({} cycles)",
+            inst.mnemonic,
+            inst.operand_string(|_| None),
+            inst.cycles
+        ),
+        None => tracing::trace!("{pc:04X} {location:16} ???"),
+    }
+}
+
+fn print_coverage(
+    out: &mut dyn Write,
+    cov: &Coverage,
+    symbols: &HashMap<u16, Vec<String>>,
+) -> io::Result<()> {
+    let count = |flags: u8, range: std::ops::RangeInclusive<u16>| {
+        range.filter(|&addr| (cov.flags(addr) & flags) != 0).count()
+    };
+    writeln!(
+        out,
+        "executed: {} bytes ({} in ROM), read: {} bytes, written: {} bytes",
+        count(CoverageFlags::EXECUTED, 0x0000..=0xFFFF),
+        count(CoverageFlags::EXECUTED, 0xF100..=0xFFFF),
+        count(CoverageFlags::READ, 0x0000..=0xFFFF),
+        count(CoverageFlags::WRITTEN, 0x0000..=0xFFFF),
+    )?;
+
+    // list the ROM ranges that never executed
+    let mut ranges = Vec::new();
+    let mut start = None;
+    for addr in 0xF100..=0xFFFF {
+        let executed = (cov.flags(addr) & CoverageFlags::EXECUTED) != 0;
+        match (start, executed) {
+            (None, false) => start = Some(addr),
+            (Some(base), true) => {
+                ranges.push((base, addr - 1));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(base) = start {
+        ranges.push((base, 0xFFFF));
+    }
+    writeln!(out, "unexecuted ROM ranges:")?;
+    for (start, end) in ranges {
+        writeln!(
+            out,
+            "  {start:04X}-{end:04X}  {}",
+            symbolize(symbols, start)
+        )?;
+    }
+    Ok(())
+}
+
+fn export_coverage(out: &mut dyn Write, cov: &Coverage, path: Option<&str>) -> io::Result<()> {
+    let Some(path) = path else {
+        writeln!(out, "missing file path")?;
+        return Ok(());
+    };
+    // one `ADDR:FLAGS` entry per touched address, in the spirit of the sym file
+    let mut export = String::new();
+    for addr in 0x0000..=0xFFFF {
+        let flags = cov.flags(addr);
+        if flags == 0 {
+            continue;
+        }
+        export.push_str(&format!(
+            "{addr:04X}:{}{}{}\n",
+            if (flags & CoverageFlags::EXECUTED) == 0 {
+                "-"
+            } else {
+                "X"
+            },
+            if (flags & CoverageFlags::READ) == 0 {
+                "-"
+            } else {
+                "R"
+            },
+            if (flags & CoverageFlags::WRITTEN) == 0 {
+                "-"
+            } else {
+                "W"
+            },
+        ));
+    }
+    match File::create(path).and_then(|mut file| file.write_all(export.as_bytes())) {
+        Ok(()) => writeln!(out, "exported coverage to {path}")?,
+        Err(e) => writeln!(out, "error exporting coverage: {e}")?,
+    }
+    Ok(())
+}
+
+/// List every command, or the forms of one
+fn print_help(out: &mut dyn Write, name: Option<&str>) -> io::Result<()> {
+    let Some(name) = name else {
+        writeln!(out, "debugger commands (`? <command>` for one):")?;
+        for command in COMMANDS {
+            print_command_help(out, command)?;
+        }
+        return Ok(());
+    };
+    match find_command(name) {
+        Some(command) => print_command_help(out, command),
+        None => writeln!(out, "unknown command: `{name}`"),
+    }
+}
+
+fn print_command_help(out: &mut dyn Write, command: &DebugCommand) -> io::Result<()> {
+    for (i, (usage, help)) in command.help.iter().enumerate() {
+        write!(out, "`{usage}`")?;
+        if i == 0 && command.names.len() > 1 {
+            write!(out, " (or `{}`)", command.names[1..].join("`, `"))?;
+        }
+        writeln!(out, ": {help}")?;
+    }
+    Ok(())
+}
+
+fn print_cpu_regs(out: &mut dyn Write, cpu: &Cpu) -> io::Result<()> {
+    write!(
+        out,
+        "A={:02X} B={:02X} X={:02X} Y={:02X} Z={:02X} PC={:04X} SP={:04X} ",
+        cpu.a(),
+        cpu.b(),
+        cpu.x(),
+        cpu.y(),
+        cpu.z(),
+        cpu.pc(),
+        cpu.sp()
+    )?;
+    let p = cpu.p();
+    write!(out, "P={:02X} [", p)?;
+    #[rustfmt::skip]
+    {
+        write!(out, "{}", if (p & Flags::NEGATIVE) == 0 { "-" } else { "N" })?;
+        write!(out, "{}", if (p & Flags::OVERFLOW) == 0 { "-" } else { "V" })?;
+        write!(out, "{}", if (p & Flags::EXTEND_STACK_DISABLE) == 0 { "-" } else { "E" })?;
+        write!(out, "{}", if (p & Flags::BREAK) == 0 { "-" } else { "B" })?;
+        write!(out, "{}", if (p & Flags::DECIMAL_MODE) == 0 { "-" } else { "D" })?;
+        write!(out, "{}", if (p & Flags::INTERRUPT_DISABLE) == 0 { "-" } else { "I" })?;
+        write!(out, "{}", if (p & Flags::ZERO) == 0 { "-" } else { "Z" })?;
+        write!(out, "{}", if (p & Flags::CARRY) == 0 { "-" } else { "C" })?;
+    };
+    writeln!(out, "]")?;
+    Ok(())
+}
+
+fn print_cpu_regs_base10(out: &mut dyn Write, cpu: &Cpu) -> io::Result<()> {
+    write!(
+        out,
+        "A={:03} B={:03} X={:03} Y={:03} Z={:03} PC={:05} SP={:05} ",
+        cpu.a(),
+        cpu.b(),
+        cpu.x(),
+        cpu.y(),
+        cpu.z(),
+        cpu.pc(),
+        cpu.sp()
+    )?;
+    let p = cpu.p();
+    write!(out, "P={:03} [", p)?;
+    #[rustfmt::skip]
+    {
+        write!(out, "{}", if (p & Flags::NEGATIVE) == 0 { "-" } else { "N" })?;
+        write!(out, "{}", if (p & Flags::OVERFLOW) == 0 { "-" } else { "V" })?;
+        write!(out, "{}", if (p & Flags::EXTEND_STACK_DISABLE) == 0 { "-" } else { "E" })?;
+        write!(out, "{}", if (p & Flags::BREAK) == 0 { "-" } else { "B" })?;
+        write!(out, "{}", if (p & Flags::DECIMAL_MODE) == 0 { "-" } else { "D" })?;
+        write!(out, "{}", if (p & Flags::INTERRUPT_DISABLE) == 0 { "-" } else { "I" })?;
+        write!(out, "{}", if (p & Flags::ZERO) == 0 { "-" } else { "Z" })?;
+        write!(out, "{}", if (p & Flags::CARRY) == 0 { "-" } else { "C" })?;
+    };
+    writeln!(out, "]")?;
+    Ok(())
+}
+
+fn print_cpu_regs_signed_base10(out: &mut dyn Write, cpu: &Cpu) -> io::Result<()> {
+    write!(
+        out,
+        "A={:+04} B={:+04} X={:+04} Y={:+04} Z={:+04} PC={:+06} SP={:+06} ",
+        cpu.a() as i8,
+        cpu.b() as i8,
+        cpu.x() as i8,
+        cpu.y() as i8,
+        cpu.z() as i8,
+        cpu.pc() as i16,
+        cpu.sp() as i16
+    )?;
+    let p = cpu.p();
+    write!(out, "P={:+04} [", p as i8)?;
+    #[rustfmt::skip]
+    {
+        write!(out, "{}", if (p & Flags::NEGATIVE) == 0 { "-" } else { "N" })?;
+        write!(out, "{}", if (p & Flags::OVERFLOW) == 0 { "-" } else { "V" })?;
+        write!(out, "{}", if (p & Flags::EXTEND_STACK_DISABLE) == 0 { "-" } else { "E" })?;
+        write!(out, "{}", if (p & Flags::BREAK) == 0 { "-" } else { "B" })?;
+        write!(out, "{}", if (p & Flags::DECIMAL_MODE) == 0 { "-" } else { "D" })?;
+        write!(out, "{}", if (p & Flags::INTERRUPT_DISABLE) == 0 { "-" } else { "I" })?;
+        write!(out, "{}", if (p & Flags::ZERO) == 0 { "-" } else { "Z" })?;
+        write!(out, "{}", if (p & Flags::CARRY) == 0 { "-" } else { "C" })?;
+    };
+    writeln!(out, "]")?;
+    Ok(())
+}
+
+/// List `count` instructions from `start`, returning the address after the
+/// last one. Branch targets without a symbol get a generated label when they
+/// fall inside the listing.
+pub fn dissasemble(
+    out: &mut dyn Write,
+    mem: &Mem,
+    symbols: &HashMap<u16, Vec<String>>,
+    start: u16,
+    count: usize,
+) -> io::Result<u16> {
+    dissasemble_noted(out, mem, symbols, start, count, None)
+}
+
+/// Disassemble with notes on where the listing crosses between RAM, the
+/// IO window, and ROM
+fn dissasemble_noted(
+    out: &mut dyn Write,
+    mem: &Mem,
+    symbols: &HashMap<u16, Vec<String>>,
+    start: u16,
+    count: usize,
+    mut notes: Option<SpaceNotes>,
+) -> io::Result<u16> {
+    let mut addr = start;
+    let mut listing = Vec::with_capacity(count);
+    for _ in 0..count {
+        let inst = Instruction::decode(addr, |addr| mem.read(addr));
+        listing.push((addr, inst));
+        addr = inst.map_or(addr.wrapping_add(1), |inst| inst.next());
+    }
+    let mut labels = HashMap::new();
+    for (_, inst) in &listing {
+        let Some(target) = inst
+            .filter(|inst| matches!(inst.mode, REL | WREL | B_REL))
+            .and_then(|inst| inst.target())
+        else {
+            continue;
+        };
+        if !symbols.contains_key(&target) && listing.iter().any(|(addr, _)| *addr == target) {
+            labels.insert(target, format!("L{target:04X}"));
+        }
+    }
+    let name = |addr: u16| {
+        symbols
+            .get(&addr)
+            .map(|names| names[0].clone())
+            .or_else(|| labels.get(&addr).cloned())
+    };
+
+    for (addr, inst) in &listing {
+        if let Some(label) = name(*addr) {
+            writeln!(out, "{};  {label}:{}  ", Fg(LightBlue), Fg(Reset))?;
+        } else if *addr == start {
+            // say which routine a listing starts inside of
+            let label = symbolize(symbols, start);
+            if !label.is_empty() {
+                writeln!(out, "{};  {label}:{}  ", Fg(LightBlue), Fg(Reset))?;
+            }
+        }
+        let bank = mem.bank(*addr);
+        write!(out, "{bank}:{}{addr:04X} {}", Fg(LightYellow), Fg(Reset))?;
+        let note = notes
+            .as_mut()
+            .and_then(|notes| notes.note(*addr, inst.map_or(1, |inst| inst.len)));
+        let Some(inst) = inst else {
+            let byte = mem.read(*addr);
+            write!(
+                out,
+                " {byte:02X}           {}???{}",
+                Fg(LightMagenta),
+                Fg(Reset)
+            )?;
+            if let Some(note) = note {
+                write!(out, "{:20}  {note}", "")?;
+            }
+            writeln!(out)?;
+            continue;
+        };
+        let bytes = (0..inst.len)
+            .map(|i| format!(" {:02X}", mem.read(addr.wrapping_add(i))))
+            .collect::<String>();
+        write!(
+            out,
+            "{bytes:12}  {}{}{} {}{:20}{}",
+            Fg(LightMagenta),
+            inst.mnemonic,
+            Fg(Reset),
+            Fg(LightRed),
+            inst.operand_string(name),
+            Fg(Reset)
+        )?;
+        // keep the raw address around when the operand was named, otherwise
+        // say which routine a jump or branch lands inside of
+        let code =
+            matches!(inst.mode, REL | WREL | B_REL) || matches!(inst.mnemonic, "JMP" | "JSR");
+        if let Some(target) = inst.target() {
+            let comment = if name(target).is_some() {
+                format!("${target:04X}")
+            } else if code {
+                symbolize(symbols, target)
+            } else {
+                String::new()
+            };
+            if !comment.is_empty() {
+                write!(out, "  {}; {comment}{}", Fg(LightBlue), Fg(Reset))?;
+            }
+        }
+        if let Some(note) = note {
+            write!(out, "  {note}")?;
+        }
+        writeln!(out)?;
+    }
+    Ok(addr)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Space {
+    Ram,
+    Io,
+    Rom,
+}
+
+impl Space {
+    fn of(addr: u16) -> Self {
+        match addr {
+            ..IO_START => Space::Ram,
+            IO_START..ROM_START => Space::Io,
+            ROM_START.. => Space::Rom,
+        }
+    }
+}
+
+/// Notes for `x` and `d` output. Everything in the IO window is noted with
+/// the registers there, since memory reads it as zeros, and otherwise the
+/// first row or line past a crossing into RAM or ROM is.
+struct SpaceNotes<'a> {
+    sys: &'a System,
+    last: Option<Space>,
+}
+
+impl<'a> SpaceNotes<'a> {
+    fn new(sys: &'a System) -> Self {
+        Self { sys, last: None }
+    }
+
+    /// The note for `len` bytes from `addr`, which are all in one space
+    fn note(&mut self, addr: u16, len: u16) -> Option<String> {
+        let space = Space::of(addr);
+        let last = self.last.replace(space);
+        match space {
+            Space::Io => {
+                let addrs = (0..len.max(1))
+                    .map(|i| addr.wrapping_add(i))
+                    .take_while(|&addr| Space::of(addr) == Space::Io);
+                let mut owners = Vec::new();
+                for addr in addrs.clone() {
+                    let owner = self.sys.io_owner(addr);
+                    if !owners.contains(&owner) {
+                        owners.push(owner);
+                    }
+                }
+                if addrs.count() == 1 {
+                    Some(format!("[IO:{}]", self.sys.register_name(addr)))
+                } else {
+                    Some(format!("[IO:{}]", owners.join(",")))
+                }
+            }
+            _ if last.is_none_or(|last| last == space) => None,
+            Space::Ram => Some("[RAM]".to_string()),
+            Space::Rom => Some("[ROM]".to_string()),
+        }
+    }
+}
+
+/// Merge a SYM file into the symbol table. Names it defines are dropped from
+/// their old addresses first, so loading a re-assembled file updates them.
+pub fn load_symbols(symbols: &mut HashMap<u16, Vec<String>>, path: &Path) -> Result<usize, String> {
+    let loaded = possum2_sym::read(path)?;
+    symbols.retain(|_, labels| {
+        labels.retain(|label| !loaded.iter().any(|symbol| symbol.name == *label));
+        !labels.is_empty()
+    });
+    for symbol in &loaded {
+        symbols
+            .entry(symbol.value)
+            .or_default()
+            .push(symbol.name.clone());
+    }
+    Ok(loaded.len())
+}
+
+/// Remove the ANSI color sequences the console output is decorated with
+pub fn strip_colors(buf: &[u8]) -> Vec<u8> {
+    let mut stripped = Vec::with_capacity(buf.len());
+    let mut iter = buf.iter();
+    while let Some(&c) = iter.next() {
+        if c == 0x1B {
+            // ESC [ params... final byte
+            for &c in iter.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+/// Parse a hex address or a label, optionally with a hex offset (`Reset+1A`)
+pub fn parse_addr(symbols: &HashMap<u16, Vec<String>>, arg: &str) -> Result<u16, ParseIntError> {
+    if let Some((base, offset)) = arg.rsplit_once('+').filter(|(base, _)| !base.is_empty()) {
+        let offset = u16::from_str_radix(offset, 16)?;
+        return Ok(parse_addr(symbols, base)?.wrapping_add(offset));
+    }
+    match u16::from_str_radix(arg, 16) {
+        Ok(addr) => Ok(addr),
+        Err(e) => {
+            for (addr, labels) in symbols {
+                for label in labels {
+                    if label == arg {
+                        return Ok(*addr);
+                    }
+                }
+            }
+            Err(e)
+        }
+    }
+}
+
+/// List the symbols starting with a prefix, in address order
+fn find_symbols(
+    out: &mut dyn Write,
+    symbols: &HashMap<u16, Vec<String>>,
+    prefix: &str,
+) -> io::Result<()> {
+    let mut found = symbols
+        .iter()
+        .flat_map(|(addr, labels)| labels.iter().map(move |label| (*addr, label)))
+        .filter(|(_, label)| label.starts_with(prefix))
+        .collect::<Vec<(u16, &String)>>();
+    if found.is_empty() {
+        writeln!(out, "no symbols found")?;
+        return Ok(());
+    }
+    found.sort();
+    for (addr, label) in found {
+        writeln!(out, "{addr:04X}  {label}")?;
+    }
+    Ok(())
+}
+
+/// Find the closest symbol at or below an address
+fn nearest_symbol(symbols: &HashMap<u16, Vec<String>>, addr: u16) -> Option<(u16, &str)> {
+    symbols
+        .iter()
+        .filter(|(&base, _)| base <= addr)
+        .max_by_key(|(&base, _)| base)
+        .map(|(&base, labels)| (base, labels[0].as_str()))
+}
+
+/// Describe an address relative to its nearest symbol (e.g. `Reset+1A`)
+pub fn symbolize(symbols: &HashMap<u16, Vec<String>>, addr: u16) -> String {
+    match nearest_symbol(symbols, addr) {
+        Some((base, label)) if base == addr => label.to_string(),
+        Some((base, label)) => format!("{label}+{:X}", addr - base),
+        None => String::new(),
+    }
+}
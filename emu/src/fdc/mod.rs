@@ -0,0 +1,907 @@
+//! FD179X FDC Emulation
+//!
+//! Registers:
+//!
+//! 0 Command/Status
+//! 1 Track
+//! 2 Sector
+//! 3 Data
+//!
+//! And the DMA registers, mapped in a block of their own so the four above
+//! stay where the 179X has them:
+//!
+//! 4 DMA Control
+//! 5 DMA Address Lo (reads return the next address)
+//! 6 DMA Address Hi
+//!
+//! With DMA enabled, sectors are gathered in the controller's buffer and
+//! moved to or from RAM in one burst, stealing a CPU cycle a byte, starting
+//! at the DMA address (which advances as it goes). DRQ stays low, so the
+//! guest only hears from the controller when the command finishes. Reads
+//! burst once the sector has come off the disk and writes burst as the
+//! sector comes round, so the transfer never races the disk and LOST_DATA
+//! can't happen.
+//!
+//! The disk turns at 300 RPM, so reads and writes wait for their sector to
+//! come round under the head, and data moves at the 250kbit/s MFM rate of a
+//! double density drive (32us a byte). A byte the guest doesn't take (or
+//! supply) in time sets LOST_DATA. Steps take as long as the command's
+//! stepping rate asks, and the head takes 30ms to settle after loading or
+//! when a command asks to verify or delay. These are the datasheet's
+//! timings for a 1MHz controller clock.
+//!
+//! The motor starts with any command and keeps turning (with the head
+//! loaded) until 15 revolutions pass without one. Index pulses are only
+//! seen while it turns. Spin-up is instant.
+//!
+//! Sectors are numbered from `first_sector` (0 unless the machine config
+//! says otherwise). Multiple record commands carry on sector by sector
+//! until the sector register runs off the end of the track, and then stop
+//! with RECORD_NOT_FOUND once the search has gone 5 revolutions, like the
+//! real thing. Single record commands leave the sector register alone.
+//! The S flag selects the side, so the side every ID field records always
+//! matches it, and side compare (C) never fails.
+//!
+//! Force interrupt stops the running command, raising an IRQ if the
+//! immediate condition (I3) is set. Its other conditions aren't emulated,
+//! and neither are read track and write track, which finish at once.
+//!
+//! Commands are logged to the `fdc` tracing target at debug level, at most
+//! `LOG_BURST` a second of emulated time so a busy guest can't flood the log.
+//! Every sector read or written is logged there at trace level. A failed
+//! read or write of the image is logged as an error, and ends the command
+//! with CRC_ERROR or WRITE_FAULT.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{Read, Seek, SeekFrom, Write},
+};
+
+use possum2_media::{NUM_SECTORS, NUM_TRACKS, SECTOR_SIZE};
+
+use crate::bus::{Bus, BusDevice, DiskActivity};
+
+#[cfg(test)]
+mod tests;
+
+/// Rate the controller is ticked at (the 1MHz clock of a 5.25" drive)
+pub const TICK_RATE: u32 = 1_000_000;
+
+/// How ID fields give the sector size (128 << 1 = 256)
+const SIZE_CODE: u8 = 1;
+
+/// Ticks per revolution at 300 RPM
+const REVOLUTION_TICKS: u32 = TICK_RATE / 5;
+
+/// How long the index hole is under the sensor
+const INDEX_PULSE_TICKS: u32 = 4_000;
+
+/// Sectors are spread evenly around the track
+const SECTOR_TICKS: u32 = REVOLUTION_TICKS / NUM_SECTORS as u32;
+
+/// Time to move one byte at 250kbit/s
+const BYTE_TICKS: u32 = 32;
+
+/// Time per step for each STEPPING_MOTOR_RATE
+const STEP_TICKS: [u32; 4] = [6_000, 12_000, 20_000, 30_000];
+
+/// Head load and verify settling time
+const SETTLE_TICKS: u32 = 30_000;
+
+/// Revolutions a search goes before giving up with RECORD_NOT_FOUND
+const SEARCH_REVOLUTIONS: u32 = 5;
+
+/// Revolutions without a command before the head unloads and the motor stops
+const IDLE_REVOLUTIONS: u32 = 15;
+
+/// Commands logged per second before the rest are only counted
+const LOG_BURST: u32 = 20;
+
+/// What's in a drive
+pub trait Image: Read + Write + Seek {
+    /// Write changes kept in an overlay back to the base image, returning
+    /// the number of sectors written
+    fn commit(&mut self) -> Result<usize, String>;
+}
+
+enum StatusFlags {}
+
+impl StatusFlags {
+    const BUSY: u8 = 1 << 0;
+
+    const DATA_REQUEST: u8 = 1 << 1;
+    const INDEX: u8 = 1 << 1;
+
+    const LOST_DATA: u8 = 1 << 2;
+    const TRACK_0: u8 = 1 << 2;
+
+    const CRC_ERROR: u8 = 1 << 3;
+
+    const RECORD_NOT_FOUND: u8 = 1 << 4;
+    // every track exists, so seeks never fail
+    #[allow(dead_code)]
+    const SEEK_ERROR: u8 = 1 << 4;
+
+    const WRITE_FAULT: u8 = 1 << 5;
+    // there are no deleted data marks
+    #[allow(dead_code)]
+    const RECORD_TYPE: u8 = 1 << 5;
+    const HEAD_LOADED: u8 = 1 << 5;
+
+    // images can't be write protected
+    #[allow(dead_code)]
+    const WRITE_PROTECT: u8 = 1 << 6;
+
+    // drives are always ready
+    #[allow(dead_code)]
+    const NOT_READY: u8 = 1 << 7;
+}
+
+enum DmaFlags {}
+
+impl DmaFlags {
+    const ENABLE: u8 = 1 << 0;
+}
+
+enum CommandFlags {}
+
+impl CommandFlags {
+    const STEPPING_MOTOR_RATE_MASK: u8 = 0b0000_0011;
+    const VERIFY: u8 = 1 << 2;
+    const HEAD_LOAD: u8 = 1 << 3;
+    const UPDATE_TRACK: u8 = 1 << 4;
+
+    // disks are single-sided
+    #[allow(dead_code)]
+    const SIDE_COMPARE: u8 = 1 << 1;
+    const DELAY: u8 = 1 << 2;
+    const SIDE_SELECT: u8 = 1 << 3;
+    const MULTIPLE_RECORD: u8 = 1 << 4;
+    // there are no deleted data marks
+    #[allow(dead_code)]
+    const DATA_ADDRESS_MARK: u8 = 1 << 0;
+
+    // drives are always ready and have no index pulse
+    #[allow(dead_code)]
+    const INTERRUPT_NOT_READY_TO_READY: u8 = 1 << 0;
+    #[allow(dead_code)]
+    const INTERRUPT_READY_TO_NOT_READY: u8 = 1 << 1;
+    #[allow(dead_code)]
+    const INTERRUPT_INDEX_PULSE: u8 = 1 << 2;
+    const INTERRUPT_IMMEDIATE: u8 = 1 << 3;
+}
+
+enum State {
+    Idle,
+    Restore,
+    Seek,
+    Step,
+    ReadSector,
+    WriteSector,
+    ReadAddress,
+    /// Searching for a sector that isn't there
+    NotFound,
+}
+
+pub struct Fdc<T> {
+    handle: T,
+    state: State,
+    status: u8,
+    command: u8,
+    track: u8,
+    sector: u8,
+    data: u8,
+
+    buf: VecDeque<u8>,
+    track_latch: u8,
+    track_target: u8,
+    /// Sectors left to transfer (multiple record commands start with more
+    /// than a track has, and stop when they run off its end)
+    sector_count: u8,
+    irq: bool,
+    first_sector: u8,
+
+    sectors_read: u64,
+    sectors_written: u64,
+    log: LogLimit,
+
+    /// Ticks since the index hole passed
+    rotation: u32,
+    /// Revolutions left before the motor stops (0 when stopped)
+    motor: u32,
+    head_loaded: bool,
+    /// The last command was a type I (or force interrupt), so status
+    /// shows the index and head
+    type_one: bool,
+    /// Ticks until the current command carries on
+    delay: u32,
+    /// The head has settled after the last step
+    settled: bool,
+    /// Bytes of the current sector still to be written, and where it goes
+    write_left: usize,
+    write_offset: u64,
+
+    dma_control: u8,
+    dma_addr: u16,
+}
+
+struct LogLimit {
+    /// Ticks left until the budget is refilled
+    window: u32,
+    budget: u32,
+    suppressed: u32,
+}
+
+impl LogLimit {
+    fn new() -> Self {
+        Self {
+            window: TICK_RATE,
+            budget: LOG_BURST,
+            suppressed: 0,
+        }
+    }
+
+    fn tick(&mut self) {
+        self.window -= 1;
+        if self.window == 0 {
+            if self.suppressed > 0 {
+                tracing::debug!(target: "fdc", "{} more commands not logged", self.suppressed);
+            }
+            *self = Self::new();
+        }
+    }
+
+    fn log(&mut self, message: fmt::Arguments) {
+        if self.budget > 0 {
+            self.budget -= 1;
+            tracing::debug!(target: "fdc", "{message}");
+        } else {
+            self.suppressed += 1;
+        }
+    }
+}
+
+impl<T> Fdc<T> {
+    pub fn new(handle: T, first_sector: u8) -> Self {
+        Self {
+            handle,
+            state: State::Idle,
+            status: 0,
+            command: 0,
+            track: 0,
+            sector: 0,
+            data: 0,
+            buf: VecDeque::with_capacity(SECTOR_SIZE),
+            track_latch: 0,
+            track_target: 0,
+            sector_count: 0,
+            irq: false,
+            first_sector,
+            sectors_read: 0,
+            sectors_written: 0,
+            log: LogLimit::new(),
+            rotation: 0,
+            motor: 0,
+            head_loaded: false,
+            type_one: true,
+            delay: 0,
+            settled: false,
+            write_left: 0,
+            write_offset: 0,
+            dma_control: 0,
+            dma_addr: 0,
+        }
+    }
+
+    fn dma(&self) -> bool {
+        (self.dma_control & DmaFlags::ENABLE) != 0
+    }
+
+    fn step_ticks(&self) -> u32 {
+        STEP_TICKS[(self.command & CommandFlags::STEPPING_MOTOR_RATE_MASK) as usize]
+    }
+
+    /// Finish a type I command once the head has settled, if asked to
+    /// verify. Returns false while still settling.
+    fn settle(&mut self) -> bool {
+        if (self.command & CommandFlags::VERIFY) != 0 && !self.settled {
+            self.settled = true;
+            self.delay = SETTLE_TICKS;
+            return false;
+        }
+        true
+    }
+
+    fn finish(&mut self) {
+        self.state = State::Idle;
+        self.status &= !StatusFlags::BUSY;
+        self.irq = true;
+    }
+
+    /// Where the sector register's sector is on the track, if the track
+    /// register matches the track under the head and there is one
+    fn sector_index(&self) -> Option<usize> {
+        let index = self.sector.checked_sub(self.first_sector)? as usize;
+        (self.track_latch == self.track && index < NUM_SECTORS).then_some(index)
+    }
+
+    /// Give up on the sector once the search has gone round enough times
+    fn not_found(&mut self) {
+        self.state = State::NotFound;
+        self.delay = SEARCH_REVOLUTIONS * REVOLUTION_TICKS;
+    }
+
+    /// Move on to the next sector of a multiple record command
+    fn next_sector(&mut self) {
+        self.sector_count -= 1;
+        if (self.command & CommandFlags::MULTIPLE_RECORD) != 0 {
+            self.sector = self.sector.wrapping_add(1);
+        }
+    }
+
+    /// Whether the sector at `index` has just come round under the head,
+    /// and if not, wait for it
+    fn sector_under_head(&mut self, index: usize) -> bool {
+        let start = index as u32 * SECTOR_TICKS;
+        let late = (self.rotation + REVOLUTION_TICKS - start) % REVOLUTION_TICKS;
+        if late < BYTE_TICKS {
+            return true;
+        }
+        self.delay = REVOLUTION_TICKS - late;
+        false
+    }
+
+    fn sector_offset(&self, index: usize) -> u64 {
+        let side_offset = if (self.command & CommandFlags::SIDE_SELECT) == 0 {
+            0
+        } else {
+            SECTOR_SIZE * NUM_SECTORS * NUM_TRACKS
+        };
+        let track_offset = (self.track as usize) * SECTOR_SIZE * NUM_SECTORS;
+        let sector_offset = index * SECTOR_SIZE;
+        (side_offset + track_offset + sector_offset) as u64
+    }
+
+    fn side(&self) -> u8 {
+        if (self.command & CommandFlags::SIDE_SELECT) == 0 {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+impl<T: Image> BusDevice for Fdc<T> {
+    fn reset(&mut self, _bus: &mut dyn Bus) {
+        self.state = State::Idle;
+        self.status = 0;
+        self.command = 0;
+        self.track = 0;
+        self.sector = 0;
+        self.data = 0;
+        self.buf.clear();
+        self.track_latch = 0;
+        self.track_target = 0;
+        self.sector_count = 0;
+        self.irq = false;
+        self.motor = 0;
+        self.head_loaded = false;
+        self.type_one = true;
+        self.delay = 0;
+        self.settled = false;
+        self.write_left = 0;
+        self.dma_control = 0;
+        self.dma_addr = 0;
+    }
+
+    fn tick(&mut self, bus: &mut dyn Bus) {
+        self.log.tick();
+        if self.motor > 0 {
+            self.rotation += 1;
+            if self.rotation == REVOLUTION_TICKS {
+                self.rotation = 0;
+                if matches!(self.state, State::Idle) {
+                    self.motor -= 1;
+                    if self.motor == 0 {
+                        self.head_loaded = false;
+                        if self.type_one {
+                            self.status &= !StatusFlags::HEAD_LOADED;
+                        }
+                    }
+                }
+            }
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        match self.state {
+            State::Idle => {}
+
+            State::Restore => {
+                if self.track > 0 {
+                    self.track -= 1;
+                    self.track_latch = self.track;
+                    self.delay = self.step_ticks();
+                } else if self.settle() {
+                    self.finish();
+                    self.status |= StatusFlags::TRACK_0;
+                }
+            }
+
+            State::Seek => {
+                if self.data > self.track {
+                    self.track += 1;
+                    self.track_latch = self.track;
+                    self.delay = self.step_ticks();
+                } else if self.data < self.track {
+                    self.track -= 1;
+                    self.track_latch = self.track;
+                    self.delay = self.step_ticks();
+                } else if self.settle() {
+                    self.finish();
+                }
+                if self.track == 0 {
+                    self.status |= StatusFlags::TRACK_0;
+                } else {
+                    self.status &= !StatusFlags::TRACK_0;
+                }
+            }
+
+            State::Step => {
+                if !self.settle() {
+                    return;
+                }
+                self.finish();
+                if self.track < self.track_target {
+                    self.track += 1;
+                } else if self.track > self.track_target {
+                    self.track -= 1;
+                }
+                if (self.command & CommandFlags::UPDATE_TRACK) != 0 {
+                    self.track_latch = self.track;
+                }
+                if self.track == 0 {
+                    self.status |= StatusFlags::TRACK_0;
+                } else {
+                    self.status &= !StatusFlags::TRACK_0;
+                }
+            }
+
+            State::ReadSector => {
+                if self.buf.is_empty() {
+                    if self.sector_count == 0 {
+                        self.finish();
+                        return;
+                    }
+                    let Some(index) = self.sector_index() else {
+                        self.not_found();
+                        return;
+                    };
+                    if !self.sector_under_head(index) {
+                        return;
+                    }
+                    let mut buf = vec![0; SECTOR_SIZE];
+                    let read = self
+                        .handle
+                        .seek(SeekFrom::Start(self.sector_offset(index)))
+                        .and_then(|_| self.handle.read_exact(&mut buf));
+                    if let Err(e) = read {
+                        // the closest the controller has to a bad read
+                        tracing::error!(
+                            target: "fdc",
+                            "failed to read track {}, sector {}: {e}",
+                            self.track,
+                            self.sector
+                        );
+                        self.status |= StatusFlags::CRC_ERROR;
+                        self.finish();
+                        return;
+                    }
+                    tracing::trace!(
+                        target: "fdc",
+                        "read track {}, side {}, sector {}",
+                        self.track,
+                        self.side(),
+                        self.sector
+                    );
+                    self.buf.extend(buf.drain(..));
+                    self.next_sector();
+                    self.sectors_read += 1;
+                    if self.dma() {
+                        // let the whole sector come off the disk first
+                        self.delay = SECTOR_SIZE as u32 * BYTE_TICKS;
+                        return;
+                    }
+                }
+                if self.dma() {
+                    for data in self.buf.drain(..) {
+                        bus.write(self.dma_addr, data);
+                        self.dma_addr = self.dma_addr.wrapping_add(1);
+                    }
+                    return;
+                }
+                // the last byte was never read before this one came off the disk
+                if (self.status & StatusFlags::DATA_REQUEST) != 0 {
+                    self.status |= StatusFlags::LOST_DATA;
+                }
+                self.data = self.buf.pop_front().unwrap();
+                self.status |= StatusFlags::DATA_REQUEST;
+                self.delay = BYTE_TICKS;
+            }
+
+            State::WriteSector => {
+                if self.write_left == 0 {
+                    if self.sector_count == 0 {
+                        self.finish();
+                        return;
+                    }
+                    let Some(index) = self.sector_index() else {
+                        self.not_found();
+                        return;
+                    };
+                    if !self.sector_under_head(index) {
+                        return;
+                    }
+                    self.buf.clear();
+                    self.write_offset = self.sector_offset(index);
+                    if self.dma() {
+                        for _ in 0..SECTOR_SIZE {
+                            self.buf.push_back(bus.read(self.dma_addr));
+                            self.dma_addr = self.dma_addr.wrapping_add(1);
+                        }
+                        // the buffer goes to the disk as the sector passes
+                        self.write_left = 1;
+                        self.delay = SECTOR_SIZE as u32 * BYTE_TICKS;
+                        return;
+                    }
+                    self.write_left = SECTOR_SIZE;
+                    self.status |= StatusFlags::DATA_REQUEST;
+                    self.delay = BYTE_TICKS;
+                    return;
+                }
+                // nothing was written in time, so zeros go to the disk
+                if (self.status & StatusFlags::DATA_REQUEST) != 0 {
+                    self.status |= StatusFlags::LOST_DATA;
+                    self.buf.push_back(0);
+                }
+                self.write_left -= 1;
+                if self.write_left > 0 {
+                    self.status |= StatusFlags::DATA_REQUEST;
+                    self.delay = BYTE_TICKS;
+                    return;
+                }
+                self.status &= !StatusFlags::DATA_REQUEST;
+                let mut buf = Vec::with_capacity(SECTOR_SIZE);
+                buf.extend(self.buf.drain(..));
+                let written = self
+                    .handle
+                    .seek(SeekFrom::Start(self.write_offset))
+                    .and_then(|_| self.handle.write_all(&buf))
+                    .and_then(|_| self.handle.flush());
+                if let Err(e) = written {
+                    tracing::error!(
+                        target: "fdc",
+                        "failed to write track {}, sector {}: {e}",
+                        self.track,
+                        self.sector
+                    );
+                    self.status |= StatusFlags::WRITE_FAULT;
+                    self.finish();
+                    return;
+                }
+                tracing::trace!(
+                    target: "fdc",
+                    "wrote track {}, side {}, sector {}",
+                    self.track,
+                    self.side(),
+                    self.sector
+                );
+                self.next_sector();
+                self.sectors_written += 1;
+            }
+
+            State::ReadAddress => {
+                if self.buf.is_empty() {
+                    if self.sector_count == 0 {
+                        // the ID's track ends up in the sector register
+                        self.sector = self.track;
+                        self.finish();
+                        return;
+                    }
+                    // wait for the next ID field to come round
+                    let late = self.rotation % SECTOR_TICKS;
+                    if late >= BYTE_TICKS {
+                        self.delay = SECTOR_TICKS - late;
+                        return;
+                    }
+                    let index = (self.rotation / SECTOR_TICKS) as u8;
+                    let id = [
+                        self.track,
+                        self.side(),
+                        self.first_sector.wrapping_add(index),
+                        SIZE_CODE,
+                    ];
+                    self.buf.extend(id);
+                    self.buf.extend(id_crc(&id).to_be_bytes());
+                    self.sector_count = 0;
+                }
+                if (self.status & StatusFlags::DATA_REQUEST) != 0 {
+                    self.status |= StatusFlags::LOST_DATA;
+                }
+                self.data = self.buf.pop_front().unwrap();
+                self.status |= StatusFlags::DATA_REQUEST;
+                self.delay = BYTE_TICKS;
+            }
+
+            State::NotFound => {
+                tracing::debug!(
+                    target: "fdc",
+                    "sector {} not found on track {}",
+                    self.sector,
+                    self.track
+                );
+                self.status |= StatusFlags::RECORD_NOT_FOUND;
+                self.finish();
+            }
+        }
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        let Some(data) = self.peek(addr) else {
+            tracing::warn!(target: "fdc", "read from register {addr}, which doesn't exist");
+            return 0;
+        };
+        match addr {
+            0 => self.irq = false,
+            3 => self.status &= !StatusFlags::DATA_REQUEST,
+            _ => {}
+        }
+        data
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0 => {
+                let mut status = self.status;
+                if self.type_one && self.motor > 0 && self.rotation < INDEX_PULSE_TICKS {
+                    status |= StatusFlags::INDEX;
+                }
+                Some(status)
+            }
+            1 => Some(self.track_latch),
+            2 => Some(self.sector),
+            3 => Some(self.data),
+            4 => Some(self.dma_control),
+            5 => Some(self.dma_addr as u8),
+            6 => Some((self.dma_addr >> 8) as u8),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0 => {
+                self.command = data;
+                self.irq = false;
+                let track = self.track;
+                let sector = self.sector;
+                let side = self.side();
+                self.log.log(format_args!(
+                    "{data:02X} {} (track {track}, side {side}, sector {sector})",
+                    command_name(data)
+                ));
+
+                self.motor = IDLE_REVOLUTIONS;
+                self.settled = false;
+                self.write_left = 0;
+                self.delay = 0;
+                self.type_one = data < 0x80 || (data & 0xF0) == 0xD0;
+                if data < 0x80 {
+                    self.head_loaded = (data & CommandFlags::HEAD_LOAD) != 0;
+                } else if !self.head_loaded || (data & CommandFlags::DELAY) != 0 {
+                    self.head_loaded = true;
+                    self.delay = SETTLE_TICKS;
+                }
+
+                match (data & 0b1110_0000) >> 5 {
+                    0 => {
+                        if (data & 0b0001_0000) == 0 {
+                            self.state = State::Restore;
+                        } else {
+                            self.state = State::Seek;
+                        }
+                        self.status |= StatusFlags::BUSY;
+                        if (data & CommandFlags::HEAD_LOAD) != 0 {
+                            self.status |= StatusFlags::HEAD_LOADED;
+                        } else {
+                            self.status &= !StatusFlags::HEAD_LOADED;
+                        }
+                    }
+                    1 => {
+                        self.state = State::Step;
+                        self.status |= StatusFlags::BUSY;
+                        self.track_target = self.data;
+                        if self.track_target > ((NUM_TRACKS - 1) as u8) {
+                            self.track_target = (NUM_TRACKS - 1) as u8;
+                        }
+                        if (data & CommandFlags::HEAD_LOAD) != 0 {
+                            self.status |= StatusFlags::HEAD_LOADED;
+                        } else {
+                            self.status &= !StatusFlags::HEAD_LOADED;
+                        }
+                    }
+                    2 => {
+                        self.state = State::Step;
+                        self.status |= StatusFlags::BUSY;
+                        self.track_target = self.track;
+                        if self.track < ((NUM_SECTORS - 1) as u8) {
+                            self.track_target += 1;
+                        }
+                        if (data & CommandFlags::HEAD_LOAD) != 0 {
+                            self.status |= StatusFlags::HEAD_LOADED;
+                        } else {
+                            self.status &= !StatusFlags::HEAD_LOADED;
+                        }
+                    }
+                    3 => {
+                        self.state = State::Step;
+                        self.status |= StatusFlags::BUSY;
+                        self.track_target = self.track;
+                        if self.track > 0 {
+                            self.track_target -= 1;
+                        }
+                        if (data & CommandFlags::HEAD_LOAD) != 0 {
+                            self.status |= StatusFlags::HEAD_LOADED;
+                        } else {
+                            self.status &= !StatusFlags::HEAD_LOADED;
+                        }
+                    }
+                    4 | 5 => {
+                        // todo: if disk is not READY, we should interrupt
+                        self.state = if (data & 0b0010_0000) == 0 {
+                            State::ReadSector
+                        } else {
+                            State::WriteSector
+                        };
+                        self.status = StatusFlags::BUSY;
+                        self.buf.clear();
+                        // the track running out is what stops multiple records
+                        self.sector_count = if (data & CommandFlags::MULTIPLE_RECORD) != 0 {
+                            u8::MAX
+                        } else {
+                            1
+                        };
+                    }
+                    6 => {
+                        if (data & 0b0001_0000) == 0 {
+                            self.state = State::ReadAddress;
+                            self.status = StatusFlags::BUSY;
+                            self.buf.clear();
+                            self.sector_count = 1;
+                        } else {
+                            // whatever is running stops where it is. only
+                            // the immediate interrupt condition is emulated
+                            self.state = State::Idle;
+                            self.status &= !StatusFlags::BUSY;
+                            if (data & CommandFlags::INTERRUPT_IMMEDIATE) != 0 {
+                                self.irq = true;
+                            }
+                            if (data & 0b0000_0111) != 0 {
+                                tracing::warn!(
+                                    target: "fdc",
+                                    "{data:02X} force interrupt conditions aren't emulated"
+                                );
+                            }
+                        }
+                    }
+                    7 => {
+                        tracing::warn!(
+                            target: "fdc",
+                            "{data:02X} {} isn't emulated",
+                            command_name(data)
+                        );
+                        self.status = 0;
+                        self.finish();
+                    }
+                    _ => unreachable!(),
+                }
+                if matches!(self.state, State::Step) {
+                    self.delay = self.step_ticks();
+                }
+            }
+
+            1 => self.track_latch = data,
+
+            // sectors that aren't on the track are only noticed by the search
+            2 => self.sector = data,
+
+            3 => {
+                // push data into output buffer during write
+                if matches!(self.state, State::WriteSector)
+                    && ((self.status & StatusFlags::DATA_REQUEST) != 0)
+                {
+                    self.buf.push_back(data);
+                }
+                self.status &= !StatusFlags::DATA_REQUEST;
+                self.data = data;
+            }
+
+            4 => self.dma_control = data & DmaFlags::ENABLE,
+            5 => self.dma_addr = (self.dma_addr & 0xFF00) | (data as u16),
+            6 => self.dma_addr = (self.dma_addr & 0x00FF) | ((data as u16) << 8),
+
+            _ => tracing::warn!(target: "fdc", "write to register {addr}, which doesn't exist"),
+        }
+    }
+
+    fn register_name(&self, addr: u16) -> Option<&'static str> {
+        match addr {
+            0 => Some("Command/Status"),
+            1 => Some("Track"),
+            2 => Some("Sector"),
+            3 => Some("Data"),
+            4 => Some("DMA Control"),
+            5 => Some("DMA Address Lo"),
+            6 => Some("DMA Address Hi"),
+            _ => None,
+        }
+    }
+
+    fn commit(&mut self) -> Option<Result<usize, String>> {
+        Some(self.handle.commit())
+    }
+
+    fn disk(&self) -> Option<DiskActivity> {
+        Some(DiskActivity {
+            track: self.track,
+            side: self.side(),
+            sector: self.sector,
+            busy: (self.status & StatusFlags::BUSY) != 0,
+            sectors_read: self.sectors_read,
+            sectors_written: self.sectors_written,
+        })
+    }
+
+    fn irq(&self) -> bool {
+        self.irq
+    }
+
+    fn drq(&self) -> bool {
+        (self.status & StatusFlags::DATA_REQUEST) != 0
+    }
+}
+
+fn command_name(command: u8) -> &'static str {
+    match command >> 4 {
+        0x0 => "restore",
+        0x1 => "seek",
+        0x2 | 0x3 => "step",
+        0x4 | 0x5 => "step in",
+        0x6 | 0x7 => "step out",
+        0x8 | 0x9 => "read sector",
+        0xA | 0xB => "write sector",
+        0xC => "read address",
+        0xD => "force interrupt",
+        0xE => "read track",
+        _ => "write track",
+    }
+}
+
+/// CRC-CCITT of an ID field, which starts from the address mark
+fn id_crc(id: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &byte in [0xA1, 0xA1, 0xA1, 0xFE].iter().chain(id) {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if (crc & 0x8000) != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
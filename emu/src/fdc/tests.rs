@@ -0,0 +1,211 @@
+use std::io::Cursor;
+
+use super::*;
+use crate::bus::test::{NoBus, Ram};
+
+const IMAGE_SIZE: usize = 2 * NUM_TRACKS * NUM_SECTORS * SECTOR_SIZE;
+
+impl Image for Cursor<Vec<u8>> {
+    fn commit(&mut self) -> Result<usize, String> {
+        Err("no overlay".to_string())
+    }
+}
+
+/// Every sector filled with its index in the image
+fn image() -> Cursor<Vec<u8>> {
+    let mut image = vec![0; IMAGE_SIZE];
+    for (i, sector) in image.chunks_mut(SECTOR_SIZE).enumerate() {
+        sector.fill(i as u8);
+    }
+    Cursor::new(image)
+}
+
+/// Run a command to completion, reading every byte the controller offers
+/// (or writing `write` a byte at a time). Returns what was read and the
+/// final status.
+fn run(fdc: &mut Fdc<Cursor<Vec<u8>>>, command: u8, write: &[u8]) -> (Vec<u8>, u8) {
+    let mut read = Vec::new();
+    let mut write = write.iter();
+    fdc.write(0, command);
+    for _ in 0..(20 * REVOLUTION_TICKS) {
+        fdc.tick(&mut NoBus);
+        if fdc.drq() {
+            if matches!(fdc.state, State::WriteSector) {
+                fdc.write(3, *write.next().unwrap_or(&0));
+            } else {
+                read.push(fdc.read(3));
+            }
+        }
+        if (fdc.read(0) & StatusFlags::BUSY) == 0 {
+            return (read, fdc.read(0));
+        }
+    }
+    panic!("command {command:02X} never finished");
+}
+
+#[test]
+fn read_single_sector() {
+    let mut fdc = Fdc::new(image(), 0);
+    fdc.write(2, 5);
+    let (data, status) = run(&mut fdc, 0x80, &[]);
+    assert_eq!(data, [5; SECTOR_SIZE]);
+    assert_eq!(
+        status & (StatusFlags::LOST_DATA | StatusFlags::RECORD_NOT_FOUND),
+        0
+    );
+    assert_eq!(fdc.read(2), 5);
+}
+
+#[test]
+fn multiple_records_stop_at_the_end_of_the_track() {
+    let mut fdc = Fdc::new(image(), 0);
+    fdc.write(2, 14);
+    let (data, status) = run(&mut fdc, 0x90, &[]);
+    assert_eq!(data.len(), 2 * SECTOR_SIZE);
+    assert_eq!(data[0], 14);
+    assert_eq!(data[SECTOR_SIZE], 15);
+    assert_ne!(status & StatusFlags::RECORD_NOT_FOUND, 0);
+    assert_eq!(fdc.read(2), NUM_SECTORS as u8);
+}
+
+#[test]
+fn sector_numbering_base() {
+    let mut fdc = Fdc::new(image(), 1);
+    fdc.write(2, 1);
+    let (data, _) = run(&mut fdc, 0x80, &[]);
+    assert_eq!(data, [0; SECTOR_SIZE]);
+
+    fdc.write(2, 0);
+    let (data, status) = run(&mut fdc, 0x80, &[]);
+    assert!(data.is_empty());
+    assert_ne!(status & StatusFlags::RECORD_NOT_FOUND, 0);
+
+    fdc.write(2, 16);
+    let (data, _) = run(&mut fdc, 0x80, &[]);
+    assert_eq!(data, [15; SECTOR_SIZE]);
+}
+
+#[test]
+fn wrong_track_is_not_found() {
+    let mut fdc = Fdc::new(image(), 0);
+    fdc.write(1, 3);
+    let (data, status) = run(&mut fdc, 0x80, &[]);
+    assert!(data.is_empty());
+    assert_ne!(status & StatusFlags::RECORD_NOT_FOUND, 0);
+}
+
+#[test]
+fn side_select() {
+    let mut fdc = Fdc::new(image(), 0);
+    fdc.write(2, 2);
+    let (data, _) = run(&mut fdc, 0x88, &[]);
+    assert_eq!(data, [(NUM_TRACKS * NUM_SECTORS + 2) as u8; SECTOR_SIZE]);
+}
+
+#[test]
+fn write_sectors() {
+    let mut fdc = Fdc::new(image(), 0);
+    fdc.write(2, 15);
+    let written = (0..SECTOR_SIZE).map(|i| i as u8).collect::<Vec<u8>>();
+    let (_, status) = run(&mut fdc, 0xA0, &written);
+    assert_eq!(status & StatusFlags::LOST_DATA, 0);
+    let image = fdc.handle.get_ref();
+    assert_eq!(image[15 * SECTOR_SIZE..16 * SECTOR_SIZE], written);
+    assert_eq!(image[14 * SECTOR_SIZE], 14);
+    assert_eq!(image[16 * SECTOR_SIZE], 16);
+}
+
+#[test]
+fn read_address() {
+    let mut fdc = Fdc::new(image(), 1);
+    fdc.write(3, 7);
+    run(&mut fdc, 0x17, &[]);
+    let (id, _) = run(&mut fdc, 0xC0, &[]);
+    assert_eq!(id.len(), 6);
+    assert_eq!(id[0], 7);
+    assert!((1..=16).contains(&id[2]));
+    assert_eq!(id[3], SIZE_CODE);
+    assert_eq!(u16::from_be_bytes([id[4], id[5]]), id_crc(&id[..4]));
+    assert_eq!(fdc.read(2), 7);
+}
+
+#[test]
+fn slow_reads_lose_data() {
+    let mut fdc = Fdc::new(image(), 0);
+    fdc.write(0, 0x80);
+    for _ in 0..(2 * REVOLUTION_TICKS) {
+        fdc.tick(&mut NoBus);
+    }
+    assert_ne!(fdc.read(0) & StatusFlags::LOST_DATA, 0);
+}
+
+#[test]
+fn force_interrupt_stops_a_command() {
+    let mut fdc = Fdc::new(image(), 0);
+    fdc.write(0, 0x80);
+    fdc.tick(&mut NoBus);
+    assert!(!fdc.irq());
+    fdc.write(0, 0xD8);
+    assert!(fdc.irq());
+    assert_eq!(fdc.read(0) & StatusFlags::BUSY, 0);
+}
+
+#[test]
+fn short_images_fail_reads() {
+    let mut fdc = Fdc::new(Cursor::new(vec![0; SECTOR_SIZE]), 0);
+    fdc.write(2, 1);
+    let (data, status) = run(&mut fdc, 0x80, &[]);
+    assert!(data.is_empty());
+    assert_ne!(status & StatusFlags::CRC_ERROR, 0);
+}
+
+#[test]
+fn id_crc_matches_the_datasheet() {
+    // track 0, side 0, sector 1, 512 byte sectors (an IBM PC's first ID)
+    assert_eq!(id_crc(&[0, 0, 1, 2]), 0xCA6F);
+}
+
+fn run_dma(fdc: &mut Fdc<Cursor<Vec<u8>>>, ram: &mut Ram, command: u8) -> u8 {
+    fdc.write(4, DmaFlags::ENABLE);
+    fdc.write(5, 0x00);
+    fdc.write(6, 0x20);
+    fdc.write(0, command);
+    for _ in 0..(20 * REVOLUTION_TICKS) {
+        fdc.tick(ram);
+        assert!(!fdc.drq());
+        if fdc.irq() {
+            return fdc.read(0);
+        }
+    }
+    panic!("command {command:02X} never finished");
+}
+
+#[test]
+fn dma_reads() {
+    let mut fdc = Fdc::new(image(), 0);
+    let mut ram = Ram::new(0);
+    fdc.write(2, 14);
+    let status = run_dma(&mut fdc, &mut ram, 0x90);
+    assert_ne!(status & StatusFlags::RECORD_NOT_FOUND, 0);
+    assert_eq!(ram.data[0x2000..0x2100], [14; SECTOR_SIZE]);
+    assert_eq!(ram.data[0x2100..0x2200], [15; SECTOR_SIZE]);
+    assert_eq!(ram.data[0x2200], 0);
+    assert_eq!((fdc.read(5), fdc.read(6)), (0x00, 0x22));
+    // reading status acknowledges the interrupt
+    assert!(!fdc.irq());
+}
+
+#[test]
+fn dma_writes() {
+    let mut fdc = Fdc::new(image(), 0);
+    let mut ram = Ram::from((0..0x10000).map(|i| (i / 3) as u8).collect::<Vec<_>>());
+    fdc.write(2, 4);
+    let status = run_dma(&mut fdc, &mut ram, 0xA0);
+    assert_eq!(status & StatusFlags::LOST_DATA, 0);
+    let image = fdc.handle.get_ref();
+    assert_eq!(
+        image[4 * SECTOR_SIZE..5 * SECTOR_SIZE],
+        ram.data[0x2000..0x2100]
+    );
+    assert_eq!(image[5 * SECTOR_SIZE], 5);
+}
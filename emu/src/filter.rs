@@ -0,0 +1,106 @@
+//! Display Filters
+//!
+//! Optional CRT-ish post-processing for frames on their way to the host
+//! (screenshots and recordings). The PPU's own output stays exact, so
+//! golden frame hashes don't depend on them.
+//! * `scanlines` darkens every other row, like the gaps between a CRT's lines
+//! * `ntsc` blurs each row, chroma more than luma, like composite video
+
+use std::{fmt, str::FromStr};
+
+use crate::bus::Frame;
+
+/// How bright the rows between scanlines are
+const SCANLINE_BRIGHTNESS: f32 = 0.6;
+/// Horizontal blur kernels, luma sharper than chroma
+const LUMA_TAPS: [f32; 3] = [0.25, 0.5, 0.25];
+const CHROMA_TAPS: [f32; 5] = [1.0 / 9.0, 2.0 / 9.0, 3.0 / 9.0, 2.0 / 9.0, 1.0 / 9.0];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Scanlines,
+    Ntsc,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scanlines" => Ok(Filter::Scanlines),
+            "ntsc" => Ok(Filter::Ntsc),
+            _ => Err(format!("expected `scanlines` or `ntsc`: `{s}`")),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Filter::Scanlines => "scanlines",
+            Filter::Ntsc => "ntsc",
+        })
+    }
+}
+
+/// The frame's pixels with the filters applied in order
+pub fn apply(filters: &[Filter], frame: &Frame) -> Vec<u32> {
+    let mut pixels = frame.pixels.to_vec();
+    for filter in filters {
+        for (y, row) in pixels.chunks_mut(frame.width).enumerate() {
+            match filter {
+                Filter::Scanlines if (y % 2) == 1 => scanline(row),
+                Filter::Scanlines => {}
+                Filter::Ntsc => ntsc(row),
+            }
+        }
+    }
+    pixels
+}
+
+fn scanline(row: &mut [u32]) {
+    for pixel in row {
+        let [_, r, g, b] = pixel.to_be_bytes();
+        let [r, g, b] = [r, g, b].map(|c| (c as f32 * SCANLINE_BRIGHTNESS) as u8);
+        *pixel = u32::from_be_bytes([0, r, g, b]);
+    }
+}
+
+fn ntsc(row: &mut [u32]) {
+    let yiq = row.iter().map(|&pixel| to_yiq(pixel)).collect::<Vec<_>>();
+    let last = yiq.len() - 1;
+    let blur = |taps: &[f32], x: usize, channel: usize| {
+        let reach = taps.len() / 2;
+        taps.iter().enumerate().fold(0.0, |sum, (i, tap)| {
+            // the edges repeat outwards
+            let x = (x + i).saturating_sub(reach).min(last);
+            sum + tap * yiq[x][channel]
+        })
+    };
+    for (x, pixel) in row.iter_mut().enumerate() {
+        *pixel = from_yiq([
+            blur(&LUMA_TAPS, x, 0),
+            blur(&CHROMA_TAPS, x, 1),
+            blur(&CHROMA_TAPS, x, 2),
+        ]);
+    }
+}
+
+fn to_yiq(pixel: u32) -> [f32; 3] {
+    let [_, r, g, b] = pixel.to_be_bytes().map(|c| c as f32);
+    [
+        0.299 * r + 0.587 * g + 0.114 * b,
+        0.596 * r - 0.274 * g - 0.322 * b,
+        0.211 * r - 0.523 * g + 0.312 * b,
+    ]
+}
+
+fn from_yiq([y, i, q]: [f32; 3]) -> u32 {
+    let [r, g, b] = [
+        y + 0.956 * i + 0.621 * q,
+        y - 0.272 * i - 0.647 * q,
+        y - 1.106 * i + 1.703 * q,
+    ]
+    .map(|c| c.round().clamp(0.0, 255.0) as u8);
+    u32::from_be_bytes([0, r, g, b])
+}
@@ -0,0 +1,78 @@
+//! Golden State Hashes
+//!
+//! For checking changes to the emulator against a corpus of known-good
+//! runs. `--golden FILE` hashes the CPU registers and counters, every bank
+//! of RAM, and VRAM when the run ends, and fails the run (exit status 1)
+//! if any of them differ from the hashes saved in FILE. `--bless` saves
+//! the hashes instead. With `--golden`, running out of `--max-cycles` ends
+//! the run normally, so a run can be pinned to the first instruction
+//! boundary after an exact number of cycles.
+//!
+//! The hashes are FNV-1a, which doesn't change between hosts or Rust
+//! versions. A golden file has a line per part:
+//!
+//! ```text
+//! cpu  6C62272E07BB0142
+//! ram  1F1B3B9A5C3EC05D
+//! vram CBF29CE484222325
+//! ```
+
+use std::{fmt::Write as _, fs, path::Path};
+
+use crate::sys::System;
+
+const FNV_OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(FNV_OFFSET, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// The hash of each part of the system's state
+fn hashes(sys: &System) -> Vec<(&'static str, u64)> {
+    let cpu = sys.cpu();
+    let mut regs = vec![cpu.a(), cpu.b(), cpu.x(), cpu.y(), cpu.z(), cpu.p()];
+    regs.extend(cpu.sp().to_le_bytes());
+    regs.extend(cpu.pc().to_le_bytes());
+    regs.extend(cpu.cycles().to_le_bytes());
+    regs.extend(cpu.instructions().to_le_bytes());
+    vec![
+        ("cpu", fnv1a(&regs)),
+        ("ram", fnv1a(sys.mem().ram())),
+        ("vram", fnv1a(sys.vram().unwrap_or_default())),
+    ]
+}
+
+/// Compare the system against the golden file at `path`, or overwrite
+/// the file when blessing
+pub fn check(sys: &System, path: &Path, bless: bool) -> Result<(), String> {
+    let hashes = hashes(sys);
+    if bless {
+        let mut text = String::new();
+        for (part, hash) in &hashes {
+            writeln!(text, "{part:4} {hash:016X}").unwrap();
+        }
+        return fs::write(path, text).map_err(|e| format!("{}: {e}", path.display()));
+    }
+
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut mismatches = Vec::new();
+    for (part, hash) in hashes {
+        let golden = text
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .find(|(name, _)| *name == part)
+            .and_then(|(_, golden)| u64::from_str_radix(golden.trim(), 16).ok());
+        match golden {
+            Some(golden) if golden == hash => {}
+            Some(golden) => mismatches.push(format!("{part} is {hash:016X}, not {golden:016X}")),
+            None => mismatches.push(format!("no {part} hash")),
+        }
+    }
+    if !mismatches.is_empty() {
+        return Err(format!("{}: {}", path.display(), mismatches.join(", ")));
+    }
+    Ok(())
+}
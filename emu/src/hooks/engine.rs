@@ -0,0 +1,304 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    path::Path,
+    ptr,
+    rc::Rc,
+};
+
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, FuncArgs, AST, INT};
+
+use crate::{
+    debugger::Breakpoints,
+    sys::{Access, System},
+};
+
+type Result<T> = std::result::Result<T, Box<EvalAltResult>>;
+
+/// The system the script is running against. Only set while
+/// [`Script::lend`] holds the caller's `&mut System`.
+type Current = Rc<Cell<*mut System>>;
+
+#[derive(Default)]
+struct Callbacks {
+    breaks: HashMap<u16, Vec<FnPtr>>,
+    reads: HashMap<u16, Vec<FnPtr>>,
+    writes: HashMap<u16, Vec<FnPtr>>,
+    frames: Vec<FnPtr>,
+    ticks: Vec<FnPtr>,
+    stop: bool,
+}
+
+struct Script {
+    engine: Engine,
+    ast: AST,
+    current: Current,
+    callbacks: Rc<RefCell<Callbacks>>,
+    /// The `on_break` addresses, so the run loop can check the PC
+    /// without a lookup
+    breaks: Breakpoints,
+    last_frame: u64,
+    /// Where a callback stopped, so resuming doesn't run it again
+    resume_pc: Option<u16>,
+}
+
+pub struct Hooks {
+    script: Option<Script>,
+}
+
+impl Hooks {
+    pub fn new() -> Self {
+        Self { script: None }
+    }
+
+    /// Run the script at `path`, keeping the callbacks it registers
+    pub fn load(&mut self, path: &Path, sys: &mut System) -> std::result::Result<(), String> {
+        let current = Current::new(Cell::new(ptr::null_mut()));
+        let callbacks = Rc::new(RefCell::new(Callbacks::default()));
+        let engine = engine(&current, &callbacks);
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        let mut script = Script {
+            engine,
+            ast,
+            current,
+            callbacks,
+            breaks: Breakpoints::new(),
+            last_frame: sys.frame().map_or(0, |frame| frame.number),
+            resume_pc: None,
+        };
+        script
+            .lend(sys, |script| script.engine.run_ast(&script.ast))
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        script.sync(sys);
+        self.script = Some(script);
+        Ok(())
+    }
+
+    /// Run the `on_break` callbacks for the instruction about to run
+    #[inline]
+    pub fn before(&mut self, sys: &mut System) {
+        let Some(script) = &mut self.script else {
+            return;
+        };
+        let pc = sys.cpu().pc();
+        if !script.breaks.contains(pc) || script.resume_pc.take() == Some(pc) {
+            return;
+        }
+        let callbacks = script.callbacks.borrow().breaks[&pc].clone();
+        script.lend(sys, |script| {
+            for callback in &callbacks {
+                script.call(callback, (pc as INT,));
+            }
+        });
+        script.sync(sys);
+        if script.callbacks.borrow().stop {
+            script.resume_pc = Some(pc);
+        }
+    }
+
+    /// Run the callbacks for what the last instruction did
+    #[inline]
+    pub fn after(&mut self, sys: &mut System, accesses: &[Access]) {
+        let Some(script) = &mut self.script else {
+            return;
+        };
+        let frame = sys
+            .frame()
+            .map(|frame| frame.number)
+            .filter(|&number| number != script.last_frame);
+        let ticks = !script.callbacks.borrow().ticks.is_empty();
+        if accesses.is_empty() && frame.is_none() && !ticks {
+            return;
+        }
+
+        let callbacks = script.callbacks.clone();
+        script.lend(sys, |script| {
+            for access in accesses {
+                let list = if access.write {
+                    callbacks.borrow().writes.get(&access.addr).cloned()
+                } else {
+                    callbacks.borrow().reads.get(&access.addr).cloned()
+                };
+                for callback in list.iter().flatten() {
+                    script.call(callback, (access.addr as INT, access.data as INT));
+                }
+            }
+            if let Some(number) = frame {
+                let list = callbacks.borrow().frames.clone();
+                for callback in &list {
+                    script.call(callback, (number as INT,));
+                }
+            }
+            if ticks {
+                let list = callbacks.borrow().ticks.clone();
+                let cycles = with_sys(&script.current, |sys| sys.cpu().cycles()).unwrap_or(0);
+                for callback in &list {
+                    script.call(callback, (cycles as INT,));
+                }
+            }
+        });
+        if let Some(number) = frame {
+            script.last_frame = number;
+        }
+        script.sync(sys);
+    }
+
+    /// A callback asked to stop
+    pub fn stopping(&self) -> bool {
+        self.script
+            .as_ref()
+            .is_some_and(|script| script.callbacks.borrow().stop)
+    }
+
+    pub fn take_stop(&mut self) -> bool {
+        self.script.as_mut().is_some_and(|script| {
+            let mut callbacks = script.callbacks.borrow_mut();
+            let stop = callbacks.stop;
+            callbacks.stop = false;
+            stop
+        })
+    }
+}
+
+impl Script {
+    fn lend<T>(&mut self, sys: &mut System, f: impl FnOnce(&Self) -> T) -> T {
+        /// Takes the system back even if the script panics
+        struct Lent<'a>(&'a Current);
+
+        impl Drop for Lent<'_> {
+            fn drop(&mut self) {
+                self.0.set(ptr::null_mut());
+            }
+        }
+
+        self.current.set(sys);
+        let _lent = Lent(&self.current);
+        f(self)
+    }
+
+    fn call(&self, callback: &FnPtr, args: impl FuncArgs) {
+        if let Err(e) = callback.call::<Dynamic>(&self.engine, &self.ast, args) {
+            tracing::error!("hook {} failed: {e}", callback.fn_name());
+            self.callbacks.borrow_mut().stop = true;
+        }
+    }
+
+    /// Pick up callbacks registered since the last sync
+    fn sync(&mut self, sys: &mut System) {
+        let callbacks = self.callbacks.borrow();
+        for &addr in callbacks.breaks.keys() {
+            self.breaks.add(addr, false);
+        }
+        for &addr in callbacks.reads.keys().chain(callbacks.writes.keys()) {
+            sys.hook_accesses(addr);
+        }
+    }
+}
+
+fn with_sys<T>(current: &Current, f: impl FnOnce(&mut System) -> T) -> Result<T> {
+    let sys = current.get();
+    if sys.is_null() {
+        return Err("the system is out of reach outside the script and its hooks".into());
+    }
+    // SAFETY: `current` is only set while `Script::lend` holds the
+    // caller's `&mut System`, which nothing else touches until the
+    // script returns, and no reference made here outlives `f`
+    Ok(f(unsafe { &mut *sys }))
+}
+
+fn addr(value: INT) -> Result<u16> {
+    u16::try_from(value).map_err(|_| format!("address out of range: {value}").into())
+}
+
+fn engine(current: &Current, callbacks: &Rc<RefCell<Callbacks>>) -> Engine {
+    let mut engine = Engine::new();
+
+    let cb = callbacks.clone();
+    engine.register_fn("on_break", move |at: INT, callback: FnPtr| -> Result<()> {
+        let at = addr(at)?;
+        cb.borrow_mut().breaks.entry(at).or_default().push(callback);
+        Ok(())
+    });
+    let cb = callbacks.clone();
+    engine.register_fn("on_read", move |at: INT, callback: FnPtr| -> Result<()> {
+        let at = addr(at)?;
+        cb.borrow_mut().reads.entry(at).or_default().push(callback);
+        Ok(())
+    });
+    let cb = callbacks.clone();
+    engine.register_fn("on_write", move |at: INT, callback: FnPtr| -> Result<()> {
+        let at = addr(at)?;
+        cb.borrow_mut().writes.entry(at).or_default().push(callback);
+        Ok(())
+    });
+    let cb = callbacks.clone();
+    engine.register_fn("on_frame", move |callback: FnPtr| {
+        cb.borrow_mut().frames.push(callback);
+    });
+    let cb = callbacks.clone();
+    engine.register_fn("on_tick", move |callback: FnPtr| {
+        cb.borrow_mut().ticks.push(callback);
+    });
+    let cb = callbacks.clone();
+    engine.register_fn("stop", move || {
+        cb.borrow_mut().stop = true;
+    });
+
+    let cur = current.clone();
+    engine.register_fn("peek", move |at: INT| -> Result<INT> {
+        let at = addr(at)?;
+        with_sys(&cur, |sys| sys.mem().read(at) as INT)
+    });
+    let cur = current.clone();
+    engine.register_fn("poke", move |at: INT, data: INT| -> Result<()> {
+        let at = addr(at)?;
+        with_sys(&cur, |sys| sys.mem_mut().write(at, data as u8))
+    });
+    let cur = current.clone();
+    engine.register_fn("reg", move |name: &str| -> Result<INT> {
+        with_sys(&cur, |sys| {
+            let cpu = sys.cpu();
+            Ok(match name {
+                "a" => cpu.a() as INT,
+                "b" => cpu.b() as INT,
+                "x" => cpu.x() as INT,
+                "y" => cpu.y() as INT,
+                "z" => cpu.z() as INT,
+                "p" => cpu.p() as INT,
+                "sp" => cpu.sp() as INT,
+                "pc" => cpu.pc() as INT,
+                _ => return Err(format!("no register called {name}").into()),
+            })
+        })?
+    });
+    let cur = current.clone();
+    engine.register_fn("set_reg", move |name: &str, value: INT| -> Result<()> {
+        with_sys(&cur, |sys| {
+            let cpu = sys.cpu_mut();
+            match name {
+                "a" => cpu.set_a(value as u8),
+                "b" => cpu.set_b(value as u8),
+                "x" => cpu.set_x(value as u8),
+                "y" => cpu.set_y(value as u8),
+                "z" => cpu.set_z(value as u8),
+                "p" => cpu.set_flags(value as u8),
+                "sp" => cpu.set_sp(value as u16),
+                "pc" => cpu.set_pc(value as u16),
+                _ => return Err(format!("no register called {name}").into()),
+            }
+            Ok(())
+        })?
+    });
+    let cur = current.clone();
+    engine.register_fn("cycles", move || -> Result<INT> {
+        with_sys(&cur, |sys| sys.cpu().cycles() as INT)
+    });
+    let cur = current.clone();
+    engine.register_fn("instructions", move || -> Result<INT> {
+        with_sys(&cur, |sys| sys.cpu().instructions() as INT)
+    });
+
+    engine
+}
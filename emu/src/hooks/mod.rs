@@ -0,0 +1,62 @@
+//! Script Hooks
+//!
+//! With the `scripting` feature, `--hooks FILE` runs a Rhai script that
+//! registers callbacks on the running system, for trainers, automated
+//! tests, and instrumentation without rebuilding the emulator. The script
+//! runs once after reset, and its callbacks then run as the guest does:
+//!
+//! on_break(addr, |pc| ...)          before the instruction at addr runs
+//! on_read(addr, |addr, data| ...)   after the CPU reads addr
+//! on_write(addr, |addr, data| ...)  after the CPU writes addr
+//! on_frame(|number| ...)            after the PPU finishes a frame
+//! on_tick(|cycles| ...)             after every instruction
+//!
+//! The script and its callbacks can use:
+//!
+//! peek(addr), poke(addr, data)      read and write memory (writes only reach RAM)
+//! reg(name), set_reg(name, value)   read and write a register (a b x y z p sp pc)
+//! cycles(), instructions()          the CPU's counters
+//! stop()                            stop in the debugger once the callback returns
+//!
+//! A callback that fails is logged and stops in the debugger too.
+
+#[cfg(feature = "scripting")]
+mod engine;
+
+#[cfg(feature = "scripting")]
+pub use engine::Hooks;
+
+#[cfg(not(feature = "scripting"))]
+pub use disabled::Hooks;
+
+#[cfg(not(feature = "scripting"))]
+mod disabled {
+    use std::path::Path;
+
+    use crate::sys::{Access, System};
+
+    /// Stands in for the hooks when there is no script engine
+    pub struct Hooks;
+
+    impl Hooks {
+        pub fn new() -> Self {
+            Self
+        }
+
+        pub fn load(&mut self, _path: &Path, _sys: &mut System) -> Result<(), String> {
+            Err("built without the `scripting` feature".to_string())
+        }
+
+        pub fn before(&mut self, _sys: &mut System) {}
+
+        pub fn after(&mut self, _sys: &mut System, _accesses: &[Access]) {}
+
+        pub fn stopping(&self) -> bool {
+            false
+        }
+
+        pub fn take_stop(&mut self) -> bool {
+            false
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! Host File Mailbox
+//!
+//! Not real hardware: `--host-dir DIR` fits this device so guest software
+//! can use files in DIR before there is a floppy filesystem to keep them
+//! on. One file is open at a time, and paths can't leave DIR.
+//!
+//! Registers:
+//!
+//! 0 Command (Writes run a [`Command`])
+//! 1 Status (A [`Status`] for the last command or data access)
+//! 2 Data (With no file open, writes spell the path for Open/Create. With
+//!   one open, reads and writes go to the file)
+//! 3 Position 0 (32-bit file position, low byte first. Seek moves to it,
+//!   and every command leaves it where the file is)
+//! 4 Position 1
+//! 5 Position 2
+//! 6 Position 3
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use crate::bus::{Bus, BusDevice};
+
+pub const BASE: u16 = 0xF058;
+pub const SIZE: u16 = 7;
+
+/// Longest path the guest can spell
+const PATH_SIZE: usize = 256;
+
+pub enum Command {}
+
+impl Command {
+    /// Open the named file for reading, and writing if the host allows it
+    pub const OPEN: u8 = 1;
+    /// Open the named file for writing, emptying or creating it
+    pub const CREATE: u8 = 2;
+    pub const SEEK: u8 = 3;
+    pub const CLOSE: u8 = 4;
+}
+
+pub enum Status {}
+
+impl Status {
+    pub const OK: u8 = 0;
+    pub const ERROR: u8 = 1;
+    /// A read ran off the end of the file (and returned 0)
+    pub const EOF: u8 = 2;
+}
+
+pub struct HostFs {
+    dir: PathBuf,
+    path: Vec<u8>,
+    file: Option<File>,
+    status: u8,
+    position: u32,
+}
+
+impl HostFs {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            path: Vec::new(),
+            file: None,
+            status: Status::OK,
+            position: 0,
+        }
+    }
+
+    /// The host path for what the guest spelled, if it stays inside `dir`
+    fn host_path(&self) -> Option<PathBuf> {
+        let path = Path::new(std::str::from_utf8(&self.path).ok()?);
+        let inside = path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        (inside && !self.path.is_empty()).then(|| self.dir.join(path))
+    }
+
+    fn open(&mut self, create: bool) -> Result<(), ()> {
+        self.file = None;
+        let path = self.host_path().ok_or_else(|| {
+            tracing::warn!(target: "hostfs", "invalid path {:?}", String::from_utf8_lossy(&self.path));
+        })?;
+        let file = if create {
+            File::create(&path)
+        } else {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .or_else(|_| File::open(&path))
+        };
+        let file = file.map_err(|e| {
+            tracing::warn!(target: "hostfs", "failed to open {}: {e}", path.display());
+        })?;
+        tracing::debug!(target: "hostfs", "opened {}", path.display());
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn command(&mut self, command: u8) -> Result<(), ()> {
+        match command {
+            Command::OPEN | Command::CREATE => {
+                let result = self.open(command == Command::CREATE);
+                self.path.clear();
+                result?;
+            }
+            Command::SEEK => {
+                let file = self.file.as_mut().ok_or(())?;
+                file.seek(SeekFrom::Start(self.position as u64))
+                    .map_err(|_| ())?;
+            }
+            Command::CLOSE => {
+                self.path.clear();
+                self.position = 0;
+                return self.file.take().map(drop).ok_or(());
+            }
+            _ => {
+                tracing::warn!(target: "hostfs", "unknown command {command:02X}");
+                return Err(());
+            }
+        }
+        self.update_position()
+    }
+
+    fn update_position(&mut self) -> Result<(), ()> {
+        let file = self.file.as_mut().ok_or(())?;
+        let position = file.stream_position().map_err(|_| ())?;
+        self.position = position as u32;
+        Ok(())
+    }
+
+    fn read_data(&mut self) -> u8 {
+        let Some(file) = &mut self.file else {
+            self.status = Status::ERROR;
+            return 0;
+        };
+        let mut byte = [0];
+        self.status = match file.read(&mut byte) {
+            Ok(0) => Status::EOF,
+            Ok(_) => Status::OK,
+            Err(_) => Status::ERROR,
+        };
+        byte[0]
+    }
+
+    fn write_data(&mut self, data: u8) {
+        let Some(file) = &mut self.file else {
+            if self.path.len() < PATH_SIZE {
+                self.path.push(data);
+            }
+            return;
+        };
+        self.status = match file.write_all(&[data]) {
+            Ok(()) => Status::OK,
+            Err(_) => Status::ERROR,
+        };
+    }
+}
+
+impl BusDevice for HostFs {
+    fn reset(&mut self, _bus: &mut dyn Bus) {
+        *self = Self::new(std::mem::take(&mut self.dir));
+    }
+
+    fn tick(&mut self, _bus: &mut dyn Bus) {}
+
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0 => 0,
+            2 => self.read_data(),
+            1 | 3..=6 => self.peek(addr).unwrap(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            1 => Some(self.status),
+            3..=6 => Some(self.position.to_le_bytes()[addr as usize - 3]),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0 => {
+                self.status = match self.command(data) {
+                    Ok(()) => Status::OK,
+                    Err(()) => Status::ERROR,
+                }
+            }
+            1 => {}
+            2 => self.write_data(data),
+            3..=6 => {
+                let mut position = self.position.to_le_bytes();
+                position[addr as usize - 3] = data;
+                self.position = u32::from_le_bytes(position);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn register_name(&self, addr: u16) -> Option<&'static str> {
+        match addr {
+            0 => Some("Command"),
+            1 => Some("Status"),
+            2 => Some("Data"),
+            3 => Some("Position 0"),
+            4 => Some("Position 1"),
+            5 => Some("Position 2"),
+            6 => Some("Position 3"),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
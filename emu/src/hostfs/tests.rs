@@ -0,0 +1,57 @@
+use std::{env, fs};
+
+use super::*;
+use crate::bus::test::NoBus;
+
+fn host_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("possum2-hostfs-{}-{name}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run(fs: &mut HostFs, command: u8, path: &str) -> u8 {
+    for &byte in path.as_bytes() {
+        fs.write(2, byte);
+    }
+    fs.write(0, command);
+    fs.read(1)
+}
+
+#[test]
+fn files_round_trip_through_the_mailbox() {
+    let dir = host_dir("round-trip");
+    let mut fs = HostFs::new(dir.clone());
+    fs.reset(&mut NoBus);
+
+    assert_eq!(run(&mut fs, Command::CREATE, "hello.txt"), Status::OK);
+    for &byte in b"hello" {
+        fs.write(2, byte);
+    }
+    assert_eq!(run(&mut fs, Command::CLOSE, ""), Status::OK);
+    assert_eq!(fs::read(dir.join("hello.txt")).unwrap(), b"hello");
+
+    assert_eq!(run(&mut fs, Command::OPEN, "hello.txt"), Status::OK);
+    fs.write(3, 3);
+    assert_eq!(run(&mut fs, Command::SEEK, ""), Status::OK);
+    assert_eq!(fs.read(2), b'l');
+    assert_eq!(fs.read(2), b'o');
+    assert_eq!(fs.read(1), Status::OK);
+    fs.read(2);
+    assert_eq!(fs.read(1), Status::EOF);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn paths_stay_inside_the_host_dir() {
+    let dir = host_dir("escape");
+    let mut fs = HostFs::new(dir.clone());
+
+    assert_eq!(run(&mut fs, Command::CREATE, "../escaped"), Status::ERROR);
+    assert_eq!(run(&mut fs, Command::CREATE, "/tmp/escaped"), Status::ERROR);
+    assert_eq!(run(&mut fs, Command::OPEN, "missing"), Status::ERROR);
+    assert_eq!(fs.read(2), 0);
+    assert_eq!(fs.read(1), Status::ERROR);
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,63 @@
+//! Hotkeys
+//!
+//! Keys the terminal frontend keeps for itself instead of passing them to
+//! the guest, set in the `[hotkeys]` table of the machine config as
+//! `ctrl-<key>` or `none`. Each key can only do one thing, and they are
+//! only caught in raw mode (in cooked mode ctrl-c is SIGINT, as usual).
+//!
+//! ```toml
+//! [hotkeys]
+//! debugger = "ctrl-c"    # stop in the debugger (the default)
+//! console = "ctrl-]"     # switch ports with --mux (the default)
+//! screenshot = "ctrl-\\" # save the frame as possum2-<frame>.png (none by default)
+//! ```
+//!
+//! TODO: turbo, disk swapping, and mute keys, once there is a speed limit,
+//! a way to change disks while running, and sound.
+
+use crate::{machine, mux};
+
+#[derive(Clone, Copy)]
+pub struct Hotkeys {
+    pub debugger: Option<u8>,
+    pub console: Option<u8>,
+    pub screenshot: Option<u8>,
+}
+
+impl Hotkeys {
+    pub fn new(config: &machine::Hotkeys) -> Result<Self, String> {
+        let parse = |name: &str, key: &Option<String>, default: Option<u8>| match key {
+            Some(key) => parse_key(key).map_err(|e| format!("hotkey {name}: {e}")),
+            None => Ok(default),
+        };
+        let hotkeys = Self {
+            debugger: parse("debugger", &config.debugger, Some(0x03))?,
+            console: parse("console", &config.console, Some(mux::HOTKEY))?,
+            screenshot: parse("screenshot", &config.screenshot, None)?,
+        };
+        let keys = [hotkeys.debugger, hotkeys.console, hotkeys.screenshot];
+        for (i, key) in keys.iter().enumerate() {
+            if key.is_some() && keys[..i].contains(key) {
+                return Err(format!("hotkey {} is bound twice", key_name(key.unwrap())));
+            }
+        }
+        Ok(hotkeys)
+    }
+}
+
+/// `ctrl-<key>` as the control character it types, or `none`
+fn parse_key(key: &str) -> Result<Option<u8>, String> {
+    if key == "none" {
+        return Ok(None);
+    }
+    match key.strip_prefix("ctrl-").map(str::as_bytes) {
+        Some(&[key]) if (b'@'..=b'_').contains(&key.to_ascii_uppercase()) => {
+            Ok(Some(key.to_ascii_uppercase() & 0x1F))
+        }
+        _ => Err(format!("expected `ctrl-<key>` or `none`: `{key}`")),
+    }
+}
+
+fn key_name(key: u8) -> String {
+    format!("ctrl-{}", (key | 0x40).to_ascii_lowercase() as char)
+}
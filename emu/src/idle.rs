@@ -0,0 +1,58 @@
+//! Idle Sleeping
+//!
+//! While the CPU is halted waiting for an interrupt there is nothing to
+//! run but the devices counting down to it. Rather than spin through those
+//! cycles flat out, the host sleeps until they would have passed at the
+//! machine's clock rate, so a guest idling at a prompt leaves the host CPU
+//! alone. Time spent running instructions is never slowed down.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Halted time to build up before sleeping it off
+const MIN_SLEEP: Duration = Duration::from_millis(1);
+
+pub struct Idle {
+    /// The clock rate halted cycles are slept off at, or `None` to run
+    /// them flat out
+    pub clock_hz: Option<u64>,
+    /// Halted cycles not slept off yet
+    cycles: u64,
+    /// When the first of them ran
+    since: Option<Instant>,
+}
+
+impl Idle {
+    pub fn new() -> Self {
+        Self {
+            clock_hz: None,
+            cycles: 0,
+            since: None,
+        }
+    }
+
+    /// Account for cycles spent halted
+    pub fn add(&mut self, cycles: u64) {
+        if self.clock_hz.is_some() {
+            self.since.get_or_insert_with(Instant::now);
+            self.cycles += cycles;
+        }
+    }
+
+    /// Sleep off the halted time, once there is enough to be worth it
+    pub fn sleep(&mut self) {
+        let (Some(clock_hz), Some(since)) = (self.clock_hz, self.since) else {
+            return;
+        };
+        let owed =
+            Duration::from_nanos((self.cycles as u128 * 1_000_000_000 / clock_hz as u128) as u64);
+        if owed >= MIN_SLEEP {
+            // emulating the cycles took some of that time already
+            thread::sleep(owed.saturating_sub(since.elapsed()));
+            self.cycles = 0;
+            self.since = None;
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! 74148/74574 Interrupt Controller Emulation
+//!
+//! The 8 interrupt sources feed a 74148 priority encoder whose output is
+//! captured by a 74574 latch. The encoded source is pre-shifted left by one
+//! so it can be used directly as an index into a jump table of 2-byte
+//! vectors. A latch value of 0 means no source is asserted.
+//! see http://www.6502.org/mini-projects/priority-interrupt-encoder/priority-interrupt-encoder.html
+//!
+//! Each encoder input takes one source through a routing nibble (bits 0-2
+//! the source number, bit 3 set to disconnect the input), standing in for
+//! the gates firmware would otherwise need to reorder priorities. At reset
+//! every input takes the source of the same number, in [`IrqSource`] order.
+//!
+//! On top of that, each input can be masked and configured to be level or
+//! edge triggered.
+//!
+//! `lib/irq.asm` has the guest side of the jump table (`IRQ_DISPATCH`),
+//! which the `irqdispatch` boot test runs against this encoding.
+//!
+//! Registers (the bits are encoder inputs, not sources):
+//!
+//! 0 Enable Mask (1 = enabled)
+//! 1 Pending (reads return raw pending inputs, writes acknowledge edge-triggered inputs)
+//! 2 Trigger Mode (1 = edge, 0 = level)
+//! 3 Routing 0/1 (low nibble: the source on input 0, high nibble: input 1)
+//! 4 Routing 2/3
+//! 5 Routing 4/5
+//! 6 Routing 6/7
+//! 7 Latch (reads return the encoded input and clear it)
+
+use crate::bus::{Bus, BusDevice};
+
+/// Interrupt sources in priority order (bit 0 is the highest priority)
+pub enum IrqSource {}
+
+impl IrqSource {
+    pub const FDC0_DRQ: u8 = 1 << 0;
+    pub const FDC1_DRQ: u8 = 1 << 1;
+    pub const FDC0: u8 = 1 << 2;
+    pub const FDC1: u8 = 1 << 3;
+    pub const SER0: u8 = 1 << 4;
+    pub const SER1: u8 = 1 << 5;
+    pub const PPU: u8 = 1 << 6;
+    pub const TIMER: u8 = 1 << 7; // shares the line reserved for the parallel port
+
+    pub const NAMES: [&'static str; 8] = [
+        "FDC0 DRQ", "FDC1 DRQ", "FDC0", "FDC1", "SER0", "SER1", "PPU", "TIMER",
+    ];
+}
+
+/// Interrupts taken per source and how long they waited, for `irqs`
+pub struct IrqStats {
+    pub taken: [u64; 8],
+    /// Cycles from a source being asserted to its vector being fetched
+    pub max_latency: [u64; 8],
+    pub total_latency: [u64; 8],
+    pub nmis: u64,
+    /// Cycles run, and those with the I flag set
+    pub cycles: u64,
+    pub masked_cycles: u64,
+    /// When each asserted encoder input was first asserted
+    asserted_at: [Option<u64>; 8],
+}
+
+impl IrqStats {
+    pub fn new() -> Self {
+        Self {
+            taken: [0; 8],
+            max_latency: [0; 8],
+            total_latency: [0; 8],
+            nmis: 0,
+            cycles: 0,
+            masked_cycles: 0,
+            asserted_at: [None; 8],
+        }
+    }
+
+    /// Forget the counts, but not what is asserted now
+    pub fn clear(&mut self) {
+        *self = Self {
+            asserted_at: self.asserted_at,
+            ..Self::new()
+        };
+    }
+
+    /// Note which encoder inputs are asserted as of `cycles`
+    pub fn assert(&mut self, asserted: u8, cycles: u64) {
+        for (input, at) in self.asserted_at.iter_mut().enumerate() {
+            if (asserted & (1 << input)) == 0 {
+                *at = None;
+            } else if at.is_none() {
+                *at = Some(cycles);
+            }
+        }
+    }
+
+    /// Count an IRQ against the source routed to the highest priority
+    /// encoder input asserted, with its vector fetched by `cycles`
+    pub fn taken(&mut self, irq: &IrqController, cycles: u64) {
+        let asserted = irq.asserted();
+        if asserted == 0 {
+            return;
+        }
+        let input = asserted.trailing_zeros() as usize;
+        let source = irq.source(input);
+        self.taken[source] += 1;
+        if let Some(at) = self.asserted_at[input] {
+            let latency = cycles.saturating_sub(at);
+            self.max_latency[source] = self.max_latency[source].max(latency);
+            self.total_latency[source] += latency;
+        }
+    }
+
+    pub fn run(&mut self, cycles: u64, masked: bool) {
+        self.cycles += cycles;
+        if masked {
+            self.masked_cycles += cycles;
+        }
+    }
+}
+
+/// A routing nibble that connects nothing
+const DISCONNECTED: u8 = 1 << 3;
+
+pub struct IrqController {
+    lines: u8,
+    /// The source on each encoder input
+    routes: [u8; 8],
+    prev_lines: u8,
+    pending: u8,
+    enable: u8,
+    edge: u8,
+    latch: u8,
+}
+
+impl IrqController {
+    pub fn new() -> Self {
+        Self {
+            lines: 0,
+            routes: [0, 1, 2, 3, 4, 5, 6, 7],
+            prev_lines: 0,
+            pending: 0,
+            enable: 0xFF,
+            edge: 0,
+            latch: 0,
+        }
+    }
+
+    /// Drive the interrupt input lines (one bit per `IrqSource`)
+    pub fn set_lines(&mut self, lines: u8) {
+        self.lines = lines;
+    }
+
+    /// The encoder inputs pending and enabled
+    pub fn asserted(&self) -> u8 {
+        self.pending & self.enable
+    }
+
+    /// The source routed to an encoder input, or that was before it was
+    /// disconnected
+    pub fn source(&self, input: usize) -> usize {
+        (self.routes[input] & !DISCONNECTED) as usize
+    }
+
+    /// The source lines as the encoder inputs see them
+    fn routed(&self) -> u8 {
+        self.routes
+            .iter()
+            .enumerate()
+            .filter(|(_, &route)| (route & DISCONNECTED) == 0 && (self.lines & (1 << route)) != 0)
+            .fold(0, |inputs, (input, _)| inputs | (1 << input))
+    }
+}
+
+impl BusDevice for IrqController {
+    fn reset(&mut self, _bus: &mut dyn Bus) {
+        *self = Self::new();
+    }
+
+    fn tick(&mut self, _bus: &mut dyn Bus) {
+        let lines = self.routed();
+        let rising = lines & !self.prev_lines;
+        self.prev_lines = lines;
+        self.pending = (self.pending & self.edge) | (rising & self.edge) | (lines & !self.edge);
+
+        let asserted = self.pending & self.enable;
+        if asserted != 0 {
+            let latch = ((asserted.trailing_zeros() as u8) + 1) << 1;
+            if latch != self.latch {
+                tracing::trace!(target: "irq", "latched {latch:02X} (pending {:02X})", self.pending);
+            }
+            self.latch = latch;
+        }
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0 => self.enable,
+            1 => self.pending,
+            2 => self.edge,
+            3..=6 => self.peek(addr).unwrap(),
+            7 => {
+                // reading the latch acknowledges the source it reports
+                let latch = self.latch;
+                if latch != 0 {
+                    tracing::trace!(target: "irq", "acknowledged {latch:02X}");
+                    self.pending &= !(self.edge & (1 << ((latch >> 1) - 1)));
+                }
+                self.latch = 0;
+                latch
+            }
+            _ => {
+                tracing::warn!(target: "irq", "read from register {addr}, which doesn't exist");
+                0
+            }
+        }
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0 => Some(self.enable),
+            1 => Some(self.pending),
+            2 => Some(self.edge),
+            3..=6 => {
+                let input = (addr as usize - 3) * 2;
+                Some(self.routes[input] | (self.routes[input + 1] << 4))
+            }
+            7 => Some(self.latch),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0 => {
+                tracing::debug!(target: "irq", "enable mask {data:02X}");
+                self.enable = data;
+            }
+            1 => self.pending &= !(self.edge & data),
+            2 => {
+                tracing::debug!(target: "irq", "trigger mode {data:02X}");
+                self.edge = data;
+            }
+            3..=6 => {
+                let input = (addr as usize - 3) * 2;
+                tracing::debug!(target: "irq", "routing {input}/{} {data:02X}", input + 1);
+                self.routes[input] = data & 0x0F;
+                self.routes[input + 1] = data >> 4;
+            }
+            7 => {}
+            _ => tracing::warn!(target: "irq", "write to register {addr}, which doesn't exist"),
+        }
+    }
+
+    fn register_name(&self, addr: u16) -> Option<&'static str> {
+        match addr {
+            0 => Some("Enable Mask"),
+            1 => Some("Pending"),
+            2 => Some("Trigger Mode"),
+            3 => Some("Routing 0/1"),
+            4 => Some("Routing 2/3"),
+            5 => Some("Routing 4/5"),
+            6 => Some("Routing 6/7"),
+            7 => Some("Latch"),
+            _ => None,
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.asserted() != 0
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,59 @@
+use super::*;
+use crate::bus::test::NoBus;
+
+#[test]
+fn inputs_start_routed_to_their_own_source() {
+    let mut irq = IrqController::new();
+    irq.set_lines(IrqSource::SER0 | IrqSource::PPU);
+    assert_eq!(irq.routed(), IrqSource::SER0 | IrqSource::PPU);
+}
+
+#[test]
+fn routing_moves_a_source_to_another_input() {
+    let mut irq = IrqController::new();
+    // SER0 on input 0, FDC0 DRQ on input 1
+    irq.write(3, 0x04);
+    irq.set_lines(IrqSource::SER0);
+    // input 4 still takes SER0 too
+    assert_eq!(irq.routed(), (1 << 0) | (1 << 4));
+    assert_eq!(irq.source(0), 4);
+
+    irq.tick(&mut NoBus);
+    assert_eq!(irq.read(7), 1 << 1);
+}
+
+#[test]
+fn disconnected_inputs_see_nothing() {
+    let mut irq = IrqController::new();
+    // input 0 disconnected, input 1 still takes FDC1 DRQ
+    irq.write(3, 0x10 | DISCONNECTED);
+    irq.set_lines(IrqSource::FDC0_DRQ | IrqSource::FDC1_DRQ);
+    assert_eq!(irq.routed(), 1 << 1);
+    assert_eq!(irq.peek(3), Some(0x18));
+}
+
+#[test]
+fn reset_restores_the_routes() {
+    let mut irq = IrqController::new();
+    for addr in 3..=6 {
+        irq.write(addr, DISCONNECTED | (DISCONNECTED << 4));
+    }
+    irq.reset(&mut NoBus);
+    let routes = (3..=6).map(|addr| irq.peek(addr).unwrap());
+    assert!(routes.eq([0x10, 0x32, 0x54, 0x76]));
+}
+
+#[test]
+fn stats_count_the_routed_source() {
+    let mut irq = IrqController::new();
+    let mut stats = IrqStats::new();
+    // PPU on input 0
+    irq.write(3, 0x16);
+    irq.set_lines(IrqSource::PPU);
+    irq.tick(&mut NoBus);
+    stats.assert(irq.asserted(), 10);
+    stats.taken(&irq, 25);
+    assert_eq!(stats.taken[6], 1);
+    assert_eq!(stats.max_latency[6], 15);
+    assert_eq!(stats.taken[0], 0);
+}
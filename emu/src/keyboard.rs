@@ -0,0 +1,288 @@
+//! Keyboard Matrix Emulation
+//!
+//! An 8x8 matrix of key switches. The guest selects rows and reads back
+//! which columns have a pressed key in them, like most 8-bit machines.
+//!
+//! Registers:
+//!
+//! 0 Row Select (1 = scan the row)
+//! 1 Columns (reads return a bit for each column with a pressed key in a selected row)
+//!
+//! A terminal only gives us characters, not key presses, so each character
+//! typed on the host is turned into a press of its key (plus shift or
+//! ctrl) long enough for a 50Hz scan to see, followed by a release.
+//!
+//! The layout maps characters to matrix positions. Without a layout file,
+//! letters, digits, and the usual punctuation are laid out row by row. A
+//! layout file looks like:
+//!
+//! ```toml
+//! shift = { row = 7, col = 0 }
+//! ctrl = { row = 7, col = 1 }
+//!
+//! [keys]
+//! "a" = { row = 0, col = 0 }
+//! "A" = { row = 0, col = 0, shift = true }
+//! "\r" = { row = 4, col = 5 }
+//! ```
+//!
+//! Control characters with no key of their own are typed as ctrl plus
+//! the matching letter.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    io::Read,
+    path::Path,
+};
+
+use serde::Deserialize;
+
+use crate::bus::{Bus, BusDevice};
+
+/// Rate the keyboard is ticked at (once per millisecond)
+pub const TICK_RATE: u32 = 1_000;
+
+const MATRIX_SIZE: u8 = 8;
+
+/// How long each typed key is held down, and then left up, in ticks
+const HOLD_TICKS: u32 = 40;
+
+/// The default layout, a pair per matrix row: what the keys type, and
+/// what they type with shift (a space for nothing)
+const DEFAULT_ROWS: [(&str, &str); 7] = [
+    ("abcdefgh", "ABCDEFGH"),
+    ("ijklmnop", "IJKLMNOP"),
+    ("qrstuvwx", "QRSTUVWX"),
+    ("yz012345", "YZ)!@#$%"),
+    ("6789 \r\x08\x1B", "^&*(    "),
+    ("-=[];',.", "_+{}:\"<>"),
+    ("/\\`\t", "?|~ "),
+];
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Position {
+    pub row: u8,
+    pub col: u8,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Key {
+    pub row: u8,
+    pub col: u8,
+    #[serde(default)]
+    pub shift: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Layout {
+    pub shift: Option<Position>,
+    pub ctrl: Option<Position>,
+    pub keys: HashMap<char, Key>,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        let mut keys = HashMap::new();
+        for (row, (plain, shifted)) in DEFAULT_ROWS.iter().enumerate() {
+            for (col, (plain, shifted)) in plain.chars().zip(shifted.chars()).enumerate() {
+                let (row, col) = (row as u8, col as u8);
+                keys.insert(
+                    plain,
+                    Key {
+                        row,
+                        col,
+                        shift: false,
+                    },
+                );
+                if shifted != ' ' {
+                    keys.insert(
+                        shifted,
+                        Key {
+                            row,
+                            col,
+                            shift: true,
+                        },
+                    );
+                }
+            }
+        }
+        keys.insert(
+            '\n',
+            Key {
+                row: 4,
+                col: 5,
+                shift: false,
+            },
+        );
+        Self {
+            shift: Some(Position { row: 7, col: 0 }),
+            ctrl: Some(Position { row: 7, col: 1 }),
+            keys,
+        }
+    }
+}
+
+impl Layout {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let layout: Layout =
+            toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+        let positions = layout
+            .keys
+            .values()
+            .map(|key| (key.row, key.col))
+            .chain(layout.shift.iter().map(|pos| (pos.row, pos.col)))
+            .chain(layout.ctrl.iter().map(|pos| (pos.row, pos.col)));
+        for (row, col) in positions {
+            if row >= MATRIX_SIZE || col >= MATRIX_SIZE {
+                return Err(format!(
+                    "{}: key at row {row}, column {col} is outside the {MATRIX_SIZE}x{MATRIX_SIZE} matrix",
+                    path.display()
+                ));
+            }
+        }
+        Ok(layout)
+    }
+
+    /// The switches to close for a character
+    fn chord(&self, c: char) -> Option<Vec<Position>> {
+        if let Some(key) = self.keys.get(&c) {
+            let mut chord = vec![Position {
+                row: key.row,
+                col: key.col,
+            }];
+            if key.shift {
+                chord.push(self.shift?);
+            }
+            return Some(chord);
+        }
+        // ctrl-a is 0x01 and so on
+        if ('\x01'..='\x1A').contains(&c) {
+            let letter = self.keys.get(&((c as u8 + b'a' - 1) as char))?;
+            return Some(vec![
+                Position {
+                    row: letter.row,
+                    col: letter.col,
+                },
+                self.ctrl?,
+            ]);
+        }
+        None
+    }
+}
+
+enum State {
+    Idle,
+    Pressed(u32),
+    Released(u32),
+}
+
+pub struct Keyboard<T> {
+    handle: T,
+    layout: Layout,
+    /// Closed switches, one bitmask of columns per row
+    matrix: [u8; MATRIX_SIZE as usize],
+    row_select: u8,
+    typed: VecDeque<char>,
+    state: State,
+}
+
+impl<T: Read> Keyboard<T> {
+    pub fn new(handle: T, layout: Layout) -> Self {
+        Self {
+            handle,
+            layout,
+            matrix: [0; MATRIX_SIZE as usize],
+            row_select: 0,
+            typed: VecDeque::new(),
+            state: State::Idle,
+        }
+    }
+
+    fn press_next(&mut self) {
+        while let Some(c) = self.typed.pop_front() {
+            match self.layout.chord(c) {
+                Some(chord) => {
+                    for pos in chord {
+                        self.matrix[pos.row as usize] |= 1 << pos.col;
+                    }
+                    self.state = State::Pressed(HOLD_TICKS);
+                    return;
+                }
+                None => tracing::debug!("no key for {c:?} in the keyboard layout"),
+            }
+        }
+    }
+}
+
+impl<T: Read> BusDevice for Keyboard<T> {
+    fn reset(&mut self, _bus: &mut dyn Bus) {
+        self.matrix = [0; MATRIX_SIZE as usize];
+        self.row_select = 0;
+        self.typed.clear();
+        self.state = State::Idle;
+    }
+
+    fn tick(&mut self, _bus: &mut dyn Bus) {
+        match self.state {
+            State::Idle => {
+                let mut buf = [0; 16];
+                match self.handle.read(&mut buf) {
+                    Ok(0) => {}
+                    Ok(n) => {
+                        self.typed.extend(buf[..n].iter().map(|&b| b as char));
+                        self.press_next();
+                    }
+                    Err(e) => tracing::warn!("keyboard input failed: {e}"),
+                }
+            }
+            State::Pressed(0) => {
+                self.matrix = [0; MATRIX_SIZE as usize];
+                self.state = State::Released(HOLD_TICKS);
+            }
+            State::Released(0) => {
+                self.state = State::Idle;
+                self.press_next();
+            }
+            State::Pressed(ref mut ticks) | State::Released(ref mut ticks) => *ticks -= 1,
+        }
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        self.peek(addr).unwrap()
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0 => Some(self.row_select),
+            1 => Some(
+                self.matrix
+                    .iter()
+                    .enumerate()
+                    .filter(|(row, _)| (self.row_select & (1 << row)) != 0)
+                    .fold(0, |columns, (_, cols)| columns | cols),
+            ),
+            _ => unreachable!(),
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0 => self.row_select = data,
+            1 => {}
+            _ => unreachable!(),
+        }
+    }
+
+    fn register_name(&self, addr: u16) -> Option<&'static str> {
+        match addr {
+            0 => Some("Row Select"),
+            1 => Some("Columns"),
+            _ => None,
+        }
+    }
+}
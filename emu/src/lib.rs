@@ -0,0 +1,45 @@
+//! Possum2 Emulator
+//!
+//! The machine and the debugger behind the `possum2-emu` binary, split
+//! out so the benchmarks and `trace-stat` build against the real modules.
+
+// the devices are only ever built by the system, never defaulted
+#![allow(clippy::new_without_default)]
+
+pub mod boot;
+pub mod bus;
+pub mod cov;
+pub mod cpu;
+pub mod debugger;
+pub mod fdc;
+pub mod filter;
+pub mod golden;
+pub mod hooks;
+pub mod hostfs;
+pub mod hotkeys;
+pub mod idle;
+pub mod irq;
+pub mod keyboard;
+pub mod logfile;
+pub mod machine;
+pub mod mem;
+pub mod mux;
+pub mod overlay;
+pub mod png;
+pub mod ppu;
+pub mod profile;
+pub mod record;
+pub mod remote;
+pub mod rng;
+pub mod serial;
+pub mod stack;
+pub mod stats;
+pub mod sys;
+pub mod term;
+pub mod timer;
+pub mod trace;
+pub mod trap;
+pub mod tui;
+pub mod uart;
+pub mod watchdog;
+pub mod xmodem;
@@ -0,0 +1,80 @@
+//! Log Files
+//!
+//! `--log-file` sends the log to a file instead of stderr, where it would
+//! land in the middle of the guest's terminal. Once the file passes
+//! `--log-file-size`, it is renamed to `PATH.1` (and any `PATH.1` to
+//! `PATH.2`, and so on) and a new one is started, keeping at most
+//! `--log-file-count` old files. Events are never split between files.
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+pub struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    /// Old files to keep
+    keep: usize,
+}
+
+impl LogFile {
+    /// Append to the log at `path`
+    pub fn open(path: &Path, max_size: u64, keep: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+            keep,
+        })
+    }
+
+    fn old(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{n}"));
+        PathBuf::from(path)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.keep == 0 {
+            self.file.set_len(0)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                let from = self.old(n);
+                if from.exists() {
+                    fs::rename(from, self.old(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.old(1))?;
+            self.file = File::create(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        // each event arrives in one write, so writing all of it keeps it
+        // in one file
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -0,0 +1,21 @@
+use std::env;
+
+use super::*;
+
+#[test]
+fn rotation_keeps_the_newest_files() {
+    let dir = env::temp_dir().join(format!("possum2-logfile-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("emu.log");
+    let mut log = LogFile::open(&path, 10, 2).unwrap();
+    for line in ["one\n", "two\n", "three\n", "four\n", "five\n"] {
+        log.write_all(line.as_bytes()).unwrap();
+    }
+    log.flush().unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "four\nfive\n");
+    assert_eq!(fs::read_to_string(log.old(1)).unwrap(), "three\n");
+    assert_eq!(fs::read_to_string(log.old(2)).unwrap(), "one\ntwo\n");
+    assert!(!log.old(3).exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,204 @@
+//! Machine Description
+//!
+//! A TOML file describing which devices are fitted and where their
+//! registers are mapped in the IO window, so hardware variations don't
+//! require recompiling. Leaving a device's table out leaves it unmapped.
+//!
+//! ```toml
+//! rom = "k.bin"
+//! ram_banks = 4
+//! clock_hz = 4000000
+//!
+//! [ser0]
+//! base = 0xF010
+//!
+//! [timer]
+//! base = 0xF018
+//!
+//! [fdc0]
+//! base = 0xF030
+//! dma = 0xF03A
+//! image = "test.img"
+//! overlay = "work.img"
+//! first_sector = 1
+//!
+//! [keyboard]
+//! base = 0xF040
+//! layout = "keys.toml"
+//!
+//! [rng]
+//! base = 0xF050
+//! seed = 1234
+//!
+//! [hotkeys]
+//! screenshot = "ctrl-\\"
+//! ```
+//!
+//! Relative paths are resolved against the directory of the file.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::mem::RAM_BANKS;
+
+pub const DEFAULT_CLOCK_HZ: u64 = 4_000_000;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Machine {
+    pub rom: Option<PathBuf>,
+    #[serde(default = "default_ram_banks")]
+    pub ram_banks: usize,
+    /// The CPU clock, which paces the devices and the speed report
+    #[serde(default = "default_clock_hz")]
+    pub clock_hz: u64,
+    pub ser0: Option<Device>,
+    pub ser1: Option<Device>,
+    pub timer: Option<Device>,
+    pub fdc0: Option<Drive>,
+    pub fdc1: Option<Drive>,
+    pub ppu: Option<Device>,
+    pub parallel: Option<Device>,
+    pub keyboard: Option<Keyboard>,
+    pub rng: Option<Rng>,
+    #[serde(default)]
+    pub hotkeys: Hotkeys,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Device {
+    pub base: u16,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Drive {
+    pub base: u16,
+    pub image: Option<PathBuf>,
+    /// Keep writes here instead of in the image, see [`crate::overlay`]
+    pub overlay: Option<PathBuf>,
+    /// The number of the first sector on a track (0 or 1, usually)
+    #[serde(default)]
+    pub first_sector: u8,
+    /// Where the DMA registers go (no DMA if left out)
+    pub dma: Option<u16>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Keyboard {
+    pub base: u16,
+    /// See [`crate::keyboard`] (the built-in layout if left out)
+    pub layout: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rng {
+    pub base: u16,
+    /// See [`crate::rng`] (picked at startup if left out)
+    pub seed: Option<u64>,
+}
+
+/// See [`crate::hotkeys`] (the defaults for any left out)
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hotkeys {
+    pub debugger: Option<String>,
+    pub console: Option<String>,
+    pub screenshot: Option<String>,
+}
+
+fn default_ram_banks() -> usize {
+    RAM_BANKS
+}
+
+fn default_clock_hz() -> u64 {
+    DEFAULT_CLOCK_HZ
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        let drive = |base, dma| {
+            Some(Drive {
+                base,
+                dma: Some(dma),
+                image: None,
+                overlay: None,
+                first_sector: 0,
+            })
+        };
+        Self {
+            rom: None,
+            ram_banks: RAM_BANKS,
+            clock_hz: DEFAULT_CLOCK_HZ,
+            ser0: Some(Device { base: 0xF010 }),
+            ser1: Some(Device { base: 0xF014 }),
+            timer: Some(Device { base: 0xF018 }),
+            fdc0: drive(0xF030, 0xF03A),
+            fdc1: drive(0xF034, 0xF03D),
+            ppu: Some(Device { base: 0xF020 }),
+            parallel: None,
+            keyboard: Some(Keyboard {
+                base: 0xF040,
+                layout: None,
+            }),
+            rng: Some(Rng {
+                base: 0xF050,
+                seed: None,
+            }),
+            hotkeys: Hotkeys::default(),
+        }
+    }
+}
+
+impl Machine {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let mut machine: Machine =
+            toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        if let Some(rom) = &mut machine.rom {
+            *rom = dir.join(&rom);
+        }
+        for drive in [&mut machine.fdc0, &mut machine.fdc1].into_iter().flatten() {
+            if let Some(image) = &mut drive.image {
+                *image = dir.join(&image);
+            }
+            if let Some(overlay) = &mut drive.overlay {
+                *overlay = dir.join(&overlay);
+            }
+        }
+        if let Some(layout) = machine
+            .keyboard
+            .as_mut()
+            .and_then(|kbd| kbd.layout.as_mut())
+        {
+            *layout = dir.join(&layout);
+        }
+
+        machine.validate()?;
+        Ok(machine)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=RAM_BANKS).contains(&self.ram_banks) {
+            return Err(format!("ram_banks must be between 1 and {RAM_BANKS}"));
+        }
+        if self.clock_hz == 0 {
+            return Err("clock_hz must not be 0".to_string());
+        }
+        if self.parallel.is_some() {
+            return Err("the parallel port is not emulated yet".to_string());
+        }
+
+        // device placement is checked as the devices are attached
+        Ok(())
+    }
+}
@@ -1,56 +1,80 @@
 use std::{
-    collections::HashMap,
-    fs::File,
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
     io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Stdout, Write},
-    num::ParseIntError,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    rc::Rc,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc,
+        Arc, Mutex,
     },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use clap::Parser;
-use cpu::Cpu;
+use clap::{Parser, Subcommand};
 use memmap2::MmapMut;
+use possum2_emu::{
+    boot,
+    debugger::{
+        debug_command, dissasemble, load_symbols, mark_executed, save_frame, trace_instruction,
+        DebugAction, Debugger,
+    },
+    fdc::{self, Fdc, Image},
+    filter::Filter,
+    golden,
+    hostfs::{self, HostFs},
+    hotkeys::Hotkeys,
+    irq::IrqSource,
+    keyboard::{self, Keyboard, Layout},
+    logfile::LogFile,
+    machine::{self, Machine},
+    mem::{check::MemCheck, ROM_BANKS, ROM_SIZE},
+    mux::Mux,
+    overlay::Overlay,
+    ppu::{self, Ppu},
+    record::Recorder,
+    remote::Remote,
+    rng::Rng,
+    serial::{self, Console, Port, Spec},
+    sys::{OpenBus, Slot, System, UnmappedIo},
+    term,
+    timer::Timer,
+    trace::{self, Tracer},
+    tui::Tui,
+    uart::{self, Uart},
+};
+use possum2_media::DISK_SIZE;
 use signal_hook::{consts, flag};
-use sys::{Mem, System};
 use termion::{
-    color::{Fg, LightBlue, LightMagenta, LightRed, LightYellow, Reset},
     raw::{IntoRawMode, RawTerminal},
     AsyncReader,
 };
 use tracing::Level;
+use tracing_subscriber::{filter::Targets, fmt, prelude::*};
 
-use crate::cpu::Flags;
-
-mod bus;
-mod cpu;
-mod fdc;
-mod sys;
-mod uart;
+/// How many instructions run between checks for signals, the debugger,
+/// and the remote socket (breakpoints are still caught exactly)
+const BATCH_TICKS: u64 = 0x1000;
 
-struct NoopIo {}
+/// The console without a terminal: output goes to stdout and there is no
+/// input
+struct HeadlessTty {}
 
-impl Read for NoopIo {
+impl Read for HeadlessTty {
     fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
         Ok(0)
     }
 }
 
-impl Write for NoopIo {
+impl Write for HeadlessTty {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        Ok(buf.len())
+        io::stdout().write(buf)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        Ok(())
-    }
-}
-
-impl Seek for NoopIo {
-    fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
-        Ok(0)
+        io::stdout().flush()
     }
 }
 
@@ -104,97 +128,668 @@ impl Seek for MemMap {
     }
 }
 
+/// A drive with or without a disk in it
+enum Disk {
+    Image(MemMap),
+    Overlay(Overlay),
+    Empty,
+}
+
+impl Image for Disk {
+    fn commit(&mut self) -> Result<usize, String> {
+        match self {
+            Disk::Overlay(overlay) => overlay.commit().map_err(|e| e.to_string()),
+            Disk::Image(_) => Err("the drive has no overlay".to_string()),
+            Disk::Empty => Err("the drive is empty".to_string()),
+        }
+    }
+}
+
+impl Read for Disk {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Disk::Image(image) => image.read(buf),
+            Disk::Overlay(overlay) => overlay.read(buf),
+            Disk::Empty => Ok(0),
+        }
+    }
+}
+
+impl Write for Disk {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Disk::Image(image) => image.write(buf),
+            Disk::Overlay(overlay) => overlay.write(buf),
+            Disk::Empty => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Disk::Image(image) => image.flush(),
+            Disk::Overlay(overlay) => overlay.flush(),
+            Disk::Empty => Ok(()),
+        }
+    }
+}
+
+impl Seek for Disk {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Disk::Image(image) => image.seek(pos),
+            Disk::Overlay(overlay) => overlay.seek(pos),
+            Disk::Empty => Ok(0),
+        }
+    }
+}
+
+/// SER0 on the host terminal. In raw mode keys go straight to the guest
+/// (besides the [`Hotkeys`], which we catch ourselves); otherwise the
+/// terminal line-buffers input and ctrl-c arrives as SIGINT.
+///
+/// The main loop polls for the hotkeys between batches, so they work even
+/// while the guest isn't reading SER0. Everything else typed is held
+/// until it does.
 struct Tty {
-    tx: RawTerminal<Stdout>,
+    tx: Option<RawTerminal<Stdout>>,
     rx: AsyncReader,
+    typed: VecDeque<u8>,
+    hotkeys: Hotkeys,
+    interrupt: Arc<AtomicBool>,
+    screenshot: Arc<AtomicBool>,
 }
 
 impl Tty {
-    fn new() -> Self {
-        let tx = io::stdout().into_raw_mode().unwrap();
+    fn new(
+        hotkeys: Hotkeys,
+        interrupt: Arc<AtomicBool>,
+        screenshot: Arc<AtomicBool>,
+        raw: bool,
+    ) -> io::Result<Self> {
+        let tx = if raw {
+            term::save()?;
+            Some(io::stdout().into_raw_mode()?)
+        } else {
+            None
+        };
         let rx = termion::async_stdin();
-        Self { tx, rx }
+        Ok(Self {
+            tx,
+            rx,
+            typed: VecDeque::new(),
+            hotkeys,
+            interrupt,
+            screenshot,
+        })
+    }
+
+    /// Take what has been typed, catching the hotkeys
+    fn poll(&mut self) -> io::Result<()> {
+        let mut buf = [0; 64];
+        loop {
+            let size = self.rx.read(&mut buf)?;
+            if size == 0 {
+                return Ok(());
+            }
+            // the raw tty swallows ctrl-c, so we have to catch it (and the
+            // other hotkeys) ourselves before the guest ever sees it
+            for &key in &buf[..size] {
+                if Some(key) == self.hotkeys.debugger {
+                    self.interrupt.store(true, Ordering::Relaxed);
+                } else if Some(key) == self.hotkeys.screenshot {
+                    self.screenshot.store(true, Ordering::Relaxed);
+                } else {
+                    self.typed.push_back(key);
+                }
+            }
+        }
+    }
+
+    /// Hand the terminal back for line input (the debugger prompt)
+    fn suspend_raw_mode(&self) -> io::Result<()> {
+        match &self.tx {
+            Some(tx) => tx.suspend_raw_mode(),
+            None => Ok(()),
+        }
+    }
+
+    fn activate_raw_mode(&self) -> io::Result<()> {
+        match &self.tx {
+            Some(tx) => tx.activate_raw_mode(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Tty {
+    fn drop(&mut self) {
+        term::restore();
     }
 }
 
 impl Read for Tty {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.rx.read(buf)
+        self.poll()?;
+        self.typed.read(buf)
     }
 }
 
 impl Write for Tty {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.tx.write(buf)
+        match &mut self.tx {
+            Some(tx) => tx.write(buf),
+            None => io::stdout().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.tx {
+            Some(tx) => tx.flush(),
+            None => io::stdout().flush(),
+        }
+    }
+}
+
+/// The tty is shared between SER0 and the debugger prompt
+struct SharedTty(Rc<RefCell<Tty>>);
+
+impl Read for SharedTty {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.borrow_mut().read(buf)
+    }
+}
+
+impl Write for SharedTty {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.tx.flush()
+        self.0.borrow_mut().flush()
     }
 }
 
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
-struct Args {
-    /// Path to rom file
-    rom: PathBuf,
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// With no subcommand, run the emulator
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(clap::Args)]
+struct RunArgs {
+    /// Path to rom file (overrides the machine config)
+    rom: Option<PathBuf>,
 
-    /// FD0 image file
+    /// Boot from FD0 with a built-in ROM stub instead of a ROM file,
+    /// loading its first sectors at $0200
+    #[arg(long, conflicts_with = "rom")]
+    boot_fd0: bool,
+
+    /// How many 256-byte sectors `--boot-fd0` loads
+    #[arg(long, value_name = "N", default_value_t = 1, requires = "boot_fd0",
+        value_parser = clap::value_parser!(u16).range(1..))]
+    boot_sectors: u16,
+
+    /// Give the guest the files in this directory through the host file
+    /// mailbox at $F058 (not real hardware)
+    #[arg(long, value_name = "DIR")]
+    host_dir: Option<PathBuf>,
+
+    /// FD0 image file (overrides the machine config)
     #[arg(long)]
-    fd0: PathBuf,
+    fd0: Option<PathBuf>,
+
+    /// Keep writes to FD0 in this overlay file, leaving the image untouched
+    /// until `commit` (created if missing)
+    #[arg(long, value_name = "FILE")]
+    fd0_overlay: Option<PathBuf>,
+
+    /// Machine config file describing the fitted devices
+    #[arg(short, long)]
+    machine: Option<PathBuf>,
 
     /// One of `TRACE`, `DEBUG`, `INFO`, `WARN`, or `ERROR`
     #[arg(short, long, default_value_t = Level::INFO)]
     log_level: Level,
 
+    /// Levels for particular tracing targets, over `--log-level` (e.g.
+    /// `fdc=trace,uart=debug`). The devices log to `uart`, `fdc`, `ppu`,
+    /// and `irq`, and `--io-trace` logs to `io`
+    #[arg(long, value_name = "TARGET=LEVEL,...", value_parser = parse_log_filter)]
+    log_filter: Option<Targets>,
+
+    /// Log to this file instead of stderr
+    #[arg(long, value_name = "FILE")]
+    log_file: Option<PathBuf>,
+
+    /// Start a new log file once it would pass this size (e.g. 500K, 10M)
+    #[arg(long, value_name = "SIZE", default_value = "10M", value_parser = parse_size)]
+    log_file_size: u64,
+
+    /// How many old log files to keep
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    log_file_count: usize,
+
     /// Start with debugger enabled
     #[arg(short, long)]
     debug: bool,
 
-    /// Debugger symbol file
+    /// Debugger symbol file (repeatable)
     #[arg(short, long)]
-    sym: Option<PathBuf>,
+    sym: Vec<PathBuf>,
+
+    /// File of debugger commands to run at startup
+    #[arg(long)]
+    dbg_script: Option<PathBuf>,
+
+    /// Use the full-screen debugger instead of the `dbg>` prompt
+    #[arg(long)]
+    tui: bool,
+
+    /// Serve the debugger on a socket (`HOST:PORT` or `unix:PATH`)
+    #[arg(long)]
+    dbg_listen: Option<String>,
+
+    /// Run headless, feeding debugger commands from this file whenever the
+    /// emulator stops (at startup, breakpoints, and ctrl-c)
+    #[arg(long, conflicts_with_all = ["debug", "tui", "dbg_script", "dbg_listen"])]
+    script: Option<PathBuf>,
+
+    /// Run headless like `--script`, with the commands given inline and
+    /// separated by `;` (`"b main; c; x 2000; q"`)
+    #[arg(long, value_name = "COMMANDS",
+        conflicts_with_all = ["script", "debug", "tui", "dbg_script", "dbg_listen"])]
+    dbg_commands: Option<String>,
+
+    /// Run a Rhai script that hooks breakpoints, memory accesses, frames,
+    /// and ticks (needs the `scripting` feature)
+    #[arg(long, value_name = "FILE")]
+    hooks: Option<PathBuf>,
+
+    /// Give up (exit status 1) after this many cycles
+    #[arg(long)]
+    max_cycles: Option<u64>,
+
+    /// Compare a hash of the registers, RAM, and VRAM at the end of the
+    /// run against this file, failing on a mismatch (see `--bless`)
+    #[arg(long, value_name = "FILE")]
+    golden: Option<PathBuf>,
+
+    /// Save the `--golden` hashes instead of comparing them
+    #[arg(long, requires = "golden")]
+    bless: bool,
+
+    /// Dump the 64KiB address space to this file on exit
+    #[arg(long)]
+    dump: Option<PathBuf>,
+
+    /// Save the last PPU frame to this PNG file on exit
+    #[arg(long, value_name = "FILE")]
+    dump_frame_on_exit: Option<PathBuf>,
+
+    /// Record every PPU frame, to a Y4M video if FILE ends in `.y4m` or
+    /// as numbered PNGs in the directory FILE otherwise
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+
+    /// Post-process screenshots and recordings with CRT-ish filters:
+    /// `scanlines` and/or `ntsc` (change with `filter`)
+    #[arg(long, value_name = "FILTER,...", value_delimiter = ',')]
+    filter: Vec<Filter>,
+
+    /// Bytes per second that the `paste` debugger command types into SER0
+    #[arg(long, value_name = "CPS", default_value_t = 100,
+        value_parser = clap::value_parser!(u32).range(1..))]
+    paste_rate: u32,
+
+    /// Copy a program into RAM after reset (ADDR and BANK in hex, repeatable)
+    #[arg(long, value_name = "FILE@ADDR[,BANK]", value_parser = parse_load)]
+    load: Vec<Load>,
+
+    /// Seed for the RNG device and `--open-bus random` (logged at startup
+    /// when picked for you)
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Start at this address (hex) instead of the reset vector
+    #[arg(long, value_parser = parse_hex)]
+    pc: Option<u16>,
+
+    /// Warn when the stack pointer drops below this address (hex) or
+    /// wraps around (toggle with `sp guard`)
+    #[arg(long, value_name = "ADDR", value_parser = parse_hex)]
+    stack_guard: Option<u16>,
+
+    /// Stop the run when PC stays within a few bytes with interrupts
+    /// disabled for this many million cycles (see the watchdog module)
+    #[arg(long, value_name = "MCYCLES", value_parser = clap::value_parser!(u64).range(1..))]
+    watchdog: Option<u64>,
+
+    /// SER0 backend: `tty`, `null`, `file:PATH`, `tcp:HOST:PORT`,
+    /// `unix:PATH`, or `pty`, joined with `+` to use several at once
+    #[arg(long, default_value = "tty", value_parser = serial::parse_spec)]
+    ser0: Spec,
+
+    /// SER1 backend, as for `--ser0` (default: `null`, or `tty` with `--mux`)
+    #[arg(long, value_parser = serial::parse_spec)]
+    ser1: Option<Spec>,
+
+    /// Share the terminal between SER0 and SER1, switching which is shown
+    /// with the console hotkey, ctrl-] by default (SER1 is colored, and
+    /// hidden output is held until shown)
+    #[arg(long)]
+    mux: bool,
+
+    /// Where keyboard matrix input comes from, as for `--ser0` (use
+    /// `--kbd tty --ser0 null` to type on the keyboard instead)
+    #[arg(long, default_value = "null", value_parser = serial::parse_spec)]
+    kbd: Spec,
+
+    /// Leave the terminal cooked: SER0 input is line-buffered and ctrl-c
+    /// is a signal (for CI and piping)
+    #[arg(long, conflicts_with = "tui")]
+    no_raw: bool,
+
+    /// Log the emulation speed every this many seconds
+    #[arg(long, value_name = "SECS", value_parser = parse_secs)]
+    stats_interval: Option<Duration>,
+
+    /// What guest accesses to unmapped IO addresses do: `warn` (log),
+    /// `open-bus` (nothing, and read the last byte on the bus unless
+    /// `--open-bus` says otherwise), or `break` (stop in the debugger)
+    #[arg(long, default_value = "warn")]
+    unmapped_io: UnmappedIo,
+
+    /// What reads of unmapped IO addresses return: `zero`, `last` (the
+    /// last byte on the bus), `ff`, or `random` (seeded by `--seed`)
+    #[arg(long)]
+    open_bus: Option<OpenBus>,
+
+    /// Check the guest's RAM accesses: `warn` (log each address once) or
+    /// `break` (stop in the debugger) on reads of RAM nothing has written
+    /// and on writes to `--read-only` ranges (change with `mem-check`)
+    #[arg(long)]
+    mem_check: Option<MemCheck>,
+
+    /// Treat START-END (hex, inclusive) as read-only for `--mem-check`
+    /// (repeatable)
+    #[arg(long, value_name = "START-END", value_parser = parse_hex_range,
+        requires = "mem_check")]
+    read_only: Vec<(u16, u16)>,
+
+    /// Check where the guest executes from: `warn` or `break` on running
+    /// code from the IO window, or code the CPU wrote in the last
+    /// `--smc-window` cycles (change with `exec-check`)
+    #[arg(long)]
+    exec_check: Option<MemCheck>,
+
+    /// How many cycles after being written code counts as self-modified
+    #[arg(long, value_name = "CYCLES", default_value_t = 1000)]
+    smc_window: u64,
+
+    /// Run flat out while the guest is halted waiting for an interrupt,
+    /// instead of sleeping at the machine's clock rate (scripted runs
+    /// never sleep)
+    #[arg(long)]
+    no_idle_sleep: bool,
+
+    /// Let AUG $FF call into the emulator for host services (exit,
+    /// console output, loading host files, the time, and test reports),
+    /// for test programs without device drivers
+    #[arg(long)]
+    aug_traps: bool,
+
+    /// Push the B flag set on PHP, like the 6502 (BRK always pushes it
+    /// set, interrupts clear)
+    #[arg(long)]
+    strict_cpu: bool,
+
+    /// Log every CPU access to the IO window (toggle with `io-trace`)
+    #[arg(long)]
+    io_trace: bool,
+
+    /// Record every instruction, IO access, and interrupt to this file,
+    /// for analysis tools (summarize it with `trace-stat`)
+    #[arg(long, value_name = "FILE")]
+    trace_out: Option<PathBuf>,
+
+    /// How `--trace-out` is written: `json` (a JSON object per line) or
+    /// `binary` (compact records)
+    #[arg(long, default_value = "json", requires = "trace_out")]
+    trace_format: trace::Format,
 }
 
-fn main() -> Result<(), ()> {
-    let args = Args::parse();
+#[derive(Subcommand)]
+enum Command {
+    /// Run the emulator (the default with no subcommand)
+    Run(Box<RunArgs>),
 
-    tracing_subscriber::fmt()
-        .with_max_level(args.log_level)
-        .with_writer(io::stderr)
-        .init();
+    /// Run a test ROM headless with AUG traps on, exiting with the status
+    /// it reports (SER0 goes to stdout)
+    Test {
+        /// Path to the test ROM
+        rom: PathBuf,
 
-    let mut rom = Vec::new();
-    File::open(&args.rom)
-        .map_err(|e| tracing::error!("failed to open ROM file: {e}"))?
-        .read_to_end(&mut rom)
-        .map_err(|e| tracing::error!("failed to read ROM file: {e}"))?;
-    if rom.len() != 0x0F00 {
-        tracing::error!(
-            "ROM file is {} bytes, but it must be exactly 3840 bytes (3.75KiB) in length!",
-            rom.len()
-        );
-        return Err(());
+        /// Machine config file describing the fitted devices
+        #[arg(short, long)]
+        machine: Option<PathBuf>,
+
+        /// Fail if the ROM hasn't reported after this many cycles
+        #[arg(long, default_value_t = 100_000_000)]
+        max_cycles: u64,
+    },
+
+    /// Create a blank 640KiB disk image
+    Mkdisk {
+        /// Path to the new image
+        image: PathBuf,
+
+        /// Write this file at the start of the disk, for `--boot-fd0`
+        #[arg(long, value_name = "FILE")]
+        boot: Option<PathBuf>,
+
+        /// Replace the image if it already exists
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Disassemble a binary into source `pasm` assembles to the same bytes
+    Dasm(dasm::Args),
+
+    /// List and copy the files on a disk image. Not yet: the guest OS
+    /// hasn't defined a floppy filesystem, so this only reports that
+    Fs {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, hide = true)]
+        args: Vec<String>,
+    },
+}
+
+#[derive(Clone)]
+struct Load {
+    path: PathBuf,
+    addr: u16,
+    bank: usize,
+}
+
+fn parse_load(s: &str) -> Result<Load, String> {
+    let (path, at) = s
+        .rsplit_once('@')
+        .ok_or_else(|| format!("expected FILE@ADDR[,BANK]: no `@` found in `{s}`"))?;
+    let (addr, bank) = match at.split_once(',') {
+        Some((addr, bank)) => (
+            addr,
+            usize::from_str_radix(bank, 16).map_err(|e| e.to_string())?,
+        ),
+        None => (at, 0),
+    };
+    Ok(Load {
+        path: PathBuf::from(path),
+        addr: parse_hex(addr)?,
+        bank,
+    })
+}
+
+fn parse_hex(s: &str) -> Result<u16, String> {
+    u16::from_str_radix(s.trim_start_matches('$'), 16).map_err(|e| e.to_string())
+}
+
+fn parse_hex_range(s: &str) -> Result<(u16, u16), String> {
+    let (start, end) = s
+        .split_once('-')
+        .ok_or_else(|| format!("expected START-END: `{s}`"))?;
+    let (start, end) = (parse_hex(start)?, parse_hex(end)?);
+    if end < start {
+        return Err(format!("{end:04X} is before {start:04X}"));
     }
+    Ok((start, end))
+}
 
-    let fd0 = File::options()
-        .write(true)
-        .read(true)
-        .open(&args.fd0)
-        .map_err(|e| tracing::error!("failed to open FD0 file: {e}"))?;
-    let fd0 = (unsafe { MmapMut::map_mut(&fd0) })
-        .map_err(|e| tracing::error!("failed to map FD0 file: {e}"))?;
-    if fd0.len() != 0xA0000 {
-        tracing::error!(
-            "FD0 file is {} bytes, but it must be exactly 655360 bytes (640KiB) in length!",
-            fd0.len()
-        );
-        return Err(());
+fn parse_log_filter(s: &str) -> Result<Targets, String> {
+    s.parse().map_err(|e| format!("{e}: `{s}`"))
+}
+
+/// A size in bytes, with an optional `K`, `M`, or `G` suffix
+fn parse_size(s: &str) -> Result<u64, String> {
+    let (digits, scale) = match s.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&s[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&s[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    match digits.parse::<u64>() {
+        Ok(size) if size != 0 => size
+            .checked_mul(scale)
+            .ok_or_else(|| format!("size is too big: `{s}`")),
+        _ => Err(format!("expected a positive size like 500K or 10M: `{s}`")),
+    }
+}
+
+fn parse_secs(s: &str) -> Result<Duration, String> {
+    let secs = s.parse::<f64>().map_err(|e| e.to_string())?;
+    match Duration::try_from_secs_f64(secs) {
+        Ok(duration) if !duration.is_zero() => Ok(duration),
+        _ => Err(format!("expected a positive number of seconds: `{s}`")),
     }
-    let fd0 = MemMap {
-        inner: fd0,
-        offset: 0,
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(status) => ExitCode::from(status),
+        Err(()) => ExitCode::FAILURE,
+    }
+}
+
+fn run() -> Result<u8, ()> {
+    let cli = Cli::parse();
+    let (mut args, command) = match cli.command {
+        Some(Command::Run(args)) => (*args, None),
+        command => (cli.run, command),
+    };
+
+    let filter = args
+        .log_filter
+        .clone()
+        .unwrap_or_default()
+        .with_default(args.log_level);
+    let log_file = match &args.log_file {
+        Some(path) => Some(
+            LogFile::open(path, args.log_file_size, args.log_file_count).map_err(|e| {
+                eprintln!("failed to open log file {}: {e}", path.display());
+            })?,
+        ),
+        None => None,
+    };
+    let (file_layer, stderr_layer) = match log_file {
+        Some(file) => (
+            Some(fmt::layer().with_ansi(false).with_writer(Mutex::new(file))),
+            None,
+        ),
+        None => (None, Some(fmt::layer().with_writer(io::stderr))),
     };
+    tracing_subscriber::registry()
+        .with(file_layer.with_filter(filter.clone()))
+        .with(stderr_layer.with_filter(filter))
+        .init();
+
+    let test = match command {
+        Some(Command::Test {
+            rom,
+            machine,
+            max_cycles,
+        }) => {
+            args.rom = Some(rom);
+            args.machine = machine;
+            args.max_cycles = Some(max_cycles);
+            args.aug_traps = true;
+            true
+        }
+        Some(Command::Mkdisk { image, boot, force }) => {
+            return mkdisk(&image, boot.as_deref(), force).map(|()| 0);
+        }
+        Some(Command::Dasm(dasm)) => {
+            return dasm::run(&dasm)
+                .map(|()| 0)
+                .map_err(|e| tracing::error!("{e}"));
+        }
+        Some(Command::Fs { .. }) => {
+            tracing::error!("no filesystem is defined for disk images yet");
+            return Err(());
+        }
+        Some(Command::Run(_)) | None => false,
+    };
+
+    let mut machine = match &args.machine {
+        Some(path) => Machine::load(path)
+            .map_err(|e| tracing::error!("failed to load machine config: {e}"))?,
+        None => Machine::default(),
+    };
+    if let Some(rom) = args.rom.take() {
+        machine.rom = Some(rom);
+    }
+    if let Some(fd0) = args.fd0.take() {
+        let Some(drive) = &mut machine.fdc0 else {
+            tracing::error!("an FD0 image was given, but the machine has no FDC0");
+            return Err(());
+        };
+        drive.image = Some(fd0);
+    }
+    if let Some(overlay) = args.fd0_overlay.take() {
+        let Some(drive) = &mut machine.fdc0 else {
+            tracing::error!("an FD0 overlay was given, but the machine has no FDC0");
+            return Err(());
+        };
+        drive.overlay = Some(overlay);
+    }
+
+    let rom = if args.boot_fd0 {
+        boot::rom()
+    } else {
+        read_rom(&machine)?
+    };
+
+    let mut fd0 = open_disk("FD0", machine.fdc0.as_ref())?;
+    let fd1 = open_disk("FD1", machine.fdc1.as_ref())?;
+    let boot = if args.boot_fd0 {
+        if matches!(fd0, Disk::Empty) {
+            tracing::error!("booting from FD0, but FD0 has no image");
+            return Err(());
+        }
+        let boot = boot::read_sectors(&mut fd0, args.boot_sectors as usize)
+            .map_err(|e| tracing::error!("failed to read the FD0 boot sectors: {e}"))?;
+        Some(boot)
+    } else {
+        None
+    };
+
+    let ser1 = args
+        .ser1
+        .clone()
+        .unwrap_or(if args.mux { Spec::Tty } else { Spec::Null });
 
     let debug_mode = Arc::new(AtomicBool::new(args.debug));
     flag::register(consts::SIGUSR1, debug_mode.clone())
@@ -202,49 +797,203 @@ fn main() -> Result<(), ()> {
             tracing::warn!("external debugger unavailable: failed to install SIGUSR1 handler: {e}")
         })
         .ok();
+    let interrupt = Arc::new(AtomicBool::new(false));
+    flag::register(consts::SIGINT, interrupt.clone())
+        .map_err(|e| tracing::warn!("failed to install SIGINT handler: {e}"))
+        .ok();
 
     let mut symbols = HashMap::<u16, Vec<String>>::new();
-    if let Some(sym) = args.sym {
-        let sym_file =
-            File::open(&sym).map_err(|e| tracing::error!("failed to open SYM file: {e}"))?;
-        for (line_no, line_result) in BufReader::new(sym_file).lines().enumerate() {
-            let line = line_result.map_err(|e| tracing::error!("failed to read SYM file: {e}"))?;
-            let (label, addr) = line
-                .split_once(':')
-                .ok_or_else(|| format!("{}:{line_no}: malformed entry", sym.display()))
-                .map_err(|e| tracing::error!("failed to parse SYM file: {e}"))?;
-            let addr = u16::from_str_radix(addr, 16).map_err(|e| {
-                tracing::error!("failed to parse SYM file: {}:{line_no}: {e}", sym.display())
-            })?;
-            match symbols.get_mut(&addr) {
-                Some(labels) => labels.push(label.to_string()),
-                None => {
-                    symbols.insert(addr, vec![label.to_string()]);
-                }
-            }
+    for sym in &args.sym {
+        load_symbols(&mut symbols, sym)
+            .map_err(|e| tracing::error!("failed to load SYM file: {e}"))?;
+    }
+
+    let limit = Limit {
+        max_cycles: args.max_cycles,
+        expected: args.golden.is_some(),
+    };
+
+    let mut dbg = Debugger::new(symbols);
+    dbg.stats.target_hz = Some(machine.clock_hz);
+    dbg.stats.interval = args.stats_interval;
+    dbg.paste_rate = args.paste_rate;
+    dbg.watchdog
+        .set(args.watchdog.map(|mcycles| mcycles * 1_000_000));
+    dbg.filters = args.filter.clone();
+    if let Some(path) = &args.record {
+        dbg.recorder = Some(
+            Recorder::create(path)
+                .map_err(|e| tracing::error!("failed to start recording: {e}"))?,
+        );
+    }
+    let commands = match (&args.script, &args.dbg_commands) {
+        (Some(script), _) => Some(read_script(script)?),
+        (None, Some(commands)) => Some(commands.split(';').map(String::from).collect()),
+        (None, None) => None,
+    };
+    if let Some(dir) = &args.host_dir {
+        if !dir.is_dir() {
+            tracing::error!("host dir {} is not a directory", dir.display());
+            return Err(());
         }
     }
+    let hotkeys = Hotkeys::new(&machine.hotkeys)
+        .map_err(|e| tracing::error!("failed to load machine config: {e}"))?;
+    // headless runs should come out the same every time
+    let seed = args
+        .seed
+        .or(machine.rng.as_ref().and_then(|rng| rng.seed))
+        .unwrap_or_else(|| {
+            if commands.is_some() || test {
+                0
+            } else {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.as_nanos() as u64)
+            }
+        });
+    tracing::info!("random seed {seed}");
+    if let Some(rng) = &mut machine.rng {
+        rng.seed = Some(seed);
+    }
+    let open_bus = args.open_bus.unwrap_or(match args.unmapped_io {
+        UnmappedIo::OpenBus => OpenBus::Last,
+        _ => OpenBus::Zero,
+    });
+    if commands.is_some() || test {
+        let mut console = |_: &str| Box::new(HeadlessTty {}) as Box<dyn Console>;
+        let ports = open_ports(&args.ser0, &ser1, &args.kbd, &mut console)?;
+        let mut sys = build_system(&machine, &rom, ports, fd0, fd1, args.host_dir.as_deref())?;
+        configure_system(&mut sys, &mut dbg, &args, open_bus, seed, boot.as_deref())?;
+        let status = match commands {
+            Some(commands) => run_script(&mut sys, &mut dbg, commands, &interrupt, limit),
+            None => run_test(&mut sys, &mut dbg, &interrupt, limit),
+        };
+        dump_memory(&sys, args.dump.as_deref())?;
+        dump_frame(&sys, &dbg.filters, args.dump_frame_on_exit.as_deref())?;
+        check_golden(&sys, args.golden.as_deref(), args.bless)?;
+        return status;
+    }
+    if !args.no_idle_sleep {
+        dbg.idle.clock_hz = Some(machine.clock_hz);
+    }
 
-    let mut breakpoints = Vec::new();
-    let mut sys = System::new(&rom, Tty::new(), NoopIo {}, fd0, NoopIo {});
-    sys.reset();
+    let mut remote = match &args.dbg_listen {
+        Some(addr) => Some(
+            Remote::bind(addr).map_err(|e| tracing::error!("failed to listen on {addr}: {e}"))?,
+        ),
+        None => None,
+    };
+    let raw = !args.no_raw && termion::is_tty(&io::stdin()) && termion::is_tty(&io::stdout());
+    if !raw && !args.no_raw {
+        tracing::info!("not a terminal, running without raw mode");
+    }
+    let screenshot = Arc::new(AtomicBool::new(false));
+    let tty = Tty::new(hotkeys, interrupt.clone(), screenshot.clone(), raw)
+        .map_err(|e| tracing::error!("failed to set up the terminal: {e}"))?;
+    let tty = Rc::new(RefCell::new(tty));
+    let mux = args
+        .mux
+        .then(|| Mux::new(Box::new(SharedTty(tty.clone())), hotkeys.console));
+    let mut console = |name: &str| match (&mux, name) {
+        (Some(mux), "ser0") => Box::new(Mux::port(mux, 0)) as Box<dyn Console>,
+        (Some(mux), "ser1") => Box::new(Mux::port(mux, 1)),
+        _ => Box::new(SharedTty(tty.clone())),
+    };
+    let ports = open_ports(&args.ser0, &ser1, &args.kbd, &mut console)?;
+    let mut sys = build_system(&machine, &rom, ports, fd0, fd1, args.host_dir.as_deref())?;
+    let mut tui = if args.tui {
+        Some(
+            Tui::new(SharedTty(tty.clone()))
+                .map_err(|e| tracing::error!("failed to start TUI: {e}"))?,
+        )
+    } else {
+        None
+    };
+    configure_system(&mut sys, &mut dbg, &args, open_bus, seed, boot.as_deref())?;
+
+    if let Some(script) = args.dbg_script {
+        let script_file = File::open(&script)
+            .map_err(|e| tracing::error!("failed to open debugger script: {e}"))?;
+        tty.borrow().suspend_raw_mode().unwrap();
+        for line_result in BufReader::new(script_file).lines() {
+            let line =
+                line_result.map_err(|e| tracing::error!("failed to read debugger script: {e}"))?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            println!("dbg>{line}");
+            let parts = line
+                .split_whitespace()
+                .map(String::from)
+                .collect::<Vec<String>>();
+            match debug_command(&mut io::stdout(), &mut sys, &mut dbg, &parts).unwrap() {
+                DebugAction::Prompt => {}
+                DebugAction::Continue => debug_mode.store(false, Ordering::Relaxed),
+                DebugAction::Quit => return Ok(0),
+            }
+        }
+        tty.borrow().activate_raw_mode().unwrap();
+    }
 
+    let mut status = Ok(0);
     'emu: loop {
-        if breakpoints.contains(&sys.cpu().pc()) {
+        if let Err(e) = tty.borrow_mut().poll() {
+            tracing::warn!("failed to read the terminal: {e}");
+        }
+        if dbg.breakpoints.hit(sys.cpu().pc())
+            || sys.take_fault().is_some()
+            || dbg.hooks.take_stop()
+            || dbg.watchdog.take_fired()
+        {
+            debug_mode.store(true, Ordering::Relaxed);
+        }
+        if interrupt.swap(false, Ordering::Relaxed) {
             debug_mode.store(true, Ordering::Relaxed);
         }
+        if screenshot.swap(false, Ordering::Relaxed) {
+            take_screenshot(&sys, &dbg.filters);
+        }
+        if let Some(remote) = &mut remote {
+            // the console stays with the guest while a client is attached
+            let halted = remote.connected() && debug_mode.swap(false, Ordering::Relaxed);
+            if let DebugAction::Quit = remote.serve(&mut sys, &mut dbg, halted) {
+                break 'emu;
+            }
+        }
+        if let (true, Some(tui)) = (debug_mode.load(Ordering::Relaxed), &mut tui) {
+            let mut input = SharedTty(tty.clone());
+            let action = tui.run(&mut sys, &mut dbg, &mut input, &interrupt).unwrap();
+            if let DebugAction::Quit = action {
+                break 'emu;
+            }
+            debug_mode.store(false, Ordering::Relaxed);
+        }
         if debug_mode.load(Ordering::Relaxed) {
-            sys.ser0_mut().handle_mut().tx.suspend_raw_mode().unwrap();
-            dissasemble(sys.mem(), sys.cpu(), &symbols, None, 1);
+            tty.borrow().suspend_raw_mode().unwrap();
+            dissasemble(
+                &mut io::stdout(),
+                sys.mem(),
+                &dbg.symbols,
+                sys.cpu().pc(),
+                1,
+            )
+            .unwrap();
             let mut cached_parts = Vec::new();
             loop {
                 print!("dbg>");
-                sys.ser0_mut().handle_mut().tx.flush().unwrap();
+                tty.borrow_mut().flush().unwrap();
                 let mut line = Vec::new();
                 // kind of jank, but reads are async, so we busy-wait
                 loop {
+                    // a second ctrl-c while at the prompt quits
+                    if interrupt.swap(false, Ordering::Relaxed) {
+                        println!();
+                        break 'emu;
+                    }
                     let mut buf = [0];
-                    if sys.ser0_mut().handle_mut().rx.read(&mut buf).unwrap() != 1 {
+                    if tty.borrow_mut().rx.read(&mut buf).unwrap() != 1 {
                         continue;
                     }
                     if buf[0] == 0x0A {
@@ -264,780 +1013,575 @@ fn main() -> Result<(), ()> {
                     cached_parts = parts.clone();
                     parts
                 };
-                if !parts.is_empty() {
-                    let arg = parts.get(1).map(String::as_str);
-                    match parts[0].as_str() {
-                        "c" => break,      // continue emulator
-                        "q" => break 'emu, // quit emulator
-                        "s" | "n" => {
-                            // single step
-                            sys.tick();
-                            dissasemble(sys.mem(), sys.cpu(), &symbols, None, 1);
-                        }
-                        "r" => print_cpu_regs(sys.cpu()),
-                        "R" => print_cpu_regs_base10(sys.cpu()),
-                        "RR" => print_cpu_regs_signed_base10(sys.cpu()),
-                        "b" => add_breakpoint(sys.cpu(), &mut breakpoints, &symbols, arg),
-                        "B" => remove_breakpoint(sys.cpu(), &mut breakpoints, &symbols, arg),
-                        "x" => examine(sys.mem(), sys.cpu(), &symbols, arg),
-                        "X" => examine_base10(sys.mem(), sys.cpu(), &symbols, arg),
-                        "XX" => examine_signed_base10(sys.mem(), sys.cpu(), &symbols, arg),
-                        "d" => dissasemble(sys.mem(), sys.cpu(), &symbols, arg, 24),
-                        "?" => print_help(),
-                        _ => println!("unknown command: `{}`. type `?` for help", parts[0]),
-                    }
+                match debug_command(&mut io::stdout(), &mut sys, &mut dbg, &parts).unwrap() {
+                    DebugAction::Prompt => {}
+                    DebugAction::Continue => break,
+                    DebugAction::Quit => break 'emu,
                 }
             }
             // restore raw tty
-            sys.ser0_mut().handle_mut().tx.activate_raw_mode().unwrap();
+            tty.borrow().activate_raw_mode().unwrap();
             debug_mode.store(false, Ordering::Relaxed);
         }
 
-        sys.tick();
+        if let Some(result) = run_batch(&mut sys, &mut dbg, limit) {
+            status = result;
+            break;
+        }
     }
 
-    Ok(())
+    dump_memory(&sys, args.dump.as_deref())?;
+    dump_frame(&sys, &dbg.filters, args.dump_frame_on_exit.as_deref())?;
+    check_golden(&sys, args.golden.as_deref(), args.bless)?;
+    status
+}
+
+/// Apply the checking and debugging options to a freshly built system,
+/// reset it, and load what it starts with
+fn configure_system(
+    sys: &mut System,
+    dbg: &mut Debugger,
+    args: &RunArgs,
+    open_bus: OpenBus,
+    seed: u64,
+    boot: Option<&[u8]>,
+) -> Result<(), ()> {
+    sys.set_unmapped_io(args.unmapped_io);
+    sys.set_open_bus(open_bus, seed);
+    set_mem_check(sys, args.mem_check, &args.read_only);
+    sys.set_exec_check(args.exec_check.unwrap_or(MemCheck::Off), args.smc_window);
+    sys.set_io_trace(args.io_trace);
+    sys.set_strict_cpu(args.strict_cpu);
+    sys.set_aug_traps(args.aug_traps);
+    sys.reset();
+    load_programs(sys, boot, &args.load, args.pc)?;
+    dbg.stack_guard.set(args.stack_guard, sys.cpu());
+    load_hooks(sys, dbg, args.hooks.as_deref())?;
+    start_trace(sys, dbg, args.trace_out.as_deref(), args.trace_format)
+}
+
+fn set_mem_check(sys: &mut System, mode: Option<MemCheck>, read_only: &[(u16, u16)]) {
+    sys.set_mem_check(mode.unwrap_or(MemCheck::Off));
+    for &(start, end) in read_only {
+        sys.set_read_only(start, end, true);
+    }
+}
+
+fn read_script(script: &Path) -> Result<Vec<String>, ()> {
+    let script_file =
+        File::open(script).map_err(|e| tracing::error!("failed to open script: {e}"))?;
+    BufReader::new(script_file)
+        .lines()
+        .collect::<io::Result<Vec<String>>>()
+        .map_err(|e| tracing::error!("failed to read script: {e}"))
 }
 
-fn examine(mem: &Mem, cpu: &Cpu, symbols: &HashMap<u16, Vec<String>>, start: Option<&str>) {
-    let start = if let Some(arg) = start {
-        match parse_addr(symbols, arg) {
-            Ok(addr) => addr,
-            Err(e) => {
-                println!("error parsing start address: {e}");
-                return;
+/// Headless loop that takes its debugger commands from a script
+fn run_script(
+    sys: &mut System,
+    dbg: &mut Debugger,
+    commands: Vec<String>,
+    interrupt: &AtomicBool,
+    limit: Limit,
+) -> Result<u8, ()> {
+    let mut lines = commands.into_iter();
+    let mut stopped = true;
+    loop {
+        if dbg.breakpoints.hit(sys.cpu().pc())
+            || sys.take_fault().is_some()
+            || dbg.hooks.take_stop()
+            || dbg.watchdog.take_fired()
+            || interrupt.swap(false, Ordering::Relaxed)
+        {
+            stopped = true;
+        }
+        while stopped {
+            // running out of commands while stopped ends the run
+            let Some(line) = lines.next() else {
+                return Ok(0);
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            println!("dbg>{line}");
+            let parts = line
+                .split_whitespace()
+                .map(String::from)
+                .collect::<Vec<String>>();
+            match debug_command(&mut io::stdout(), sys, dbg, &parts).unwrap() {
+                DebugAction::Prompt => {}
+                DebugAction::Continue => stopped = false,
+                DebugAction::Quit => return Ok(0),
             }
         }
-    } else {
-        cpu.pc()
-    };
-    let end = ((start as u32) + 16).min(0xFFFF) as u16;
-    print!("{start:04X}  ");
-    for addr in start..=end {
-        print!("{:02X} ", mem.read(addr));
-    }
-    print!(" |");
-    for addr in start..=end {
-        let c = mem.read(addr);
-        if c.is_ascii_graphic() {
-            print!("{}", c as char);
-        } else {
-            print!(".");
+
+        if let Some(result) = run_batch(sys, dbg, limit) {
+            return result;
         }
     }
-    println!("|");
 }
 
-fn examine_base10(mem: &Mem, cpu: &Cpu, symbols: &HashMap<u16, Vec<String>>, start: Option<&str>) {
-    let start = if let Some(arg) = start {
-        match parse_addr(symbols, arg) {
-            Ok(addr) => addr,
-            Err(e) => {
-                println!("error parsing start address: {e}");
-                return;
-            }
+/// Run a `test` ROM until it exits, and fail the run if it stops any other
+/// way
+fn run_test(
+    sys: &mut System,
+    dbg: &mut Debugger,
+    interrupt: &AtomicBool,
+    limit: Limit,
+) -> Result<u8, ()> {
+    loop {
+        if let Some(addr) = sys.take_fault() {
+            tracing::error!("test stopped by an access to {addr:04X}");
+            return Err(());
         }
-    } else {
-        cpu.pc()
-    };
-    let end = ((start as u32) + 16).min(0xFFFF) as u16;
-    print!("{start:05}  ");
-    for addr in start..=end {
-        print!("{:03} ", mem.read(addr));
-    }
-    print!(" |");
-    for addr in start..=end {
-        let c = mem.read(addr);
-        if c.is_ascii_graphic() {
-            print!("{}", c as char);
-        } else {
-            print!(".");
+        if interrupt.swap(false, Ordering::Relaxed) {
+            tracing::error!("test interrupted");
+            return Err(());
+        }
+        if dbg.watchdog.take_fired() {
+            tracing::error!("test hung");
+            return Err(());
+        }
+        if let Some(result) = run_batch(sys, dbg, limit) {
+            return result;
         }
     }
-    println!("|");
 }
 
-fn examine_signed_base10(
-    mem: &Mem,
-    cpu: &Cpu,
-    symbols: &HashMap<u16, Vec<String>>,
-    start: Option<&str>,
-) {
-    let start = if let Some(arg) = start {
-        match parse_addr(symbols, arg) {
-            Ok(addr) => addr,
-            Err(e) => {
-                println!("error parsing start address: {e}");
-                return;
+/// Run up to [`BATCH_TICKS`] instructions, stopping early at a breakpoint,
+/// an access that should break (see [`System::take_fault`]), a hook asking
+/// to stop, or the watchdog firing.
+/// The caller handles breakpoints (counting their hits), signals, and the
+/// debugger between batches, so the instruction at the current PC always
+/// runs.
+fn run_batch(sys: &mut System, dbg: &mut Debugger, limit: Limit) -> Option<Result<u8, ()>> {
+    let check_breakpoints = !dbg.breakpoints.is_empty();
+    let started = Instant::now();
+    let mut result = None;
+    for i in 0..BATCH_TICKS {
+        let waiting = sys.cpu().waiting();
+        if !waiting {
+            if i != 0 && check_breakpoints && dbg.breakpoints.contains(sys.cpu().pc()) {
+                break;
+            }
+            dbg.hooks.before(sys);
+            if dbg.hooks.stopping() {
+                break;
             }
+            mark_executed(sys);
         }
-    } else {
-        cpu.pc()
-    };
-    let end = ((start as u32) + 24).min(0xFFFF) as u16;
-    print!("{start:05}  ");
-    for addr in start..=end {
-        print!("{:+04} ", mem.read(addr) as i8);
-    }
-    print!(" |");
-    for addr in start..=end {
-        let c = mem.read(addr);
-        if c.is_ascii_graphic() {
-            print!("{}", c as char);
+        let traced = dbg.trace_filter.traces(sys.cpu().pc());
+        if !waiting && traced {
+            trace_instruction(sys, &dbg.symbols);
+        }
+        if let (Some(tracer), true) = (&mut dbg.tracer, traced) {
+            tracer.before(sys);
+        }
+        let pc = sys.cpu().pc();
+        let cycles = sys.cpu().cycles();
+        sys.tick();
+        if waiting {
+            dbg.idle.add(sys.cpu().cycles() - cycles);
         } else {
-            print!(".");
+            dbg.profiler.record(pc, sys.cpu().cycles() - cycles);
+        }
+        dbg.stack_guard.check(sys.cpu(), pc);
+        dbg.watchdog.check(sys.cpu(), pc);
+        let accesses = sys.take_accesses();
+        dbg.hooks.after(sys, &accesses);
+        if let Some(tracer) = &mut dbg.tracer {
+            if let Err(e) = tracer.after(sys, &accesses) {
+                tracing::error!("failed to write trace: {e}");
+                dbg.tracer = None;
+            }
+        }
+        if let (Some(recorder), Some(frame)) = (&mut dbg.recorder, sys.frame()) {
+            if let Err(e) = recorder.capture(&frame, &dbg.filters) {
+                tracing::error!("failed to record frame: {e}");
+                dbg.recorder = None;
+            }
+        }
+        result = finished(sys, limit);
+        if result.is_some() || sys.fault_pending() || dbg.hooks.stopping() || dbg.watchdog.fired() {
+            break;
         }
     }
-    println!("|");
+    dbg.idle.sleep();
+    dbg.stats.add_run_time(sys.cpu(), started.elapsed());
+    result
 }
 
-fn add_breakpoint(
-    cpu: &Cpu,
-    breakpoints: &mut Vec<u16>,
-    symbols: &HashMap<u16, Vec<String>>,
-    arg: Option<&str>,
-) {
-    let addr = if let Some(arg) = arg {
-        match parse_addr(symbols, arg) {
-            Ok(addr) => addr,
-            Err(e) => {
-                println!("error parsing address: {e}");
-                return;
-            }
-        }
-    } else {
-        cpu.pc()
+/// Save the frame for the screenshot hotkey, named for its number
+fn take_screenshot(sys: &System, filters: &[Filter]) {
+    let Some(frame) = sys.frame() else {
+        tracing::warn!("the machine has no video output to screenshot");
+        return;
     };
-    if breakpoints.contains(&addr) {
-        println!("breakpoint already exists");
-    } else {
-        breakpoints.push(addr);
-        println!("breakpoint added at {addr:04X}");
+    let path = format!("possum2-{}.png", frame.number);
+    match save_frame(sys, filters, Path::new(&path)) {
+        Ok(()) => tracing::info!("saved frame to {path}"),
+        Err(e) => tracing::error!("failed to save {path}: {e}"),
     }
 }
 
-fn remove_breakpoint(
-    cpu: &Cpu,
-    breakpoints: &mut Vec<u16>,
-    symbols: &HashMap<u16, Vec<String>>,
-    arg: Option<&str>,
-) {
-    let addr = if let Some(arg) = arg {
-        match parse_addr(symbols, arg) {
-            Ok(addr) => addr,
-            Err(e) => {
-                println!("error parsing address: {e}");
-                return;
-            }
-        }
-    } else {
-        cpu.pc()
+fn read_rom(machine: &Machine) -> Result<Vec<u8>, ()> {
+    let Some(rom_path) = &machine.rom else {
+        tracing::error!("no ROM file given");
+        return Err(());
     };
-    if let Some(index) = breakpoints.iter().position(|&a| a == addr) {
-        breakpoints.remove(index);
-        println!("breakpoint removed at {addr:04X}");
-    } else {
-        println!("breakpoint does not exist");
+    let mut rom = Vec::new();
+    File::open(rom_path)
+        .map_err(|e| tracing::error!("failed to open ROM file: {e}"))?
+        .read_to_end(&mut rom)
+        .map_err(|e| tracing::error!("failed to read ROM file: {e}"))?;
+    if rom.is_empty() || rom.len() % ROM_SIZE != 0 || rom.len() > ROM_BANKS * ROM_SIZE {
+        tracing::error!(
+            "ROM file is {} bytes, but it must be 1 to {ROM_BANKS} banks of exactly {ROM_SIZE} bytes (3.75KiB)!",
+            rom.len()
+        );
+        return Err(());
     }
+    Ok(rom)
 }
 
-fn print_help() {
-    println!("debugger commands:");
-    println!("`c`: continue emulator (exiting debugger)");
-    println!("`q`: quit emulator");
-    println!("`s` or `n`: single step cpu");
-    println!("`r`: print cpu registers");
-    println!("`R`: print cpu registers (base 10)");
-    println!("`RR`: print cpu registers (signed base 10)");
-    println!("`b [addr]`: add breakpoint");
-    println!("`B [addr]`: delete breakpoint");
-    println!("`x [start]`: examine memory");
-    println!("`X [start]`: examine memory (base 10)");
-    println!("`XX [start]`: examine memory (signed base 10)");
-    println!("`d [start]`: disassemble memory");
-    println!("`?`: show this help info");
+/// Copy the FD0 boot sectors and `--load` programs into RAM and apply `--pc`
+fn load_programs(
+    sys: &mut System,
+    boot: Option<&[u8]>,
+    loads: &[Load],
+    pc: Option<u16>,
+) -> Result<(), ()> {
+    if let Some(boot) = boot {
+        sys.load(boot::LOAD_ADDR, 0, boot)
+            .map_err(|e| tracing::error!("failed to load the FD0 boot sectors: {e}"))?;
+        tracing::info!(
+            "booting {} bytes from FD0 at {:04X}",
+            boot.len(),
+            boot::ENTRY
+        );
+    }
+    for load in loads {
+        let data = fs::read(&load.path)
+            .map_err(|e| tracing::error!("failed to read {}: {e}", load.path.display()))?;
+        sys.load(load.addr, load.bank, &data)
+            .map_err(|e| tracing::error!("failed to load {}: {e}", load.path.display()))?;
+        tracing::info!(
+            "loaded {} at {}:{:04X}",
+            load.path.display(),
+            load.bank,
+            load.addr
+        );
+    }
+    if let Some(pc) = pc {
+        sys.set_pc(pc);
+    }
+    Ok(())
 }
 
-fn print_cpu_regs(cpu: &Cpu) {
-    print!(
-        "A={:02X} B={:02X} X={:02X} Y={:02X} Z={:02X} PC={:04X} SP={:04X} ",
-        cpu.a(),
-        cpu.b(),
-        cpu.x(),
-        cpu.y(),
-        cpu.z(),
-        cpu.pc(),
-        cpu.sp()
-    );
-    let p = cpu.p();
-    print!("P={:02X} [", p);
-    #[rustfmt::skip]
-    {
-        print!("{}", if (p & Flags::NEGATIVE) == 0 { "-" } else { "N" });
-        print!("{}", if (p & Flags::OVERFLOW) == 0 { "-" } else { "V" });
-        print!("{}", if (p & Flags::EXTEND_STACK_DISABLE) == 0 { "-" } else { "E" });
-        print!("{}", if (p & Flags::BREAK) == 0 { "-" } else { "B" });
-        print!("{}", if (p & Flags::DECIMAL_MODE) == 0 { "-" } else { "D" });
-        print!("{}", if (p & Flags::INTERRUPT_DISABLE) == 0 { "-" } else { "I" });
-        print!("{}", if (p & Flags::ZERO) == 0 { "-" } else { "Z" });
-        print!("{}", if (p & Flags::CARRY) == 0 { "-" } else { "C" });
+fn load_hooks(sys: &mut System, dbg: &mut Debugger, path: Option<&Path>) -> Result<(), ()> {
+    let Some(path) = path else {
+        return Ok(());
     };
-    println!("]");
+    dbg.hooks
+        .load(path, sys)
+        .map_err(|e| tracing::error!("failed to load hooks: {e}"))?;
+    tracing::info!("loaded hooks from {}", path.display());
+    Ok(())
 }
 
-fn print_cpu_regs_base10(cpu: &Cpu) {
-    print!(
-        "A={:03} B={:03} X={:03} Y={:03} Z={:03} PC={:05} SP={:05} ",
-        cpu.a(),
-        cpu.b(),
-        cpu.x(),
-        cpu.y(),
-        cpu.z(),
-        cpu.pc(),
-        cpu.sp()
-    );
-    let p = cpu.p();
-    print!("P={:03} [", p);
-    #[rustfmt::skip]
-    {
-        print!("{}", if (p & Flags::NEGATIVE) == 0 { "-" } else { "N" });
-        print!("{}", if (p & Flags::OVERFLOW) == 0 { "-" } else { "V" });
-        print!("{}", if (p & Flags::EXTEND_STACK_DISABLE) == 0 { "-" } else { "E" });
-        print!("{}", if (p & Flags::BREAK) == 0 { "-" } else { "B" });
-        print!("{}", if (p & Flags::DECIMAL_MODE) == 0 { "-" } else { "D" });
-        print!("{}", if (p & Flags::INTERRUPT_DISABLE) == 0 { "-" } else { "I" });
-        print!("{}", if (p & Flags::ZERO) == 0 { "-" } else { "Z" });
-        print!("{}", if (p & Flags::CARRY) == 0 { "-" } else { "C" });
+fn start_trace(
+    sys: &mut System,
+    dbg: &mut Debugger,
+    path: Option<&Path>,
+    format: trace::Format,
+) -> Result<(), ()> {
+    let Some(path) = path else {
+        return Ok(());
     };
-    println!("]");
+    dbg.tracer = Some(
+        Tracer::create(path, format, sys)
+            .map_err(|e| tracing::error!("failed to start trace: {e}"))?,
+    );
+    Ok(())
 }
 
-fn print_cpu_regs_signed_base10(cpu: &Cpu) {
-    print!(
-        "A={:+04} B={:+04} X={:+04} Y={:+04} Z={:+04} PC={:+06} SP={:+06} ",
-        cpu.a() as i8,
-        cpu.b() as i8,
-        cpu.x() as i8,
-        cpu.y() as i8,
-        cpu.z() as i8,
-        cpu.pc() as i16,
-        cpu.sp() as i16
-    );
-    let p = cpu.p();
-    print!("P={:+04} [", p as i8);
-    #[rustfmt::skip]
-    {
-        print!("{}", if (p & Flags::NEGATIVE) == 0 { "-" } else { "N" });
-        print!("{}", if (p & Flags::OVERFLOW) == 0 { "-" } else { "V" });
-        print!("{}", if (p & Flags::EXTEND_STACK_DISABLE) == 0 { "-" } else { "E" });
-        print!("{}", if (p & Flags::BREAK) == 0 { "-" } else { "B" });
-        print!("{}", if (p & Flags::DECIMAL_MODE) == 0 { "-" } else { "D" });
-        print!("{}", if (p & Flags::INTERRUPT_DISABLE) == 0 { "-" } else { "I" });
-        print!("{}", if (p & Flags::ZERO) == 0 { "-" } else { "Z" });
-        print!("{}", if (p & Flags::CARRY) == 0 { "-" } else { "C" });
-    };
-    println!("]");
+/// When to stop a run that the guest doesn't end
+#[derive(Clone, Copy)]
+struct Limit {
+    max_cycles: Option<u64>,
+    /// Running out of cycles is how the run ends, not a failure (for
+    /// golden runs)
+    expected: bool,
 }
 
-fn dissasemble(
-    mem: &Mem,
-    cpu: &Cpu,
-    symbols: &HashMap<u16, Vec<String>>,
-    start: Option<&str>,
-    count: usize,
-) {
-    let mut addr = if let Some(arg) = start {
-        match parse_addr(symbols, arg) {
-            Ok(addr) => addr,
-            Err(e) => {
-                println!("error parsing start address: {e}");
-                return;
-            }
-        }
-    } else {
-        cpu.pc()
-    };
-    for _ in 0..count {
-        if let Some(labels) = symbols.get(&addr) {
-            println!("{};  {}:{}  ", Fg(LightBlue), labels[0], Fg(Reset));
+/// Whether the guest asked to exit, or ran out of cycles
+fn finished(sys: &System, limit: Limit) -> Option<Result<u8, ()>> {
+    if let Some(status) = sys.exit_status() {
+        return Some(Ok(status));
+    }
+    let cycles = sys.cpu().cycles();
+    if limit
+        .max_cycles
+        .is_some_and(|max_cycles| cycles >= max_cycles)
+    {
+        if limit.expected {
+            tracing::info!("stopped after {cycles} cycles");
+            return Some(Ok(0));
         }
-        let bank = mem.bank(addr);
-        let byte = mem.read(addr);
-        print!(
-            "{bank}:{}{addr:04X}  {}{byte:02X}",
-            Fg(LightYellow),
-            Fg(Reset)
-        );
-        addr += 1;
-        let (name, mode) = find_op(byte).unwrap();
-        match mode {
-            IMM => {
-                let byte = mem.read(addr);
-                addr += 1;
-                print!(" {byte:02X}      ");
-                print!(
-                    "  {}{name} {}#{}${byte:02X}{}               ",
-                    Fg(LightMagenta),
-                    Fg(Reset),
-                    Fg(LightRed),
-                    Fg(Reset),
-                );
-            }
-
-            ABS => {
-                let lo = mem.read(addr);
-                addr += 1;
-                let hi = mem.read(addr);
-                addr += 1;
-                print!(" {lo:02X} {hi:02X}   ");
-                print!(
-                    "  {}{name} {}${hi:02X}{lo:02X}{}          ",
-                    Fg(LightMagenta),
-                    Fg(LightRed),
-                    Fg(Reset),
-                );
-                let addr = ((hi as u16) << 8) | (lo as u16);
-                if let Some(labels) = symbols.get(&addr) {
-                    print!("  {}; {}{}", Fg(LightBlue), labels[0], Fg(Reset));
-                }
-            }
-
-            B => {
-                let byte = mem.read(addr);
-                addr += 1;
-                print!(" {byte:02X}      ");
-                print!(
-                    "  {}{name} {}${byte:02X}{}                ",
-                    Fg(LightMagenta),
-                    Fg(LightRed),
-                    Fg(Reset),
-                );
-            }
-
-            ACCUM => {
-                print!("         ");
-                print!(
-                    "  {}{name} A{}                          ",
-                    Fg(LightMagenta),
-                    Fg(Reset)
-                );
-            }
-
-            IMPL if name == "AUG" => {
-                let lo = mem.read(addr);
-                addr += 1;
-                let mid = mem.read(addr);
-                addr += 1;
-                let hi = mem.read(addr);
-                addr += 1;
-                print!(" {lo:02X} {mid:02X} {hi:02X}");
-                print!(
-                    "  {}{name} {}${hi:02X}${mid:02X}{lo:02X}{}",
-                    Fg(LightMagenta),
-                    Fg(LightRed),
-                    Fg(Reset)
-                );
-            }
-
-            IMPL if name == "BRK" => {
-                let byte = mem.read(addr);
-                addr += 1;
-                print!(" {byte:02X}      ");
-                print!(
-                    "  {}{name} {}#{}${byte:02X}{}               ",
-                    Fg(LightMagenta),
-                    Fg(Reset),
-                    Fg(LightRed),
-                    Fg(Reset)
-                );
-            }
-
-            IMPL if name == "RTN" => {
-                let byte = mem.read(addr);
-                addr += 1;
-                print!(" {byte:02X}      ");
-                print!(
-                    "  {}{name} {}#{}${byte:02X}{}               ",
-                    Fg(LightMagenta),
-                    Fg(Reset),
-                    Fg(LightRed),
-                    Fg(Reset)
-                );
-            }
-
-            IMPL => {
-                print!("         ");
-                print!(
-                    "  {}{name}{}                            ",
-                    Fg(LightMagenta),
-                    Fg(Reset)
-                );
-            }
-
-            IND_X => {
-                let byte = mem.read(addr);
-                addr += 1;
-                print!(" {byte:02X}      ");
-                print!(
-                    "  {}{name} {}({}${byte:02X}{},{}X{})            ",
-                    Fg(LightMagenta),
-                    Fg(Reset),
-                    Fg(LightRed),
-                    Fg(Reset),
-                    Fg(LightMagenta),
-                    Fg(Reset),
-                );
-            }
-
-            IND_Y => {
-                let byte = mem.read(addr);
-                addr += 1;
-                print!(" {byte:02X}      ");
-                print!(
-                    "  {}{name} {}({}${byte:02X}{}),{}Y{}            ",
-                    Fg(LightMagenta),
-                    Fg(Reset),
-                    Fg(LightRed),
-                    Fg(Reset),
-                    Fg(LightMagenta),
-                    Fg(Reset),
-                );
-            }
-
-            IND_Z => {
-                let byte = mem.read(addr);
-                addr += 1;
-                print!(" {byte:02X}      ");
-                print!(
-                    "  {}{name} {}({}${byte:02X}{}),{}Z{}            ",
-                    Fg(LightMagenta),
-                    Fg(Reset),
-                    Fg(LightRed),
-                    Fg(Reset),
-                    Fg(LightMagenta),
-                    Fg(Reset),
-                );
-            }
-
-            IND_SP => {
-                let byte = mem.read(addr);
-                addr += 1;
-                print!(" {byte:02X}      ");
-                print!(
-                    "  {}{name} {}({}${byte:02X}{},{}SP{}),{}Y{}         ",
-                    Fg(LightMagenta),
-                    Fg(Reset),
-                    Fg(LightRed),
-                    Fg(Reset),
-                    Fg(LightMagenta),
-                    Fg(Reset),
-                    Fg(LightMagenta),
-                    Fg(Reset),
-                );
-            }
-
-            B_X => {
-                let byte = mem.read(addr);
-                addr += 1;
-                print!(" {byte:02X}      ");
-                print!(
-                    "  {}{name} {}${byte:02X}{},{}X{}              ",
-                    Fg(LightMagenta),
-                    Fg(LightRed),
-                    Fg(Reset),
-                    Fg(LightMagenta),
-                    Fg(Reset),
-                );
-            }
-
-            B_Y => {
-                let byte = mem.read(addr);
-                addr += 1;
-                print!(" {byte:02X}      ");
-                print!("  {name} ${byte:02X},Y              ");
-            }
-
-            ABS_X => {
-                let lo = mem.read(addr);
-                addr += 1;
-                let hi = mem.read(addr);
-                addr += 1;
-                print!(" {lo:02X} {hi:02X}   ");
-                print!(
-                    "  {}{name} {}${hi:02X}{lo:02X}{},{}X{}        ",
-                    Fg(LightMagenta),
-                    Fg(LightRed),
-                    Fg(Reset),
-                    Fg(LightMagenta),
-                    Fg(Reset)
-                );
-                let addr = ((hi as u16) << 8) | (lo as u16);
-                if let Some(labels) = symbols.get(&addr) {
-                    print!("  {}; {}{}", Fg(LightBlue), labels[0], Fg(Reset));
-                }
-            }
+        tracing::error!("gave up after {cycles} cycles");
+        return Some(Err(()));
+    }
+    None
+}
 
-            ABS_Y => {
-                let lo = mem.read(addr);
-                addr += 1;
-                let hi = mem.read(addr);
-                addr += 1;
-                print!(" {lo:02X} {hi:02X}   ");
-                print!(
-                    "  {}{name} {}${hi:02X}{lo:02X}{},{}Y{}        ",
-                    Fg(LightMagenta),
-                    Fg(LightRed),
-                    Fg(Reset),
-                    Fg(LightMagenta),
-                    Fg(Reset)
-                );
-                let addr = ((hi as u16) << 8) | (lo as u16);
-                if let Some(labels) = symbols.get(&addr) {
-                    print!("  ; {}", labels[0]);
-                }
-            }
+fn check_golden(sys: &System, path: Option<&Path>, bless: bool) -> Result<(), ()> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+    golden::check(sys, path, bless).map_err(|e| tracing::error!("golden check failed: {e}"))?;
+    if bless {
+        tracing::info!("saved golden hashes to {}", path.display());
+    }
+    Ok(())
+}
 
-            REL => {
-                let byte = mem.read(addr);
-                addr += 1;
-                print!(" {byte:02X}      ");
-                print!(
-                    "  {}{name} {}${byte:02X}{}            ",
-                    Fg(LightMagenta),
-                    Fg(LightRed),
-                    Fg(Reset)
-                );
-                let addr = addr.wrapping_add_signed((byte as i8) as i16);
-                if let Some(labels) = symbols.get(&addr) {
-                    print!("  {}; {}{}", Fg(LightBlue), labels[0], Fg(Reset));
-                } else {
-                    print!("  {}; {addr:04X}{}", Fg(LightBlue), Fg(Reset));
-                }
-            }
+fn dump_memory(sys: &System, path: Option<&Path>) -> Result<(), ()> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+    let dump = (0x0000..=0xFFFF)
+        .map(|addr| sys.mem().read(addr))
+        .collect::<Vec<u8>>();
+    File::create(path)
+        .and_then(|mut file| file.write_all(&dump))
+        .map_err(|e| tracing::error!("failed to dump memory: {e}"))
+}
 
-            WREL => {
-                let lo = mem.read(addr);
-                addr += 1;
-                let hi = mem.read(addr);
-                addr += 1;
-                print!(" {lo:02X} {hi:02X}   ");
-                print!(
-                    "  {}{name} {}${hi:02X}{lo:02X}{}          ",
-                    Fg(LightMagenta),
-                    Fg(LightRed),
-                    Fg(Reset)
-                );
-                let addr = addr.wrapping_add_signed((((hi as u16) << 8) | (lo as u16)) as i16);
-                if let Some(labels) = symbols.get(&addr) {
-                    print!("  {}; {}{}", Fg(LightBlue), labels[0], Fg(Reset));
-                } else {
-                    print!("  {}; {addr:04X}{}", Fg(LightBlue), Fg(Reset));
-                }
-            }
+fn dump_frame(sys: &System, filters: &[Filter], path: Option<&Path>) -> Result<(), ()> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+    save_frame(sys, filters, path).map_err(|e| tracing::error!("failed to dump frame: {e}"))
+}
 
-            IND_ABS => {
-                let lo = mem.read(addr);
-                addr += 1;
-                let hi = mem.read(addr);
-                addr += 1;
-                print!(" {lo:02X} {hi:02X}   ");
-                print!(
-                    "  {}{name} {}({}${hi:02X}{lo:02X}{})        ",
-                    Fg(LightMagenta),
-                    Fg(Reset),
-                    Fg(LightRed),
-                    Fg(Reset)
-                );
-                let addr = ((hi as u16) << 8) | (lo as u16);
-                if let Some(labels) = symbols.get(&addr) {
-                    print!("  {}; {}{}", Fg(LightBlue), labels[0], Fg(Reset));
-                }
-            }
+/// The host ends of the devices that take input
+struct Ports {
+    ser0: Port,
+    ser1: Port,
+    kbd: Port,
+}
 
-            B_REL => {
-                let lo = mem.read(addr);
-                addr += 1;
-                let hi = mem.read(addr);
-                addr += 1;
-                print!(" {lo:02X} {hi:02X}   ");
-                print!(
-                    "  {}{name} {}${hi:02X}{},{}${lo:02X}{}        ",
-                    Fg(LightMagenta),
-                    Fg(LightRed),
-                    Fg(Reset),
-                    Fg(LightRed),
-                    Fg(Reset)
-                );
-                let addr = ((hi as u16) << 8) | (lo as u16);
-                if let Some(labels) = symbols.get(&addr) {
-                    print!("  {}; {}{}", Fg(LightBlue), labels[0], Fg(Reset));
-                }
-            }
+fn open_ports(
+    ser0: &Spec,
+    ser1: &Spec,
+    kbd: &Spec,
+    console: &mut dyn FnMut(&str) -> Box<dyn Console>,
+) -> Result<Ports, ()> {
+    let open = |spec, name, console: &mut dyn FnMut(&str) -> Box<dyn Console>| {
+        Port::open(spec, name, console)
+            .map_err(|e| tracing::error!("failed to open {name} backend: {e}"))
+    };
+    Ok(Ports {
+        ser0: open(ser0, "ser0", console)?,
+        ser1: open(ser1, "ser1", console)?,
+        kbd: open(kbd, "kbd", console)?,
+    })
+}
 
-            IND_ABS_X => {
-                let lo = mem.read(addr);
-                addr += 1;
-                let hi = mem.read(addr);
-                addr += 1;
-                print!(" {lo:02X} {hi:02X}   ");
-                print!(
-                    "  {}{name} {}({}${hi:02X}{lo:02X}{},{}X{})      ",
-                    Fg(LightMagenta),
-                    Fg(Reset),
-                    Fg(LightRed),
-                    Fg(Reset),
-                    Fg(LightMagenta),
-                    Fg(Reset)
-                );
-                let addr = ((hi as u16) << 8) | (lo as u16);
-                if let Some(labels) = symbols.get(&addr) {
-                    print!("  {}; {}{}", Fg(LightBlue), labels[0], Fg(Reset));
-                }
-            }
-            _ => unreachable!(),
-        }
-        println!();
+fn build_system(
+    machine: &Machine,
+    rom: &[u8],
+    ports: Ports,
+    fd0: Disk,
+    fd1: Disk,
+    host_dir: Option<&Path>,
+) -> Result<System, ()> {
+    let mut sys = System::new(machine.ram_banks, rom);
+    let mut slots = Vec::new();
+    if let Some(ser0) = &machine.ser0 {
+        slots.push(Slot {
+            name: "ser0",
+            base: ser0.base,
+            size: 4,
+            irq: IrqSource::SER0,
+            drq: 0,
+            divisor: divisor(machine, uart::TICK_RATE),
+            device: Box::new(Uart::new(ports.ser0)),
+            extra: None,
+        });
+    }
+    if let Some(ser1) = &machine.ser1 {
+        slots.push(Slot {
+            name: "ser1",
+            base: ser1.base,
+            size: 4,
+            irq: IrqSource::SER1,
+            drq: 0,
+            divisor: divisor(machine, uart::TICK_RATE),
+            device: Box::new(Uart::new(ports.ser1)),
+            extra: None,
+        });
+    }
+    if let Some(timer) = &machine.timer {
+        slots.push(Slot {
+            name: "timer",
+            base: timer.base,
+            size: 4,
+            irq: IrqSource::TIMER,
+            drq: 0,
+            divisor: 1,
+            device: Box::new(Timer::new()),
+            extra: None,
+        });
+    }
+    if let Some(ppu) = &machine.ppu {
+        slots.push(Slot {
+            name: "ppu",
+            base: ppu.base,
+            size: 13,
+            irq: IrqSource::PPU,
+            drq: 0,
+            divisor: divisor(machine, ppu::TICK_RATE),
+            device: Box::new(Ppu::new()),
+            extra: None,
+        });
     }
+    if let Some(fdc0) = &machine.fdc0 {
+        slots.push(Slot {
+            name: "fdc0",
+            base: fdc0.base,
+            size: 4,
+            irq: IrqSource::FDC0,
+            drq: IrqSource::FDC0_DRQ,
+            divisor: divisor(machine, fdc::TICK_RATE),
+            device: Box::new(Fdc::new(fd0, fdc0.first_sector)),
+            extra: fdc0.dma.map(|base| (base, 3)),
+        });
+    }
+    if let Some(fdc1) = &machine.fdc1 {
+        slots.push(Slot {
+            name: "fdc1",
+            base: fdc1.base,
+            size: 4,
+            irq: IrqSource::FDC1,
+            drq: IrqSource::FDC1_DRQ,
+            divisor: divisor(machine, fdc::TICK_RATE),
+            device: Box::new(Fdc::new(fd1, fdc1.first_sector)),
+            extra: fdc1.dma.map(|base| (base, 3)),
+        });
+    }
+    if let Some(keyboard) = &machine.keyboard {
+        let layout = match &keyboard.layout {
+            Some(path) => Layout::load(path)
+                .map_err(|e| tracing::error!("failed to load keyboard layout: {e}"))?,
+            None => Layout::default(),
+        };
+        slots.push(Slot {
+            name: "kbd",
+            base: keyboard.base,
+            size: 2,
+            irq: 0,
+            drq: 0,
+            divisor: divisor(machine, keyboard::TICK_RATE),
+            device: Box::new(Keyboard::new(ports.kbd, layout)),
+            extra: None,
+        });
+    }
+    if let Some(rng) = &machine.rng {
+        slots.push(Slot {
+            name: "rng",
+            base: rng.base,
+            size: 1,
+            irq: 0,
+            drq: 0,
+            // it has nothing to do on ticks
+            divisor: divisor(machine, 1),
+            device: Box::new(Rng::new(rng.seed.unwrap_or(0))),
+            extra: None,
+        });
+    }
+    if let Some(dir) = host_dir {
+        slots.push(Slot {
+            name: "hostfs",
+            base: hostfs::BASE,
+            size: hostfs::SIZE,
+            irq: 0,
+            drq: 0,
+            divisor: divisor(machine, 1),
+            device: Box::new(HostFs::new(dir.to_path_buf())),
+            extra: None,
+        });
+    }
+    for slot in slots {
+        sys.attach(slot)
+            .map_err(|e| tracing::error!("invalid machine config: {e}"))?;
+    }
+    Ok(sys)
 }
 
-fn parse_addr(symbols: &HashMap<u16, Vec<String>>, arg: &str) -> Result<u16, ParseIntError> {
-    match u16::from_str_radix(arg, 16) {
-        Ok(addr) => Ok(addr),
-        Err(e) => {
-            for (addr, labels) in symbols {
-                for label in labels {
-                    if label == arg {
-                        return Ok(*addr);
-                    }
-                }
-            }
-            Err(e)
+/// CPU cycles per tick of a device that runs at `tick_rate`
+fn divisor(machine: &Machine, tick_rate: u32) -> u32 {
+    (machine.clock_hz / tick_rate as u64).clamp(1, u32::MAX as u64) as u32
+}
+
+fn mkdisk(image: &Path, boot: Option<&Path>, force: bool) -> Result<(), ()> {
+    let mut disk = vec![0; DISK_SIZE];
+    if let Some(path) = boot {
+        let data = fs::read(path).map_err(|e| tracing::error!("failed to read boot file: {e}"))?;
+        if data.len() > disk.len() {
+            tracing::error!(
+                "boot file is {} bytes, which won't fit on a disk",
+                data.len()
+            );
+            return Err(());
         }
+        disk[..data.len()].copy_from_slice(&data);
     }
+    let mut file = File::options()
+        .write(true)
+        .create(true)
+        .create_new(!force)
+        .truncate(true)
+        .open(image)
+        .map_err(|e| tracing::error!("failed to create {}: {e}", image.display()))?;
+    file.write_all(&disk)
+        .map_err(|e| tracing::error!("failed to write {}: {e}", image.display()))
 }
 
-fn find_op(byte: u8) -> Option<(&'static str, u8)> {
-    for (op, modes) in OPS {
-        for (mode, opcode) in *modes {
-            if *opcode == byte {
-                return Some((op, *mode));
-            }
+fn open_disk(name: &str, drive: Option<&machine::Drive>) -> Result<Disk, ()> {
+    let Some(drive) = drive else {
+        return Ok(Disk::Empty);
+    };
+    let Some(path) = &drive.image else {
+        if drive.overlay.is_some() {
+            tracing::error!("{name} has an overlay, but no image to lay it over");
+            return Err(());
         }
+        return Ok(Disk::Empty);
+    };
+    let len = fs::metadata(path)
+        .map_err(|e| tracing::error!("failed to open {name} file: {e}"))?
+        .len();
+    if len != DISK_SIZE as u64 {
+        tracing::error!(
+            "{name} file is {len} bytes, but it must be exactly {} bytes (640KiB) in length!",
+            DISK_SIZE
+        );
+        return Err(());
     }
-    None
+    // the base image is only read while an overlay takes the writes
+    if let Some(overlay) = &drive.overlay {
+        let overlay = Overlay::open(path, overlay)
+            .map_err(|e| tracing::error!("failed to open {name} overlay: {e}"))?;
+        return Ok(Disk::Overlay(overlay));
+    }
+    let file = File::options()
+        .write(true)
+        .read(true)
+        .open(path)
+        .map_err(|e| tracing::error!("failed to open {name} file: {e}"))?;
+    let inner = (unsafe { MmapMut::map_mut(&file) })
+        .map_err(|e| tracing::error!("failed to map {name} file: {e}"))?;
+    Ok(Disk::Image(MemMap { inner, offset: 0 }))
 }
-
-const IMM: u8 = 0;
-const ABS: u8 = 1;
-const B: u8 = 2;
-const ACCUM: u8 = 3;
-const IMPL: u8 = 4;
-const IND_X: u8 = 5; // (B,X)
-const IND_Y: u8 = 6; // (B),Y
-const IND_Z: u8 = 7; // (B),Z
-const IND_SP: u8 = 8; // (d,SP),Y
-const B_X: u8 = 9; // B,X
-const B_Y: u8 = 10; // B,Y
-const ABS_X: u8 = 11;
-const ABS_Y: u8 = 12;
-const REL: u8 = 13;
-const WREL: u8 = 14;
-const IND_ABS: u8 = 15; // (ABS)
-const B_REL: u8 = 16;
-const IND_ABS_X: u8 = 17; // (ABS,X)
-
-type Op = (&'static str, &'static [(u8, u8)]);
-
-#[rustfmt::skip]
-const OPS: &[Op] = &[
-    ("AUG", &[(IMPL, 0x5C)]), // special
-    ("BRK", &[(IMPL, 0x00)]), // special
-    ("CLC", &[(IMPL, 0x18)]),
-    ("CLD", &[(IMPL, 0xD8)]),
-    ("CLE", &[(IMPL, 0x02)]),
-    ("CLI", &[(IMPL, 0x58)]),
-    ("CLV", &[(IMPL, 0xB8)]),
-    ("DEX", &[(IMPL, 0xCA)]),
-    ("DEY", &[(IMPL, 0x88)]),
-    ("DEZ", &[(IMPL, 0x3B)]),
-    ("INX", &[(IMPL, 0xE8)]),
-    ("INY", &[(IMPL, 0xC8)]),
-    ("INZ", &[(IMPL, 0x1B)]),
-    ("NOP", &[(IMPL, 0xEA)]),
-    ("PHA", &[(IMPL, 0x48)]),
-    ("PHP", &[(IMPL, 0x08)]),
-    ("PHX", &[(IMPL, 0xDA)]),
-    ("PHY", &[(IMPL, 0x5A)]),
-    ("PHZ", &[(IMPL, 0xDB)]),
-    ("PLA", &[(IMPL, 0x68)]),
-    ("PLP", &[(IMPL, 0x28)]),
-    ("PLX", &[(IMPL, 0xFA)]),
-    ("PLY", &[(IMPL, 0x7A)]),
-    ("PLZ", &[(IMPL, 0xFB)]),
-    ("RTI", &[(IMPL, 0x40)]),
-    ("RTN", &[(IMPL, 0x62)]), // special
-    ("RTS", &[(IMPL, 0x60)]),
-    ("SEC", &[(IMPL, 0x38)]),
-    ("SED", &[(IMPL, 0xF8)]),
-    ("SEE", &[(IMPL, 0x03)]),
-    ("SEI", &[(IMPL, 0x78)]),
-    ("TAB", &[(IMPL, 0x5B)]),
-    ("TAX", &[(IMPL, 0xAA)]),
-    ("TAY", &[(IMPL, 0xA8)]),
-    ("TBA", &[(IMPL, 0x7B)]),
-    ("TSX", &[(IMPL, 0xBA)]),
-    ("TSY", &[(IMPL, 0x0B)]),
-    ("TXA", &[(IMPL, 0x8A)]),
-    ("TXS", &[(IMPL, 0x9A)]),
-    ("TYA", &[(IMPL, 0x98)]),
-    ("TYS", &[(IMPL, 0x2B)]),
-    ("TZA", &[(IMPL, 0x6B)]),
-
-    ("ADC", &[(IMM, 0x69), (ABS, 0x6D), (B, 0x65), (IND_X, 0x61), (IND_Y, 0x71), (IND_Z, 0x72), (B_X, 0x75), (ABS_X, 0x7D), (ABS_Y, 0x79)]),
-    ("AND", &[(IMM, 0x29), (ABS, 0x2D), (B, 0x25), (IND_X, 0x21), (IND_Y, 0x31), (IND_Z, 0x32), (B_X, 0x35), (ABS_X, 0x3D), (ABS_Y, 0x39)]),
-    ("ASL", &[(ABS, 0x0E), (B, 0x06), (ACCUM, 0x0A), (B_X, 0x16), (ABS_X, 0x1E)]),
-    ("ASR", &[(B, 0x44), (ACCUM, 0x43), (B_X, 0x54)]),
-    ("ASW", &[(ABS, 0xCB)]),
-    ("BIT", &[(IMM, 0x89), (ABS, 0x2C), (B, 0x24), (B_X, 0x34), (ABS_X, 0x3C)]),
-    ("BBR", &[(B_REL, 0x0F), (B_REL, 0x1F), (B_REL, 0x2F), (B_REL, 0x3F), (B_REL, 0x4F), (B_REL, 0x5F), (B_REL, 0x6F), (B_REL, 0x7F)]), // special
-    ("BBS", &[(B_REL, 0x8F), (B_REL, 0x9F), (B_REL, 0xAF), (B_REL, 0xBF), (B_REL, 0xCF), (B_REL, 0xDF), (B_REL, 0xEF), (B_REL, 0xFF)]), // special
-    ("BCC", &[(REL, 0x90), (WREL, 0x93)]),
-    ("BCS", &[(REL, 0xB0), (WREL, 0xB3)]),
-    ("BEQ", &[(REL, 0xF0), (WREL, 0xF3)]),
-    ("BMI", &[(REL, 0x30), (WREL, 0x33)]),
-    ("BNE", &[(REL, 0xD0), (WREL, 0xD3)]),
-    ("BPL", &[(REL, 0x10), (WREL, 0x13)]),
-    ("BRU", &[(REL, 0x80), (WREL, 0x83)]),
-    ("BSR", &[(WREL, 0x63)]),
-    ("BVC", &[(REL, 0x50), (WREL, 0x53)]),
-    ("BVS", &[(REL, 0x70), (WREL, 0x73)]),
-    ("CMP", &[(IMM, 0xC9), (ABS, 0xCD), (B, 0xC5), (IND_X, 0xC1), (IND_Y, 0xD1), (IND_Z, 0xD2), (B_X, 0xD5), (ABS_X, 0xDD), (ABS_Y, 0xD9)]),
-    ("CPX", &[(IMM, 0xE0), (ABS, 0xEC), (B, 0xE4)]),
-    ("CPY", &[(IMM, 0xC0), (ABS, 0xCC), (B, 0xC4)]),
-    ("CPZ", &[(IMM, 0xC2), (ABS, 0xDC), (B, 0xD4)]),
-    ("DEC", &[(ABS, 0xCE), (B, 0xC6), (ACCUM, 0x3A), (B_X, 0xD6), (ABS_X, 0xDE)]),
-    ("EOR", &[(IMM, 0x49), (ABS, 0x4D), (B, 0x45), (IND_X, 0x41), (IND_Y, 0x51), (IND_Z, 0x52), (B_X, 0x55), (ABS_X, 0x5D), (ABS_Y, 0x59)]),
-    ("INC", &[(ABS, 0xEE), (B, 0xE6), (ACCUM, 0x1A), (B_X, 0xF6), (ABS_X, 0xFE)]),
-    ("INW", &[(B, 0xE3)]),
-    ("JMP", &[(ABS, 0x4C), (IND_ABS, 0x6C), (IND_ABS_X, 0x7C)]),
-    ("JSR", &[(ABS, 0x20), (IND_ABS, 0x22), (IND_ABS_X, 0x23)]),
-    ("LDA", &[(IMM, 0xA9), (ABS, 0xAD), (B, 0xA5), (IND_X, 0xA1), (IND_Y, 0xB1), (IND_Z, 0xB2), (IND_SP, 0xE2), (B_X, 0xB5), (ABS_X, 0xBD), (ABS_Y, 0xB9)]),
-    ("LDX", &[(IMM, 0xA2), (ABS, 0xAE), (B, 0xA6), (B_Y, 0xB6), (ABS_Y, 0xBE)]),
-    ("LDY", &[(IMM, 0xA0), (ABS, 0xAC), (B, 0xA4), (B_X, 0xB4), (ABS_X, 0xBC)]),
-    ("LDZ", &[(IMM, 0xA3), (ABS, 0xAB), (ABS_X, 0xBB)]),
-    ("LSR", &[(ABS, 0x4E), (B, 0x46), (ACCUM, 0x4A), (B_X, 0x56), (ABS_X, 0x5E)]),
-    ("NEG", &[(ACCUM, 0x42)]),
-    ("ORA", &[(IMM, 0x09), (ABS, 0x0D), (B, 0x05), (IND_X, 0x01), (IND_Y, 0x11), (IND_Z, 0x12), (B_X, 0x15), (ABS_X, 0x1D), (ABS_Y, 0x19)]),
-    ("RMB", &[(B, 0x07), (B, 0x17), (B, 0x27), (B, 0x37), (B, 0x47), (B, 0x57), (B, 0x67), (B, 0x77)]), // special
-    ("ROL", &[(ABS, 0x2E), (B, 0x26), (ACCUM, 0x2A), (B_X, 0x36), (ABS_X, 0x3E)]),
-    ("ROR", &[(ABS, 0x6E), (B, 0x66), (ACCUM, 0x6A), (B_X, 0x76), (ABS_X, 0x7E)]),
-    ("ROW", &[(ABS, 0xEB)]),
-    ("SBC", &[(IMM, 0xE9), (ABS, 0xED), (B, 0xE5), (IND_X, 0xE1), (IND_Y, 0xF1), (IND_Z, 0xF2), (B_X, 0xF5), (ABS_X, 0xFD), (ABS_Y, 0xF9)]),
-    ("SMB", &[(B, 0x87), (B, 0x97), (B, 0xA7), (B, 0xB7), (B, 0xC7), (B, 0xD7), (B, 0xE7), (B, 0xF7)]), // special
-    ("STA", &[(ABS, 0x8D), (B, 0x85), (IND_X, 0x81), (IND_Y, 0x91), (IND_Z, 0x92), (IND_SP, 0x82), (B_X, 0x95), (ABS_X, 0x9D), (ABS_Y, 0x99)]),
-    ("STX", &[(ABS, 0x8E), (B, 0x86), (ABS_Y, 0x96), (ABS_Y, 0x9B)]),
-    ("STY", &[(ABS, 0x8C), (B, 0x84), (ABS_X, 0x94), (ABS_X, 0x8B)]),
-    ("STZ", &[(ABS, 0x9C), (B, 0x64), (ABS_X, 0x74), (ABS_X, 0x9E)]),
-    ("TRB", &[(ABS, 0x1C), (B, 0x14)]), // xfer reset bits, M[addr] &= ~A
-    ("TSB", &[(ABS, 0x0C), (B, 0x04)]), // xfer set bits, M[addr] |= A
-];
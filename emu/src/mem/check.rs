@@ -0,0 +1,192 @@
+//! Memory Checks
+//!
+//! Catches the CPU:
+//! * reading RAM that nothing has written since power on
+//! * writing to ranges marked read-only (addresses as the CPU sees them,
+//!   whatever bank is selected)
+//!
+//! And separately, since loaders legitimately run code they just copied:
+//! * executing from the IO window
+//! * executing code the CPU wrote within the last few cycles (DMA doesn't
+//!   count)
+//!
+//! Each address is logged once when warning. Breaking logs every access
+//! and stops in the debugger.
+
+use std::{fmt, str::FromStr};
+
+use super::Mem;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemCheck {
+    Off,
+    Warn,
+    Break,
+}
+
+impl FromStr for MemCheck {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(MemCheck::Off),
+            "warn" => Ok(MemCheck::Warn),
+            "break" => Ok(MemCheck::Break),
+            _ => Err(format!("expected `off`, `warn`, or `break`: `{s}`")),
+        }
+    }
+}
+
+impl fmt::Display for MemCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MemCheck::Off => "off",
+            MemCheck::Warn => "warn",
+            MemCheck::Break => "break",
+        })
+    }
+}
+
+enum Warned {}
+
+impl Warned {
+    const ACCESS: u8 = 1 << 0;
+    const EXEC: u8 = 1 << 1;
+}
+
+pub struct Checker {
+    mode: MemCheck,
+    read_only: Vec<bool>,
+    exec_mode: MemCheck,
+    /// How many cycles after being written code still counts as modified
+    window: u64,
+    /// When the CPU last wrote each address (`u64::MAX` for never)
+    written_at: Vec<u64>,
+    /// `Warned` flags for each address
+    warned: Vec<u8>,
+    /// The access waiting to stop the emulator
+    fault: Option<u16>,
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        Self {
+            mode: MemCheck::Off,
+            read_only: vec![false; 0x10000],
+            exec_mode: MemCheck::Off,
+            window: 0,
+            written_at: vec![u64::MAX; 0x10000],
+            warned: vec![0; 0x10000],
+            fault: None,
+        }
+    }
+
+    pub fn mode(&self) -> MemCheck {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: MemCheck) {
+        self.mode = mode;
+        for warned in &mut self.warned {
+            *warned &= !Warned::ACCESS;
+        }
+    }
+
+    pub fn exec_mode(&self) -> (MemCheck, u64) {
+        (self.exec_mode, self.window)
+    }
+
+    /// Check where the CPU executes from, counting code written in the
+    /// last `window` cycles as modified
+    pub fn set_exec_mode(&mut self, mode: MemCheck, window: u64) {
+        self.exec_mode = mode;
+        self.window = window;
+        for warned in &mut self.warned {
+            *warned &= !Warned::EXEC;
+        }
+    }
+
+    pub fn set_read_only(&mut self, start: u16, end: u16, read_only: bool) {
+        self.read_only[start as usize..=end as usize].fill(read_only);
+    }
+
+    /// The read-only ranges, as first and last addresses
+    pub fn read_only(&self) -> Vec<(u16, u16)> {
+        let mut ranges: Vec<(u16, u16)> = Vec::new();
+        for addr in (0..=0xFFFF).filter(|&addr| self.read_only[addr as usize]) {
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == addr => *end = addr,
+                _ => ranges.push((addr, addr)),
+            }
+        }
+        ranges
+    }
+
+    pub fn take_fault(&mut self) -> Option<u16> {
+        self.fault.take()
+    }
+
+    pub fn fault_pending(&self) -> bool {
+        self.fault.is_some()
+    }
+
+    /// Check a read by the instruction at `pc`
+    #[inline]
+    pub fn read(&mut self, mem: &Mem, pc: u16, addr: u16) {
+        if self.mode != MemCheck::Off && !mem.initialized(addr) {
+            self.report(self.mode, Warned::ACCESS, addr, || {
+                format!("{pc:04X} read from uninitialized address {addr:04X}")
+            });
+        }
+    }
+
+    /// Check a write by the instruction at `pc`, which started at `cycles`
+    #[inline]
+    pub fn write(&mut self, pc: u16, cycles: u64, addr: u16) {
+        if self.exec_mode != MemCheck::Off {
+            self.written_at[addr as usize] = cycles;
+        }
+        if self.mode != MemCheck::Off && self.read_only[addr as usize] {
+            self.report(self.mode, Warned::ACCESS, addr, || {
+                format!("{pc:04X} write to read-only address {addr:04X}")
+            });
+        }
+    }
+
+    /// Check the instruction about to run at `pc`
+    #[inline]
+    pub fn exec(&mut self, pc: u16, cycles: u64) {
+        if self.exec_mode == MemCheck::Off {
+            return;
+        }
+        if (0xF000..=0xF0FF).contains(&pc) {
+            self.report(self.exec_mode, Warned::EXEC, pc, || {
+                format!("executing from the IO window at {pc:04X}")
+            });
+            return;
+        }
+        let written_at = self.written_at[pc as usize];
+        if written_at != u64::MAX && cycles - written_at <= self.window {
+            self.report(self.exec_mode, Warned::EXEC, pc, || {
+                format!(
+                    "executing code written {} cycles ago at {pc:04X}",
+                    cycles - written_at
+                )
+            });
+        }
+    }
+
+    fn report(&mut self, mode: MemCheck, kind: u8, addr: u16, message: impl FnOnce() -> String) {
+        match mode {
+            MemCheck::Warn if (self.warned[addr as usize] & kind) == 0 => {
+                self.warned[addr as usize] |= kind;
+                tracing::warn!("{} (further accesses not logged)", message());
+            }
+            MemCheck::Break => {
+                tracing::warn!("{}", message());
+                self.fault = Some(addr);
+            }
+            _ => {}
+        }
+    }
+}
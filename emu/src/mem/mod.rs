@@ -0,0 +1,208 @@
+//! Banked Memory
+//!
+//! The address space is split into 16 4K "chapters". Chapters 0-E are
+//! RAM, and each can be switched between 4 banks through the bank select
+//! registers at F000-F00E. Chapter F holds the IO window (F000-F0FF),
+//! which is decoded by the system bus and never reaches memory, followed
+//! by the ROM (F100-FFFF), which is write-protected.
+//!
+//! ROM images can hold up to 256 banks of 3840 bytes, switched as a whole
+//! through the ROM bank select register at F00F. Each bank sees the same
+//! window, so every bank needs its own vectors and the code doing the
+//! switching has to be at the same address in each one.
+//!
+//! Every RAM byte remembers whether it has been written, for [`check`].
+
+pub mod check;
+
+#[cfg(test)]
+mod tests;
+
+pub use possum2_media::{ROM_SIZE, ROM_START};
+
+pub const CHAPTER_SIZE: usize = 0x1000;
+pub const RAM_CHAPTERS: usize = 15;
+pub const RAM_BANKS: usize = 4;
+pub const IO_START: u16 = 0xF000;
+pub const ROM_BANKS: usize = 256;
+
+enum Region {
+    Ram(usize),
+    Io,
+    Rom(usize),
+}
+
+pub struct Mem {
+    ram: Vec<u8>,
+    /// Which RAM bytes have been written since power on
+    written: Vec<bool>,
+    rom: Vec<u8>,
+    banks: usize,
+    bank_select: [u8; RAM_CHAPTERS],
+    rom_select: u8,
+}
+
+impl Mem {
+    /// Bank selects wrap around when fewer than 4 banks are fitted
+    pub fn with_banks(banks: usize) -> Self {
+        assert!((1..=RAM_BANKS).contains(&banks), "invalid RAM bank count");
+        Self {
+            ram: vec![0; RAM_CHAPTERS * banks * CHAPTER_SIZE],
+            written: vec![false; RAM_CHAPTERS * banks * CHAPTER_SIZE],
+            rom: vec![0; ROM_SIZE],
+            banks,
+            bank_select: [0; RAM_CHAPTERS],
+            rom_select: 0,
+        }
+    }
+
+    /// Every bank of RAM, bank by bank
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// Copy an image into ROM, bypassing the write-protection. The ROM
+    /// grows to as many banks as the image covers, and the last is padded.
+    pub fn load_rom(&mut self, rom: &[u8]) {
+        let len = rom.len().min(ROM_BANKS * ROM_SIZE);
+        let banks = len.div_ceil(ROM_SIZE).max(1);
+        self.rom.resize(banks * ROM_SIZE, 0);
+        self.rom[..len].copy_from_slice(&rom[..len]);
+    }
+
+    /// Copy a program into one bank of RAM, regardless of the bank selects
+    pub fn load_ram(&mut self, addr: u16, bank: usize, data: &[u8]) -> Result<(), String> {
+        if bank >= self.banks {
+            return Err(format!("bank {bank} is not fitted"));
+        }
+        let end = addr as usize + data.len();
+        if end > IO_START as usize {
+            return Err(format!(
+                "{addr:04X}-{:04X} runs past the end of RAM",
+                end - 1
+            ));
+        }
+        for (addr, &byte) in (addr..).zip(data) {
+            let chapter = ((addr & 0xF000) >> 12) as usize;
+            let base = (bank * RAM_CHAPTERS + chapter) * CHAPTER_SIZE;
+            self.ram[base + (addr & 0x0FFF) as usize] = byte;
+            self.written[base + (addr & 0x0FFF) as usize] = true;
+        }
+        Ok(())
+    }
+
+    fn region(&self, addr: u16) -> Region {
+        match addr {
+            ..IO_START => {
+                // get the high nibble to determine which 4K "chapter" we are in
+                let chapter = ((addr & 0xF000) >> 12) as usize;
+                let bank = self.bank_select[chapter] as usize;
+                let base = (bank * RAM_CHAPTERS + chapter) * CHAPTER_SIZE;
+                let offset = (addr & 0x0FFF) as usize;
+                Region::Ram(base + offset)
+            }
+            IO_START..ROM_START => Region::Io,
+            ROM_START.. => {
+                let base = self.rom_select as usize * ROM_SIZE;
+                Region::Rom(base + (addr - ROM_START) as usize)
+            }
+        }
+    }
+
+    /// Reads from the IO window return 0 since IO is decoded by the bus
+    pub fn read(&self, addr: u16) -> u8 {
+        match self.region(addr) {
+            Region::Ram(offset) => self.ram[offset],
+            Region::Io => 0,
+            Region::Rom(offset) => self.rom[offset],
+        }
+    }
+
+    /// Writes to the IO window and ROM are ignored
+    pub fn write(&mut self, addr: u16, data: u8) {
+        if let Region::Ram(offset) = self.region(addr) {
+            self.ram[offset] = data;
+            self.written[offset] = true;
+        }
+    }
+
+    /// Write bytes the way the CPU sees memory, except ROM is writable too.
+    /// Nothing is written if any byte would land in the IO window.
+    pub fn patch(&mut self, addr: u16, data: &[u8]) -> Result<(), String> {
+        let end = addr as usize + data.len();
+        if end > 0x10000 {
+            return Err(format!(
+                "{} bytes at {addr:04X} run past the end of memory",
+                data.len()
+            ));
+        }
+        if let Some(io) = (addr..)
+            .take(data.len())
+            .find(|&addr| matches!(self.region(addr), Region::Io))
+        {
+            return Err(format!("{io:04X} is in the IO window"));
+        }
+        for (addr, &byte) in (addr..).zip(data) {
+            match self.region(addr) {
+                Region::Ram(offset) => {
+                    self.ram[offset] = byte;
+                    self.written[offset] = true;
+                }
+                Region::Rom(offset) => self.rom[offset] = byte,
+                Region::Io => unreachable!(),
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the byte at an address has been written (only RAM can
+    /// start out uninitialized)
+    pub fn initialized(&self, addr: u16) -> bool {
+        match self.region(addr) {
+            Region::Ram(offset) => self.written[offset],
+            _ => true,
+        }
+    }
+
+    /// The bank mapped to the chapter containing an address (always 0 outside RAM)
+    pub fn bank(&self, addr: u16) -> usize {
+        let chapter = ((addr & 0xF000) >> 12) as usize;
+        if chapter < RAM_CHAPTERS {
+            self.bank_select[chapter] as usize
+        } else {
+            0
+        }
+    }
+
+    pub fn banks(&self) -> usize {
+        self.banks
+    }
+
+    /// Read RAM from one bank, regardless of the bank selects
+    pub fn read_bank(&self, addr: u16, bank: usize) -> u8 {
+        debug_assert!(addr < IO_START, "{addr:04X} is not RAM");
+        let chapter = ((addr & 0xF000) >> 12) as usize;
+        self.ram[(bank * RAM_CHAPTERS + chapter) * CHAPTER_SIZE + (addr & 0x0FFF) as usize]
+    }
+
+    pub fn bank_select(&self, chapter: usize) -> u8 {
+        self.bank_select[chapter]
+    }
+
+    pub fn set_bank_select(&mut self, chapter: usize, bank: u8) {
+        self.bank_select[chapter] = (bank & ((RAM_BANKS - 1) as u8)) % (self.banks as u8);
+    }
+
+    pub fn rom_banks(&self) -> usize {
+        self.rom.len() / ROM_SIZE
+    }
+
+    pub fn rom_bank_select(&self) -> u8 {
+        self.rom_select
+    }
+
+    /// ROM bank selects wrap around like the RAM ones
+    pub fn set_rom_bank_select(&mut self, bank: u8) {
+        self.rom_select = ((bank as usize) % self.rom_banks()) as u8;
+    }
+}
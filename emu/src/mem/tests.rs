@@ -0,0 +1,145 @@
+use super::*;
+
+fn chapter_addr(chapter: usize, offset: usize) -> u16 {
+    (chapter * CHAPTER_SIZE + offset) as u16
+}
+
+#[test]
+fn banks_are_isolated() {
+    let mut mem = Mem::with_banks(RAM_BANKS);
+    for chapter in 0..RAM_CHAPTERS {
+        for bank in 0..RAM_BANKS {
+            mem.set_bank_select(chapter, bank as u8);
+            let tag = ((chapter << 2) | bank) as u8;
+            mem.write(chapter_addr(chapter, 0x000), tag);
+            mem.write(chapter_addr(chapter, 0xFFF), !tag);
+        }
+    }
+    for chapter in 0..RAM_CHAPTERS {
+        for bank in 0..RAM_BANKS {
+            mem.set_bank_select(chapter, bank as u8);
+            let tag = ((chapter << 2) | bank) as u8;
+            assert_eq!(mem.bank(chapter_addr(chapter, 0)), bank);
+            assert_eq!(mem.read(chapter_addr(chapter, 0x000)), tag);
+            assert_eq!(mem.read(chapter_addr(chapter, 0xFFF)), !tag);
+        }
+    }
+}
+
+#[test]
+fn bank_select_only_affects_its_chapter() {
+    let mut mem = Mem::with_banks(RAM_BANKS);
+    for chapter in 0..RAM_CHAPTERS {
+        mem.write(chapter_addr(chapter, 0x123), chapter as u8);
+    }
+    for chapter in 0..RAM_CHAPTERS {
+        mem.set_bank_select(chapter, 3);
+        for other in (0..RAM_CHAPTERS).filter(|&other| other != chapter) {
+            assert_eq!(mem.read(chapter_addr(other, 0x123)), other as u8);
+        }
+        mem.set_bank_select(chapter, 0);
+    }
+}
+
+#[test]
+fn bank_select_is_masked() {
+    let mut mem = Mem::with_banks(RAM_BANKS);
+    mem.set_bank_select(0, 0xFD);
+    assert_eq!(mem.bank_select(0), 1);
+}
+
+#[test]
+fn missing_banks_wrap() {
+    let mut mem = Mem::with_banks(2);
+    mem.set_bank_select(4, 1);
+    mem.write(chapter_addr(4, 0), 0x42);
+    mem.set_bank_select(4, 3);
+    assert_eq!(mem.bank_select(4), 1);
+    assert_eq!(mem.read(chapter_addr(4, 0)), 0x42);
+}
+
+#[test]
+fn rom_is_write_protected() {
+    let mut mem = Mem::with_banks(RAM_BANKS);
+    let rom = (0..ROM_SIZE).map(|i| i as u8).collect::<Vec<_>>();
+    mem.load_rom(&rom);
+    for addr in ROM_START..=0xFFFF {
+        mem.write(addr, 0xAA);
+        assert_eq!(mem.read(addr), (addr - ROM_START) as u8);
+    }
+}
+
+#[test]
+fn rom_banks_switch_the_whole_window() {
+    let mut mem = Mem::with_banks(RAM_BANKS);
+    let rom = (0..3 * ROM_SIZE)
+        .map(|i| (i / ROM_SIZE) as u8)
+        .collect::<Vec<_>>();
+    mem.load_rom(&rom);
+    assert_eq!(mem.rom_banks(), 3);
+    for bank in 0..3 {
+        mem.set_rom_bank_select(bank);
+        assert_eq!(mem.read(ROM_START), bank);
+        assert_eq!(mem.read(0xFFFF), bank);
+    }
+    mem.set_rom_bank_select(4);
+    assert_eq!(mem.rom_bank_select(), 1);
+    assert_eq!(mem.read(0xFFFC), 1);
+}
+
+#[test]
+fn io_window_does_not_alias_ram() {
+    let mut mem = Mem::with_banks(RAM_BANKS);
+    for addr in IO_START..ROM_START {
+        mem.write(addr, 0xAA);
+        assert_eq!(mem.read(addr), 0);
+    }
+    for chapter in 0..RAM_CHAPTERS {
+        for bank in 0..RAM_BANKS {
+            mem.set_bank_select(chapter, bank as u8);
+            for offset in 0..0x100 {
+                assert_eq!(mem.read(chapter_addr(chapter, offset)), 0);
+            }
+        }
+        mem.set_bank_select(chapter, 0);
+    }
+}
+
+#[test]
+fn load_ram_ignores_bank_selects() {
+    let mut mem = Mem::with_banks(RAM_BANKS);
+    mem.load_ram(0x0FFF, 2, &[0x12, 0x34]).unwrap();
+    assert_eq!(mem.read(0x0FFF), 0);
+    mem.set_bank_select(0, 2);
+    mem.set_bank_select(1, 2);
+    assert_eq!(mem.read(0x0FFF), 0x12);
+    assert_eq!(mem.read(0x1000), 0x34);
+    assert!(mem.load_ram(0xEFFF, 0, &[0, 0]).is_err());
+    assert!(Mem::with_banks(1).load_ram(0, 1, &[0]).is_err());
+}
+
+#[test]
+fn writes_initialize_only_their_bank() {
+    let mut mem = Mem::with_banks(RAM_BANKS);
+    assert!(!mem.initialized(0x1234));
+    mem.write(0x1234, 0);
+    assert!(mem.initialized(0x1234));
+    mem.set_bank_select(1, 2);
+    assert!(!mem.initialized(0x1234));
+    mem.load_ram(0x1234, 2, &[1]).unwrap();
+    assert!(mem.initialized(0x1234));
+    assert!(mem.initialized(0xF100));
+}
+
+#[test]
+fn patch_writes_rom_but_not_io() {
+    let mut mem = Mem::with_banks(RAM_BANKS);
+    mem.patch(0xF100, &[0xA9, 0x12]).unwrap();
+    assert_eq!((mem.read(0xF100), mem.read(0xF101)), (0xA9, 0x12));
+    mem.set_bank_select(1, 2);
+    mem.patch(0x1000, &[0xEA]).unwrap();
+    assert_eq!(mem.ram()[(2 * RAM_CHAPTERS + 1) * CHAPTER_SIZE], 0xEA);
+    assert!(mem.patch(0xEFFF, &[0xEA, 0xEA]).is_err());
+    assert_eq!(mem.read(0xEFFF), 0);
+    assert!(mem.patch(0xFFFF, &[0xEA, 0xEA]).is_err());
+}
@@ -0,0 +1,134 @@
+//! Console Multiplexer
+//!
+//! `--mux` shares the host terminal between SER0 and SER1. One port is
+//! visible at a time:
+//! * the console hotkey (ctrl-] unless [`crate::hotkeys`] says otherwise)
+//!   switches ports, with a banner naming the one now visible
+//! * input goes to the visible port
+//! * output from the hidden port is held (the last 64KiB of it) and
+//!   written out when it becomes visible
+//! * SER1 output is colored, so the two can't be mistaken for each other
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{self, Read, Write},
+    rc::Rc,
+};
+
+use crate::serial::Console;
+
+/// ctrl-], by default
+pub const HOTKEY: u8 = 0x1D;
+
+const PORTS: usize = 2;
+const NAMES: [&str; PORTS] = ["SER0", "SER1"];
+const COLORS: [&str; PORTS] = ["", "\x1b[36m"];
+const RESET: &str = "\x1b[0m";
+const HELD_SIZE: usize = 0x10000;
+
+pub struct Mux {
+    console: Box<dyn Console>,
+    hotkey: Option<u8>,
+    visible: usize,
+    input: [VecDeque<u8>; PORTS],
+    held: [VecDeque<u8>; PORTS],
+}
+
+impl Mux {
+    pub fn new(console: Box<dyn Console>, hotkey: Option<u8>) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self {
+            console,
+            hotkey,
+            visible: 0,
+            input: Default::default(),
+            held: Default::default(),
+        }))
+    }
+
+    /// The console end for SER0 (`0`) or SER1 (`1`)
+    pub fn port(mux: &Rc<RefCell<Self>>, port: usize) -> MuxPort {
+        assert!(port < PORTS, "invalid mux port");
+        MuxPort {
+            mux: mux.clone(),
+            port,
+        }
+    }
+
+    fn switch(&mut self) -> io::Result<()> {
+        self.visible = (self.visible + 1) % PORTS;
+        let port = self.visible;
+        write!(self.console, "\r\n[{}]\r\n", NAMES[port])?;
+        let held = self.held[port].drain(..).collect::<Vec<u8>>();
+        self.show(port, &held)
+    }
+
+    fn show(&mut self, port: usize, buf: &[u8]) -> io::Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        if COLORS[port].is_empty() {
+            self.console.write_all(buf)
+        } else {
+            self.console.write_all(COLORS[port].as_bytes())?;
+            self.console.write_all(buf)?;
+            self.console.write_all(RESET.as_bytes())
+        }
+    }
+
+    fn read(&mut self, port: usize, buf: &mut [u8]) -> io::Result<usize> {
+        // route what was typed to whichever port was visible at the time
+        let mut typed = [0; 64];
+        let size = self.console.read(&mut typed)?;
+        for &byte in &typed[..size] {
+            if Some(byte) == self.hotkey {
+                self.switch()?;
+            } else {
+                self.input[self.visible].push_back(byte);
+            }
+        }
+        let input = &mut self.input[port];
+        let len = buf.len().min(input.len());
+        for (dst, src) in buf.iter_mut().zip(input.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+
+    fn write(&mut self, port: usize, buf: &[u8]) -> io::Result<usize> {
+        if port == self.visible {
+            self.show(port, buf)?;
+        } else {
+            let held = &mut self.held[port];
+            held.extend(buf);
+            let excess = held.len().saturating_sub(HELD_SIZE);
+            held.drain(..excess);
+        }
+        Ok(buf.len())
+    }
+}
+
+/// One port's view of the [`Mux`]
+pub struct MuxPort {
+    mux: Rc<RefCell<Mux>>,
+    port: usize,
+}
+
+impl Read for MuxPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.mux.borrow_mut().read(self.port, buf)
+    }
+}
+
+impl Write for MuxPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.mux.borrow_mut().write(self.port, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.mux.borrow_mut().console.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,77 @@
+use super::*;
+
+/// A terminal whose keys and screen the test can get at
+#[derive(Clone, Default)]
+struct Term {
+    keys: Rc<RefCell<VecDeque<u8>>>,
+    screen: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Term {
+    fn type_keys(&self, keys: &[u8]) {
+        self.keys.borrow_mut().extend(keys);
+    }
+
+    fn take_screen(&self) -> String {
+        String::from_utf8(self.screen.take()).unwrap()
+    }
+}
+
+impl Read for Term {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.keys.borrow_mut().read(buf)
+    }
+}
+
+impl Write for Term {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.screen.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn read_all(port: &mut MuxPort) -> Vec<u8> {
+    let mut buf = [0; 64];
+    let len = port.read(&mut buf).unwrap();
+    buf[..len].to_vec()
+}
+
+#[test]
+fn hidden_output_is_held_until_switched_to() {
+    let term = Term::default();
+    let mux = Mux::new(Box::new(term.clone()), Some(HOTKEY));
+    let mut ser0 = Mux::port(&mux, 0);
+    let mut ser1 = Mux::port(&mux, 1);
+
+    ser0.write_all(b"zero").unwrap();
+    ser1.write_all(b"one").unwrap();
+    assert_eq!(term.take_screen(), "zero");
+
+    term.type_keys(&[HOTKEY]);
+    read_all(&mut ser0);
+    assert_eq!(term.take_screen(), "\r\n[SER1]\r\n\x1b[36mone\x1b[0m");
+    ser0.write_all(b"held").unwrap();
+    assert_eq!(term.take_screen(), "");
+
+    term.type_keys(&[HOTKEY]);
+    read_all(&mut ser1);
+    assert_eq!(term.take_screen(), "\r\n[SER0]\r\nheld");
+}
+
+#[test]
+fn input_goes_to_the_visible_port() {
+    let term = Term::default();
+    let mux = Mux::new(Box::new(term.clone()), Some(HOTKEY));
+    let mut ser0 = Mux::port(&mux, 0);
+    let mut ser1 = Mux::port(&mux, 1);
+
+    term.type_keys(b"ab");
+    term.type_keys(&[HOTKEY]);
+    term.type_keys(b"cd");
+    assert_eq!(read_all(&mut ser1), b"cd");
+    assert_eq!(read_all(&mut ser0), b"ab");
+    assert_eq!(read_all(&mut ser0), b"");
+}
@@ -0,0 +1,206 @@
+//! Disk Overlays
+//!
+//! Lets a pristine base image be shared while the guest's writes go to a
+//! separate overlay file. Sectors are copied from the base the first time
+//! they are written, and `commit` writes them all back.
+//!
+//! The overlay is sparse: an 8 byte magic, then a little-endian u16 per
+//! base sector (0 if the sector isn't in the overlay, otherwise 1 + its
+//! slot), then the slots in the order they were first written.
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+const MAGIC: &[u8; 8] = b"P2OVRLY\0";
+
+const SECTOR_SIZE: u64 = possum2_media::SECTOR_SIZE as u64;
+
+pub struct Overlay {
+    base_path: PathBuf,
+    base: File,
+    file: File,
+    /// Slot (plus 1) of every base sector
+    map: Vec<u16>,
+    slots: u16,
+    offset: u64,
+}
+
+impl Overlay {
+    /// Open the overlay at `path` on top of `base_path`, creating an empty
+    /// one if it doesn't exist yet
+    pub fn open(base_path: &Path, path: &Path) -> io::Result<Self> {
+        let base = File::open(base_path)?;
+        let sectors = base.metadata()?.len().div_ceil(SECTOR_SIZE) as usize;
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut overlay = Self {
+            base_path: base_path.to_path_buf(),
+            base,
+            file,
+            map: vec![0; sectors],
+            slots: 0,
+            offset: 0,
+        };
+        if overlay.file.metadata()?.len() == 0 {
+            overlay.clear()?;
+        } else {
+            overlay
+                .load()
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+        }
+        Ok(overlay)
+    }
+
+    fn load(&mut self) -> io::Result<()> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut header = vec![0; self.header_len() as usize];
+        self.file
+            .read_exact(&mut header)
+            .map_err(|_| invalid("not an overlay for an image this size"))?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(invalid("not a disk overlay"));
+        }
+        let slots = (self.file.metadata()?.len() - self.header_len()) / SECTOR_SIZE;
+        for (sector, entry) in header[MAGIC.len()..].chunks(2).enumerate() {
+            let entry = u16::from_le_bytes([entry[0], entry[1]]);
+            if u64::from(entry) > slots {
+                return Err(invalid("truncated"));
+            }
+            self.map[sector] = entry;
+        }
+        self.slots = slots as u16;
+        Ok(())
+    }
+
+    /// Write every overlaid sector back to the base image and empty the
+    /// overlay. Returns the number of sectors written.
+    pub fn commit(&mut self) -> io::Result<usize> {
+        let mut base = File::options().write(true).open(&self.base_path)?;
+        let mut buf = [0; SECTOR_SIZE as usize];
+        let mut count = 0;
+        for sector in 0..self.map.len() {
+            if self.map[sector] == 0 {
+                continue;
+            }
+            self.file.seek(SeekFrom::Start(self.slot_offset(sector)))?;
+            self.file.read_exact(&mut buf)?;
+            base.seek(SeekFrom::Start(sector as u64 * SECTOR_SIZE))?;
+            base.write_all(&buf)?;
+            count += 1;
+        }
+        base.sync_all()?;
+        self.clear()?;
+        Ok(count)
+    }
+
+    /// Reset the overlay to no sectors at all
+    fn clear(&mut self) -> io::Result<()> {
+        self.map.fill(0);
+        self.slots = 0;
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(MAGIC)?;
+        self.file.write_all(&vec![0; self.map.len() * 2])?;
+        self.file.flush()
+    }
+
+    fn header_len(&self) -> u64 {
+        (MAGIC.len() + self.map.len() * 2) as u64
+    }
+
+    fn slot_offset(&self, sector: usize) -> u64 {
+        self.header_len() + u64::from(self.map[sector] - 1) * SECTOR_SIZE
+    }
+
+    /// Give `sector` a slot holding a copy of the base sector
+    fn copy_on_write(&mut self, sector: usize) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(SECTOR_SIZE as usize);
+        self.base
+            .seek(SeekFrom::Start(sector as u64 * SECTOR_SIZE))?;
+        (&mut self.base).take(SECTOR_SIZE).read_to_end(&mut buf)?;
+        // the end of an odd-sized base is padded
+        buf.resize(SECTOR_SIZE as usize, 0);
+        self.slots += 1;
+        self.map[sector] = self.slots;
+        self.file.seek(SeekFrom::Start(self.slot_offset(sector)))?;
+        self.file.write_all(&buf)?;
+        self.file
+            .seek(SeekFrom::Start((MAGIC.len() + sector * 2) as u64))?;
+        self.file.write_all(&self.slots.to_le_bytes())
+    }
+
+    /// The sector under the offset, and how far into it the offset is
+    fn position(&self) -> Option<(usize, u64)> {
+        let sector = (self.offset / SECTOR_SIZE) as usize;
+        (sector < self.map.len()).then_some((sector, self.offset % SECTOR_SIZE))
+    }
+}
+
+impl Read for Overlay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some((sector, within)) = self.position() else {
+            return Ok(0);
+        };
+        let len = buf.len().min((SECTOR_SIZE - within) as usize);
+        let size = if self.map[sector] == 0 {
+            self.base.seek(SeekFrom::Start(self.offset))?;
+            self.base.read(&mut buf[..len])?
+        } else {
+            self.file
+                .seek(SeekFrom::Start(self.slot_offset(sector) + within))?;
+            self.file.read(&mut buf[..len])?
+        };
+        self.offset += size as u64;
+        Ok(size)
+    }
+}
+
+impl Write for Overlay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some((sector, within)) = self.position() else {
+            return Ok(0);
+        };
+        if self.map[sector] == 0 {
+            self.copy_on_write(sector)?;
+        }
+        let len = buf.len().min((SECTOR_SIZE - within) as usize);
+        self.file
+            .seek(SeekFrom::Start(self.slot_offset(sector) + within))?;
+        let size = self.file.write(&buf[..len])?;
+        self.offset += size as u64;
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for Overlay {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let len = self.map.len() as u64 * SECTOR_SIZE;
+        let offset = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.offset.checked_add_signed(offset),
+        };
+        let Some(offset) = offset else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "attempted to seek before the start of the disk",
+            ));
+        };
+        self.offset = offset.min(len);
+        Ok(self.offset)
+    }
+}
@@ -0,0 +1,76 @@
+use std::{env, fs};
+
+use super::*;
+
+fn paths(name: &str) -> (PathBuf, PathBuf) {
+    let dir = env::temp_dir();
+    let id = std::process::id();
+    (
+        dir.join(format!("possum2-overlay-{id}-{name}-base.img")),
+        dir.join(format!("possum2-overlay-{id}-{name}-work.img")),
+    )
+}
+
+fn read_at(overlay: &mut Overlay, offset: u64, len: usize) -> Vec<u8> {
+    let mut buf = vec![0; len];
+    overlay.seek(SeekFrom::Start(offset)).unwrap();
+    overlay.read_exact(&mut buf).unwrap();
+    buf
+}
+
+#[test]
+fn writes_go_to_the_overlay() {
+    let (base, work) = paths("writes");
+    let image = (0..4 * SECTOR_SIZE).map(|i| i as u8).collect::<Vec<u8>>();
+    fs::write(&base, &image).unwrap();
+
+    let mut overlay = Overlay::open(&base, &work).unwrap();
+    // straddle sectors 1 and 2
+    overlay.seek(SeekFrom::Start(500)).unwrap();
+    overlay.write_all(&[0xAA; 20]).unwrap();
+    assert_eq!(read_at(&mut overlay, 490, 40)[10..30], [0xAA; 20]);
+    assert_eq!(read_at(&mut overlay, 256, 244), image[256..500]);
+    assert_eq!(fs::read(&base).unwrap(), image);
+
+    // the overlay survives being reopened
+    drop(overlay);
+    let mut overlay = Overlay::open(&base, &work).unwrap();
+    assert_eq!(read_at(&mut overlay, 500, 20), [0xAA; 20]);
+    assert_eq!(read_at(&mut overlay, 0, 256), image[..256]);
+
+    fs::remove_file(&base).unwrap();
+    fs::remove_file(&work).unwrap();
+}
+
+#[test]
+fn commit_writes_back_to_the_base() {
+    let (base, work) = paths("commit");
+    fs::write(&base, vec![0; 4 * SECTOR_SIZE as usize]).unwrap();
+
+    let mut overlay = Overlay::open(&base, &work).unwrap();
+    overlay.seek(SeekFrom::Start(3 * SECTOR_SIZE + 1)).unwrap();
+    overlay.write_all(b"hello").unwrap();
+    assert_eq!(overlay.commit().unwrap(), 1);
+    assert_eq!(overlay.commit().unwrap(), 0);
+    assert_eq!(read_at(&mut overlay, 3 * SECTOR_SIZE + 1, 5), b"hello");
+
+    let image = fs::read(&base).unwrap();
+    assert_eq!(&image[(3 * SECTOR_SIZE as usize + 1)..][..5], b"hello");
+    assert_eq!(
+        fs::metadata(&work).unwrap().len(),
+        (MAGIC.len() + 4 * 2) as u64
+    );
+
+    fs::remove_file(&base).unwrap();
+    fs::remove_file(&work).unwrap();
+}
+
+#[test]
+fn rejects_other_files() {
+    let (base, work) = paths("rejects");
+    fs::write(&base, vec![0; 4 * SECTOR_SIZE as usize]).unwrap();
+    fs::write(&work, vec![0; 100]).unwrap();
+    assert!(Overlay::open(&base, &work).is_err());
+    fs::remove_file(&base).unwrap();
+    fs::remove_file(&work).unwrap();
+}
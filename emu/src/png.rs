@@ -0,0 +1,93 @@
+//! PNG Writer
+//!
+//! Just enough PNG to save 24-bit RGB images. The image data is stored
+//! with uncompressed deflate blocks, which keeps this small and
+//! dependency free at the cost of file size.
+
+use std::io::{self, Write};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Largest payload of a stored deflate block
+const STORED_BLOCK_LEN: usize = 0xFFFF;
+
+/// Write `pixels` (0x00RRGGBB, row by row) as a PNG
+pub fn write(out: &mut dyn Write, width: usize, height: usize, pixels: &[u32]) -> io::Result<()> {
+    assert_eq!(pixels.len(), width * height);
+    out.write_all(&SIGNATURE)?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, truecolor, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(out, b"IHDR", &header)?;
+
+    // every row starts with its filter type (0 for none)
+    let mut raw = Vec::with_capacity(height * (1 + width * 3));
+    for row in pixels.chunks(width.max(1)) {
+        raw.push(0);
+        for pixel in row {
+            raw.extend_from_slice(&pixel.to_be_bytes()[1..]);
+        }
+    }
+    write_chunk(out, b"IDAT", &zlib_stored(&raw))?;
+    write_chunk(out, b"IEND", &[])
+}
+
+fn write_chunk(out: &mut dyn Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = crc32(crc32(0xFFFF_FFFF, kind), data) ^ 0xFFFF_FFFF;
+    out.write_all(&crc.to_be_bytes())
+}
+
+/// A zlib stream of stored deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let blocks = data.len().div_ceil(STORED_BLOCK_LEN).max(1);
+    let mut stream = Vec::with_capacity(data.len() + blocks * 5 + 6);
+    stream.extend_from_slice(&[0x78, 0x01]);
+    let mut chunks = data.chunks(STORED_BLOCK_LEN).peekable();
+    if chunks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        let len = chunk.len() as u16;
+        stream.push(last as u8);
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(chunk);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+/// The CRC-32 register after `data`, without the initial and final inversions
+pub fn crc32(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if (crc & 1) != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
@@ -0,0 +1,79 @@
+//! VRAM Inspector
+//!
+//! Renders what's in VRAM apart from the picture, for the debugger's
+//! `ppu` command: whole BG and FG planes without scrolling, tile banks as
+//! a grid, and the sprite and palette tables. Everything is read straight
+//! out of VRAM, so it works whatever the control register has enabled.
+
+use super::{
+    layer_pixel, palette_rgb, tile_pixel, Layer, Sprite, BG_ATTRIBUTES, BG_MAP, FG_ATTRIBUTES,
+    FG_MAP, PALETTES, PLANE_MASK, SPRITES, SPRITE_PALETTES,
+};
+
+/// Width and height of a BG or FG plane
+pub const PLANE_SIZE: usize = PLANE_MASK as usize + 1;
+
+/// Tiles per row (and column) of a rendered tile bank
+pub const BANK_TILES: usize = 16;
+
+/// Width and height of a rendered tile bank
+pub const BANK_SIZE: usize = BANK_TILES * 8;
+
+/// Colors per palette, and palettes per table
+pub const COLORS: usize = 8;
+pub const PALETTE_COUNT: usize = 4;
+
+/// The whole BG plane, or the FG plane with `fg`, as 0x00RRGGBB pixels.
+/// FG color 0 is drawn, since there's nothing behind it.
+pub fn plane(vram: &[u8], fg: bool) -> Vec<u32> {
+    let layer = if fg {
+        Layer {
+            map: FG_MAP,
+            attributes: FG_ATTRIBUTES,
+            scroll_x: 0,
+            scroll_y: 0,
+        }
+    } else {
+        Layer {
+            map: BG_MAP,
+            attributes: BG_ATTRIBUTES,
+            scroll_x: 0,
+            scroll_y: 0,
+        }
+    };
+    (0..PLANE_SIZE * PLANE_SIZE)
+        .map(|i| layer_pixel(vram, &layer, i % PLANE_SIZE, i / PLANE_SIZE).1)
+        .collect()
+}
+
+/// The 256 tiles of a bank in a 16x16 grid, colored with one of the
+/// BG/FG palettes
+pub fn tile_bank(vram: &[u8], bank: usize, palette: usize) -> Vec<u32> {
+    (0..BANK_SIZE * BANK_SIZE)
+        .map(|i| {
+            let (x, y) = (i % BANK_SIZE, i / BANK_SIZE);
+            let tile = (y / 8) * BANK_TILES + (x / 8);
+            let color = tile_pixel(vram, bank, tile, x % 8, y % 8);
+            palette_rgb(vram, PALETTES, palette, color)
+        })
+        .collect()
+}
+
+/// Every sprite, in priority order
+pub fn sprites(vram: &[u8]) -> Vec<Sprite> {
+    (0..SPRITES)
+        .map(|index| Sprite::read(vram, index))
+        .collect()
+}
+
+/// The BG/FG palettes, or the sprite palettes with `sprites`
+pub fn palettes(vram: &[u8], sprites: bool) -> [[u32; COLORS]; PALETTE_COUNT] {
+    let table = if sprites { SPRITE_PALETTES } else { PALETTES };
+    let mut palettes = [[0; COLORS]; PALETTE_COUNT];
+    for (palette, colors) in palettes.iter_mut().enumerate() {
+        for (color, rgb) in colors.iter_mut().enumerate() {
+            *rgb = palette_rgb(vram, table, palette, color);
+        }
+    }
+    palettes
+}
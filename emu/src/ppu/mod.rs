@@ -0,0 +1,617 @@
+//! PPU Emulation
+//!
+//! Runs with standard 640x480@60 timing (525 lines of which 480 are
+//! visible), or 1024x768@60 (806 lines of which 768 are visible) when the
+//! hi-res control bit is set. A new mode takes effect when the next frame
+//! starts. Each visible line is drawn into the framebuffer as the beam
+//! passes it, so changing registers mid-frame (from a raster IRQ) affects
+//! the lines below. The BG and FG layers are windows into
+//! 1024x1024 planes of 8x8 tiles.
+//!
+//! Registers:
+//!
+//! 0 Control/Status (Reads return Status and clear the IRQ flags)
+//! 1 Data (Reads and writes increment the address)
+//! 2 Address (2 writes, lo then hi)
+//! 3 DMA Control (Writes only)
+//! 4 DMA Src (2 writes)
+//! 5 DMA Dst (2 writes)
+//! 6 DMA Length (2 writes)
+//! 7 BG Scroll-X (2 writes)
+//! 8 BG Scroll-Y (2 writes)
+//! 9 FG Scroll-X (2 writes)
+//! A FG Scroll-Y (2 writes)
+//! B Raster Line Lo (Reads return the current line, writes set the compare line)
+//! C Raster Line Hi
+//!
+//! 2 write registers change on the second write. The low byte waits in a
+//! latch shared by all of them, so a half-written address or scroll never
+//! takes effect. Reading Status empties the latch, discarding the pending
+//! byte, which resyncs code that lost track of where it was.
+//!
+//! VRAM is locked while the raster line is a visible one and any layer is
+//! enabled. Data port writes are ignored then (the address still
+//! advances), and the status register reports the lock, so code can wait
+//! for vblank or disable the layers to upload. Data port reads are always
+//! allowed.
+//!
+//! DMA copies Length bytes from Src in CPU memory to Dst in VRAM, or the
+//! other way with the to-CPU control bit, advancing the registers as it
+//! goes. It moves up to 64 bytes per scanline while VRAM is unlocked,
+//! stealing a CPU cycle for each. The busy status bit is set until it
+//! finishes, and then the DMA IRQ if it's enabled. Writing the control
+//! register without the start bit stops a transfer.
+//!
+//! Each map byte selects a tile, and its 4-bit attribute holds the palette
+//! (bits 0-1) and tile bank (bit 2). Tiles are 3 bitplanes of 8 rows each,
+//! and FG color 0 is transparent.
+//!
+//! Sprites are single 8x8 tiles placed on the screen, wrapping at 1024 so
+//! they can hang off the left and top edges. Each has 2 attribute bytes:
+//! * the tile
+//! * palette (bits 0-1, from the sprite palettes), tile bank (bit 2),
+//!   horizontal and vertical flip (bits 3 and 4), and priority (bits 5-6:
+//!   hidden, behind the BG showing through its color 0, between BG and FG,
+//!   or in front of both)
+//!
+//! and a 3 byte position with X in bits 0-9 and Y in bits 10-19. Only the
+//! first 32 visible sprites on a line are drawn, and any more set the
+//! overflow flag until the next frame starts. Lower numbered sprites are
+//! in front, and color 0 is transparent.
+
+use crate::bus::{Bus, BusDevice, Frame};
+
+pub mod inspect;
+
+/// A display mode's geometry and timing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mode {
+    pub width: usize,
+    pub height: usize,
+    /// Scanlines per frame, including the blanking interval
+    pub lines: u16,
+    /// Scanlines per second
+    pub line_rate: u32,
+}
+
+pub const VGA: Mode = Mode {
+    width: 640,
+    height: 480,
+    lines: 525,
+    line_rate: 31_469,
+};
+
+pub const XGA: Mode = Mode {
+    width: 1024,
+    height: 768,
+    lines: 806,
+    line_rate: 48_363,
+};
+
+/// Ticks per second, one per scanline in the fastest mode. Slower modes
+/// skip some.
+pub const TICK_RATE: u32 = XGA.line_rate;
+const MAX_WIDTH: usize = XGA.width;
+const MAX_HEIGHT: usize = XGA.height;
+
+const VRAM_SIZE: usize = 0x10000;
+const BG_MAP: usize = 0x0000;
+const FG_MAP: usize = 0x4000;
+const BG_ATTRIBUTES: usize = 0x8000;
+const FG_ATTRIBUTES: usize = 0xA000;
+const TILE_BANKS: [usize; 2] = [0xC000, 0xD800];
+const PALETTES: usize = 0xF280;
+const SPRITE_ATTRIBUTES: usize = 0xF000;
+const SPRITE_POSITIONS: usize = 0xF100;
+const SPRITE_PALETTES: usize = 0xF2E0;
+const SPRITES: usize = 128;
+const SPRITES_PER_LINE: usize = 32;
+const PLANE_TILES: usize = 128;
+const PLANE_MASK: u16 = 0x3FF;
+const DMA_BYTES_PER_LINE: u16 = 64;
+
+enum ControlFlags {}
+
+impl ControlFlags {
+    const VBLANK_IRQ_ENABLE: u8 = 1 << 0;
+    const RASTER_IRQ_ENABLE: u8 = 1 << 1;
+    const BG_ENABLE: u8 = 1 << 2;
+    const FG_ENABLE: u8 = 1 << 3;
+    const SPRITE_ENABLE: u8 = 1 << 4;
+    const HIRES: u8 = 1 << 5;
+}
+
+enum DmaFlags {}
+
+impl DmaFlags {
+    const START: u8 = 1 << 0;
+    const TO_CPU: u8 = 1 << 1;
+    const IRQ_ENABLE: u8 = 1 << 2;
+}
+
+enum StatusFlags {}
+
+impl StatusFlags {
+    const VBLANK: u8 = 1 << 0;
+    const VBLANK_IRQ: u8 = 1 << 1;
+    const RASTER_IRQ: u8 = 1 << 2;
+    const SPRITE_OVERFLOW: u8 = 1 << 3;
+    const DMA_BUSY: u8 = 1 << 4;
+    const DMA_IRQ: u8 = 1 << 5;
+    const VRAM_LOCKED: u8 = 1 << 6;
+}
+
+enum SpriteFlags {}
+
+impl SpriteFlags {
+    const FLIP_X: u8 = 1 << 3;
+    const FLIP_Y: u8 = 1 << 4;
+}
+
+/// Bits 5-6 of a sprite's attributes
+enum Priority {}
+
+impl Priority {
+    const HIDDEN: u8 = 0;
+    const BEHIND_BG: u8 = 1;
+    const BEHIND_FG: u8 = 2;
+    const FRONT: u8 = 3;
+}
+
+struct Layer {
+    map: usize,
+    attributes: usize,
+    scroll_x: u16,
+    scroll_y: u16,
+}
+
+pub struct Ppu {
+    vram: Box<[u8; VRAM_SIZE]>,
+    /// 0x00RRGGBB pixels, as many rows of the mode's width as it has
+    framebuffer: Box<[u32]>,
+    /// The front sprite's priority and color at each pixel of the line
+    sprite_row: Box<[(u8, u32)]>,
+    mode: Mode,
+    /// Line rate accumulated over ticks, a line starts each time it passes
+    /// the tick rate
+    phase: u32,
+    control: u8,
+    status: u8,
+    addr: u16,
+    bg: Layer,
+    fg: Layer,
+    line: u16,
+    compare: u16,
+    frames: u64,
+    /// The low byte written to a 2 write register, waiting for the high byte
+    latch: Option<u8>,
+    dma_control: u8,
+    dma_src: u16,
+    dma_dst: u16,
+    dma_len: u16,
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Self {
+            vram: Box::new([0; VRAM_SIZE]),
+            framebuffer: vec![0; MAX_WIDTH * MAX_HEIGHT].into_boxed_slice(),
+            sprite_row: vec![(Priority::HIDDEN, 0); MAX_WIDTH].into_boxed_slice(),
+            mode: VGA,
+            phase: 0,
+            control: 0,
+            status: 0,
+            addr: 0,
+            bg: Layer {
+                map: BG_MAP,
+                attributes: BG_ATTRIBUTES,
+                scroll_x: 0,
+                scroll_y: 0,
+            },
+            fg: Layer {
+                map: FG_MAP,
+                attributes: FG_ATTRIBUTES,
+                scroll_x: 0,
+                scroll_y: 0,
+            },
+            line: 0,
+            compare: 0,
+            frames: 0,
+            latch: None,
+            dma_control: 0,
+            dma_src: 0,
+            dma_dst: 0,
+            dma_len: 0,
+        }
+    }
+
+    /// Whether the CPU is shut out of VRAM while the picture is drawn
+    fn vram_locked(&self) -> bool {
+        let layers =
+            ControlFlags::BG_ENABLE | ControlFlags::FG_ENABLE | ControlFlags::SPRITE_ENABLE;
+        (self.line as usize) < self.mode.height && (self.control & layers) != 0
+    }
+
+    /// Move the next burst of a DMA transfer
+    fn dma(&mut self, bus: &mut dyn Bus) {
+        let burst = self.dma_len.min(DMA_BYTES_PER_LINE);
+        for _ in 0..burst {
+            if (self.dma_control & DmaFlags::TO_CPU) != 0 {
+                bus.write(self.dma_dst, self.vram[self.dma_src as usize]);
+            } else {
+                self.vram[self.dma_dst as usize] = bus.read(self.dma_src);
+            }
+            self.dma_src = self.dma_src.wrapping_add(1);
+            self.dma_dst = self.dma_dst.wrapping_add(1);
+        }
+        self.dma_len -= burst;
+        if self.dma_len == 0 {
+            tracing::trace!(target: "ppu", "DMA finished");
+            self.status &= !StatusFlags::DMA_BUSY;
+            if (self.dma_control & DmaFlags::IRQ_ENABLE) != 0 {
+                self.status |= StatusFlags::DMA_IRQ;
+            }
+        }
+    }
+
+    /// Apply one write of a 2 write register, returning the word once both
+    /// halves are in
+    fn latch(&mut self, data: u8) -> Option<u16> {
+        match self.latch.take() {
+            Some(lo) => Some(u16::from_le_bytes([lo, data])),
+            None => {
+                self.latch = Some(data);
+                None
+            }
+        }
+    }
+
+    fn scanline(&mut self) {
+        let line = self.line;
+        if line == self.compare && (self.control & ControlFlags::RASTER_IRQ_ENABLE) != 0 {
+            tracing::trace!(target: "ppu", "raster IRQ at line {line}");
+            self.status |= StatusFlags::RASTER_IRQ;
+        }
+        let height = self.mode.height;
+        if (line as usize) < height {
+            self.draw_line(line as usize);
+        } else if line as usize == height {
+            self.frames += 1;
+            tracing::trace!(target: "ppu", "frame {} finished", self.frames);
+            self.status |= StatusFlags::VBLANK;
+            if (self.control & ControlFlags::VBLANK_IRQ_ENABLE) != 0 {
+                self.status |= StatusFlags::VBLANK_IRQ;
+            }
+        }
+        self.line += 1;
+        if self.line == self.mode.lines {
+            self.line = 0;
+            self.status &= !(StatusFlags::VBLANK | StatusFlags::SPRITE_OVERFLOW);
+            let mode = if (self.control & ControlFlags::HIRES) != 0 {
+                XGA
+            } else {
+                VGA
+            };
+            if mode != self.mode {
+                tracing::debug!(target: "ppu", "switched to {}x{}", mode.width, mode.height);
+                self.mode = mode;
+            }
+        }
+    }
+
+    fn draw_line(&mut self, y: usize) {
+        self.draw_sprites(y);
+        let bg = ((self.control & ControlFlags::BG_ENABLE) != 0).then_some(&self.bg);
+        let fg = ((self.control & ControlFlags::FG_ENABLE) != 0).then_some(&self.fg);
+        let width = self.mode.width;
+        let row = &mut self.framebuffer[(y * width)..((y + 1) * width)];
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let (priority, sprite) = self.sprite_row[x];
+            // back to front, and BG color 0 is drawn
+            let mut rgb = 0;
+            let mut bg_clear = true;
+            if let Some(bg) = bg {
+                let (color, bg_rgb) = layer_pixel(&self.vram[..], bg, x, y);
+                rgb = bg_rgb;
+                bg_clear = color == 0;
+            }
+            if priority == Priority::BEHIND_FG || (priority == Priority::BEHIND_BG && bg_clear) {
+                rgb = sprite;
+            }
+            if let Some(fg) = fg {
+                let (color, fg_rgb) = layer_pixel(&self.vram[..], fg, x, y);
+                if color != 0 {
+                    rgb = fg_rgb;
+                }
+            }
+            if priority == Priority::FRONT {
+                rgb = sprite;
+            }
+            *pixel = rgb;
+        }
+    }
+
+    /// Find the sprites on line `y` and draw them into the sprite row
+    fn draw_sprites(&mut self, y: usize) {
+        self.sprite_row.fill((Priority::HIDDEN, 0));
+        if (self.control & ControlFlags::SPRITE_ENABLE) == 0 {
+            return;
+        }
+        let mut drawn = 0;
+        for index in 0..SPRITES {
+            let sprite = Sprite::read(&self.vram[..], index);
+            let row = (y as u16).wrapping_sub(sprite.y) & PLANE_MASK;
+            if sprite.priority == Priority::HIDDEN || row >= 8 {
+                continue;
+            }
+            if drawn == SPRITES_PER_LINE {
+                if (self.status & StatusFlags::SPRITE_OVERFLOW) == 0 {
+                    tracing::trace!(target: "ppu", "sprite overflow on line {y}");
+                }
+                self.status |= StatusFlags::SPRITE_OVERFLOW;
+                break;
+            }
+            drawn += 1;
+
+            let row = if sprite.flip_y { 7 - row } else { row };
+            for column in 0..8 {
+                let x = (sprite.x + column) & PLANE_MASK;
+                // a lower numbered sprite is already in front
+                if (x as usize) >= self.mode.width
+                    || self.sprite_row[x as usize].0 != Priority::HIDDEN
+                {
+                    continue;
+                }
+                let column = if sprite.flip_x { 7 - column } else { column };
+                let color = tile_pixel(
+                    &self.vram[..],
+                    sprite.bank,
+                    sprite.tile as usize,
+                    column as usize,
+                    row as usize,
+                );
+                if color != 0 {
+                    let rgb = palette_rgb(&self.vram[..], SPRITE_PALETTES, sprite.palette, color);
+                    self.sprite_row[x as usize] = (sprite.priority, rgb);
+                }
+            }
+        }
+    }
+}
+
+/// A sprite's attributes and position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sprite {
+    pub tile: u8,
+    pub palette: usize,
+    pub bank: usize,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// 0 hidden, 1 behind the BG, 2 between BG and FG, 3 in front
+    pub priority: u8,
+    pub x: u16,
+    pub y: u16,
+}
+
+impl Sprite {
+    pub fn read(vram: &[u8], index: usize) -> Self {
+        let attributes = SPRITE_ATTRIBUTES + index * 2;
+        let (tile, attribute) = (vram[attributes], vram[attributes + 1]);
+        let position = SPRITE_POSITIONS + index * 3;
+        let position =
+            u32::from_le_bytes([vram[position], vram[position + 1], vram[position + 2], 0]);
+        Self {
+            tile,
+            palette: (attribute & 0x03) as usize,
+            bank: ((attribute >> 2) & 0x01) as usize,
+            flip_x: (attribute & SpriteFlags::FLIP_X) != 0,
+            flip_y: (attribute & SpriteFlags::FLIP_Y) != 0,
+            priority: (attribute >> 5) & 0x03,
+            x: (position as u16) & PLANE_MASK,
+            y: ((position >> 10) as u16) & PLANE_MASK,
+        }
+    }
+}
+
+/// The color index and RGB of a layer at a pixel of the screen
+fn layer_pixel(vram: &[u8], layer: &Layer, x: usize, y: usize) -> (usize, u32) {
+    let plane_x = ((x as u16).wrapping_add(layer.scroll_x) & PLANE_MASK) as usize;
+    let plane_y = ((y as u16).wrapping_add(layer.scroll_y) & PLANE_MASK) as usize;
+    let index = (plane_y / 8) * PLANE_TILES + (plane_x / 8);
+    let tile = vram[layer.map + index] as usize;
+    let attribute = (vram[layer.attributes + index / 2] >> ((index & 1) * 4)) & 0x0F;
+    let palette = (attribute & 0x03) as usize;
+    let bank = ((attribute >> 2) & 0x01) as usize;
+    let color = tile_pixel(vram, bank, tile, plane_x % 8, plane_y % 8);
+    (color, palette_rgb(vram, PALETTES, palette, color))
+}
+
+/// The color index of a pixel of a tile, from its 3 bitplanes
+fn tile_pixel(vram: &[u8], bank: usize, tile: usize, x: usize, y: usize) -> usize {
+    let rows = TILE_BANKS[bank] + tile * 24 + y;
+    (0..3).fold(0, |color, plane| {
+        color | (((vram[rows + plane * 8] >> (7 - x)) & 1) << plane)
+    }) as usize
+}
+
+fn palette_rgb(vram: &[u8], palettes: usize, palette: usize, color: usize) -> u32 {
+    let rgb = palettes + (palette * 8 + color) * 3;
+    u32::from_be_bytes([0, vram[rgb], vram[rgb + 1], vram[rgb + 2]])
+}
+
+impl BusDevice for Ppu {
+    fn reset(&mut self, _bus: &mut dyn Bus) {
+        // VRAM survives a reset, like the RAM does
+        self.control = 0;
+        self.status = 0;
+        self.addr = 0;
+        self.bg.scroll_x = 0;
+        self.bg.scroll_y = 0;
+        self.fg.scroll_x = 0;
+        self.fg.scroll_y = 0;
+        self.line = 0;
+        self.compare = 0;
+        self.latch = None;
+        self.mode = VGA;
+        self.phase = 0;
+        self.dma_control = 0;
+        self.dma_src = 0;
+        self.dma_dst = 0;
+        self.dma_len = 0;
+    }
+
+    fn tick(&mut self, bus: &mut dyn Bus) {
+        self.phase += self.mode.line_rate;
+        if self.phase >= TICK_RATE {
+            self.phase -= TICK_RATE;
+            self.scanline();
+            if (self.status & StatusFlags::DMA_BUSY) != 0 && !self.vram_locked() {
+                self.dma(bus);
+            }
+        }
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0 => {
+                let status = self.peek(0).unwrap();
+                self.status &=
+                    !(StatusFlags::VBLANK_IRQ | StatusFlags::RASTER_IRQ | StatusFlags::DMA_IRQ);
+                self.latch = None;
+                status
+            }
+            1 => {
+                let data = self.vram[self.addr as usize];
+                self.addr = self.addr.wrapping_add(1);
+                data
+            }
+            0xB => self.line as u8,
+            0xC => (self.line >> 8) as u8,
+            2..=0xA => 0,
+            _ => {
+                tracing::warn!(target: "ppu", "read from register {addr}, which doesn't exist");
+                0
+            }
+        }
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0 => {
+                let mut status = self.status;
+                if self.vram_locked() {
+                    status |= StatusFlags::VRAM_LOCKED;
+                }
+                Some(status)
+            }
+            1 => Some(self.vram[self.addr as usize]),
+            0xB => Some(self.line as u8),
+            0xC => Some((self.line >> 8) as u8),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0 => {
+                tracing::debug!(target: "ppu", "control {data:02X}");
+                self.control = data;
+            }
+            1 => {
+                if self.vram_locked() {
+                    tracing::debug!(
+                        target: "ppu",
+                        "write of {data:02X} to VRAM {:04X} during line {} ignored",
+                        self.addr,
+                        self.line
+                    );
+                } else {
+                    self.vram[self.addr as usize] = data;
+                }
+                self.addr = self.addr.wrapping_add(1);
+            }
+            2 | 4..=0xA => {
+                let Some(word) = self.latch(data) else {
+                    return;
+                };
+                match addr {
+                    2 => self.addr = word,
+                    7 => self.bg.scroll_x = word,
+                    8 => self.bg.scroll_y = word,
+                    9 => self.fg.scroll_x = word,
+                    0xA => self.fg.scroll_y = word,
+                    4 => self.dma_src = word,
+                    5 => self.dma_dst = word,
+                    _ => self.dma_len = word,
+                }
+            }
+            3 => {
+                self.dma_control = data;
+                if (data & DmaFlags::START) != 0 && self.dma_len != 0 {
+                    tracing::debug!(
+                        target: "ppu",
+                        "DMA of {:04X} bytes from {:04X} to {:04X}{}",
+                        self.dma_len,
+                        self.dma_src,
+                        self.dma_dst,
+                        if (data & DmaFlags::TO_CPU) != 0 { " (to CPU)" } else { "" }
+                    );
+                    self.status |= StatusFlags::DMA_BUSY;
+                } else {
+                    self.status &= !StatusFlags::DMA_BUSY;
+                }
+            }
+            0xB => self.compare = (self.compare & 0xFF00) | (data as u16),
+            0xC => self.compare = (self.compare & 0x00FF) | ((data as u16) << 8),
+            _ => tracing::warn!(target: "ppu", "write to register {addr}, which doesn't exist"),
+        }
+    }
+
+    fn register_name(&self, addr: u16) -> Option<&'static str> {
+        match addr {
+            0 => Some("Control/Status"),
+            1 => Some("Data"),
+            2 => Some("Address"),
+            3 => Some("DMA Control"),
+            4 => Some("DMA Src"),
+            5 => Some("DMA Dst"),
+            6 => Some("DMA Length"),
+            7 => Some("BG Scroll-X"),
+            8 => Some("BG Scroll-Y"),
+            9 => Some("FG Scroll-X"),
+            0xA => Some("FG Scroll-Y"),
+            0xB => Some("Raster Line Lo"),
+            0xC => Some("Raster Line Hi"),
+            _ => None,
+        }
+    }
+
+    fn frame(&self) -> Option<Frame<'_>> {
+        let Mode {
+            width,
+            height,
+            lines,
+            line_rate,
+        } = self.mode;
+        Some(Frame {
+            width,
+            height,
+            pixels: &self.framebuffer[..width * height],
+            number: self.frames,
+            line: self.line,
+            rate: (line_rate, lines as u32),
+        })
+    }
+
+    fn vram(&self) -> Option<&[u8]> {
+        Some(&self.vram[..])
+    }
+
+    fn irq(&self) -> bool {
+        (self.status & (StatusFlags::VBLANK_IRQ | StatusFlags::RASTER_IRQ | StatusFlags::DMA_IRQ))
+            != 0
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,437 @@
+use super::*;
+use crate::bus::test::{NoBus, Ram};
+
+fn set_addr(ppu: &mut Ppu, addr: u16) {
+    let [lo, hi] = addr.to_le_bytes();
+    ppu.write(2, lo);
+    ppu.write(2, hi);
+}
+
+fn run_lines(ppu: &mut Ppu, lines: usize) {
+    for _ in 0..lines {
+        ppu.scanline();
+    }
+}
+
+#[test]
+fn ticks_follow_the_line_rate() {
+    let mut ppu = Ppu::new();
+    for mode in [VGA, XGA] {
+        ppu.write(0, if mode == XGA { ControlFlags::HIRES } else { 0 });
+        // finish the frame so the mode takes effect
+        while ppu.mode != mode || ppu.line != 0 {
+            ppu.tick(&mut NoBus);
+        }
+        let mut lines = 0;
+        for _ in 0..TICK_RATE {
+            let line = ppu.line;
+            ppu.tick(&mut NoBus);
+            if ppu.line != line {
+                lines += 1;
+            }
+        }
+        assert_eq!(lines, mode.line_rate);
+    }
+}
+
+#[test]
+fn hires_switches_at_the_next_frame() {
+    let mut ppu = Ppu::new();
+    ppu.write(0, ControlFlags::HIRES);
+    run_lines(&mut ppu, 100);
+    assert_eq!(ppu.mode, VGA);
+    run_lines(&mut ppu, VGA.lines as usize - 100);
+    assert_eq!(ppu.mode, XGA);
+
+    run_lines(&mut ppu, XGA.height);
+    assert_eq!(ppu.read(0), 0);
+    run_lines(&mut ppu, 1);
+    assert_eq!(ppu.read(0), StatusFlags::VBLANK);
+    let frame = ppu.frame().unwrap();
+    assert_eq!((frame.width, frame.height), (1024, 768));
+    assert_eq!(frame.pixels.len(), 1024 * 768);
+    assert_eq!(frame.rate, (48_363, 806));
+
+    ppu.write(0, 0);
+    ppu.reset(&mut NoBus);
+    assert_eq!(ppu.mode, VGA);
+}
+
+#[test]
+fn control_enables_vblank_irq() {
+    let mut ppu = Ppu::new();
+    run_lines(&mut ppu, VGA.height + 1);
+    assert!(!ppu.irq());
+
+    ppu.write(0, ControlFlags::VBLANK_IRQ_ENABLE);
+    run_lines(&mut ppu, VGA.lines as usize);
+    assert!(ppu.irq());
+}
+
+#[test]
+fn status_read_acknowledges_irqs() {
+    let mut ppu = Ppu::new();
+    ppu.write(0, ControlFlags::VBLANK_IRQ_ENABLE);
+    run_lines(&mut ppu, VGA.height + 1);
+    assert_eq!(ppu.read(0), StatusFlags::VBLANK | StatusFlags::VBLANK_IRQ);
+    assert!(!ppu.irq());
+    // vblank itself lasts until the frame wraps
+    assert_eq!(ppu.read(0), StatusFlags::VBLANK);
+    run_lines(&mut ppu, VGA.lines as usize - VGA.height - 1);
+    assert_eq!(ppu.read(0), 0);
+}
+
+#[test]
+fn data_port_increments_the_address() {
+    let mut ppu = Ppu::new();
+    set_addr(&mut ppu, 0xFFFF);
+    ppu.write(1, 0xAA);
+    ppu.write(1, 0xBB);
+    assert_eq!((ppu.vram[0xFFFF], ppu.vram[0x0000]), (0xAA, 0xBB));
+
+    set_addr(&mut ppu, 0xFFFF);
+    assert_eq!(ppu.read(1), 0xAA);
+    assert_eq!(ppu.read(1), 0xBB);
+    assert_eq!(ppu.addr, 0x0001);
+}
+
+#[test]
+fn address_changes_on_the_second_write() {
+    let mut ppu = Ppu::new();
+    set_addr(&mut ppu, 0x1234);
+    ppu.write(2, 0x78);
+    assert_eq!(ppu.addr, 0x1234);
+    ppu.write(1, 0xAA);
+    assert_eq!(ppu.vram[0x1234], 0xAA);
+
+    ppu.write(2, 0x56);
+    assert_eq!(ppu.addr, 0x5678);
+}
+
+#[test]
+fn status_read_empties_the_latch() {
+    let mut ppu = Ppu::new();
+    ppu.write(2, 0x34);
+    ppu.read(0);
+    set_addr(&mut ppu, 0x0200);
+    assert_eq!(ppu.addr, 0x0200);
+}
+
+#[test]
+fn dma_registers_share_the_latch() {
+    let mut ppu = Ppu::new();
+    for reg in 4..=6 {
+        ppu.write(reg, 0x12);
+        ppu.write(reg, 0x34);
+    }
+    set_addr(&mut ppu, 0x4000);
+    assert_eq!(ppu.addr, 0x4000);
+}
+
+#[test]
+fn vram_is_locked_while_layers_draw() {
+    let mut ppu = Ppu::new();
+    ppu.write(0, ControlFlags::BG_ENABLE);
+    assert_eq!(ppu.read(0), StatusFlags::VRAM_LOCKED);
+    set_addr(&mut ppu, 0x1000);
+    ppu.write(1, 0xAA);
+    ppu.write(1, 0xBB);
+    assert_eq!(ppu.vram[0x1000..0x1002], [0x00, 0x00]);
+    assert_eq!(ppu.addr, 0x1002);
+
+    // reads are always allowed
+    set_addr(&mut ppu, 0xFFFF);
+    ppu.vram[0xFFFF] = 0xCC;
+    assert_eq!(ppu.read(1), 0xCC);
+
+    run_lines(&mut ppu, VGA.height);
+    assert_eq!(ppu.read(0), 0);
+    set_addr(&mut ppu, 0x1000);
+    ppu.write(1, 0xAA);
+    assert_eq!(ppu.vram[0x1000], 0xAA);
+
+    // and so are writes with the layers off
+    run_lines(&mut ppu, VGA.lines as usize - VGA.height);
+    ppu.write(0, 0);
+    ppu.write(1, 0xBB);
+    assert_eq!(ppu.vram[0x1001], 0xBB);
+}
+
+fn start_dma(ppu: &mut Ppu, src: u16, dst: u16, len: u16, control: u8) {
+    for (reg, word) in [(4, src), (5, dst), (6, len)] {
+        let [lo, hi] = word.to_le_bytes();
+        ppu.write(reg, lo);
+        ppu.write(reg, hi);
+    }
+    ppu.write(3, DmaFlags::START | control);
+}
+
+/// Tick until the next scanline starts
+fn tick_line(ppu: &mut Ppu, bus: &mut dyn Bus) {
+    let line = ppu.line;
+    while ppu.line == line {
+        ppu.tick(bus);
+    }
+}
+
+#[test]
+fn dma_waits_for_vblank_and_raises_irq() {
+    let mut ram = Ram::new(0);
+    for (i, byte) in ram.data[0x2000..0x2100].iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let mut ppu = Ppu::new();
+    ppu.write(0, ControlFlags::BG_ENABLE);
+    start_dma(&mut ppu, 0x2000, 0x4000, 0x100, DmaFlags::IRQ_ENABLE);
+    assert_eq!(
+        ppu.read(0),
+        StatusFlags::DMA_BUSY | StatusFlags::VRAM_LOCKED
+    );
+
+    while (ppu.line as usize) < VGA.height - 1 {
+        tick_line(&mut ppu, &mut ram);
+    }
+    assert_eq!(ram.accesses, 0);
+    // one burst per line, starting after the last visible one is drawn
+    tick_line(&mut ppu, &mut ram);
+    assert_eq!(ram.accesses, DMA_BYTES_PER_LINE as usize);
+    for _ in 0..3 {
+        assert!(!ppu.irq());
+        tick_line(&mut ppu, &mut ram);
+    }
+    assert!(ppu.irq());
+    assert_eq!(ram.accesses, 0x100);
+    assert_eq!(ppu.vram[0x4000..0x4100], ram.data[0x2000..0x2100]);
+    assert_eq!((ppu.dma_src, ppu.dma_dst, ppu.dma_len), (0x2100, 0x4100, 0));
+    assert_eq!(ppu.read(0), StatusFlags::VBLANK | StatusFlags::DMA_IRQ);
+    assert!(!ppu.irq());
+}
+
+#[test]
+fn dma_to_cpu_and_stopping() {
+    let mut ram = Ram::new(0);
+    let mut ppu = Ppu::new();
+    ppu.vram[0x8000..0x8010].fill(0x55);
+    start_dma(&mut ppu, 0x8000, 0x3000, 0x10, DmaFlags::TO_CPU);
+    tick_line(&mut ppu, &mut ram);
+    assert_eq!(ram.data[0x3000..0x3010], [0x55; 0x10]);
+    assert_eq!(ppu.read(0), 0);
+    assert!(!ppu.irq());
+
+    start_dma(&mut ppu, 0x8000, 0x3000, 0x1000, 0);
+    ppu.write(3, 0);
+    tick_line(&mut ppu, &mut ram);
+    assert_eq!(ram.accesses, 0x10);
+    assert_eq!(ppu.read(0), 0);
+}
+
+#[test]
+fn scroll_changes_on_the_second_write() {
+    let mut ppu = Ppu::new();
+    for reg in 7..=0xA {
+        ppu.write(reg, 0x23);
+        ppu.write(reg, 0x01 + reg as u8);
+    }
+    assert_eq!(ppu.bg.scroll_x, 0x0823);
+    assert_eq!(ppu.bg.scroll_y, 0x0923);
+    assert_eq!(ppu.fg.scroll_x, 0x0A23);
+    assert_eq!(ppu.fg.scroll_y, 0x0B23);
+
+    ppu.write(7, 0x00);
+    assert_eq!(ppu.bg.scroll_x, 0x0823);
+}
+
+#[test]
+fn raster_line_reads_and_compares() {
+    let mut ppu = Ppu::new();
+    ppu.write(0xB, 0x2C);
+    ppu.write(0xC, 0x01);
+    ppu.write(0, ControlFlags::RASTER_IRQ_ENABLE);
+    run_lines(&mut ppu, 0x12C);
+    assert!(!ppu.irq());
+    assert_eq!((ppu.read(0xB), ppu.read(0xC)), (0x2C, 0x01));
+    run_lines(&mut ppu, 1);
+    assert!(ppu.irq());
+    assert_eq!(ppu.read(0), StatusFlags::RASTER_IRQ);
+}
+
+#[test]
+fn missing_registers_read_zero() {
+    let mut ppu = Ppu::new();
+    set_addr(&mut ppu, 0x1234);
+    for reg in [2, 3, 7, 0xD, 0xF] {
+        assert_eq!(ppu.read(reg), 0);
+    }
+}
+
+const RED: u32 = 0xFF0000;
+const GREEN: u32 = 0x00FF00;
+const BLUE: u32 = 0x0000FF;
+
+/// Tile 0 of bank 1 is a single color 1 pixel in its top-left corner, and
+/// tile 1 is solid color 1. Sprite palette 0 draws it red, palette 1
+/// green, and the BG/FG palette blue.
+fn sprite_ppu() -> Ppu {
+    let mut ppu = Ppu::new();
+    ppu.vram[TILE_BANKS[1]] = 0x80;
+    ppu.vram[TILE_BANKS[1] + 24..][..8].fill(0xFF);
+    ppu.vram[SPRITE_PALETTES + 3..][..3].copy_from_slice(&[0xFF, 0, 0]);
+    ppu.vram[SPRITE_PALETTES + 27..][..3].copy_from_slice(&[0, 0xFF, 0]);
+    ppu.vram[PALETTES + 3..][..3].copy_from_slice(&[0, 0, 0xFF]);
+    ppu
+}
+
+fn sprite(ppu: &mut Ppu, index: usize, tile: u8, attribute: u8, x: u16, y: u16) {
+    let attribute = attribute | 0x04; // tile bank 1
+    ppu.vram[SPRITE_ATTRIBUTES + index * 2..][..2].copy_from_slice(&[tile, attribute]);
+    let position = (x as u32) | ((y as u32) << 10);
+    ppu.vram[SPRITE_POSITIONS + index * 3..][..3].copy_from_slice(&position.to_le_bytes()[..3]);
+}
+
+fn pixel(ppu: &Ppu, x: usize, y: usize) -> u32 {
+    ppu.framebuffer[y * VGA.width + x]
+}
+
+#[test]
+fn sprites_flip() {
+    let mut ppu = sprite_ppu();
+    ppu.write(0, ControlFlags::SPRITE_ENABLE);
+    sprite(&mut ppu, 0, 0, 0x40, 10, 20);
+    sprite(&mut ppu, 1, 0, 0x40 | SpriteFlags::FLIP_X, 30, 20);
+    sprite(&mut ppu, 2, 0, 0x40 | SpriteFlags::FLIP_Y, 50, 20);
+    // wrapped around the left edge
+    sprite(&mut ppu, 3, 0, 0x40 | SpriteFlags::FLIP_X, 0x3FC, 40);
+    run_lines(&mut ppu, VGA.height);
+    assert_eq!(pixel(&ppu, 10, 20), RED);
+    assert_eq!(pixel(&ppu, 11, 20), 0);
+    assert_eq!(pixel(&ppu, 37, 20), RED);
+    assert_eq!(pixel(&ppu, 30, 20), 0);
+    assert_eq!(pixel(&ppu, 50, 27), RED);
+    assert_eq!(pixel(&ppu, 50, 20), 0);
+    assert_eq!(pixel(&ppu, 3, 40), RED);
+}
+
+#[test]
+fn lower_sprites_are_in_front() {
+    let mut ppu = sprite_ppu();
+    ppu.write(0, ControlFlags::SPRITE_ENABLE);
+    sprite(&mut ppu, 5, 0, 0x40, 10, 10);
+    sprite(&mut ppu, 6, 1, 0x41, 10, 10);
+    run_lines(&mut ppu, VGA.height);
+    assert_eq!(pixel(&ppu, 10, 10), RED);
+    // transparent pixels of the front sprite show the one behind
+    assert_eq!(pixel(&ppu, 11, 10), GREEN);
+}
+
+#[test]
+fn sprite_priority() {
+    let mut ppu = sprite_ppu();
+    // the BG is solid color 1, and the FG only covers the top half of the screen
+    ppu.vram[BG_ATTRIBUTES..FG_ATTRIBUTES].fill(0x44);
+    ppu.vram[BG_MAP..FG_MAP].fill(1);
+    ppu.vram[FG_ATTRIBUTES..TILE_BANKS[0]].fill(0x44);
+    ppu.vram[FG_MAP..FG_MAP + 30 * PLANE_TILES].fill(1);
+    ppu.write(
+        0,
+        ControlFlags::BG_ENABLE | ControlFlags::FG_ENABLE | ControlFlags::SPRITE_ENABLE,
+    );
+    for (i, priority) in [Priority::BEHIND_BG, Priority::BEHIND_FG, Priority::FRONT]
+        .into_iter()
+        .enumerate()
+    {
+        let attribute = 0x01 | (priority << 5);
+        sprite(&mut ppu, i, 1, attribute, 8 * i as u16, 100);
+        sprite(&mut ppu, 3 + i, 1, attribute, 8 * i as u16, 300);
+    }
+    run_lines(&mut ppu, VGA.height);
+    assert_eq!(pixel(&ppu, 0, 100), BLUE);
+    assert_eq!(pixel(&ppu, 8, 100), BLUE);
+    assert_eq!(pixel(&ppu, 16, 100), GREEN);
+    assert_eq!(pixel(&ppu, 0, 300), BLUE);
+    assert_eq!(pixel(&ppu, 8, 300), GREEN);
+    assert_eq!(pixel(&ppu, 16, 300), GREEN);
+
+    // behind the BG shows through its color 0
+    ppu.vram[BG_MAP..FG_MAP].fill(2);
+    run_lines(&mut ppu, VGA.lines as usize);
+    assert_eq!(pixel(&ppu, 0, 300), GREEN);
+}
+
+#[test]
+fn sprites_per_line_limit() {
+    let mut ppu = sprite_ppu();
+    ppu.write(0, ControlFlags::SPRITE_ENABLE);
+    for i in 0..SPRITES_PER_LINE {
+        sprite(&mut ppu, i, 0, 0x40, 8 * i as u16, 10);
+    }
+    run_lines(&mut ppu, VGA.height);
+    assert_eq!(ppu.read(0) & StatusFlags::SPRITE_OVERFLOW, 0);
+
+    sprite(
+        &mut ppu,
+        SPRITES - 1,
+        0,
+        0x40,
+        8 * SPRITES_PER_LINE as u16,
+        10,
+    );
+    run_lines(&mut ppu, VGA.lines as usize);
+    assert_eq!(pixel(&ppu, 8 * (SPRITES_PER_LINE - 1), 10), RED);
+    assert_eq!(pixel(&ppu, 8 * SPRITES_PER_LINE, 10), 0);
+    assert_ne!(ppu.read(0) & StatusFlags::SPRITE_OVERFLOW, 0);
+    // it lasts until the next frame
+    assert_ne!(ppu.read(0) & StatusFlags::SPRITE_OVERFLOW, 0);
+    sprite(&mut ppu, SPRITES - 1, 0, 0, 0, 0);
+    run_lines(&mut ppu, VGA.lines as usize - VGA.height);
+    assert_eq!(ppu.read(0) & StatusFlags::SPRITE_OVERFLOW, 0);
+}
+
+#[test]
+fn inspect_tile_bank() {
+    let ppu = sprite_ppu();
+    let pixels = inspect::tile_bank(&ppu.vram[..], 1, 0);
+    assert_eq!(pixels.len(), inspect::BANK_SIZE * inspect::BANK_SIZE);
+    // tile 0's corner, then all of tile 1 just to its right
+    assert_eq!(pixels[0], BLUE);
+    assert_eq!(pixels[1], 0);
+    assert_eq!(pixels[8], BLUE);
+    assert_eq!(pixels[7 * inspect::BANK_SIZE + 15], BLUE);
+    assert_eq!(pixels[16], 0);
+}
+
+#[test]
+fn inspect_plane_ignores_scroll() {
+    let mut ppu = sprite_ppu();
+    // the BG tile in the second row and column is tile 1 of bank 1
+    let index = PLANE_TILES + 1;
+    ppu.vram[BG_MAP + index] = 1;
+    ppu.vram[BG_ATTRIBUTES + index / 2] = 0x04 << 4;
+    ppu.bg.scroll_x = 0x55;
+    ppu.bg.scroll_y = 0x55;
+    let pixels = inspect::plane(&ppu.vram[..], false);
+    assert_eq!(pixels.len(), inspect::PLANE_SIZE * inspect::PLANE_SIZE);
+    assert_eq!(pixels[8 * inspect::PLANE_SIZE + 8], BLUE);
+    assert_eq!(pixels[8 * inspect::PLANE_SIZE + 7], 0);
+    assert!(inspect::plane(&ppu.vram[..], true)
+        .iter()
+        .all(|&rgb| rgb == 0));
+}
+
+#[test]
+fn inspect_sprites_and_palettes() {
+    let mut ppu = sprite_ppu();
+    sprite(&mut ppu, 3, 1, 0x01 | 0x08 | 0x60, 300, 200);
+    let sprites = inspect::sprites(&ppu.vram[..]);
+    assert_eq!(sprites.len(), SPRITES);
+    let sprite = &sprites[3];
+    assert_eq!((sprite.tile, sprite.palette, sprite.bank), (1, 1, 1));
+    assert_eq!((sprite.flip_x, sprite.flip_y), (true, false));
+    assert_eq!(sprite.priority, Priority::FRONT);
+    assert_eq!((sprite.x, sprite.y), (300, 200));
+    assert_eq!(sprites[0].priority, Priority::HIDDEN);
+
+    let palettes = inspect::palettes(&ppu.vram[..], true);
+    assert_eq!(palettes[0][1], RED);
+    assert_eq!(palettes[1][1], GREEN);
+    assert_eq!(inspect::palettes(&ppu.vram[..], false)[0][1], BLUE);
+}
@@ -0,0 +1,55 @@
+//! Guest Code Profiler
+//!
+//! Counts the CPU cycles spent executing the instruction at each PC while
+//! running, to find the hot spots in guest code. Cycles the CPU is held
+//! off the bus during an instruction count against it too.
+
+pub struct Profiler {
+    running: bool,
+    cycles: Vec<u64>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            running: false,
+            cycles: vec![0; 0x10000],
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.cycles.fill(0);
+        self.running = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+
+    pub fn running(&self) -> bool {
+        self.running
+    }
+
+    pub fn record(&mut self, pc: u16, cycles: u64) {
+        if self.running {
+            self.cycles[pc as usize] += cycles;
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.cycles.iter().sum()
+    }
+
+    /// Every address that was hit, hottest first
+    pub fn hot_spots(&self) -> Vec<(u16, u64)> {
+        let mut spots = self
+            .cycles
+            .iter()
+            .enumerate()
+            .filter(|(_, &cycles)| cycles != 0)
+            .map(|(addr, &cycles)| (addr as u16, cycles))
+            .collect::<Vec<_>>();
+        spots.sort_by(|(a_addr, a), (b_addr, b)| b.cmp(a).then(a_addr.cmp(b_addr)));
+        spots
+    }
+}
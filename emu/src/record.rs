@@ -0,0 +1,135 @@
+//! Video Recording
+//!
+//! Saves every frame the PPU finishes, either as a Y4M video (when the
+//! path ends in `.y4m`) or as numbered PNGs in a directory. The video is
+//! stamped with the PPU's nominal frame rate, so it plays back at the
+//! speed the guest would run on hardware however fast the emulator went.
+//! Y4M can't change size partway, so switching video modes stops a video
+//! recording (PNGs just change size).
+//! There is no sound device yet, so there is no audio to record.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    bus::Frame,
+    filter::{self, Filter},
+    png,
+};
+
+enum Output {
+    Y4m {
+        file: BufWriter<File>,
+        /// The frame size, once the header is written
+        size: Option<(usize, usize)>,
+    },
+    Png(PathBuf),
+}
+
+pub struct Recorder {
+    output: Output,
+    /// The number of the last frame saved
+    last: u64,
+    saved: u64,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let output = if path.extension().is_some_and(|ext| ext == "y4m") {
+            Output::Y4m {
+                file: BufWriter::new(File::create(path)?),
+                size: None,
+            }
+        } else {
+            fs::create_dir_all(path)?;
+            Output::Png(path.to_path_buf())
+        };
+        tracing::info!("recording video to {}", path.display());
+        Ok(Self {
+            output,
+            last: 0,
+            saved: 0,
+        })
+    }
+
+    /// Save the frame through the display filters if it was finished since
+    /// the last one saved
+    pub fn capture(&mut self, frame: &Frame, filters: &[Filter]) -> io::Result<()> {
+        if frame.number == self.last {
+            return Ok(());
+        }
+        self.last = frame.number;
+        let pixels;
+        let filtered;
+        let frame = if filters.is_empty() {
+            frame
+        } else {
+            pixels = filter::apply(filters, frame);
+            filtered = Frame {
+                pixels: &pixels,
+                ..*frame
+            };
+            &filtered
+        };
+        match &mut self.output {
+            Output::Y4m { file, size } => {
+                match *size {
+                    None => {
+                        let (num, den) = frame.rate;
+                        writeln!(
+                            file,
+                            "YUV4MPEG2 W{} H{} F{num}:{den} Ip A1:1 C444",
+                            frame.width, frame.height,
+                        )?;
+                        *size = Some((frame.width, frame.height));
+                    }
+                    Some(size) if size != (frame.width, frame.height) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "the video mode changed, which Y4M can't follow",
+                        ));
+                    }
+                    Some(_) => {}
+                }
+                write_y4m_frame(file, frame)?;
+            }
+            Output::Png(dir) => {
+                let path = dir.join(format!("{:06}.png", self.saved));
+                let mut file = BufWriter::new(File::create(path)?);
+                png::write(&mut file, frame.width, frame.height, frame.pixels)?;
+                file.flush()?;
+            }
+        }
+        self.saved += 1;
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Output::Y4m { file, .. } = &mut self.output {
+            if let Err(e) = file.flush() {
+                tracing::error!("failed to finish recording: {e}");
+            }
+        }
+        tracing::info!("recorded {} frames", self.saved);
+    }
+}
+
+/// One frame of full resolution (4:4:4) BT.601 YCbCr planes
+fn write_y4m_frame(out: &mut dyn Write, frame: &Frame) -> io::Result<()> {
+    let mut planes = vec![0; frame.pixels.len() * 3];
+    let (y, cbcr) = planes.split_at_mut(frame.pixels.len());
+    let (cb, cr) = cbcr.split_at_mut(frame.pixels.len());
+    for (i, pixel) in frame.pixels.iter().enumerate() {
+        let [_, r, g, b] = pixel.to_be_bytes().map(|c| c as i32);
+        y[i] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+        cb[i] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+        cr[i] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+    }
+    out.write_all(b"FRAME\n")?;
+    out.write_all(&planes)
+}
@@ -0,0 +1,239 @@
+//! Remote Debugger
+//!
+//! Serves the debugger on a TCP or Unix socket so editors and other
+//! front-ends can drive the emulator while the TTY stays dedicated to the
+//! guest console. One client is served at a time.
+//!
+//! The protocol is line based. Clients send the same commands typed at the
+//! `dbg>` prompt, and each response is the command's output (without
+//! colors) followed by a line holding a single `.`. While a client is
+//! attached, breakpoints, ctrl-c, and SIGUSR1 stop the emulator and send
+//! `!stop XXXX` (the PC) instead of opening the console prompt. `halt`
+//! stops a running emulator and `c` resumes it. Commands sent while the
+//! emulator is running are handled between batches of instructions.
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    debugger::{debug_command, strip_colors, DebugAction, Debugger},
+    sys::System,
+};
+
+/// A non-blocking socket to accept clients on, shared with the serial ports
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    /// Listen on `HOST:PORT`, or `unix:PATH` for a Unix socket
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        if let Some(path) = addr.strip_prefix("unix:") {
+            let listener = UnixListener::bind(path)?;
+            listener.set_nonblocking(true)?;
+            Ok(Listener::Unix(listener, PathBuf::from(path)))
+        } else {
+            let listener = TcpListener::bind(addr)?;
+            listener.set_nonblocking(true)?;
+            Ok(Listener::Tcp(listener))
+        }
+    }
+
+    /// Accept a waiting client as a non-blocking stream
+    pub fn accept(&self) -> io::Result<Stream> {
+        let stream = match self {
+            Listener::Tcp(listener) => listener.accept().map(|(stream, _)| Stream::Tcp(stream)),
+            Listener::Unix(listener, _) => {
+                listener.accept().map(|(stream, _)| Stream::Unix(stream))
+            }
+        }?;
+        stream.set_nonblocking(true)?;
+        Ok(stream)
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, path) = self {
+            fs::remove_file(path).ok();
+        }
+    }
+}
+
+pub enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Stream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            Stream::Unix(stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+struct Client {
+    stream: Stream,
+    line: Vec<u8>,
+}
+
+impl Client {
+    /// Read the next complete line if one has arrived
+    fn next_line(&mut self) -> io::Result<Option<String>> {
+        loop {
+            if let Some(end) = self.line.iter().position(|&c| c == b'\n') {
+                let line = self.line.drain(..=end).collect::<Vec<u8>>();
+                return Ok(Some(String::from_utf8_lossy(&line).into_owned()));
+            }
+            let mut buf = [0; 256];
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(size) => self.line.extend_from_slice(&buf[..size]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn send(&mut self, buf: &[u8]) -> io::Result<()> {
+        // the stream is only non-blocking for reads
+        self.stream.set_nonblocking(false)?;
+        self.stream.write_all(buf)?;
+        self.stream.set_nonblocking(true)
+    }
+}
+
+pub struct Remote {
+    listener: Listener,
+    client: Option<Client>,
+}
+
+impl Remote {
+    /// Listen on `HOST:PORT`, or `unix:PATH` for a Unix socket
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        Ok(Self {
+            listener: Listener::bind(addr)?,
+            client: None,
+        })
+    }
+
+    pub fn connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    fn accept(&mut self) {
+        if self.client.is_some() {
+            return;
+        }
+        match self.listener.accept() {
+            Ok(stream) => {
+                tracing::info!("remote debugger attached");
+                self.client = Some(Client {
+                    stream,
+                    line: Vec::new(),
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => tracing::warn!("failed to accept remote debugger: {e}"),
+        }
+    }
+
+    fn disconnect(&mut self, e: io::Error) {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            tracing::info!("remote debugger detached");
+        } else {
+            tracing::warn!("remote debugger detached: {e}");
+        }
+        self.client = None;
+    }
+
+    /// Handle any pending commands. When `halted`, keeps serving until the
+    /// client continues, quits, or goes away.
+    pub fn serve(&mut self, sys: &mut System, dbg: &mut Debugger, halted: bool) -> DebugAction {
+        self.accept();
+        let mut halted = halted;
+        if halted {
+            let stop = format!("!stop {:04X}\n", sys.cpu().pc());
+            if let Err(e) = self
+                .client
+                .as_mut()
+                .map_or(Ok(()), |c| c.send(stop.as_bytes()))
+            {
+                self.disconnect(e);
+            }
+        }
+        loop {
+            let Some(client) = &mut self.client else {
+                return DebugAction::Continue;
+            };
+            let line = match client.next_line() {
+                Ok(Some(line)) => line,
+                Ok(None) if halted => {
+                    thread::sleep(Duration::from_millis(1));
+                    continue;
+                }
+                Ok(None) => return DebugAction::Continue,
+                Err(e) => {
+                    self.disconnect(e);
+                    continue;
+                }
+            };
+
+            let parts = line
+                .split_whitespace()
+                .map(String::from)
+                .collect::<Vec<String>>();
+            if parts.first().map(String::as_str) == Some("halt") {
+                halted = true;
+            }
+            let mut out = Vec::new();
+            let action = debug_command(&mut out, sys, dbg, &parts).unwrap();
+            let mut response = strip_colors(&out);
+            response.extend_from_slice(b".\n");
+            if let Err(e) = client.send(&response) {
+                self.disconnect(e);
+            }
+            match action {
+                DebugAction::Prompt => {}
+                DebugAction::Continue => halted = false,
+                DebugAction::Quit => return DebugAction::Quit,
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! Pseudo-Entropy Source
+//!
+//! Not real hardware: a register that reads as a stream of pseudo-random
+//! bytes, so guest games get randomness while replays and test runs stay
+//! deterministic. The stream is xorshift64* from a 64-bit seed, which is
+//! logged at startup and can be pinned with `--seed` (or `seed` in the
+//! machine config). Headless runs default to a seed of 0, interactive
+//! ones to the host clock. Reset restarts the stream from the seed.
+//!
+//! Registers:
+//!
+//! 0 Data (Reads return the next byte)
+
+use crate::bus::{Bus, BusDevice};
+
+pub struct Rng {
+    seed: u64,
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            state: Self::start(seed),
+        }
+    }
+
+    /// Spread the seed over the state with SplitMix64, since xorshift is
+    /// stuck at 0 and slow to get going from small seeds
+    fn start(seed: u64) -> u64 {
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        (z ^ (z >> 31)).max(1)
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        // the high bits are the best mixed
+        (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+}
+
+impl BusDevice for Rng {
+    fn reset(&mut self, _bus: &mut dyn Bus) {
+        *self = Self::new(self.seed);
+    }
+
+    fn tick(&mut self, _bus: &mut dyn Bus) {}
+
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0 => self.next_byte(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn register_name(&self, addr: u16) -> Option<&'static str> {
+        match addr {
+            0 => Some("Data"),
+            _ => None,
+        }
+    }
+}
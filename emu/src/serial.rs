@@ -0,0 +1,269 @@
+//! Serial Port Backends
+//!
+//! What sits on the host end of each UART, picked per port on the command
+//! line:
+//!
+//! - `tty`: the host terminal (shared with the debugger prompt)
+//! - `null`: nothing, output is dropped and there is never input
+//! - `file:PATH`: output is written to a file
+//! - `tcp:HOST:PORT` or `unix:PATH`: a socket serving one client at a time
+//! - `pty`: a new pseudo-terminal, whose path is logged at startup
+//!
+//! Several can be joined with `+` (`tty+file:ser0.log`). Output then goes
+//! to all of them and input comes from whichever has some.
+
+use std::{
+    ffi::CStr,
+    fs::File,
+    io::{self, Read, Write},
+    mem,
+    os::fd::{AsRawFd, FromRawFd},
+    path::PathBuf,
+};
+
+use crate::remote::{Listener, Stream};
+
+#[derive(Clone, Debug)]
+pub enum Spec {
+    Tty,
+    Null,
+    File(PathBuf),
+    Socket(String),
+    Pty,
+    Tee(Vec<Spec>),
+}
+
+pub fn parse_spec(s: &str) -> Result<Spec, String> {
+    let mut specs = s
+        .split('+')
+        .map(|part| match part {
+            "tty" => Ok(Spec::Tty),
+            "null" => Ok(Spec::Null),
+            "pty" => Ok(Spec::Pty),
+            _ => {
+                if let Some(path) = part.strip_prefix("file:") {
+                    Ok(Spec::File(PathBuf::from(path)))
+                } else if let Some(addr) = part.strip_prefix("tcp:") {
+                    Ok(Spec::Socket(addr.to_string()))
+                } else if part.starts_with("unix:") {
+                    Ok(Spec::Socket(part.to_string()))
+                } else {
+                    Err(format!("unknown serial backend `{part}`"))
+                }
+            }
+        })
+        .collect::<Result<Vec<Spec>, String>>()?;
+    if specs.len() == 1 {
+        Ok(specs.remove(0))
+    } else {
+        Ok(Spec::Tee(specs))
+    }
+}
+
+pub enum Port {
+    Tty(Box<dyn Console>),
+    Null,
+    File(File),
+    Socket(Socket),
+    Pty(File),
+    Tee(Vec<Port>),
+}
+
+/// The host terminal end, which the front-end provides
+pub trait Console: Read + Write {}
+
+impl<T: Read + Write> Console for T {}
+
+impl Port {
+    /// Open the backend for the port called `name`, asking `console` for
+    /// the terminal end by the port's name
+    pub fn open(
+        spec: &Spec,
+        name: &str,
+        console: &mut dyn FnMut(&str) -> Box<dyn Console>,
+    ) -> io::Result<Self> {
+        Ok(match spec {
+            Spec::Tty => Port::Tty(console(name)),
+            Spec::Null => Port::Null,
+            Spec::File(path) => Port::File(File::create(path)?),
+            Spec::Socket(addr) => {
+                tracing::info!("{name} listening on {addr}");
+                Port::Socket(Socket {
+                    name: name.to_string(),
+                    listener: Listener::bind(addr)?,
+                    client: None,
+                })
+            }
+            Spec::Pty => Port::Pty(open_pty(name)?),
+            Spec::Tee(specs) => Port::Tee(
+                specs
+                    .iter()
+                    .map(|spec| Port::open(spec, name, console))
+                    .collect::<io::Result<Vec<Port>>>()?,
+            ),
+        })
+    }
+}
+
+impl Read for Port {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Port::Tty(console) => console.read(buf),
+            Port::Null | Port::File(_) => Ok(0),
+            Port::Socket(socket) => socket.read(buf),
+            // EIO just means nothing has the other end open
+            Port::Pty(master) => match master.read(buf) {
+                Err(e) if would_block(&e) || e.raw_os_error() == Some(libc::EIO) => Ok(0),
+                result => result,
+            },
+            Port::Tee(ports) => {
+                for port in ports {
+                    let size = port.read(buf)?;
+                    if size != 0 {
+                        return Ok(size);
+                    }
+                }
+                Ok(0)
+            }
+        }
+    }
+}
+
+impl Write for Port {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Port::Tty(console) => console.write(buf),
+            Port::Null => Ok(buf.len()),
+            Port::File(file) => file.write(buf),
+            Port::Socket(socket) => socket.write(buf),
+            // like a cable nobody plugged in, the bytes are lost
+            Port::Pty(master) => match master.write(buf) {
+                Err(e) if would_block(&e) || e.raw_os_error() == Some(libc::EIO) => Ok(buf.len()),
+                result => result,
+            },
+            Port::Tee(ports) => {
+                for port in ports {
+                    port.write_all(buf)?;
+                }
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Port::Tty(console) => console.flush(),
+            Port::File(file) => file.flush(),
+            Port::Tee(ports) => ports.iter_mut().try_for_each(Port::flush),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn would_block(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::WouldBlock
+}
+
+/// A listening socket. Output is dropped while no client is connected.
+pub struct Socket {
+    name: String,
+    listener: Listener,
+    client: Option<Stream>,
+}
+
+impl Socket {
+    fn client(&mut self) -> Option<&mut Stream> {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok(stream) => {
+                    tracing::info!("{} client connected", self.name);
+                    self.client = Some(stream);
+                }
+                Err(e) if would_block(&e) => {}
+                Err(e) => tracing::warn!("{} failed to accept client: {e}", self.name),
+            }
+        }
+        self.client.as_mut()
+    }
+
+    fn disconnect(&mut self) {
+        tracing::info!("{} client disconnected", self.name);
+        self.client = None;
+    }
+}
+
+impl Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(client) = self.client() else {
+            return Ok(0);
+        };
+        match client.read(buf) {
+            Ok(0) => {
+                self.disconnect();
+                Ok(0)
+            }
+            Err(e) if would_block(&e) => Ok(0),
+            Err(_) => {
+                self.disconnect();
+                Ok(0)
+            }
+            result => result,
+        }
+    }
+}
+
+impl Write for Socket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(client) = self.client() else {
+            return Ok(buf.len());
+        };
+        match client.write(buf) {
+            Err(e) if would_block(&e) => Ok(buf.len()),
+            Err(_) => {
+                self.disconnect();
+                Ok(buf.len())
+            }
+            result => result,
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Create a pseudo-terminal in raw mode and return its (non-blocking)
+/// master side
+fn open_pty(name: &str) -> io::Result<File> {
+    let check = |result: libc::c_int| {
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(result)
+        }
+    };
+    // SAFETY: plain libc calls on a descriptor we own, and ptsname_r gets
+    // the length of its buffer
+    unsafe {
+        let fd = check(libc::posix_openpt(
+            libc::O_RDWR | libc::O_NOCTTY | libc::O_NONBLOCK,
+        ))?;
+        let master = File::from_raw_fd(fd);
+        check(libc::grantpt(fd))?;
+        check(libc::unlockpt(fd))?;
+        let mut path = [0; 128];
+        if libc::ptsname_r(fd, path.as_mut_ptr(), path.len()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let path = CStr::from_ptr(path.as_ptr()).to_string_lossy();
+
+        // bytes should pass through the line discipline untouched
+        let mut termios = mem::zeroed();
+        check(libc::tcgetattr(master.as_raw_fd(), &mut termios))?;
+        libc::cfmakeraw(&mut termios);
+        check(libc::tcsetattr(master.as_raw_fd(), libc::TCSANOW, &termios))?;
+
+        tracing::info!("{name} is on {path}");
+        Ok(master)
+    }
+}
@@ -0,0 +1,80 @@
+//! Stack Guard
+//!
+//! Watches the stack pointer after every instruction while running, and
+//! warns when it:
+//! * drops below a low-water mark (once, until it climbs back up)
+//! * wraps around its page (or the whole address space with the 16-bit
+//!   stack), which is almost always a runaway push or pull loop
+
+use crate::cpu::{Cpu, Flags};
+
+/// How far a single instruction can move the stack on its own. Bigger
+/// jumps are the guest setting SP, not wrapping it.
+const MAX_STEP: u16 = 0x10;
+
+pub struct StackGuard {
+    /// The lowest SP that is fine, or `None` when the guard is off
+    low: Option<u16>,
+    /// SP after the last instruction
+    sp: u16,
+    /// Whether SP was already below the mark
+    below: bool,
+}
+
+impl StackGuard {
+    pub fn new() -> Self {
+        Self {
+            low: None,
+            sp: 0,
+            below: false,
+        }
+    }
+
+    pub fn low(&self) -> Option<u16> {
+        self.low
+    }
+
+    /// Guard the stack of `cpu` from here on, or stop guarding it
+    pub fn set(&mut self, low: Option<u16>, cpu: &Cpu) {
+        self.low = low;
+        self.sp = cpu.sp();
+        self.below = low.is_some_and(|low| self.sp < low);
+    }
+
+    /// Check SP after the instruction at `pc` ran
+    #[inline]
+    pub fn check(&mut self, cpu: &Cpu, pc: u16) {
+        let Some(low) = self.low else {
+            return;
+        };
+        let sp = cpu.sp();
+        if sp == self.sp {
+            return;
+        }
+        let old = self.sp;
+        self.sp = sp;
+
+        let paged = (cpu.p() & Flags::EXTEND_STACK_DISABLE) != 0;
+        let (step, span) = if paged {
+            let step = (sp as u8).wrapping_sub(old as u8);
+            (step.min(step.wrapping_neg()) as u16, 0x100)
+        } else {
+            let step = sp.wrapping_sub(old);
+            (step.min(step.wrapping_neg()), 0x1_0000)
+        };
+        let moved = (sp as i32 - old as i32).unsigned_abs();
+        let same_page = !paged || (sp >> 8) == (old >> 8);
+        if same_page && step <= MAX_STEP && moved > span / 2 {
+            tracing::warn!("stack wrapped from {old:04X} to {sp:04X} at {pc:04X}");
+        }
+
+        if sp < low {
+            if !self.below {
+                tracing::warn!("stack pointer {sp:04X} below the guard at {low:04X} at {pc:04X}");
+            }
+            self.below = true;
+        } else {
+            self.below = false;
+        }
+    }
+}
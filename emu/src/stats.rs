@@ -0,0 +1,101 @@
+//! Performance Counters
+//!
+//! Measures how fast the guest runs against the wall clock. Only time spent
+//! running counts, so sitting at the debugger prompt doesn't drag the rates
+//! down. The counts themselves come from the CPU.
+
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use crate::cpu::Cpu;
+
+#[derive(Clone, Copy, Default)]
+struct Sample {
+    run_time: Duration,
+    instructions: u64,
+    cycles: u64,
+}
+
+impl Sample {
+    fn take(cpu: &Cpu, run_time: Duration) -> Self {
+        Self {
+            run_time,
+            instructions: cpu.instructions(),
+            cycles: cpu.cycles(),
+        }
+    }
+
+    /// Instructions and cycles per second since `since`
+    fn rates(&self, since: &Sample) -> Option<(f64, f64)> {
+        let secs = (self.run_time - since.run_time).as_secs_f64();
+        if secs == 0.0 {
+            return None;
+        }
+        Some((
+            (self.instructions - since.instructions) as f64 / secs,
+            (self.cycles - since.cycles) as f64 / secs,
+        ))
+    }
+}
+
+pub struct Stats {
+    /// The clock rate the guest expects, in Hz
+    pub target_hz: Option<u64>,
+    /// How often to log the speed, in running time
+    pub interval: Option<Duration>,
+    run_time: Duration,
+    last_report: Sample,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            target_hz: None,
+            interval: None,
+            run_time: Duration::ZERO,
+            last_report: Sample::default(),
+        }
+    }
+
+    /// Account for time spent running the guest, logging the speed when
+    /// the report interval has passed
+    pub fn add_run_time(&mut self, cpu: &Cpu, elapsed: Duration) {
+        self.run_time += elapsed;
+        let Some(interval) = self.interval else {
+            return;
+        };
+        if self.run_time - self.last_report.run_time < interval {
+            return;
+        }
+        let sample = Sample::take(cpu, self.run_time);
+        if let Some((ips, cps)) = sample.rates(&self.last_report) {
+            tracing::info!("{}", self.speed(ips, cps));
+        }
+        self.last_report = sample;
+    }
+
+    fn speed(&self, ips: f64, cps: f64) -> String {
+        let mut speed = format!("{:.2} MIPS, {:.2} MHz", ips / 1e6, cps / 1e6);
+        if let Some(target_hz) = self.target_hz {
+            speed.push_str(&format!(
+                " ({:.0}% of {:.2} MHz)",
+                cps * 100.0 / target_hz as f64,
+                target_hz as f64 / 1e6
+            ));
+        }
+        speed
+    }
+
+    pub fn print(&self, out: &mut dyn Write, cpu: &Cpu) -> io::Result<()> {
+        let sample = Sample::take(cpu, self.run_time);
+        writeln!(out, "instructions: {}", sample.instructions)?;
+        writeln!(out, "cycles:       {}", sample.cycles)?;
+        writeln!(out, "run time:     {:.3}s", self.run_time.as_secs_f64())?;
+        match sample.rates(&Sample::default()) {
+            Some((ips, cps)) => writeln!(out, "speed:        {}", self.speed(ips, cps)),
+            None => writeln!(out, "speed:        not run yet"),
+        }
+    }
+}
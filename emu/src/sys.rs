@@ -5,11 +5,12 @@
 //! * 2 6551 UARTs
 //! * 2 FD179X Floppy Disk Controllers
 //! * Simple Interrupt Controller using 74148 and 74574
+//! * Programmable Interval Timer
 //! * NES/GBC-ish PPU with external VRAM and DMA
 //! * Banked RAM
 //!
-//! PPU has 2 resolutions? (640x480 and 1024x768) since it
-//! internally maintains a 1024x1024 plane of tiles.
+//! PPU has 2 resolutions (640x480 and 1024x768, picked with the
+//! control register) since it internally maintains a 1024x1024 plane of tiles.
 //!
 //! 32 sprites per line!
 //!
@@ -19,6 +20,11 @@
 //!   The same would have to be done for sprites... so I don't know.
 //!   I could just _not_ support such effects for sprites.
 //!
+//! TODO: Once there's a windowed frontend, overlay a HUD on it (toggled
+//!   with a key) showing the emulated FPS, host CPU usage, emulated MIPS,
+//!   the beam position, and audio buffer health once there's audio. The
+//!   `stats` command and `Frame` already have everything but the audio.
+//!
 //! Memory Map:
 //!
 //! 0000-0FFF RAM0
@@ -42,6 +48,7 @@
 //! IO Addresses:
 //!
 //! F000-F00E RAM Bank Select
+//! F00F      ROM Bank Select
 //! F010      SER0 Data
 //! F011      SER0 Status
 //! F012      SER0 Command
@@ -50,6 +57,10 @@
 //! F015      SER1 Status
 //! F016      SER1 Command
 //! F017      SER1 Control
+//! F018      Timer Counter Lo (Writes set reload value)
+//! F019      Timer Counter Hi (Writes set reload value)
+//! F01A      Timer Control
+//! F01B      Timer Status
 //! F020      PPU Contol/Status (Reads return Status)
 //! F021      PPU Data
 //! F022      PPU Address (2 writes)
@@ -61,6 +72,8 @@
 //! F028      PPU BG Scroll-Y (2 writes)
 //! F029      PPU FG Scroll-X (2 writes)
 //! F02A      PPU FG Scroll-Y (2 writes)
+//! F02B      PPU Raster Line Lo (Reads return the current line, writes set the compare line)
+//! F02C      PPU Raster Line Hi
 //! F030      FDC0 Command/Status
 //! F031      FDC0 Track
 //! F032      FDC0 Sector
@@ -69,8 +82,34 @@
 //! F035      FDC1 Track
 //! F036      FDC1 Sector
 //! F037      FDC1 Data
+//! F038      FDC DRQ Routing (bit 0/1: route FDC0/FDC1 DRQ to IRQ, bit 4/5: FDC0/FDC1 DRQ status)
+//! F03A      FDC0 DMA Control
+//! F03B      FDC0 DMA Address Lo
+//! F03C      FDC0 DMA Address Hi
+//! F03D      FDC1 DMA Control
+//! F03E      FDC1 DMA Address Lo
+//! F03F      FDC1 DMA Address Hi
+//! F040      Keyboard Row Select
+//! F041      Keyboard Columns (Reads return pressed keys in the selected rows)
+//! F050      RNG Data (Reads return the next pseudo-random byte)
+//! F058      Host File Command (only with --host-dir, see [`crate::hostfs`])
+//! F059      Host File Status
+//! F05A      Host File Data
+//! F05B-F05E Host File Position
+//! F0F0      Emulator Exit (writes stop the emulator with the written exit status)
+//! F0F1      Emulator Reset (writes warm reset the system, RAM is kept)
+//! F0F2      Device Reset (writes reset the device with a register at F0xx, e.g. $10 for SER0)
+//! F0F8      Interrupt Enable Mask
+//! F0F9      Interrupt Pending (Writes acknowledge edge-triggered sources)
+//! F0FA      Interrupt Trigger Mode
+//! F0FB-F0FE Interrupt Routing (a nibble per encoder input picking its source)
 //! F0FF      Interrupt Latch
 //!
+//! Only the bank select, DRQ routing, emulator exit and reset, device
+//! reset, and interrupt controller registers are fixed. Everything else
+//! is attached at startup, so the addresses above are just the standard
+//! layout.
+//!
 //! PPU Memory Map:
 //!
 //! 0000-3FFF BG Map 16K (128x128 tiles)
@@ -83,167 +122,505 @@
 //! F100-F27F Sprite Positions (128 sprites, 3 bytes each, 20-bits for x and y)
 //! F280-F2DF BG/FG Palettes (4 palettes of 8 24-bit colors)
 //! F2E0-F33F Sprite Palette (4 palettes of 8 24-bit colors)
-use std::io::{Read, Seek, Write};
+use std::str::FromStr;
 
 use crate::{
-    bus::{Bus, BusDevice},
-    cpu::Cpu,
-    fdc::Fdc,
-    uart::Uart,
+    bus::{Bus, BusDevice, DiskActivity, Frame, Trap},
+    cov::{Coverage, CoverageFlags},
+    cpu::{Cpu, Flags, Interrupt},
+    irq::{IrqController, IrqSource, IrqStats},
+    mem::{
+        check::{Checker, MemCheck},
+        Mem,
+    },
+    rng::Rng,
+    trap,
+    xmodem::Xmodem,
 };
 
-pub struct Mem {
-    inner: Vec<u8>,
-    bank_select: [usize; 16], // we create 16 bank selects, but rom is static
+enum DrqRouteFlags {}
+
+impl DrqRouteFlags {
+    const FDC0_IRQ: u8 = 1 << 0;
+    const FDC1_IRQ: u8 = 1 << 1;
+    const FDC0_DRQ: u8 = 1 << 4;
+    const FDC1_DRQ: u8 = 1 << 5;
 }
 
-impl Mem {
-    fn new() -> Self {
-        Self {
-            inner: vec![0; 65536 * 4],
-            bank_select: [0; 16],
+/// A device attached to the IO window
+pub struct Slot {
+    pub name: &'static str,
+    pub base: u16,
+    pub size: u16,
+    /// `IrqSource` driven by the device's IRQ output (0 if not wired)
+    pub irq: u8,
+    /// `IrqSource` driven by the device's DRQ output (0 if not wired)
+    pub drq: u8,
+    /// CPU cycles per device tick (at least 1)
+    pub divisor: u32,
+    pub device: Box<dyn BusDevice>,
+    /// A second block of registers elsewhere in the IO window, as (base,
+    /// size), numbered on from `size`
+    pub extra: Option<(u16, u16)>,
+}
+
+impl Slot {
+    /// The number of registers, counting the extra block
+    pub fn registers(&self) -> u16 {
+        self.size + self.extra.map_or(0, |(_, size)| size)
+    }
+
+    /// The register at an IO address the device is mapped at
+    fn register(&self, addr: u16) -> u16 {
+        match self.extra {
+            Some((base, _)) if addr < self.base || addr >= self.base + self.size => {
+                self.size + (addr - base)
+            }
+            _ => addr - self.base,
         }
     }
+}
+
+/// What happens when the guest touches an unmapped IO address. Reads
+/// return the [`OpenBus`] value either way.
+#[derive(Clone, Copy, Debug)]
+pub enum UnmappedIo {
+    /// Log the first access to each address
+    Warn,
+    /// Say nothing, for guests that lean on the [`OpenBus`] value (which
+    /// is [`OpenBus::Last`] unless set otherwise)
+    OpenBus,
+    /// Log the access and stop in the debugger
+    Break,
+}
 
-    pub fn read(&self, addr: u16) -> u8 {
-        // get the high nibble to determine which 4K "chapter" we are in
-        let chapter = ((addr & 0xF000) >> 12) as usize;
-        let base = chapter * (0x1000 + self.bank_select[chapter]);
-        let offset = (addr & 0x0FFF) as usize;
-        self.inner[base + offset]
+impl FromStr for UnmappedIo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(UnmappedIo::Warn),
+            "open-bus" => Ok(UnmappedIo::OpenBus),
+            "break" => Ok(UnmappedIo::Break),
+            _ => Err(format!("expected `warn`, `open-bus`, or `break`: `{s}`")),
+        }
     }
+}
 
-    fn write(&mut self, addr: u16, data: u8) {
-        // get the high nibble to determine which 4K "chapter" we are in
-        let chapter = ((addr & 0xF000) >> 12) as usize;
-        let base = chapter * (0x1000 + self.bank_select[chapter]);
-        let offset = (addr & 0x0FFF) as usize;
-        self.inner[base + offset] = data;
+/// What reads of unmapped IO addresses return
+#[derive(Clone, Copy, Debug)]
+pub enum OpenBus {
+    Zero,
+    /// The last value on the data bus, like a floating bus
+    Last,
+    Ff,
+    /// Bytes from a stream seeded like [`crate::rng`]
+    Random,
+}
+
+impl FromStr for OpenBus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zero" => Ok(OpenBus::Zero),
+            "last" => Ok(OpenBus::Last),
+            "ff" => Ok(OpenBus::Ff),
+            "random" => Ok(OpenBus::Random),
+            _ => Err(format!("expected `zero`, `last`, `ff`, or `random`: `{s}`")),
+        }
+    }
+}
+
+struct Unmapped {
+    policy: UnmappedIo,
+    warned: [bool; 0x100],
+    /// The access waiting to stop the emulator
+    fault: Option<u16>,
+    open_bus: OpenBus,
+    rng: Rng,
+}
+
+impl Unmapped {
+    /// What a read returns, given the last byte on the bus
+    fn read(&mut self, bus_value: u8) -> u8 {
+        match self.open_bus {
+            OpenBus::Zero => 0,
+            OpenBus::Last => bus_value,
+            OpenBus::Ff => 0xFF,
+            OpenBus::Random => self.rng.next_byte(),
+        }
     }
 
-    pub fn bank(&self, addr: u16) -> usize {
-        // get the high nibble to determine which 4K "chapter" we are in
-        let chapter = ((addr & 0xF000) >> 12) as usize;
-        self.bank_select[chapter]
+    fn access(&mut self, addr: u16, what: &str) {
+        match self.policy {
+            UnmappedIo::Warn if !self.warned[(addr & 0xFF) as usize] => {
+                self.warned[(addr & 0xFF) as usize] = true;
+                tracing::warn!(
+                    "{what} unmapped io address {addr:04X} (further accesses not logged)"
+                );
+            }
+            UnmappedIo::Break => {
+                tracing::warn!("{what} unmapped io address {addr:04X}");
+                self.fault = Some(addr);
+            }
+            _ => {}
+        }
     }
 }
 
-pub struct System<S0, S1, F0, F1> {
+pub enum IoBreakFlags {}
+
+impl IoBreakFlags {
+    pub const READ: u8 = 1 << 0;
+    pub const WRITE: u8 = 1 << 1;
+}
+
+/// IO registers that stop the emulator when the CPU touches them
+struct IoBreaks {
+    flags: [u8; 0x100],
+    /// The access waiting to stop the emulator
+    hit: Option<u16>,
+}
+
+/// What answers at each address of the IO window
+#[derive(Clone, Copy)]
+enum Decode {
+    Unmapped,
+    BankSelect,
+    RomBankSelect,
+    DrqRoute,
+    Exit,
+    Reset,
+    DeviceReset,
+    Irq,
+    Device(usize),
+}
+
+/// A CPU access to a hooked address, see [`System::hook_accesses`]
+pub struct Access {
+    pub addr: u16,
+    pub data: u8,
+    pub write: bool,
+}
+
+pub struct System {
     cpu: Cpu,
-    ser0: Uart<S0>,
-    ser1: Uart<S1>,
-    fdc0: Fdc<F0>,
-    fdc1: Fdc<F1>,
+    slots: Vec<Slot>,
+    /// Cycles each slot has been given but not yet ticked through
+    phases: Vec<u32>,
+    decoder: [Decode; 0x100],
 
-    irq_latch: u8,
+    irq: IrqController,
+    irq_stats: IrqStats,
+    drq_route: u8,
+    exit: Option<u8>,
+    reset: bool,
+    /// The slot the guest asked to reset, at the end of the instruction
+    reset_slot: Option<usize>,
+    unmapped: Unmapped,
+    io_breaks: IoBreaks,
+    checker: Checker,
+    /// The last byte the CPU moved, for open-bus reads
+    bus_value: u8,
+    io_trace: bool,
+    aug_traps: bool,
+    /// Addresses whose accesses are recorded, and the accesses
+    hooked: Vec<bool>,
+    accesses: Vec<Access>,
     mem: Mem,
+    cov: Coverage,
 }
 
-impl<S0, S1, F0, F1> System<S0, S1, F0, F1>
-where
-    S0: Read + Write,
-    S1: Read + Write,
-    F0: Read + Write + Seek,
-    F1: Read + Write + Seek,
-{
-    pub fn new(rom: &[u8], ser0: S0, ser1: S1, fdc0: F0, fdc1: F1) -> Self {
-        let cpu = Cpu::new();
-        let ser0 = Uart::new(ser0);
-        let ser1 = Uart::new(ser1);
-        let fdc0 = Fdc::new(fdc0);
-        let fdc1 = Fdc::new(fdc1);
-        let mut mem = Mem::new();
-
-        for (i, data) in rom.iter().enumerate() {
-            mem.write((0xF100 + i) as u16, *data);
-        }
+impl System {
+    pub fn new(ram_banks: usize, rom: &[u8]) -> Self {
+        let mut mem = Mem::with_banks(ram_banks);
+        mem.load_rom(rom);
+
+        let mut decoder = [Decode::Unmapped; 0x100];
+        decoder[0x00..=0x0E].fill(Decode::BankSelect);
+        decoder[0x0F] = Decode::RomBankSelect;
+        decoder[0x38] = Decode::DrqRoute;
+        decoder[0xF0] = Decode::Exit;
+        decoder[0xF1] = Decode::Reset;
+        decoder[0xF2] = Decode::DeviceReset;
+        decoder[0xF8..=0xFF].fill(Decode::Irq);
 
         Self {
-            cpu,
-            ser0,
-            ser1,
-            fdc0,
-            fdc1,
-            irq_latch: 0,
+            cpu: Cpu::new(),
+            slots: Vec::new(),
+            phases: Vec::new(),
+            decoder,
+            irq: IrqController::new(),
+            irq_stats: IrqStats::new(),
+            drq_route: DrqRouteFlags::FDC0_IRQ | DrqRouteFlags::FDC1_IRQ,
+            exit: None,
+            reset: false,
+            reset_slot: None,
+            unmapped: Unmapped {
+                policy: UnmappedIo::Warn,
+                warned: [false; 0x100],
+                fault: None,
+                open_bus: OpenBus::Zero,
+                rng: Rng::new(0),
+            },
+            io_breaks: IoBreaks {
+                flags: [0; 0x100],
+                hit: None,
+            },
+            checker: Checker::new(),
+            bus_value: 0,
+            io_trace: false,
+            aug_traps: false,
+            hooked: vec![false; 0x10000],
+            accesses: Vec::new(),
             mem,
+            cov: Coverage::new(),
+        }
+    }
+
+    /// Map a device into the IO window
+    pub fn attach(&mut self, slot: Slot) -> Result<(), String> {
+        let Slot {
+            name,
+            base,
+            size,
+            divisor,
+            extra,
+            ..
+        } = slot;
+        if divisor == 0 {
+            return Err(format!("{name} has a clock divisor of 0"));
+        }
+        let mut ranges = Vec::new();
+        for (base, size) in [Some((base, size)), extra].into_iter().flatten() {
+            let end = base as usize + size as usize;
+            if !(0xF000..=0xF100).contains(&(base as usize)) || end > 0xF100 {
+                return Err(format!("{name} at {base:04X} is outside the IO window"));
+            }
+            let range = (base as usize - 0xF000)..(end - 0xF000);
+            if ranges.iter().any(|other: &std::ops::Range<usize>| {
+                range.start < other.end && other.start < range.end
+            }) {
+                return Err(format!("{name} at {base:04X} overlaps its other registers"));
+            }
+            for decode in &self.decoder[range.clone()] {
+                match decode {
+                    Decode::Unmapped => {}
+                    Decode::Device(index) => {
+                        let other = self.slots[*index].name;
+                        return Err(format!("{name} at {base:04X} overlaps {other}"));
+                    }
+                    _ => return Err(format!("{name} at {base:04X} overlaps system registers")),
+                }
+            }
+            ranges.push(range);
+        }
+        for range in ranges {
+            self.decoder[range].fill(Decode::Device(self.slots.len()));
         }
+        self.slots.push(slot);
+        self.phases.push(0);
+        Ok(())
     }
 
     pub fn reset(&mut self) {
         let System {
             cpu,
-            ser0,
-            ser1,
-            fdc0,
-            fdc1,
-            irq_latch,
+            slots,
+            phases,
+            decoder,
+            irq,
+            irq_stats,
+            drq_route,
+            exit,
+            reset,
+            reset_slot,
+            unmapped,
+            io_breaks,
+            checker,
+            bus_value,
+            io_trace,
+            aug_traps,
+            hooked,
+            accesses,
             mem,
+            cov,
         } = self;
+        let pc = cpu.pc();
+        let cycles = cpu.cycles();
+        // the vectors come from bank 0
+        mem.set_rom_bank_select(0);
         cpu.reset(&mut CpuView {
-            ser0,
-            ser1,
-            fdc0,
-            fdc1,
-            irq_latch,
+            slots,
+            decoder,
+            irq,
+            drq_route,
+            exit,
+            reset,
+            reset_slot,
+            unmapped,
+            io_breaks,
+            checker,
+            bus_value,
+            io_trace: *io_trace,
+            aug_traps: *aug_traps,
+            hooked,
+            accesses,
+            pc,
+            cycles,
             mem,
+            cov,
         });
-        let mut io_view = IoView {};
-        ser0.reset(&mut io_view);
-        ser1.reset(&mut io_view);
-        fdc0.reset(&mut io_view);
-        fdc1.reset(&mut io_view);
-        *irq_latch = 0;
+        let mut io_view = IoView { mem, stolen: 0 };
+        for slot in slots.iter_mut() {
+            slot.device.reset(&mut io_view);
+        }
+        phases.fill(0);
+        irq.reset(&mut io_view);
+        irq_stats.assert(0, 0);
+        *drq_route = DrqRouteFlags::FDC0_IRQ | DrqRouteFlags::FDC1_IRQ;
+        *exit = None;
+        *reset = false;
+        *reset_slot = None;
     }
 
     pub fn tick(&mut self) {
         let System {
             cpu,
-            ser0,
-            ser1,
-            fdc0,
-            fdc1,
-            irq_latch,
+            slots,
+            phases,
+            decoder,
+            irq,
+            irq_stats,
+            drq_route,
+            exit,
+            reset,
+            reset_slot,
+            unmapped,
+            io_breaks,
+            checker,
+            bus_value,
+            io_trace,
+            aug_traps,
+            hooked,
+            accesses,
             mem,
+            cov,
         } = self;
+        let pc = cpu.pc();
+        let started = cpu.cycles();
+        let masked = (cpu.p() & Flags::INTERRUPT_DISABLE) != 0;
+        if !cpu.waiting() {
+            checker.exec(pc, started);
+        }
         cpu.tick(&mut CpuView {
-            ser0,
-            ser1,
-            fdc0,
-            fdc1,
-            irq_latch,
+            slots,
+            decoder,
+            irq,
+            drq_route,
+            exit,
+            reset,
+            reset_slot,
+            unmapped,
+            io_breaks,
+            checker,
+            bus_value,
+            io_trace: *io_trace,
+            aug_traps: *aug_traps,
+            hooked,
+            accesses,
+            pc,
+            cycles: started,
             mem,
+            cov,
         });
-        let mut io_view = IoView {};
-        ser0.tick(&mut io_view);
-        ser1.tick(&mut io_view);
-        fdc0.tick(&mut io_view);
-        fdc1.tick(&mut io_view);
+        if let Some(interrupt) = cpu.interrupt_taken() {
+            let name = match interrupt {
+                Interrupt::Nmi => {
+                    irq_stats.nmis += 1;
+                    "NMI"
+                }
+                Interrupt::Irq => {
+                    irq_stats.taken(irq, cpu.cycles());
+                    "IRQ"
+                }
+            };
+            tracing::trace!(
+                target: "irq",
+                "{name} taken at {pc:04X}, handler at {:04X}",
+                cpu.pc()
+            );
+        }
 
-        // update IRQ latch (pre-shifting makes implementing the jump table trivial)
-        // see http://www.6502.org/mini-projects/priority-interrupt-encoder/priority-interrupt-encoder.html
-        if fdc0.drq() {
-            *irq_latch = 1 << 1;
-        } else if fdc1.drq() {
-            *irq_latch = 2 << 1;
-        } else if fdc0.irq() {
-            *irq_latch = 3 << 1;
-        } else if fdc1.irq() {
-            *irq_latch = 4 << 1;
-        } else if ser0.irq() {
-            *irq_latch = 5 << 1;
-        } else if ser1.irq() {
-            *irq_latch = 6 << 1;
+        // the devices catch up on the cycles the instruction took, and then
+        // on any cycles their DMA stole from the CPU (something always
+        // passes, even for opcodes with no cycle count)
+        let mut cycles = (cpu.cycles() - started).max(1) as u32;
+        let mut io_view = IoView { mem, stolen: 0 };
+        while cycles != 0 {
+            for (slot, phase) in slots.iter_mut().zip(phases.iter_mut()) {
+                *phase += cycles;
+                while *phase >= slot.divisor {
+                    *phase -= slot.divisor;
+                    slot.device.tick(&mut io_view);
+                }
+            }
+            cycles = io_view.stolen;
+            io_view.stolen = 0;
+            cpu.stall(cycles as u64);
+        }
+
+        // before the lines are sampled, so a reset device drops its IRQ
+        if let Some(index) = reset_slot.take() {
+            tracing::debug!("guest reset {}", slots[index].name);
+            slots[index].device.reset(&mut io_view);
+            phases[index] = 0;
         }
-        // the last 2 IRQs: PPU, and Parallel Port
 
-        // tie all IRQs to CPU
-        if ser0.irq() || ser1.irq() || fdc0.irq() || fdc0.drq() || fdc1.irq() || fdc1.drq() {
-            cpu.irq();
+        let mut lines = 0;
+        for slot in slots.iter() {
+            if slot.device.irq() {
+                lines |= slot.irq;
+            }
+            if slot.device.drq() {
+                lines |= slot.drq;
+            }
         }
+
+        // DRQs that aren't routed to the IRQ line can only be polled
+        // (the routing bits line up with the DRQ sources)
+        lines &= !((IrqSource::FDC0_DRQ | IrqSource::FDC1_DRQ) & !*drq_route);
+
+        irq.set_lines(lines);
+        irq.tick(&mut io_view);
+
+        cpu.set_irq(irq.irq());
+        irq_stats.assert(irq.asserted(), cpu.cycles());
+        irq_stats.run(cpu.cycles() - started, masked);
+
+        if *reset {
+            tracing::info!("guest requested a reset");
+            self.reset();
+        }
+    }
+
+    pub fn nmi(&mut self) {
+        self.cpu.nmi();
     }
 
-    pub fn ser0_mut(&mut self) -> &mut Uart<S0> {
-        &mut self.ser0
+    /// Copy a program into RAM, see [`Mem::load_ram`]
+    pub fn load(&mut self, addr: u16, bank: usize, data: &[u8]) -> Result<(), String> {
+        self.mem.load_ram(addr, bank, data)
+    }
+
+    /// Write code or data into memory, see [`Mem::patch`]
+    pub fn patch(&mut self, addr: u16, data: &[u8]) -> Result<(), String> {
+        self.mem.patch(addr, data)
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.cpu.set_pc(pc);
     }
 
     pub fn cpu(&self) -> &Cpu {
@@ -253,68 +630,438 @@ where
     pub fn mem(&self) -> &Mem {
         &self.mem
     }
+
+    pub fn cov(&self) -> &Coverage {
+        &self.cov
+    }
+
+    pub fn cov_mut(&mut self) -> &mut Coverage {
+        &mut self.cov
+    }
+
+    /// Queue text as input to the device called `name`, see
+    /// [`BusDevice::paste`]
+    pub fn paste(&mut self, name: &str, data: &[u8], rate: u32) -> Result<(), String> {
+        if !self.device(name)?.paste(data, rate) {
+            return Err(format!("{name} doesn't take input"));
+        }
+        Ok(())
+    }
+
+    /// Run an XMODEM transfer over the device called `name`, see
+    /// [`BusDevice::xmodem`]
+    pub fn xmodem(&mut self, name: &str, transfer: Xmodem) -> Result<(), String> {
+        if !self.device(name)?.xmodem(transfer) {
+            return Err(format!("{name} can't transfer files"));
+        }
+        Ok(())
+    }
+
+    /// Write the overlay of the drive called `name` back to its base
+    /// image, returning the number of sectors written
+    pub fn commit(&mut self, name: &str) -> Result<usize, String> {
+        match self.device(name)?.commit() {
+            Some(result) => result,
+            None => Err(format!("{name} isn't a disk drive")),
+        }
+    }
+
+    fn device(&mut self, name: &str) -> Result<&mut dyn BusDevice, String> {
+        match self.slots.iter_mut().find(|slot| slot.name == name) {
+            Some(slot) => Ok(slot.device.as_mut()),
+            None => Err(format!("the machine has no {name}")),
+        }
+    }
+
+    /// The picture of the first device with a video output
+    pub fn frame(&self) -> Option<Frame<'_>> {
+        self.slots.iter().find_map(|slot| slot.device.frame())
+    }
+
+    /// The video memory of the first device with its own
+    pub fn vram(&self) -> Option<&[u8]> {
+        self.slots.iter().find_map(|slot| slot.device.vram())
+    }
+
+    /// Every disk drive and what it is up to
+    pub fn disks(&self) -> impl Iterator<Item = (&'static str, DiskActivity)> + '_ {
+        self.slots
+            .iter()
+            .filter_map(|slot| Some((slot.name, slot.device.disk()?)))
+    }
+
+    pub fn irq_stats(&self) -> &IrqStats {
+        &self.irq_stats
+    }
+
+    pub fn irq_stats_mut(&mut self) -> &mut IrqStats {
+        &mut self.irq_stats
+    }
+
+    /// The status the guest asked to exit with, if it has
+    pub fn exit_status(&self) -> Option<u8> {
+        self.exit
+    }
+
+    pub fn io_trace(&self) -> bool {
+        self.io_trace
+    }
+
+    /// Log every CPU access to the IO window
+    pub fn set_io_trace(&mut self, enabled: bool) {
+        self.io_trace = enabled;
+    }
+
+    /// Record the CPU's reads and writes of `addr`, for
+    /// [`System::take_accesses`]
+    pub fn hook_accesses(&mut self, addr: u16) {
+        self.hooked[addr as usize] = true;
+    }
+
+    /// Accesses to hooked addresses since the last call
+    pub fn take_accesses(&mut self) -> Vec<Access> {
+        std::mem::take(&mut self.accesses)
+    }
+
+    /// Let AUG call into the emulator, see [`crate::trap`]
+    pub fn set_aug_traps(&mut self, enabled: bool) {
+        self.aug_traps = enabled;
+    }
+
+    /// See [`Cpu::set_strict`]
+    pub fn set_strict_cpu(&mut self, strict: bool) {
+        self.cpu.set_strict(strict);
+    }
+
+    pub fn set_unmapped_io(&mut self, policy: UnmappedIo) {
+        self.unmapped.policy = policy;
+    }
+
+    /// What unmapped IO reads return, with `seed` for [`OpenBus::Random`]
+    pub fn set_open_bus(&mut self, open_bus: OpenBus, seed: u64) {
+        self.unmapped.open_bus = open_bus;
+        self.unmapped.rng = Rng::new(seed);
+    }
+
+    /// Stop the emulator when the CPU reads or writes (`IoBreakFlags`) an
+    /// IO register. No flags clears the breakpoint.
+    pub fn set_io_break(&mut self, addr: u16, flags: u8) {
+        self.io_breaks.flags[(addr - 0xF000) as usize] = flags;
+    }
+
+    /// The IO breakpoints and their `IoBreakFlags`
+    pub fn io_breaks(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.io_breaks
+            .flags
+            .iter()
+            .enumerate()
+            .filter(|(_, &flags)| flags != 0)
+            .map(|(i, &flags)| (0xF000 + i as u16, flags))
+    }
+
+    /// The first register and number of registers of the device called
+    /// `name`, not counting its extra block
+    pub fn device_registers(&self, name: &str) -> Option<(u16, u16)> {
+        let slot = self.slots.iter().find(|slot| slot.name == name)?;
+        Some((slot.base, slot.size))
+    }
+
+    /// What the IO register at `addr` is called
+    pub fn register_name(&self, addr: u16) -> String {
+        register_name(&self.decoder, &self.irq, &self.slots, addr)
+    }
+
+    /// What answers at an IO address: a device's name, or the part of the
+    /// bus it is
+    pub fn io_owner(&self, addr: u16) -> &'static str {
+        match self.decoder[(addr - 0xF000) as usize] {
+            Decode::Unmapped => "unmapped",
+            Decode::BankSelect => "bank select",
+            Decode::RomBankSelect => "ROM bank select",
+            Decode::DrqRoute => "DRQ routing",
+            Decode::Exit | Decode::Reset | Decode::DeviceReset => "emulator",
+            Decode::Irq => "IRQ",
+            Decode::Device(index) => self.slots[index].name,
+        }
+    }
+
+    /// The IO register at `addr` as the CPU would read it, without side
+    /// effects, see [`BusDevice::peek`]
+    pub fn peek_io(&self, addr: u16) -> Option<u8> {
+        match self.decoder[(addr - 0xF000) as usize] {
+            Decode::BankSelect => Some(self.mem.bank_select((addr as usize) - 0xF000)),
+            Decode::RomBankSelect => Some(self.mem.rom_bank_select()),
+            Decode::DrqRoute => Some(self.drq_route | drq_status(&self.slots)),
+            Decode::Irq => self.irq.peek(addr - 0xF0F8),
+            Decode::Device(index) => {
+                let slot = &self.slots[index];
+                slot.device.peek(slot.register(addr))
+            }
+            Decode::Exit | Decode::Reset | Decode::DeviceReset | Decode::Unmapped => None,
+        }
+    }
+
+    pub fn slots(&self) -> impl Iterator<Item = &Slot> + '_ {
+        self.slots.iter()
+    }
+
+    /// Check the CPU's RAM accesses, see [`crate::mem::check`]
+    pub fn set_mem_check(&mut self, mode: MemCheck) {
+        self.checker.set_mode(mode);
+    }
+
+    pub fn mem_check(&self) -> MemCheck {
+        self.checker.mode()
+    }
+
+    /// Check where the CPU executes from, see [`crate::mem::check`]
+    pub fn set_exec_check(&mut self, mode: MemCheck, window: u64) {
+        self.checker.set_exec_mode(mode, window);
+    }
+
+    /// The execution check and its window in cycles
+    pub fn exec_check(&self) -> (MemCheck, u64) {
+        self.checker.exec_mode()
+    }
+
+    /// Mark `start..=end` read-only (or writable again) for the memory
+    /// checks
+    pub fn set_read_only(&mut self, start: u16, end: u16, read_only: bool) {
+        self.checker.set_read_only(start, end, read_only);
+    }
+
+    /// The read-only ranges, as first and last addresses
+    pub fn read_only(&self) -> Vec<(u16, u16)> {
+        self.checker.read_only()
+    }
+
+    /// Whether an unmapped IO access, an IO breakpoint, or a memory check
+    /// is waiting to stop the emulator
+    pub fn fault_pending(&self) -> bool {
+        self.unmapped.fault.is_some()
+            || self.io_breaks.hit.is_some()
+            || self.checker.fault_pending()
+    }
+
+    /// The address of the access that should stop the emulator, if any
+    pub fn take_fault(&mut self) -> Option<u16> {
+        let fault = self.unmapped.fault.take();
+        let hit = self.io_breaks.hit.take();
+        let checked = self.checker.take_fault();
+        fault.or(hit).or(checked)
+    }
 }
 
-struct IoView {}
+/// What script hooks get at, see [`crate::hooks`]
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+impl System {
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
 
-impl Bus for IoView {
-    fn read(&mut self, _addr: u16) -> u8 {
-        0
+    pub fn mem_mut(&mut self) -> &mut Mem {
+        &mut self.mem
     }
+}
 
-    fn write(&mut self, _addr: u16, _data: u8) {}
+/// The bus as devices see it, for DMA. Every access steals a cycle from
+/// the CPU, and devices can't reach each other's registers.
+struct IoView<'a> {
+    mem: &'a mut Mem,
+    stolen: u32,
 }
 
-pub struct CpuView<'a, S0, S1, F0, F1> {
-    ser0: &'a mut Uart<S0>,
-    ser1: &'a mut Uart<S1>,
-    fdc0: &'a mut Fdc<F0>,
-    fdc1: &'a mut Fdc<F1>,
+impl<'a> Bus for IoView<'a> {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.stolen += 1;
+        self.mem.read(addr)
+    }
 
-    irq_latch: &'a mut u8,
+    fn write(&mut self, addr: u16, data: u8) {
+        self.stolen += 1;
+        self.mem.write(addr, data)
+    }
+}
+
+pub struct CpuView<'a> {
+    slots: &'a mut [Slot],
+    decoder: &'a [Decode; 0x100],
+
+    irq: &'a mut IrqController,
+    drq_route: &'a mut u8,
+    exit: &'a mut Option<u8>,
+    reset: &'a mut bool,
+    reset_slot: &'a mut Option<usize>,
+    unmapped: &'a mut Unmapped,
+    io_breaks: &'a mut IoBreaks,
+    checker: &'a mut Checker,
+    bus_value: &'a mut u8,
+    io_trace: bool,
+    aug_traps: bool,
+    hooked: &'a [bool],
+    accesses: &'a mut Vec<Access>,
+    /// Where the current instruction started, for IO traces and memory
+    /// checks
+    pc: u16,
+    /// When the current instruction started, for memory checks
+    cycles: u64,
     mem: &'a mut Mem,
+    cov: &'a mut Coverage,
 }
 
-impl<'a, S0, S1, F0, F1> Bus for CpuView<'a, S0, S1, F0, F1>
-where
-    S0: Read + Write,
-    S1: Read + Write,
-    F0: Read + Write + Seek,
-    F1: Read + Write + Seek,
-{
+impl<'a> CpuView<'a> {
+    fn register_name(&self, addr: u16) -> String {
+        register_name(self.decoder, self.irq, self.slots, addr)
+    }
+
+    fn trace(&self, addr: u16, access: &str, data: u8) {
+        tracing::info!(
+            target: "io",
+            "{:04X} {access} {addr:04X} {:24} {data:02X}",
+            self.pc,
+            self.register_name(addr)
+        );
+    }
+
+    fn check_break(&mut self, addr: u16, flag: u8, access: &str, data: u8) {
+        if (self.io_breaks.flags[(addr - 0xF000) as usize] & flag) != 0 {
+            tracing::info!(
+                "io breakpoint: {:04X} {access} {addr:04X} {} {data:02X}",
+                self.pc,
+                self.register_name(addr)
+            );
+            self.io_breaks.hit = Some(addr);
+        }
+    }
+}
+
+fn drq_status(slots: &[Slot]) -> u8 {
+    let mut status = 0;
+    for slot in slots {
+        if slot.device.drq() {
+            if (slot.drq & IrqSource::FDC0_DRQ) != 0 {
+                status |= DrqRouteFlags::FDC0_DRQ;
+            }
+            if (slot.drq & IrqSource::FDC1_DRQ) != 0 {
+                status |= DrqRouteFlags::FDC1_DRQ;
+            }
+        }
+    }
+    status
+}
+
+fn register_name(
+    decoder: &[Decode; 0x100],
+    irq: &IrqController,
+    slots: &[Slot],
+    addr: u16,
+) -> String {
+    match decoder[(addr - 0xF000) as usize] {
+        Decode::Unmapped => "unmapped".to_string(),
+        Decode::BankSelect => format!("Bank Select {:X}", addr & 0x0F),
+        Decode::RomBankSelect => "ROM Bank Select".to_string(),
+        Decode::DrqRoute => "DRQ Routing".to_string(),
+        Decode::Exit => "Emulator Exit".to_string(),
+        Decode::Reset => "Emulator Reset".to_string(),
+        Decode::DeviceReset => "Device Reset".to_string(),
+        Decode::Irq => match irq.register_name(addr - 0xF0F8) {
+            Some(register) => format!("IRQ {register}"),
+            None => "IRQ".to_string(),
+        },
+        Decode::Device(index) => {
+            let slot = &slots[index];
+            match slot.device.register_name(slot.register(addr)) {
+                Some(register) => format!("{} {register}", slot.name),
+                None => slot.name.to_string(),
+            }
+        }
+    }
+}
+
+impl<'a> Bus for CpuView<'a> {
     fn read(&mut self, addr: u16) -> u8 {
-        match addr {
-            0xF000..=0xF00E => self.mem.bank_select[(addr as usize) - 0xF000] as u8,
-            0xF00F => 0,
-            0xF010..=0xF013 => self.ser0.read(addr - 0xF010),
-            0xF014..=0xF017 => self.ser1.read(addr - 0xF014),
-            0xF024..=0xF029 => todo!("reading io address {addr:04X}"),
-            0xF030..=0xF033 => self.fdc0.read(addr - 0xF030),
-            0xF034..=0xF037 => self.fdc1.read(addr - 0xF034),
-            0xF038..=0xF0FE => todo!("reading io address {addr:04X}"),
-            0xF0FF => {
-                let irq = *self.irq_latch;
-                *self.irq_latch = 0;
-                irq
+        self.cov.mark(addr, CoverageFlags::READ);
+        let data = if !(0xF000..=0xF0FF).contains(&addr) {
+            self.checker.read(self.mem, self.pc, addr);
+            self.mem.read(addr)
+        } else {
+            match self.decoder[(addr - 0xF000) as usize] {
+                Decode::BankSelect => self.mem.bank_select((addr as usize) - 0xF000),
+                Decode::RomBankSelect => self.mem.rom_bank_select(),
+                Decode::DrqRoute => *self.drq_route | drq_status(self.slots),
+                Decode::Exit | Decode::Reset | Decode::DeviceReset => 0,
+                Decode::Irq => self.irq.read(addr - 0xF0F8),
+                Decode::Device(index) => {
+                    let slot = &mut self.slots[index];
+                    slot.device.read(slot.register(addr))
+                }
+                Decode::Unmapped => {
+                    self.unmapped.access(addr, "read from");
+                    self.unmapped.read(*self.bus_value)
+                }
+            }
+        };
+        if (0xF000..=0xF0FF).contains(&addr) {
+            if self.io_trace {
+                self.trace(addr, "read ", data);
             }
-            _ => self.mem.read(addr),
+            self.check_break(addr, IoBreakFlags::READ, "read", data);
         }
+        if self.hooked[addr as usize] {
+            self.accesses.push(Access {
+                addr,
+                data,
+                write: false,
+            });
+        }
+        *self.bus_value = data;
+        data
     }
 
     fn write(&mut self, addr: u16, data: u8) {
-        match addr {
-            0xF000..=0xF00E => {
-                self.mem.bank_select[(addr as usize) - 0xF000] = (data & 0b11) as usize
+        self.cov.mark(addr, CoverageFlags::WRITTEN);
+        *self.bus_value = data;
+        if self.hooked[addr as usize] {
+            self.accesses.push(Access {
+                addr,
+                data,
+                write: true,
+            });
+        }
+        if !(0xF000..=0xF0FF).contains(&addr) {
+            self.checker.write(self.pc, self.cycles, addr);
+            return self.mem.write(addr, data);
+        }
+        if self.io_trace {
+            self.trace(addr, "write", data);
+        }
+        self.check_break(addr, IoBreakFlags::WRITE, "write", data);
+        match self.decoder[(addr - 0xF000) as usize] {
+            Decode::BankSelect => self.mem.set_bank_select((addr as usize) - 0xF000, data),
+            Decode::RomBankSelect => self.mem.set_rom_bank_select(data),
+            Decode::DrqRoute => {
+                *self.drq_route = data & (DrqRouteFlags::FDC0_IRQ | DrqRouteFlags::FDC1_IRQ)
+            }
+            Decode::Exit => *self.exit = Some(data),
+            Decode::Reset => *self.reset = true,
+            Decode::DeviceReset => match self.decoder[data as usize] {
+                Decode::Device(index) => *self.reset_slot = Some(index),
+                _ => tracing::warn!("device reset of F0{data:02X}, which isn't a device"),
+            },
+            Decode::Irq => self.irq.write(addr - 0xF0F8, data),
+            Decode::Device(index) => {
+                let slot = &mut self.slots[index];
+                slot.device.write(slot.register(addr), data)
             }
-            0xF00F => {}
-            0xF010..=0xF013 => self.ser0.write(addr - 0xF010, data),
-            0xF014..=0xF017 => self.ser1.write(addr - 0xF014, data),
-            0xF024..=0xF029 => todo!("writing to io address {addr:04X}"),
-            0xF030..=0xF033 => self.fdc0.write(addr - 0xF030, data),
-            0xF034..=0xF037 => self.fdc1.write(addr - 0xF034, data),
-            0xF038..=0xF0FE => todo!("writing to io address {addr:04X}"),
-            0xF0FF => {}
-            _ => self.mem.write(addr, data),
+            Decode::Unmapped => self.unmapped.access(addr, "write to"),
+        }
+    }
+
+    fn aug(&mut self, trap: &mut Trap) -> bool {
+        if !self.aug_traps || trap.operands[0] != trap::TRAP {
+            return false;
         }
+        trap::call(trap, self.mem, self.exit);
+        true
     }
 }
@@ -0,0 +1,58 @@
+//! Host Terminal
+//!
+//! Puts the terminal back the way we found it, even when the emulator
+//! panics while SER0 has it in raw mode or the TUI owns the screen. The
+//! settings are saved before going raw, and a panic hook restores them
+//! before the panic message is printed.
+
+use std::{
+    io::{self, Write},
+    mem, panic,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+};
+
+use termion::{cursor, screen::ToMainScreen};
+
+static SAVED: OnceLock<libc::termios> = OnceLock::new();
+
+/// Set while the TUI has switched to the alternate screen
+static ALTERNATE_SCREEN: AtomicBool = AtomicBool::new(false);
+
+/// Remember the current terminal settings and restore them on panic
+pub fn save() -> io::Result<()> {
+    // SAFETY: termios is plain data and tcgetattr fills it in
+    let mut termios = unsafe { mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDOUT_FILENO, &mut termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if SAVED.set(termios).is_ok() {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore();
+            hook(info);
+        }));
+    }
+    Ok(())
+}
+
+pub fn set_alternate_screen(active: bool) {
+    ALTERNATE_SCREEN.store(active, Ordering::Relaxed);
+}
+
+/// Leave the alternate screen, show the cursor, and undo raw mode
+pub fn restore() {
+    let Some(termios) = SAVED.get() else {
+        return;
+    };
+    let mut stdout = io::stdout();
+    if ALTERNATE_SCREEN.swap(false, Ordering::Relaxed) {
+        write!(stdout, "{ToMainScreen}").ok();
+    }
+    write!(stdout, "{}", cursor::Show).ok();
+    stdout.flush().ok();
+    // SAFETY: the settings came from tcgetattr
+    unsafe { libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, termios) };
+}
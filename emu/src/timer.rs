@@ -0,0 +1,144 @@
+//! Programmable Interval Timer Emulation
+//!
+//! A 16-bit down counter clocked by the CPU clock through a prescaler.
+//! When the counter reaches zero it sets the expired flag, optionally raises
+//! an IRQ, and either reloads (periodic mode) or stops (one-shot mode).
+//!
+//! Registers:
+//!
+//! 0 Counter Lo (Writes set the reload value)
+//! 1 Counter Hi (Writes set the reload value)
+//! 2 Control
+//! 3 Status (Reads clear the expired flag and IRQ)
+
+use crate::bus::{Bus, BusDevice};
+
+enum StatusFlags {}
+
+impl StatusFlags {
+    const EXPIRED: u8 = 1 << 0;
+    const RUNNING: u8 = 1 << 1;
+}
+
+enum ControlFlags {}
+
+impl ControlFlags {
+    const ENABLE: u8 = 1 << 0;
+    const IRQ_ENABLE: u8 = 1 << 1;
+    const ONE_SHOT: u8 = 1 << 2;
+    const PRESCALER_MASK: u8 = 0b0011_0000; // divide by 1, 16, 256, or 4096
+}
+
+pub struct Timer {
+    control: u8,
+    status: u8,
+    reload: u16,
+    counter: u16,
+    prescaler: u16,
+    irq: bool,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self {
+            control: 0,
+            status: 0,
+            reload: 0,
+            counter: 0,
+            prescaler: 0,
+            irq: false,
+        }
+    }
+
+    fn divisor(&self) -> u16 {
+        1 << (((self.control & ControlFlags::PRESCALER_MASK) >> 4) * 4)
+    }
+}
+
+impl BusDevice for Timer {
+    fn reset(&mut self, _bus: &mut dyn Bus) {
+        *self = Self::new();
+    }
+
+    fn tick(&mut self, _bus: &mut dyn Bus) {
+        if (self.control & ControlFlags::ENABLE) == 0 {
+            return;
+        }
+
+        self.prescaler += 1;
+        if self.prescaler < self.divisor() {
+            return;
+        }
+        self.prescaler = 0;
+
+        // a reload value of 0 gives the full 65536 count period
+        self.counter = self.counter.wrapping_sub(1);
+        if self.counter == 0 {
+            self.status |= StatusFlags::EXPIRED;
+            if (self.control & ControlFlags::IRQ_ENABLE) != 0 {
+                self.irq = true;
+            }
+            if (self.control & ControlFlags::ONE_SHOT) != 0 {
+                self.control &= !ControlFlags::ENABLE;
+            }
+            self.counter = self.reload;
+        }
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        let data = self.peek(addr).unwrap();
+        if addr == 3 {
+            self.status &= !StatusFlags::EXPIRED;
+            self.irq = false;
+        }
+        data
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0 => Some(self.counter as u8),
+            1 => Some((self.counter >> 8) as u8),
+            2 => Some(self.control),
+            3 => {
+                let mut status = self.status;
+                if (self.control & ControlFlags::ENABLE) != 0 {
+                    status |= StatusFlags::RUNNING;
+                }
+                Some(status)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0 => self.reload = (self.reload & 0xFF00) | (data as u16),
+            1 => self.reload = (self.reload & 0x00FF) | ((data as u16) << 8),
+            2 => {
+                // starting the timer (re)loads the counter
+                if (self.control & ControlFlags::ENABLE) == 0 && (data & ControlFlags::ENABLE) != 0
+                {
+                    self.counter = self.reload;
+                    self.prescaler = 0;
+                }
+                self.control = data;
+            }
+            3 => {}
+            _ => unreachable!(),
+        }
+    }
+
+    fn register_name(&self, addr: u16) -> Option<&'static str> {
+        match addr {
+            0 => Some("Counter Lo"),
+            1 => Some("Counter Hi"),
+            2 => Some("Control"),
+            3 => Some("Status"),
+            _ => None,
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq
+    }
+}
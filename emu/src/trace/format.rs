@@ -0,0 +1,293 @@
+//! Trace Events and Files
+//!
+//! Shared with `trace-stat`, so this only leans on std and serde.
+//!
+//! JSON traces have an object per line, tagged with its `type`:
+//!
+//! ```text
+//! {"type":"insn","cycles":0,"pc":61696,"opcode":120,"a":0,"b":0,"x":0,"y":0,"z":0,"p":36,"sp":256}
+//! {"type":"io","cycles":88,"pc":61712,"addr":61688,"data":1,"write":true}
+//! {"type":"irq","cycles":4007,"pc":61720,"handler":61800,"nmi":false}
+//! ```
+//!
+//! Binary traces start with the 8 bytes `P2TRACE` 01, followed by records
+//! of a type byte and the same fields, little-endian:
+//!
+//! 00 Insn (cycles: u64, pc: u16, opcode, a, b, x, y, z, p: u8, sp: u16)
+//! 01 Io (cycles: u64, pc: u16, addr: u16, data: u8, write: u8)
+//! 02 Irq (cycles: u64, pc: u16, handler: u16, nmi: u8)
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+const MAGIC: &[u8; 8] = b"P2TRACE\x01";
+
+enum Tag {}
+
+impl Tag {
+    const INSN: u8 = 0x00;
+    const IO: u8 = 0x01;
+    const IRQ: u8 = 0x02;
+}
+
+/// Something the CPU did, stamped with the cycle count it started at
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Event {
+    /// An instruction, with the registers as it started
+    Insn {
+        cycles: u64,
+        pc: u16,
+        opcode: u8,
+        a: u8,
+        b: u8,
+        x: u8,
+        y: u8,
+        z: u8,
+        p: u8,
+        sp: u16,
+    },
+    /// An access to the IO window by the instruction at `pc`
+    Io {
+        cycles: u64,
+        pc: u16,
+        addr: u16,
+        data: u8,
+        write: bool,
+    },
+    /// An interrupt taken at `pc`
+    Irq {
+        cycles: u64,
+        pc: u16,
+        handler: u16,
+        nmi: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Binary,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "binary" => Ok(Format::Binary),
+            _ => Err(format!("expected `json` or `binary`: `{s}`")),
+        }
+    }
+}
+
+pub struct Writer<W: Write> {
+    out: W,
+    format: Format,
+}
+
+impl Writer<BufWriter<File>> {
+    pub fn create(path: &Path, format: Format) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), format)
+    }
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(mut out: W, format: Format) -> io::Result<Self> {
+        if format == Format::Binary {
+            out.write_all(MAGIC)?;
+        }
+        Ok(Self { out, format })
+    }
+
+    pub fn write(&mut self, event: &Event) -> io::Result<()> {
+        match self.format {
+            Format::Json => {
+                serde_json::to_writer(&mut self.out, event)?;
+                self.out.write_all(b"\n")
+            }
+            Format::Binary => write_binary(&mut self.out, event),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+fn write_binary(out: &mut impl Write, event: &Event) -> io::Result<()> {
+    let mut record = Vec::with_capacity(20);
+    match *event {
+        Event::Insn {
+            cycles,
+            pc,
+            opcode,
+            a,
+            b,
+            x,
+            y,
+            z,
+            p,
+            sp,
+        } => {
+            record.push(Tag::INSN);
+            record.extend(cycles.to_le_bytes());
+            record.extend(pc.to_le_bytes());
+            record.extend([opcode, a, b, x, y, z, p]);
+            record.extend(sp.to_le_bytes());
+        }
+        Event::Io {
+            cycles,
+            pc,
+            addr,
+            data,
+            write,
+        } => {
+            record.push(Tag::IO);
+            record.extend(cycles.to_le_bytes());
+            record.extend(pc.to_le_bytes());
+            record.extend(addr.to_le_bytes());
+            record.extend([data, write as u8]);
+        }
+        Event::Irq {
+            cycles,
+            pc,
+            handler,
+            nmi,
+        } => {
+            record.push(Tag::IRQ);
+            record.extend(cycles.to_le_bytes());
+            record.extend(pc.to_le_bytes());
+            record.extend(handler.to_le_bytes());
+            record.push(nmi as u8);
+        }
+    }
+    out.write_all(&record)
+}
+
+/// Reads either format, telling them apart by the binary header
+pub struct Reader<R: BufRead> {
+    input: R,
+    format: Format,
+    line: String,
+}
+
+impl Reader<BufReader<File>> {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let format = if input.fill_buf()?.starts_with(MAGIC) {
+            input.consume(MAGIC.len());
+            Format::Binary
+        } else {
+            Format::Json
+        };
+        Ok(Self {
+            input,
+            format,
+            line: String::new(),
+        })
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    fn read_json(&mut self) -> io::Result<Option<Event>> {
+        loop {
+            self.line.clear();
+            if self.input.read_line(&mut self.line)? == 0 {
+                return Ok(None);
+            }
+            let line = self.line.trim();
+            if !line.is_empty() {
+                return Ok(Some(serde_json::from_str(line)?));
+            }
+        }
+    }
+
+    fn read_binary(&mut self) -> io::Result<Option<Event>> {
+        let mut tag = [0];
+        if self.input.read(&mut tag)? == 0 {
+            return Ok(None);
+        }
+        let input = &mut self.input;
+        let cycles = u64::from_le_bytes(read_array(input)?);
+        let pc = u16::from_le_bytes(read_array(input)?);
+        let event = match tag[0] {
+            Tag::INSN => {
+                let [opcode, a, b, x, y, z, p] = read_array(input)?;
+                let sp = u16::from_le_bytes(read_array(input)?);
+                Event::Insn {
+                    cycles,
+                    pc,
+                    opcode,
+                    a,
+                    b,
+                    x,
+                    y,
+                    z,
+                    p,
+                    sp,
+                }
+            }
+            Tag::IO => {
+                let addr = u16::from_le_bytes(read_array(input)?);
+                let [data, write] = read_array(input)?;
+                Event::Io {
+                    cycles,
+                    pc,
+                    addr,
+                    data,
+                    write: write != 0,
+                }
+            }
+            Tag::IRQ => {
+                let handler = u16::from_le_bytes(read_array(input)?);
+                let [nmi] = read_array(input)?;
+                Event::Irq {
+                    cycles,
+                    pc,
+                    handler,
+                    nmi: nmi != 0,
+                }
+            }
+            tag => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown record type {tag:02X}"),
+                ))
+            }
+        };
+        Ok(Some(event))
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.format {
+            Format::Json => self.read_json(),
+            Format::Binary => self.read_binary(),
+        }
+        .transpose()
+    }
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
@@ -0,0 +1,177 @@
+//! Structured Traces
+//!
+//! `--trace-out FILE` records what the CPU does as events for other tools
+//! to pick apart, unlike the instruction log at trace level, which is for
+//! reading. There is an event for every instruction, every access to the
+//! IO window, and every interrupt taken. Events are JSON lines or compact
+//! binary records (see [`format`]), and `trace-stat` summarizes either.
+//!
+//! Only running is traced, not single steps in the debugger. The `trace`
+//! debugger command narrows both this and the instruction log to the code
+//! of interest with a [`TraceFilter`].
+
+// reading traces is for trace-stat
+#[allow(dead_code)]
+pub mod format;
+
+#[cfg(test)]
+mod tests;
+
+use std::{fs::File, io::BufWriter, ops::Range, path::Path};
+
+use crate::{
+    cpu::Interrupt,
+    sys::{Access, System},
+};
+
+pub use format::{Event, Format, Writer};
+
+/// The CPU as an instruction started
+#[derive(Clone, Copy)]
+struct Start {
+    cycles: u64,
+    instructions: u64,
+    pc: u16,
+    opcode: u8,
+    regs: [u8; 6],
+    sp: u16,
+}
+
+pub struct Tracer {
+    out: Writer<BufWriter<File>>,
+    start: Option<Start>,
+    events: u64,
+}
+
+impl Tracer {
+    /// Start tracing `sys` into `path`
+    pub fn create(path: &Path, format: Format, sys: &mut System) -> Result<Self, String> {
+        let out = Writer::create(path, format).map_err(|e| format!("{}: {e}", path.display()))?;
+        for addr in 0xF000..=0xF0FF {
+            sys.hook_accesses(addr);
+        }
+        tracing::info!("tracing to {}", path.display());
+        Ok(Self {
+            out,
+            start: None,
+            events: 0,
+        })
+    }
+
+    /// Note the state before a tick
+    #[inline]
+    pub fn before(&mut self, sys: &System) {
+        let cpu = sys.cpu();
+        self.start = Some(Start {
+            cycles: cpu.cycles(),
+            instructions: cpu.instructions(),
+            pc: cpu.pc(),
+            opcode: sys.mem().read(cpu.pc()),
+            regs: [cpu.a(), cpu.b(), cpu.x(), cpu.y(), cpu.z(), cpu.p()],
+            sp: cpu.sp(),
+        });
+    }
+
+    /// Write the events for the tick since [`Tracer::before`]
+    pub fn after(&mut self, sys: &System, accesses: &[Access]) -> Result<(), String> {
+        let Some(start) = self.start.take() else {
+            return Ok(());
+        };
+        let cpu = sys.cpu();
+        if let Some(interrupt) = cpu.interrupt_taken() {
+            self.write(Event::Irq {
+                cycles: start.cycles,
+                pc: start.pc,
+                handler: cpu.pc(),
+                nmi: interrupt == Interrupt::Nmi,
+            })?;
+        } else if cpu.instructions() != start.instructions {
+            let [a, b, x, y, z, p] = start.regs;
+            self.write(Event::Insn {
+                cycles: start.cycles,
+                pc: start.pc,
+                opcode: start.opcode,
+                a,
+                b,
+                x,
+                y,
+                z,
+                p,
+                sp: start.sp,
+            })?;
+        }
+        for access in accesses {
+            if (0xF000..=0xF0FF).contains(&access.addr) {
+                self.write(Event::Io {
+                    cycles: start.cycles,
+                    pc: start.pc,
+                    addr: access.addr,
+                    data: access.data,
+                    write: access.write,
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, event: Event) -> Result<(), String> {
+        self.events += 1;
+        self.out.write(&event).map_err(|e| e.to_string())
+    }
+}
+
+/// Which instructions get traced, by address. With no `only` ranges
+/// everything is, less the `skip` ranges.
+pub struct TraceFilter {
+    only: Vec<Range<u32>>,
+    skip: Vec<Range<u32>>,
+}
+
+impl TraceFilter {
+    pub fn new() -> Self {
+        Self {
+            only: Vec::new(),
+            skip: Vec::new(),
+        }
+    }
+
+    /// Trace `range` (and any other `only` ranges) and nothing else
+    pub fn only(&mut self, range: Range<u32>) {
+        self.only.push(range);
+    }
+
+    /// Don't trace `range`
+    pub fn skip(&mut self, range: Range<u32>) {
+        self.skip.push(range);
+    }
+
+    pub fn clear(&mut self) {
+        self.only.clear();
+        self.skip.clear();
+    }
+
+    pub fn only_ranges(&self) -> &[Range<u32>] {
+        &self.only
+    }
+
+    pub fn skip_ranges(&self) -> &[Range<u32>] {
+        &self.skip
+    }
+
+    /// Whether the instruction at `pc` is traced
+    #[inline]
+    pub fn traces(&self, pc: u16) -> bool {
+        let pc = pc as u32;
+        (self.only.is_empty() || self.only.iter().any(|range| range.contains(&pc)))
+            && !self.skip.iter().any(|range| range.contains(&pc))
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        if let Err(e) = self.out.flush() {
+            tracing::error!("failed to finish trace: {e}");
+        }
+        tracing::info!("traced {} events", self.events);
+    }
+}
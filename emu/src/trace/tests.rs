@@ -0,0 +1,76 @@
+use super::format::Reader;
+use super::*;
+
+fn events() -> Vec<Event> {
+    vec![
+        Event::Insn {
+            cycles: 0,
+            pc: 0xF100,
+            opcode: 0x78,
+            a: 1,
+            b: 2,
+            x: 3,
+            y: 4,
+            z: 5,
+            p: 0x24,
+            sp: 0x0100,
+        },
+        Event::Io {
+            cycles: 0x1_0000_0002,
+            pc: 0xF105,
+            addr: 0xF0F8,
+            data: 0x80,
+            write: true,
+        },
+        Event::Irq {
+            cycles: 4007,
+            pc: 0xF110,
+            handler: 0xF180,
+            nmi: false,
+        },
+    ]
+}
+
+fn round_trip(format: Format) {
+    let mut out = Vec::new();
+    {
+        let mut writer = Writer::new(&mut out, format).unwrap();
+        for event in &events() {
+            writer.write(event).unwrap();
+        }
+    }
+
+    let reader = Reader::new(&out[..]).unwrap();
+    assert_eq!(reader.format(), format);
+    let read = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(read, events());
+}
+
+#[test]
+fn json_round_trip() {
+    round_trip(Format::Json);
+}
+
+#[test]
+fn binary_round_trip() {
+    round_trip(Format::Binary);
+}
+
+#[test]
+fn filter_narrows_to_only_ranges_less_skips() {
+    let mut filter = TraceFilter::new();
+    assert!(filter.traces(0x0000) && filter.traces(0xFFFF));
+
+    filter.skip(0xF200..0xF210);
+    assert!(filter.traces(0xF1FF));
+    assert!(!filter.traces(0xF200));
+    assert!(filter.traces(0xF210));
+
+    filter.only(0xF100..0x10000);
+    assert!(!filter.traces(0x0200));
+    assert!(filter.traces(0xFFFF));
+    assert!(!filter.traces(0xF20F));
+
+    filter.clear();
+    assert!(filter.traces(0x0200));
+}
@@ -0,0 +1,133 @@
+//! AUG Traps
+//!
+//! With `--aug-traps`, an AUG whose first operand byte is FF calls into
+//! the emulator instead of being a 4-byte NOP, so test programs can reach
+//! the host without any device drivers. The second operand byte picks the
+//! service, and the third is left for the service to use. Arguments and
+//! results go in the registers, with addresses in Y (hi) and X (lo), and
+//! carry is set when a call fails.
+//!
+//! Services:
+//!
+//! 00 Exit (stop the emulator with exit status A)
+//! 01 Put Char (write A to the host's stdout)
+//! 02 Put String (write the NUL-terminated string at YX to the host's stdout)
+//! 03 Load File (load a host file as described by the block at YX, leaving its length in YX)
+//! 04 Get Time (store the host's wall-clock time at YX)
+//! 05 Report (print the NUL-terminated message at YX as a pass if A is 0,
+//!    or a failure otherwise, and stop the emulator with exit status A)
+//!
+//! The Load File block is:
+//!
+//! 0-1 Destination address
+//! 2-3 Maximum length (longer files fail without loading anything)
+//! 4-  NUL-terminated host path, relative to the emulator's directory
+//!
+//! Get Time stores the seconds since the Unix epoch as 4 little-endian
+//! bytes, followed by 2 bytes of milliseconds.
+//!
+//! Report is how test ROMs hand their result to CI: `possum2-emu test`
+//! turns the traps on and exits with the reported status, and the message
+//! goes to stdout as `PASS: message` or `FAIL (status): message`.
+//!
+//! Unknown services are logged and fail.
+
+use std::{
+    fs,
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{bus::Trap, mem::Mem};
+
+/// First AUG operand byte of a trap
+pub const TRAP: u8 = 0xFF;
+
+pub enum Service {}
+
+impl Service {
+    pub const EXIT: u8 = 0x00;
+    pub const PUT_CHAR: u8 = 0x01;
+    pub const PUT_STRING: u8 = 0x02;
+    pub const LOAD_FILE: u8 = 0x03;
+    pub const GET_TIME: u8 = 0x04;
+    pub const REPORT: u8 = 0x05;
+}
+
+/// Run the service a trap asks for
+pub fn call(trap: &mut Trap, mem: &mut Mem, exit: &mut Option<u8>) {
+    let [_, service, _] = trap.operands;
+    let addr = u16::from_le_bytes([trap.x, trap.y]);
+    let result = match service {
+        Service::EXIT => {
+            *exit = Some(trap.a);
+            Ok(())
+        }
+        Service::PUT_CHAR => put(&[trap.a]),
+        Service::PUT_STRING => put(&string(mem, addr)),
+        Service::LOAD_FILE => load_file(mem, addr).map(|len| [trap.x, trap.y] = len.to_le_bytes()),
+        Service::GET_TIME => get_time(mem, addr),
+        Service::REPORT => {
+            let message = String::from_utf8_lossy(&string(mem, addr)).into_owned();
+            *exit = Some(trap.a);
+            match trap.a {
+                0 => put(format!("PASS: {message}\n").as_bytes()),
+                status => put(format!("FAIL ({status}): {message}\n").as_bytes()),
+            }
+        }
+        _ => Err(format!("unknown trap service {service:02X}")),
+    };
+    trap.carry = result
+        .map_err(|e| tracing::warn!("trap {service:02X} failed: {e}"))
+        .is_err();
+}
+
+fn put(data: &[u8]) -> Result<(), String> {
+    let mut stdout = io::stdout();
+    stdout
+        .write_all(data)
+        .and_then(|_| stdout.flush())
+        .map_err(|e| e.to_string())
+}
+
+/// The NUL-terminated string at `addr`
+fn string(mem: &Mem, addr: u16) -> Vec<u8> {
+    (0..=u16::MAX)
+        .map(|i| mem.read(addr.wrapping_add(i)))
+        .take_while(|&byte| byte != 0)
+        .collect()
+}
+
+fn load_file(mem: &mut Mem, block: u16) -> Result<u16, String> {
+    let word = |offset| {
+        u16::from_le_bytes([
+            mem.read(block.wrapping_add(offset)),
+            mem.read(block.wrapping_add(offset + 1)),
+        ])
+    };
+    let (dest, max_len) = (word(0), word(2));
+    let path = String::from_utf8_lossy(&string(mem, block.wrapping_add(4))).into_owned();
+    let data = fs::read(&path).map_err(|e| format!("{path}: {e}"))?;
+    if data.len() > max_len as usize {
+        return Err(format!(
+            "{path}: {} bytes is more than the {max_len} allowed",
+            data.len()
+        ));
+    }
+    for (i, &byte) in data.iter().enumerate() {
+        mem.write(dest.wrapping_add(i as u16), byte);
+    }
+    Ok(data.len() as u16)
+}
+
+fn get_time(mem: &mut Mem, addr: u16) -> Result<(), String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?;
+    let secs = (now.as_secs() as u32).to_le_bytes();
+    let millis = (now.subsec_millis() as u16).to_le_bytes();
+    for (i, &byte) in secs.iter().chain(&millis).enumerate() {
+        mem.write(addr.wrapping_add(i as u16), byte);
+    }
+    Ok(())
+}
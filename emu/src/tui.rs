@@ -0,0 +1,338 @@
+//! TUI Debugger
+//!
+//! A full-screen alternative to the `dbg>` prompt. Disassembly around the
+//! PC, registers, stack, watches, and a memory dump are shown at once and
+//! redrawn after every command. Commands are the same as at the prompt,
+//! and `x <addr>` also moves the memory pane.
+
+use std::{
+    io::{self, Read, Write},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use possum2_ops::op_len;
+use ratatui::{
+    backend::TermionBackend,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, Paragraph},
+    Frame, Terminal,
+};
+use termion::screen::{ToAlternateScreen, ToMainScreen};
+
+use crate::{
+    cpu::{Cpu, Flags},
+    debugger::{
+        debug_command, dissasemble, parse_addr, strip_colors, symbolize, DebugAction, Debugger,
+    },
+    mem::Mem,
+    sys::System,
+    term,
+};
+
+const LOG_LINES: usize = 200;
+
+pub struct Tui<W: Write> {
+    terminal: Terminal<TermionBackend<W>>,
+    input: String,
+    cached_parts: Vec<String>,
+    log: Vec<String>,
+    mem_base: u16,
+}
+
+impl<W: Write> Tui<W> {
+    pub fn new(term: W) -> io::Result<Self> {
+        Ok(Self {
+            terminal: Terminal::new(TermionBackend::new(term))?,
+            input: String::new(),
+            cached_parts: Vec::new(),
+            log: vec!["type `?` for help, `c` to continue".to_string()],
+            mem_base: 0,
+        })
+    }
+
+    /// Take over the screen until the user continues or quits
+    pub fn run<R: Read>(
+        &mut self,
+        sys: &mut System,
+        dbg: &mut Debugger,
+        input: &mut R,
+        interrupt: &AtomicBool,
+    ) -> io::Result<DebugAction> {
+        write!(self.terminal.backend_mut(), "{ToAlternateScreen}")?;
+        term::set_alternate_screen(true);
+        self.terminal.clear()?;
+        let action = self.prompt(sys, dbg, input, interrupt);
+        write!(self.terminal.backend_mut(), "{ToMainScreen}")?;
+        term::set_alternate_screen(false);
+        self.terminal.backend_mut().flush()?;
+        action
+    }
+
+    fn prompt<R: Read>(
+        &mut self,
+        sys: &mut System,
+        dbg: &mut Debugger,
+        input: &mut R,
+        interrupt: &AtomicBool,
+    ) -> io::Result<DebugAction> {
+        let mut escape = false;
+        loop {
+            self.terminal
+                .draw(|f| draw(f, sys, dbg, &self.log, &self.input, self.mem_base))?;
+
+            let line = loop {
+                // ctrl-c at the prompt quits
+                if interrupt.swap(false, Ordering::Relaxed) {
+                    return Ok(DebugAction::Quit);
+                }
+                let mut buf = [0];
+                if input.read(&mut buf)? != 1 {
+                    thread::sleep(Duration::from_millis(5));
+                    continue;
+                }
+                match buf[0] {
+                    // skip over escape sequences (arrow keys and the like)
+                    0x1B => escape = true,
+                    c if escape => escape = !(c.is_ascii_alphabetic() || c == b'~'),
+                    b'\r' | b'\n' => break self.input.split_off(0),
+                    0x08 | 0x7F => {
+                        self.input.pop();
+                    }
+                    c if c.is_ascii_graphic() || c == b' ' => self.input.push(c as char),
+                    _ => continue,
+                }
+                self.terminal
+                    .draw(|f| draw(f, sys, dbg, &self.log, &self.input, self.mem_base))?;
+            };
+
+            let parts = line
+                .split_whitespace()
+                .map(String::from)
+                .collect::<Vec<String>>();
+            let parts = if parts.is_empty() {
+                self.cached_parts.clone()
+            } else {
+                self.cached_parts = parts.clone();
+                parts
+            };
+            if let (Some("x" | "X" | "XX" | "xw" | "xd"), Some(arg)) = (
+                parts.first().map(String::as_str),
+                parts.get(1).map(String::as_str),
+            ) {
+                if let Ok(addr) = parse_addr(&dbg.symbols, arg) {
+                    self.mem_base = addr;
+                }
+            }
+
+            let mut out = Vec::new();
+            let action = debug_command(&mut out, sys, dbg, &parts)?;
+            self.log.push(format!("dbg>{}", parts.join(" ")));
+            self.log.extend(
+                String::from_utf8_lossy(&strip_colors(&out))
+                    .lines()
+                    .map(String::from),
+            );
+            if self.log.len() > LOG_LINES {
+                self.log.drain(..self.log.len() - LOG_LINES);
+            }
+            match action {
+                DebugAction::Prompt => {}
+                action => return Ok(action),
+            }
+        }
+    }
+}
+
+fn draw(f: &mut Frame, sys: &System, dbg: &Debugger, log: &[String], input: &str, mem_base: u16) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(12),
+            Constraint::Length(10),
+            Constraint::Length(8),
+            Constraint::Length(1),
+        ])
+        .split(f.size());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Min(48), Constraint::Length(32)])
+        .split(rows[0]);
+    let side = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(5),
+            Constraint::Length(10),
+            Constraint::Min(3),
+        ])
+        .split(columns[1]);
+
+    draw_disassembly(f, columns[0], sys.mem(), sys.cpu(), dbg);
+    draw_registers(f, side[0], sys.cpu());
+    draw_stack(f, side[1], sys.mem(), sys.cpu());
+    draw_watches(f, side[2], sys.mem(), dbg);
+    draw_memory(f, rows[1], sys.mem(), mem_base);
+
+    let height = rows[2].height.saturating_sub(2) as usize;
+    let lines = log[log.len().saturating_sub(height)..]
+        .iter()
+        .map(|line| Line::from(line.as_str()))
+        .collect::<Vec<Line>>();
+    f.render_widget(Paragraph::new(lines).block(pane("Output")), rows[2]);
+
+    let prompt = format!("dbg>{input}");
+    f.set_cursor(rows[3].x + prompt.len() as u16, rows[3].y);
+    f.render_widget(Paragraph::new(prompt), rows[3]);
+    draw_disks(f, rows[3], sys);
+}
+
+/// A light per drive at the right of the prompt line, lit while busy
+fn draw_disks(f: &mut Frame, area: Rect, sys: &System) {
+    let status = sys
+        .disks()
+        .map(|(name, disk)| {
+            format!(
+                "{name} {} T{:02} S{:02}",
+                if disk.busy { '*' } else { '-' },
+                disk.track,
+                disk.sector
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("  ");
+    f.render_widget(Paragraph::new(status).alignment(Alignment::Right), area);
+}
+
+fn pane(title: &str) -> Block<'_> {
+    Block::default().borders(Borders::ALL).title(title)
+}
+
+fn draw_disassembly(f: &mut Frame, area: Rect, mem: &Mem, cpu: &Cpu, dbg: &Debugger) {
+    let height = area.height.saturating_sub(2) as usize;
+    let pc = cpu.pc();
+    let start = disassembly_start(mem, pc, height / 3);
+
+    let mut out = Vec::new();
+    dissasemble(&mut out, mem, &dbg.symbols, start, height).unwrap();
+    let lines = String::from_utf8_lossy(&strip_colors(&out))
+        .lines()
+        .take(height)
+        .map(|line| {
+            let addr = line
+                .split_once(':')
+                .and_then(|(_, rest)| rest.get(..4))
+                .and_then(|addr| u16::from_str_radix(addr, 16).ok());
+            let breakpoint = addr.is_some_and(|addr| dbg.breakpoints.contains(addr));
+            let line = format!("{}{line}", if breakpoint { "*" } else { " " });
+            if addr == Some(pc) {
+                Line::styled(line, Style::default().add_modifier(Modifier::REVERSED))
+            } else {
+                Line::from(line)
+            }
+        })
+        .collect::<Vec<Line>>();
+    f.render_widget(Paragraph::new(lines).block(pane("Disassembly")), area);
+}
+
+/// Find an address a few instructions before the PC that decodes into an
+/// instruction boundary at the PC
+fn disassembly_start(mem: &Mem, pc: u16, before: usize) -> u16 {
+    for back in (1..=(before as u16) * 3).rev() {
+        let Some(start) = pc.checked_sub(back) else {
+            continue;
+        };
+        let mut addr = start;
+        let mut count = 0;
+        while addr < pc {
+            addr = addr.saturating_add(op_len(mem.read(addr)));
+            count += 1;
+        }
+        if addr == pc && count <= before {
+            return start;
+        }
+    }
+    pc
+}
+
+fn draw_registers(f: &mut Frame, area: Rect, cpu: &Cpu) {
+    let p = cpu.p();
+    let flags = [
+        (Flags::NEGATIVE, 'N'),
+        (Flags::OVERFLOW, 'V'),
+        (Flags::EXTEND_STACK_DISABLE, 'E'),
+        (Flags::BREAK, 'B'),
+        (Flags::DECIMAL_MODE, 'D'),
+        (Flags::INTERRUPT_DISABLE, 'I'),
+        (Flags::ZERO, 'Z'),
+        (Flags::CARRY, 'C'),
+    ]
+    .iter()
+    .map(|&(flag, c)| if (p & flag) == 0 { '-' } else { c })
+    .collect::<String>();
+    let lines = vec![
+        Line::from(format!(
+            "A={:02X} B={:02X} X={:02X} Y={:02X} Z={:02X}",
+            cpu.a(),
+            cpu.b(),
+            cpu.x(),
+            cpu.y(),
+            cpu.z()
+        )),
+        Line::from(format!("PC={:04X} SP={:04X}", cpu.pc(), cpu.sp())),
+        Line::from(format!("P={p:02X} [{flags}]")),
+    ];
+    f.render_widget(Paragraph::new(lines).block(pane("Registers")), area);
+}
+
+fn draw_stack(f: &mut Frame, area: Rect, mem: &Mem, cpu: &Cpu) {
+    let height = area.height.saturating_sub(2);
+    let sp = cpu.sp();
+    let lines = (1..=height)
+        .map(|i| {
+            let addr = sp.wrapping_add(i);
+            Line::from(format!("{addr:04X}  {:02X}", mem.read(addr)))
+        })
+        .collect::<Vec<Line>>();
+    f.render_widget(Paragraph::new(lines).block(pane("Stack")), area);
+}
+
+fn draw_watches(f: &mut Frame, area: Rect, mem: &Mem, dbg: &Debugger) {
+    let lines = dbg
+        .watches
+        .iter()
+        .map(|&addr| {
+            let value = mem.read(addr);
+            Line::from(format!(
+                "{addr:04X}  {value:02X} {value:03}  {}",
+                symbolize(&dbg.symbols, addr)
+            ))
+        })
+        .collect::<Vec<Line>>();
+    f.render_widget(Paragraph::new(lines).block(pane("Watches")), area);
+}
+
+fn draw_memory(f: &mut Frame, area: Rect, mem: &Mem, base: u16) {
+    let height = area.height.saturating_sub(2);
+    let lines = (0..height)
+        .map(|row| {
+            let start = base.wrapping_add(row * 16);
+            let mut line = format!("{}:{start:04X}  ", mem.bank(start));
+            let bytes = (0..16)
+                .map(|i| mem.read(start.wrapping_add(i)))
+                .collect::<Vec<u8>>();
+            for byte in &bytes {
+                line.push_str(&format!("{byte:02X} "));
+            }
+            line.push_str(" |");
+            for &c in &bytes {
+                line.push(if c.is_ascii_graphic() { c as char } else { '.' });
+            }
+            line.push('|');
+            Line::from(line)
+        })
+        .collect::<Vec<Line>>();
+    f.render_widget(Paragraph::new(lines).block(pane("Memory")), area);
+}
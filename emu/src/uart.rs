@@ -1,8 +1,14 @@
 //! 6551 UART Emulation
 
-use std::io::{Read, Write};
+use std::{
+    collections::VecDeque,
+    io::{Read, Write},
+};
 
-use crate::bus::{Bus, BusDevice};
+use crate::{
+    bus::{Bus, BusDevice},
+    xmodem::Xmodem,
+};
 
 enum StatusFlags {}
 
@@ -21,6 +27,8 @@ enum ControlFlags {}
 
 impl ControlFlags {
     const BAUD_RATE_MASK: u8 = 0b0000_1111;
+    // the receiver always uses the baud rate generator
+    #[allow(dead_code)]
     const RX_CLOCK_SOURCE: u8 = 1 << 4;
     const WORD_LENGTH_MASK: u8 = 0b0110_0000;
     const STOP_BIT: u8 = 1 << 7;
@@ -37,6 +45,15 @@ impl CommandFlags {
     const PARITY_MODE_CONTROL_MASK: u8 = 0b1100_0000;
 }
 
+/// Rate the UART is ticked at, used to pace the baud rate generator
+pub const TICK_RATE: u32 = 1_000_000;
+
+/// Baud rates selected by the low nibble of the control register.
+/// 0 selects the external 16x clock, which we treat as unpaced.
+const BAUD_RATES: [u32; 16] = [
+    0, 50, 75, 110, 135, 150, 300, 600, 1200, 1800, 2400, 3600, 4800, 7200, 9600, 19200,
+];
+
 pub struct Uart<T> {
     handle: T,
     status: u8,
@@ -44,7 +61,17 @@ pub struct Uart<T> {
     command: u8,
     tx: Option<u8>,
     rx: Option<u8>,
+    tx_busy: u32, // ticks until the transmit shift register is free
+    rx_busy: u32, // ticks until the next frame can be received
+    carrier: bool,
+    data_set_ready: bool,
     irq: bool,
+    /// Pasted bytes waiting to be received ahead of the host input
+    paste: VecDeque<u8>,
+    paste_interval: u32,
+    paste_wait: u32,
+    /// Takes over the host end while it runs
+    transfer: Option<Xmodem>,
 }
 
 impl<T> Uart<T> {
@@ -56,71 +83,227 @@ impl<T> Uart<T> {
             command: 0,
             tx: None,
             rx: None,
+            tx_busy: 0,
+            rx_busy: 0,
+            carrier: true,
+            data_set_ready: true,
             irq: false,
+            paste: VecDeque::new(),
+            paste_interval: 0,
+            paste_wait: 0,
+            transfer: None,
         }
     }
 
-    pub fn irq(&self) -> bool {
-        (self.status & StatusFlags::INTERRUPT) != 0
+    fn word_bits(&self) -> u32 {
+        8 - (((self.control & ControlFlags::WORD_LENGTH_MASK) >> 5) as u32)
     }
 
-    pub fn handle_mut(&mut self) -> &mut T {
-        &mut self.handle
+    fn word_mask(&self) -> u8 {
+        0xFF >> (8 - self.word_bits())
+    }
+
+    /// The parity bit for a word, if parity is enabled
+    fn parity(&self, data: u8) -> Option<bool> {
+        if (self.command & CommandFlags::PARITY_MODE_ENABLED) == 0 {
+            return None;
+        }
+        let ones = (data & self.word_mask()).count_ones();
+        match (self.command & CommandFlags::PARITY_MODE_CONTROL_MASK) >> 6 {
+            0 => Some(ones.is_multiple_of(2)),  // odd
+            1 => Some(!ones.is_multiple_of(2)), // even
+            2 => Some(true),                    // mark
+            3 => Some(false),                   // space
+            _ => unreachable!(),
+        }
+    }
+
+    /// The host side only moves bytes, so for words shorter than 8 bits
+    /// the parity bit is carried in the bit following the word.
+    fn frame(&self, data: u8) -> u8 {
+        let word_bits = self.word_bits();
+        let data = data & self.word_mask();
+        match self.parity(data) {
+            Some(true) if word_bits < 8 => data | (1 << word_bits),
+            _ => data,
+        }
+    }
+
+    fn unframe(&mut self, frame: u8) -> u8 {
+        let word_bits = self.word_bits();
+        let data = frame & self.word_mask();
+        // mark and space parity are transmitted, but never checked
+        let checked = ((self.command & CommandFlags::PARITY_MODE_CONTROL_MASK) >> 6) < 2;
+        if let Some(parity) = self.parity(data) {
+            if checked && word_bits < 8 && (((frame >> word_bits) & 1) != 0) != parity {
+                self.status |= StatusFlags::PARITY_ERROR;
+            }
+        }
+        data
+    }
+
+    /// Host I/O failed, so drop carrier as if the modem hung up
+    fn hang_up(&mut self) {
+        if self.carrier || self.data_set_ready {
+            tracing::debug!(target: "uart", "carrier lost");
+            self.carrier = false;
+            self.data_set_ready = false;
+            if (self.command & CommandFlags::RX_INTERRUPT_REQUEST_DISABLED) == 0 {
+                self.raise_irq();
+            }
+        }
+    }
+
+    /// Number of ticks it takes to shift a whole frame in or out
+    fn frame_ticks(&self) -> u32 {
+        let baud = BAUD_RATES[(self.control & ControlFlags::BAUD_RATE_MASK) as usize];
+        if baud == 0 {
+            return 0;
+        }
+        let word_bits = self.word_bits();
+        let parity_bits = if (self.command & CommandFlags::PARITY_MODE_ENABLED) != 0 {
+            1
+        } else {
+            0
+        };
+        let stop_bits = if (self.control & ControlFlags::STOP_BIT) != 0 {
+            2
+        } else {
+            1
+        };
+        let frame_bits = 1 + word_bits + parity_bits + stop_bits;
+        (TICK_RATE * frame_bits) / baud
+    }
+
+    fn raise_irq(&mut self) {
+        self.irq = true;
+        self.status |= StatusFlags::INTERRUPT;
     }
 }
 
 impl<T: Read + Write> BusDevice for Uart<T> {
-    fn reset<B: Bus>(&mut self, _bus: &mut B) {
+    fn reset(&mut self, _bus: &mut dyn Bus) {
         self.status = StatusFlags::TX_DATA_REGISTER_EMPTY;
         self.control = 0;
         self.command = 0;
         self.tx = None;
         self.rx = None;
+        self.tx_busy = 0;
+        self.rx_busy = 0;
+        self.carrier = true;
+        self.data_set_ready = true;
         self.irq = false;
     }
 
-    fn tick<B: Bus>(&mut self, bus: &mut B) {
+    fn tick(&mut self, _bus: &mut dyn Bus) {
+        self.tx_busy = self.tx_busy.saturating_sub(1);
+        self.rx_busy = self.rx_busy.saturating_sub(1);
+        self.paste_wait = self.paste_wait.saturating_sub(1);
+        if let Some(transfer) = &mut self.transfer {
+            transfer.tick();
+            match transfer.result() {
+                Some(Ok(message)) => tracing::info!(target: "uart", "xmodem: {message}"),
+                Some(Err(e)) => tracing::error!(target: "uart", "xmodem transfer failed: {e}"),
+                None => {}
+            }
+            if transfer.result().is_some() {
+                self.transfer = None;
+            }
+        }
+
         if (self.command & CommandFlags::DATA_TERMINAL_READY) == 0 {
             return;
         }
 
-        if let Some(tx) = self.tx.take() {
-            match self.handle.write(&[tx]) {
-                // no transmit happened. can this even happen?
-                Ok(n) if n == 0 => {
-                    self.tx = Some(tx);
-                }
-                Err(e) => {
-                    todo!("need to handle tx error: {e}");
-                }
-                _ => {
+        // move the data register into the shift register once it is free
+        if self.tx_busy == 0 {
+            if let Some(tx) = self.tx.take() {
+                let mask = self.word_mask();
+                let sent = if let Some(transfer) = &mut self.transfer {
+                    transfer.input(tx & mask);
+                    true
+                } else if self.carrier {
+                    let frame = self.frame(tx);
+                    match self.handle.write(&[frame]).and_then(|n| {
+                        self.handle.flush()?;
+                        Ok(n)
+                    }) {
+                        Ok(n) => {
+                            tracing::trace!(target: "uart", "sent {frame:02X}");
+                            n != 0
+                        }
+                        Err(e) => {
+                            tracing::warn!(target: "uart", "tx failed: {e}");
+                            self.hang_up();
+                            true
+                        }
+                    }
+                } else {
+                    // nobody is listening, the byte goes nowhere
+                    true
+                };
+                if sent {
+                    self.tx_busy = self.frame_ticks();
                     self.status |= StatusFlags::TX_DATA_REGISTER_EMPTY;
                     if (self.command & CommandFlags::TX_INTERRUPT_CONTROL_MASK) == 0b0000_0100 {
-                        self.status |= StatusFlags::INTERRUPT;
+                        self.raise_irq();
                     }
+                } else {
+                    // no transmit happened. can this even happen?
+                    self.tx = Some(tx);
                 }
             }
-            self.handle.flush().unwrap();
-        } else {
         }
 
-        if self.rx.is_none() {
+        // the receiver is disabled while carrier is lost
+        if self.carrier && self.rx.is_none() && self.rx_busy == 0 {
             let mut buf = [0];
-            match self.handle.read(&mut buf) {
+            let received = match self.paste.front() {
+                // a transfer has the line to itself
+                _ if self.transfer.is_some() => {
+                    match self.transfer.as_mut().and_then(Xmodem::poll) {
+                        Some(data) => {
+                            buf[0] = data;
+                            Ok(1)
+                        }
+                        None => Ok(0),
+                    }
+                }
+                Some(&data) if self.paste_wait == 0 => {
+                    self.paste.pop_front();
+                    self.paste_wait = self.paste_interval;
+                    buf[0] = data;
+                    Ok(1)
+                }
+                Some(_) => Ok(0),
+                None => self.handle.read(&mut buf),
+            };
+            match received {
                 // modem has nothing else to send us?
-                Ok(n) if n == 0 => {}
+                Ok(0) => {}
                 Err(e) => {
-                    todo!("need to handle rx error: {e}");
+                    tracing::warn!(target: "uart", "rx failed: {e}");
+                    self.hang_up();
                 }
                 _ => {
-                    self.rx = Some(buf[0]);
+                    self.rx_busy = self.frame_ticks();
+                    let data = self.unframe(buf[0]);
+                    tracing::trace!(target: "uart", "received {data:02X}");
+                    self.rx = Some(data);
                     self.status |= StatusFlags::RX_DATA_REGISTER_FULL;
+                    // echo mode retransmits everything received (only with TX interrupts off)
+                    if (self.command & CommandFlags::RX_ECHO_MODE) != 0
+                        && (self.command & CommandFlags::TX_INTERRUPT_CONTROL_MASK) == 0
+                        && self.tx.is_none()
+                    {
+                        self.tx = Some(data);
+                        self.status &= !StatusFlags::TX_DATA_REGISTER_EMPTY;
+                    }
                     if (self.command & CommandFlags::RX_INTERRUPT_REQUEST_DISABLED) == 0 {
-                        self.status |= StatusFlags::INTERRUPT;
+                        self.raise_irq();
                     }
                 }
             }
-        } else {
         }
     }
 
@@ -135,14 +318,38 @@ impl<T: Read + Write> BusDevice for Uart<T> {
                 self.rx.take().unwrap_or(0)
             }
             1 => {
-                // clear interrupt on status read.
-                let status = self.status;
+                // clear interrupt on status read
+                let status = self.peek(1).unwrap();
                 self.status &= !StatusFlags::INTERRUPT;
+                self.irq = false;
                 status
             }
             2 => self.command,
             3 => self.control,
-            _ => unreachable!(),
+            _ => {
+                tracing::warn!(target: "uart", "read from register {addr}, which doesn't exist");
+                0
+            }
+        }
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0 => Some(self.rx.unwrap_or(0)),
+            1 => {
+                // modem lines are active low, so a set bit means the line dropped
+                let mut status = self.status;
+                if !self.carrier {
+                    status |= StatusFlags::DATA_CARRIER_DETECT;
+                }
+                if !self.data_set_ready {
+                    status |= StatusFlags::DATA_SET_READY;
+                }
+                Some(status)
+            }
+            2 => Some(self.command),
+            3 => Some(self.control),
+            _ => None,
         }
     }
 
@@ -153,16 +360,63 @@ impl<T: Read + Write> BusDevice for Uart<T> {
                 self.tx = Some(data);
             }
             1 => {
+                tracing::debug!(target: "uart", "reset");
                 self.tx = None;
                 self.rx = None;
                 self.command = CommandFlags::RX_INTERRUPT_REQUEST_DISABLED;
                 // TODO: this isn't accurate. only overrun should clear on soft reset
                 //   but I use this flag to know whether to push bytes
                 self.status = StatusFlags::TX_DATA_REGISTER_EMPTY;
+                self.irq = false;
             }
-            2 => self.command = data,
-            3 => self.control = data,
-            _ => unreachable!(),
+            2 => {
+                tracing::debug!(target: "uart", "command {data:02X}");
+                self.command = data;
+            }
+            3 => {
+                self.control = data;
+                match BAUD_RATES[(data & ControlFlags::BAUD_RATE_MASK) as usize] {
+                    0 => tracing::debug!(
+                        target: "uart",
+                        "control {data:02X} (unpaced, {} bits)",
+                        self.word_bits()
+                    ),
+                    baud => tracing::debug!(
+                        target: "uart",
+                        "control {data:02X} ({baud} baud, {} bits)",
+                        self.word_bits()
+                    ),
+                }
+            }
+            _ => tracing::warn!(target: "uart", "write to register {addr}, which doesn't exist"),
+        }
+    }
+
+    fn paste(&mut self, data: &[u8], rate: u32) -> bool {
+        tracing::debug!(target: "uart", "pasting {} bytes", data.len());
+        self.paste.extend(data);
+        self.paste_interval = TICK_RATE / rate.max(1);
+        true
+    }
+
+    fn xmodem(&mut self, transfer: Xmodem) -> bool {
+        tracing::debug!(target: "uart", "xmodem transfer started");
+        self.transfer = Some(transfer);
+        true
+    }
+
+    fn register_name(&self, addr: u16) -> Option<&'static str> {
+        match addr {
+            0 => Some("Data"),
+            1 => Some("Status/Reset"),
+            2 => Some("Command"),
+            3 => Some("Control"),
+            _ => None,
         }
     }
+
+    fn irq(&self) -> bool {
+        // interrupts are disabled along with the receiver when DTR is off
+        self.irq && (self.command & CommandFlags::DATA_TERMINAL_READY) != 0
+    }
 }
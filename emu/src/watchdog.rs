@@ -0,0 +1,90 @@
+//! Hang Watchdog
+//!
+//! Watches PC after every instruction while running, and stops the run
+//! (dropping to the debugger, or failing a `test`) when the guest has spent
+//! too many cycles with interrupts disabled and PC inside a window of
+//! [`WINDOW`] bytes. Nothing can break it out of a loop like that short of
+//! an NMI, so it is almost always polling for something that won't come.
+
+use crate::cpu::{Cpu, Flags};
+
+/// How many bytes of code a hung loop can span
+pub const WINDOW: u16 = 16;
+
+pub struct Watchdog {
+    /// Cycles the guest can stay in one window, or `None` when it's off
+    limit: Option<u64>,
+    /// The lowest and highest PC since the guest entered the window
+    low: u16,
+    high: u16,
+    /// Cycle count when it did, or `None` while interrupts are enabled
+    since: Option<u64>,
+    fired: bool,
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self {
+            limit: None,
+            low: 0,
+            high: 0,
+            since: None,
+            fired: false,
+        }
+    }
+
+    /// Watch for hangs of `limit` cycles from here on, or stop watching
+    pub fn set(&mut self, limit: Option<u64>) {
+        *self = Self::new();
+        self.limit = limit;
+    }
+
+    /// Check the instruction that just ran at `pc`
+    #[inline]
+    pub fn check(&mut self, cpu: &Cpu, pc: u16) {
+        let Some(limit) = self.limit else {
+            return;
+        };
+        if (cpu.p() & Flags::INTERRUPT_DISABLE) == 0 {
+            self.since = None;
+            return;
+        }
+        let cycles = cpu.cycles();
+        let low = self.low.min(pc);
+        let high = self.high.max(pc);
+        let Some(since) = self.since.filter(|_| high - low < WINDOW) else {
+            self.low = pc;
+            self.high = pc;
+            self.since = Some(cycles);
+            return;
+        };
+        self.low = low;
+        self.high = high;
+        if cycles - since < limit {
+            return;
+        }
+        if low == high {
+            tracing::warn!(
+                "guest hung: the instruction at {pc:04X} ran for {} cycles with interrupts disabled",
+                cycles - since
+            );
+        } else {
+            tracing::warn!(
+                "guest hung: PC stayed in {low:04X}-{high:04X} for {} cycles with interrupts disabled",
+                cycles - since
+            );
+        }
+        // give it another full period before firing again
+        self.since = None;
+        self.fired = true;
+    }
+
+    /// Whether the watchdog fired since the last [`Self::take_fired`]
+    pub fn fired(&self) -> bool {
+        self.fired
+    }
+
+    pub fn take_fired(&mut self) -> bool {
+        std::mem::take(&mut self.fired)
+    }
+}
@@ -0,0 +1,359 @@
+//! XMODEM File Transfer
+//!
+//! The host end of an XMODEM transfer, which stands in for a UART's host
+//! backend while it runs. Both the CRC-16 and the original checksum
+//! variants are spoken, picked by the receiver as usual. Blocks are sent
+//! 128 bytes at a time, and 1K blocks are accepted when receiving.
+//!
+//! XMODEM pads the last block with SUB (1A), and there is no way to tell
+//! padding from data, so trailing SUBs are dropped from received files.
+//!
+//! Timeouts are counted in UART ticks, so they follow emulated time.
+
+#[cfg(test)]
+mod tests;
+
+use std::{collections::VecDeque, fs, path::PathBuf};
+
+use crate::uart::TICK_RATE;
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+const SUB: u8 = 0x1A;
+const CRC_START: u8 = b'C';
+
+const BLOCK_SIZE: usize = 128;
+const MAX_RETRIES: u32 = 10;
+
+const START_TIMEOUT: u32 = 60 * TICK_RATE;
+const ACK_TIMEOUT: u32 = 10 * TICK_RATE;
+const POLL_TIMEOUT: u32 = 3 * TICK_RATE;
+const BYTE_TIMEOUT: u32 = TICK_RATE;
+
+/// Tries with CRC-16 before falling back to checksums
+const CRC_POLLS: u32 = 3;
+
+#[derive(Clone, Copy)]
+enum SendState {
+    /// Waiting for the receiver to ask for the first block
+    Start,
+    /// Waiting for a block to be acknowledged
+    Block,
+    /// Waiting for EOT to be acknowledged
+    End,
+}
+
+enum Mode {
+    Send {
+        data: Vec<u8>,
+        /// Index of the block waiting to be acknowledged
+        block: usize,
+        state: SendState,
+    },
+    Receive {
+        path: PathBuf,
+        data: Vec<u8>,
+        packet: Vec<u8>,
+        expected: u8,
+        started: bool,
+    },
+}
+
+pub struct Xmodem {
+    mode: Mode,
+    crc: bool,
+    /// Bytes waiting to go to the guest
+    out: VecDeque<u8>,
+    timer: u32,
+    retries: u32,
+    result: Option<Result<String, String>>,
+}
+
+impl Xmodem {
+    /// Send `data` to the guest once it asks for the first block
+    pub fn send(data: Vec<u8>) -> Self {
+        Self {
+            mode: Mode::Send {
+                data,
+                block: 0,
+                state: SendState::Start,
+            },
+            crc: false,
+            out: VecDeque::new(),
+            timer: START_TIMEOUT,
+            retries: 0,
+            result: None,
+        }
+    }
+
+    /// Receive a file from the guest into `path`
+    pub fn receive(path: PathBuf) -> Self {
+        let mut xmodem = Self {
+            mode: Mode::Receive {
+                path,
+                data: Vec::new(),
+                packet: Vec::new(),
+                expected: 1,
+                started: false,
+            },
+            crc: true,
+            out: VecDeque::new(),
+            timer: POLL_TIMEOUT,
+            retries: 0,
+            result: None,
+        };
+        xmodem.out.push_back(CRC_START);
+        xmodem
+    }
+
+    /// How the transfer went, once it is over and the guest has been
+    /// sent everything
+    pub fn result(&self) -> Option<&Result<String, String>> {
+        if !self.out.is_empty() {
+            return None;
+        }
+        self.result.as_ref()
+    }
+
+    /// The next byte for the guest
+    pub fn poll(&mut self) -> Option<u8> {
+        self.out.pop_front()
+    }
+
+    pub fn tick(&mut self) {
+        if self.result.is_some() {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timeout();
+        }
+    }
+
+    /// A byte from the guest
+    pub fn input(&mut self, byte: u8) {
+        if self.result.is_some() {
+            return;
+        }
+        match &self.mode {
+            Mode::Send { state, .. } => self.send_input(*state, byte),
+            Mode::Receive { .. } => self.receive_input(byte),
+        }
+    }
+
+    fn send_input(&mut self, state: SendState, byte: u8) {
+        match (state, byte) {
+            (_, CAN) => self.finish(Err("cancelled by the guest".to_string())),
+            (SendState::Start, NAK | CRC_START) => {
+                self.crc = byte == CRC_START;
+                self.send_block();
+            }
+            (SendState::Block, ACK) => {
+                self.retries = 0;
+                if let Mode::Send { block, .. } = &mut self.mode {
+                    *block += 1;
+                }
+                self.send_block();
+            }
+            (SendState::End, ACK) => {
+                let Mode::Send { data, .. } = &self.mode else {
+                    unreachable!()
+                };
+                let message = format!("sent {} bytes", data.len());
+                self.finish(Ok(message));
+            }
+            (SendState::Block | SendState::End, NAK) => self.retry(),
+            // the receiver may poll again while the first block is on its way
+            _ => {}
+        }
+    }
+
+    fn receive_input(&mut self, byte: u8) {
+        let Mode::Receive { packet, .. } = &mut self.mode else {
+            unreachable!()
+        };
+        self.timer = BYTE_TIMEOUT;
+        if !packet.is_empty() {
+            packet.push(byte);
+            self.check_packet();
+            return;
+        }
+        match byte {
+            SOH | STX => packet.push(byte),
+            EOT => {
+                self.out.push_back(ACK);
+                self.save();
+            }
+            CAN => self.finish(Err("cancelled by the guest".to_string())),
+            // line noise between packets
+            _ => {}
+        }
+    }
+
+    fn send_block(&mut self) {
+        let Mode::Send { data, block, state } = &mut self.mode else {
+            unreachable!()
+        };
+        let start = *block * BLOCK_SIZE;
+        if start >= data.len() {
+            self.out.push_back(EOT);
+            *state = SendState::End;
+            self.timer = ACK_TIMEOUT;
+            return;
+        }
+        let mut payload = data[start..]
+            .iter()
+            .copied()
+            .take(BLOCK_SIZE)
+            .collect::<Vec<u8>>();
+        payload.resize(BLOCK_SIZE, SUB);
+        let number = (*block + 1) as u8;
+        self.out.extend([SOH, number, !number]);
+        self.out.extend(&payload);
+        if self.crc {
+            self.out.extend(crc16(&payload).to_be_bytes());
+        } else {
+            self.out.push_back(checksum(&payload));
+        }
+        *state = SendState::Block;
+        self.timer = ACK_TIMEOUT;
+    }
+
+    /// Send the last block (or EOT) again
+    fn retry(&mut self) {
+        self.retries += 1;
+        if self.retries > MAX_RETRIES {
+            self.abort("too many retries");
+            return;
+        }
+        self.out.clear();
+        self.send_block();
+    }
+
+    fn timeout(&mut self) {
+        match &mut self.mode {
+            Mode::Send {
+                state: SendState::Start,
+                ..
+            } => self.abort("the guest never started receiving"),
+            Mode::Send { .. } => self.retry(),
+            Mode::Receive {
+                started, packet, ..
+            } => {
+                packet.clear();
+                let started = *started;
+                self.retries += 1;
+                if self.retries > MAX_RETRIES {
+                    self.abort(if started {
+                        "the guest stopped sending"
+                    } else {
+                        "the guest never started sending"
+                    });
+                    return;
+                }
+                if !started && self.retries >= CRC_POLLS {
+                    self.crc = false;
+                }
+                self.out
+                    .push_back(if !started && self.crc { CRC_START } else { NAK });
+                self.timer = if started { ACK_TIMEOUT } else { POLL_TIMEOUT };
+            }
+        }
+    }
+
+    fn check_packet(&mut self) {
+        let crc = self.crc;
+        let Mode::Receive {
+            data,
+            packet,
+            expected,
+            started,
+            ..
+        } = &mut self.mode
+        else {
+            unreachable!()
+        };
+        let size = if packet[0] == STX { 1024 } else { BLOCK_SIZE };
+        if packet.len() < 3 + size + if crc { 2 } else { 1 } {
+            return;
+        }
+        let number = packet[1];
+        let payload = &packet[3..(3 + size)];
+        let valid = packet[2] == !number
+            && if crc {
+                packet[(3 + size)..] == crc16(payload).to_be_bytes()
+            } else {
+                packet[3 + size] == checksum(payload)
+            };
+        if !valid {
+            packet.clear();
+            self.out.push_back(NAK);
+            return;
+        }
+        *started = true;
+        if number == *expected {
+            data.extend_from_slice(payload);
+            *expected = expected.wrapping_add(1);
+        } else if number != expected.wrapping_sub(1) {
+            // the previous block is resent when our ACK is lost, but
+            // anything else means we can't tell where we are
+            self.abort("lost track of the block numbers");
+            return;
+        }
+        packet.clear();
+        self.retries = 0;
+        self.timer = ACK_TIMEOUT;
+        self.out.push_back(ACK);
+    }
+
+    fn save(&mut self) {
+        let Mode::Receive { path, data, .. } = &mut self.mode else {
+            unreachable!()
+        };
+        while data.last() == Some(&SUB) {
+            data.pop();
+        }
+        let result = match fs::write(&*path, &data) {
+            Ok(()) => Ok(format!(
+                "received {} bytes into {}",
+                data.len(),
+                path.display()
+            )),
+            Err(e) => Err(format!("failed to write {}: {e}", path.display())),
+        };
+        self.finish(result);
+    }
+
+    fn abort(&mut self, reason: &str) {
+        self.out.extend([CAN, CAN]);
+        self.finish(Err(reason.to_string()));
+    }
+
+    fn finish(&mut self, result: Result<String, String>) {
+        self.result = Some(result);
+    }
+}
+
+/// CRC-16/XMODEM (polynomial 1021, starting from 0)
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if (crc & 0x8000) != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
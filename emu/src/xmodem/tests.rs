@@ -0,0 +1,89 @@
+use std::env;
+
+use super::*;
+
+/// Run a sender against a receiver until both are done, `corrupt`ing
+/// bytes on their way to the receiver
+fn transfer(data: &[u8], name: &str, mut corrupt: impl FnMut(usize, u8) -> u8) -> Vec<u8> {
+    let path = env::temp_dir().join(format!("possum2-xmodem-{}-{name}", std::process::id()));
+    let mut sender = Xmodem::send(data.to_vec());
+    let mut receiver = Xmodem::receive(path.clone());
+    let mut sent = 0;
+    for _ in 0..(100 * TICK_RATE) {
+        if let Some(byte) = sender.poll() {
+            receiver.input(corrupt(sent, byte));
+            sent += 1;
+        }
+        if let Some(byte) = receiver.poll() {
+            sender.input(byte);
+        }
+        sender.tick();
+        receiver.tick();
+        if sender.result().is_some() && receiver.result().is_some() {
+            break;
+        }
+    }
+    assert!(
+        matches!(sender.result(), Some(Ok(_))),
+        "{:?}",
+        sender.result()
+    );
+    assert!(
+        matches!(receiver.result(), Some(Ok(_))),
+        "{:?}",
+        receiver.result()
+    );
+    let received = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    received
+}
+
+#[test]
+fn loopback() {
+    let data = (0..1000).map(|i| (i * 7) as u8).collect::<Vec<u8>>();
+    assert_eq!(transfer(&data, "loopback", |_, byte| byte), data);
+}
+
+#[test]
+fn corrupted_block_is_resent() {
+    let data = (0..300).map(|i| i as u8).collect::<Vec<u8>>();
+    // flip a bit in the middle of the second block, once
+    let received = transfer(&data, "corrupt", |index, byte| {
+        if index == 133 + 50 {
+            byte ^ 0x10
+        } else {
+            byte
+        }
+    });
+    assert_eq!(received, data);
+}
+
+#[test]
+fn falls_back_to_checksums() {
+    let path = env::temp_dir().join(format!("possum2-xmodem-{}-checksum", std::process::id()));
+    let mut receiver = Xmodem::receive(path.clone());
+    let mut polls = Vec::new();
+    for _ in 0..(4 * POLL_TIMEOUT) {
+        receiver.tick();
+        polls.extend(receiver.poll());
+    }
+    assert_eq!(polls, [CRC_START, CRC_START, CRC_START, NAK, NAK]);
+
+    let mut block = vec![SOH, 1, !1];
+    block.extend([b'x'; BLOCK_SIZE]);
+    block.push(checksum(&[b'x'; BLOCK_SIZE]));
+    for byte in block {
+        receiver.input(byte);
+    }
+    assert_eq!(receiver.poll(), Some(ACK));
+    receiver.input(EOT);
+    assert_eq!(receiver.poll(), Some(ACK));
+    assert!(matches!(receiver.result(), Some(Ok(_))));
+    assert_eq!(fs::read(&path).unwrap(), [b'x'; BLOCK_SIZE]);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn crc16_check_value() {
+    assert_eq!(crc16(b"123456789"), 0x31C3);
+}
@@ -0,0 +1,325 @@
+//! End-to-End Boot Tests
+//!
+//! Assembles ROMs from `roms` with `pasm` and boots them headless:
+//! * `echo.asm` is typed at on SER0 from a debugger script, checking the
+//!   bytes it sends back and the status it exits the emulator with
+//! * `echo.asm` is also examined with `--dbg-commands`, checking the
+//!   debugger's output reaches stdout
+//! * `report.asm` runs under `possum2-emu test`, checking a passing and a
+//!   failing report reach stdout and the exit status
+//! * `banked.asm` is assembled once per bank into a 2-bank ROM, checking
+//!   the boot bank can switch to the other
+//! * `boot.asm` is written to the first sector of a disk image made with
+//!   `possum2-emu mkdisk` and booted with `--boot-fd0`, checking its
+//!   interrupt header is used
+//! * `hang.asm` polls with interrupts disabled, checking `--watchdog`
+//!   stops it
+//! * `openbus.asm` exits with an unmapped IO read, checking each
+//!   `--open-bus` model
+//! * `echo.asm` has its state saved with `state`, checking the JSON
+//! * `irqroute.asm` routes the timer to another encoder input, checking
+//!   the latch follows it
+//! * `irqdispatch.asm` routes the timer to each encoder input in turn,
+//!   checking `IRQ_DISPATCH` from `lib/irq.asm` reaches that input's handler
+//! * `devreset.asm` resets the timer through the Device Reset register,
+//!   checking its IRQ drops and SER0 keeps its settings
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const EMU: &str = env!("CARGO_BIN_EXE_possum2-emu");
+
+/// Build `pasm` from the workspace, which lands next to the emulator
+fn pasm() -> PathBuf {
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--quiet", "--package", "pasm"])
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "failed to build pasm");
+    Path::new(EMU).with_file_name(format!("pasm{}", env::consts::EXE_SUFFIX))
+}
+
+fn work_dir() -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("boot");
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Assemble `roms/NAME.asm` into `rom` as a ROM image, with `lib` to include from
+fn assemble(name: &str, rom: &Path, defines: &[&str]) {
+    assemble_target(name, rom, "possum2-rom", defines);
+}
+
+fn assemble_target(name: &str, out: &Path, target: &str, defines: &[&str]) {
+    let source = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/roms")
+        .join(name)
+        .with_extension("asm");
+    let assembled = Command::new(pasm())
+        .arg(&source)
+        .arg("-I")
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("../lib"))
+        .arg("-o")
+        .arg(out)
+        .args(["--target", target])
+        .args(defines.iter().flat_map(|define| ["-D", define]))
+        .output()
+        .unwrap();
+    assert!(
+        assembled.status.success(),
+        "pasm failed: {}",
+        String::from_utf8_lossy(&assembled.stderr)
+    );
+}
+
+#[test]
+fn echo_rom_boots_and_echoes_ser0() {
+    let dir = work_dir();
+    let rom = dir.join("echo.rom");
+    let input = dir.join("echo.in");
+    let script = dir.join("echo.scr");
+    let output = dir.join("echo.out");
+    assemble("echo", &rom, &[]);
+
+    fs::write(&input, "hello, possum.").unwrap();
+    fs::write(&script, format!("paste {}\nc\n", input.display())).unwrap();
+    let _ = fs::remove_file(&output);
+    let run = Command::new(EMU)
+        .arg(&rom)
+        .arg("--script")
+        .arg(&script)
+        .arg("--ser0")
+        .arg(format!("file:{}", output.display()))
+        .args(["--max-cycles", "20000000"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        run.status.code(),
+        Some(0x2A),
+        "emulator failed: {}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+    assert_eq!(fs::read(&output).unwrap(), b"ready\r\nHELLO, POSSUM");
+}
+
+#[test]
+fn dbg_commands_run_unattended() {
+    let dir = work_dir();
+    let rom = dir.join("echo-dbg.rom");
+    assemble("echo", &rom, &[]);
+
+    let run = Command::new(EMU)
+        .arg(&rom)
+        .args(["--dbg-commands", "x F100 +5; q"])
+        .output()
+        .unwrap();
+    assert_eq!(run.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert!(stdout.contains("dbg>x F100 +5\n"), "{stdout}");
+    assert!(stdout.contains("|ready|"), "{stdout}");
+}
+
+#[test]
+fn test_command_reports_results() {
+    let dir = work_dir();
+    for (expect, status, report) in [
+        ("EXPECT=4", 0, "PASS: 2 + 2 is right\n"),
+        ("EXPECT=5", 1, "FAIL (1): 2 + 2 is wrong\n"),
+    ] {
+        let rom = dir.join(format!("report-{expect}.rom"));
+        assemble("report", &rom, &[expect]);
+        let run = Command::new(EMU)
+            .arg("test")
+            .arg(&rom)
+            .args(["--max-cycles", "1000"])
+            .output()
+            .unwrap();
+        assert_eq!(run.status.code(), Some(status));
+        assert_eq!(String::from_utf8_lossy(&run.stdout), report);
+    }
+}
+
+#[test]
+fn rom_bank_select_switches_banks() {
+    let dir = work_dir();
+    let mut image = Vec::new();
+    for bank in 0..2 {
+        let rom = dir.join(format!("banked-{bank}.rom"));
+        assemble("banked", &rom, &[&format!("BANK={bank}")]);
+        image.extend(fs::read(&rom).unwrap());
+    }
+    let rom = dir.join("banked.rom");
+    fs::write(&rom, image).unwrap();
+    let run = Command::new(EMU)
+        .arg("test")
+        .arg(&rom)
+        .args(["--max-cycles", "1000"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&run.stdout),
+        "PASS: switched ROM banks\n"
+    );
+    assert_eq!(run.status.code(), Some(0));
+}
+
+#[test]
+fn boots_from_fd0_without_a_rom() {
+    let dir = work_dir();
+    let program = dir.join("boot.bin");
+    let image = dir.join("boot.img");
+    let script = dir.join("boot.scr");
+    assemble_target("boot", &program, "raw", &[]);
+
+    let mkdisk = Command::new(EMU)
+        .args(["mkdisk", "--force", "--boot"])
+        .arg(&program)
+        .arg(&image)
+        .output()
+        .unwrap();
+    assert!(mkdisk.status.success());
+    fs::write(&script, "c\n").unwrap();
+    let run = Command::new(EMU)
+        .arg("--boot-fd0")
+        .arg("--fd0")
+        .arg(&image)
+        .arg("--script")
+        .arg(&script)
+        .args(["--max-cycles", "1000"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        run.status.code(),
+        Some(0x2A),
+        "emulator failed: {}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+}
+
+#[test]
+fn watchdog_stops_a_hung_guest() {
+    let dir = work_dir();
+    let rom = dir.join("hang.rom");
+    assemble("hang", &rom, &[]);
+
+    let run = Command::new(EMU)
+        .arg(&rom)
+        .args(["--ser0", "null", "--watchdog", "1"])
+        .args(["--dbg-commands", "c; q"])
+        .args(["--max-cycles", "20000000"])
+        .output()
+        .unwrap();
+    assert_eq!(run.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&run.stderr);
+    assert!(
+        stderr.contains("guest hung: PC stayed in F100-F106"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn state_saves_json() {
+    let dir = work_dir();
+    let rom = dir.join("echo-state.rom");
+    let state = dir.join("echo.json");
+    assemble("echo", &rom, &[]);
+
+    let _ = fs::remove_file(&state);
+    let run = Command::new(EMU)
+        .arg(&rom)
+        .args(["--dbg-commands", &format!("state {}; q", state.display())])
+        .output()
+        .unwrap();
+    assert_eq!(run.status.code(), Some(0));
+    let state: serde_json::Value = serde_json::from_slice(&fs::read(&state).unwrap()).unwrap();
+    assert_eq!(state["cpu"]["flags"]["interrupt_disable"], true);
+    assert_eq!(state["devices"]["ser0"]["base"], 0xF010);
+    assert_eq!(state["devices"]["ser0"]["registers"]["Command"], 0);
+    // FDC1 keeps the 179X's four registers, with DMA mapped apart
+    assert_eq!(state["devices"]["fdc1"]["base"], 0xF034);
+    assert_eq!(state["devices"]["fdc1"]["registers"]["DMA Control"], 0);
+}
+
+#[test]
+fn open_bus_reads_are_selectable() {
+    let dir = work_dir();
+    let rom = dir.join("openbus.rom");
+    assemble("openbus", &rom, &[]);
+
+    let read = |args: &[&str]| {
+        let run = Command::new(EMU)
+            .arg(&rom)
+            .args(["--ser0", "null", "--max-cycles", "1000"])
+            .args(["--unmapped-io", "open-bus"])
+            .args(args)
+            .output()
+            .unwrap();
+        run.status.code()
+    };
+    assert_eq!(read(&["--open-bus", "zero"]), Some(0x00));
+    assert_eq!(read(&["--open-bus", "ff"]), Some(0xFF));
+    // the last byte fetched is the address's high byte
+    assert_eq!(read(&[]), Some(0xF0));
+    let random = read(&["--open-bus", "random", "--seed", "1"]);
+    assert_eq!(read(&["--open-bus", "random", "--seed", "1"]), random);
+}
+
+#[test]
+fn irq_routing_reorders_sources() {
+    let dir = work_dir();
+    let rom = dir.join("irqroute.rom");
+    assemble("irqroute", &rom, &[]);
+
+    let run = Command::new(EMU)
+        .arg(&rom)
+        .args(["--ser0", "null", "--max-cycles", "100000"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        run.status.code(),
+        Some(2),
+        "emulator failed: {}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+}
+
+#[test]
+fn irq_dispatch_reaches_each_input() {
+    let dir = work_dir();
+    let rom = dir.join("irqdispatch.rom");
+    assemble("irqdispatch", &rom, &[]);
+
+    let run = Command::new(EMU)
+        .arg(&rom)
+        .args(["--ser0", "null", "--max-cycles", "100000"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        run.status.code(),
+        Some(8),
+        "emulator failed: {}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+}
+
+#[test]
+fn device_reset_resets_one_device() {
+    let dir = work_dir();
+    let rom = dir.join("devreset.rom");
+    assemble("devreset", &rom, &[]);
+
+    let run = Command::new(EMU)
+        .arg(&rom)
+        .args(["--ser0", "null", "--max-cycles", "100000"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        run.status.code(),
+        Some(0x0B),
+        "emulator failed: {}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+}
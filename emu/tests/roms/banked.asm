@@ -0,0 +1,23 @@
+; vim: ft=pasm sw=8 ts=8 cc=80 noet
+;
+; One bank of a 2-bank ROM (BANK given with -D). Bank 0 boots and switches
+; to bank 1 through the ROM bank select register, and whichever bank runs
+; the next instruction reports.
+
+		txt
+*		equ $F100
+
+Reset		lda #1
+		sta $F00F
+		ldx #<Msg
+		ldy #>Msg
+		lda #BANK
+		eor #1
+		byt $5C,$FF,$05,$EA	; report
+
+Irq		rti
+
+Msg		byt "switched ROM banks",0
+
+		pad $FFFA-*
+		wrd Irq,Reset,Irq
@@ -0,0 +1,17 @@
+; vim: ft=pasm sw=8 ts=8 cc=80 noet
+;
+; A boot sector for --boot-fd0. It takes a BRK through the IRQ vector in
+; its header, which exits the emulator with $2A.
+
+		txt
+*		equ $0200
+
+		wrd Nmi,Irq
+
+Boot		brk
+		byt 0
+
+Nmi		rti
+
+Irq		lda #$2A
+		sta $F0F0
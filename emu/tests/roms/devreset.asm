@@ -0,0 +1,31 @@
+; vim: ft=pasm sw=8 ts=8 cc=80 noet
+;
+; Lets the timer expire with interrupts disabled, programs SER0, and then
+; resets the timer alone through the Device Reset register. Exits with
+; the IRQ pending bits, the timer control, and the SER0 command ORed
+; together, which is just the SER0 command ($0B) if only the timer reset.
+
+		txt
+*		equ $F100
+
+Reset		sei
+		lda #$0B
+		sta $F012
+		lda #$10
+		sta $F018
+		stz $F019
+		lda #$03		; enable, IRQ enable, periodic
+		sta $F01A
+Wait		lda $F0F9
+		beq Wait
+		lda #$18		; any timer register will do
+		sta $F0F2
+		lda $F0F9
+		ora $F01A
+		ora $F012
+		sta $F0F0
+
+Irq		rti
+
+		pad $FFFA-*
+		wrd Irq,Reset,Irq
@@ -0,0 +1,67 @@
+; vim: ft=pasm sw=8 ts=8 cc=80 noet
+;
+; Prints a banner on SER0, then echoes what it receives in upper case
+; until a `.`, which exits the emulator with status $2A.
+;
+; Branch targets all come before their branches, since a forward branch
+; is sized long in the first pass.
+
+SER0_DATA	equ $F010
+SER0_STATUS	equ $F011
+SER0_CMD	equ $F012
+EXIT		equ $F0F0
+
+		txt
+*		equ $F100
+
+Msg		byt "ready",$0D,$0A,0
+
+; send A once the transmitter is free
+Send		pha
+Send.wait	lda SER0_STATUS
+		and #$10
+		beq Send.wait
+		pla
+		sta SER0_DATA
+		rts
+
+Upper.out	rts
+
+; upper case the letter in A
+Upper		cmp #$61	; `a`
+		bcc Upper.out
+		cmp #$7B	; past `z`
+		bcs Upper.out
+		and #$DF
+		rts
+
+Done		lda SER0_STATUS
+		and #$10
+		beq Done
+		lda #$2A
+		sta EXIT
+Done.halt	bru Done.halt
+
+Echo		lda SER0_STATUS
+		and #$08
+		beq Echo
+		lda SER0_DATA
+		cmp #$2E	; `.`
+		beq Done
+		jsr Upper
+		jsr Send
+		bru Echo
+
+Reset		lda #$0B
+		sta SER0_CMD
+		ldx #0
+Banner		lda Msg,x
+		beq Echo
+		jsr Send
+		inx
+		bru Banner
+
+Irq		rti
+
+		pad $FFFA-*
+		wrd Irq,Reset,Irq
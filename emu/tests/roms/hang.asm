@@ -0,0 +1,18 @@
+; vim: ft=pasm sw=8 ts=8 cc=80 noet
+;
+; Polls SER0 for a byte that never comes, with interrupts disabled, for the
+; watchdog to catch.
+
+		txt
+*		equ $F100
+
+Reset		sei
+Wait		lda $F011
+		and #$08
+		beq Wait
+		stp
+
+Irq		rti
+
+		pad $FFFA-*
+		wrd Irq,Reset,Irq
@@ -0,0 +1,98 @@
+; vim: ft=pasm sw=8 ts=8 cc=80 noet
+;
+; Routes the timer to each encoder input in turn and lets it expire,
+; dispatching with IRQ_DISPATCH from lib/irq.asm. Exits with 8 once every
+; input reached its own handler, or $80 plus the first input that didn't.
+;
+; Branches only go backwards and the handlers use jmp, since pasm sizes
+; forward branches differently in each pass.
+
+		inf "irq.asm"
+
+TIMER_LO	equ $F018
+TIMER_HI	equ $F019
+TIMER_CTRL	equ $F01A
+TIMER_STATUS	equ $F01B
+
+Handled		equ $0000	; the input whose handler ran, $FF for none
+
+; route the timer to input ?1 alone with routing byte ?3 in register ?2
+CHECK		mac
+		ldy #?1
+		ldx #?2
+		lda #?3
+		jsr Fire
+		cpy Handled
+		bne Fail
+		end
+
+		txt
+*		equ $F100
+
+Fail		tya
+		ora #$80
+		sta $F0F0
+
+; write routing byte A to register X, with the other inputs disconnected,
+; and wait out a one-shot timer with interrupts enabled
+Fire		pha
+		lda #$88
+		sta IRQ_ROUTE+0
+		sta IRQ_ROUTE+1
+		sta IRQ_ROUTE+2
+		sta IRQ_ROUTE+3
+		pla
+		sta IRQ_ROUTE,x
+		lda #$FF
+		sta Handled
+		lda #$10
+		sta TIMER_LO
+		stz TIMER_HI
+		lda #$07		; enable, IRQ enable, one-shot
+		sta TIMER_CTRL
+		cli
+.wait		lda Handled
+		cmp #$FF
+		beq .wait
+		sei
+		rts
+
+Reset		sei
+		CHECK 0,0,$87
+		CHECK 1,0,$78
+		CHECK 2,1,$87
+		CHECK 3,1,$78
+		CHECK 4,2,$87
+		CHECK 5,2,$78
+		CHECK 6,3,$87
+		CHECK 7,3,$78
+		lda #8
+		sta $F0F0
+
+Irq		pha
+		phx
+		IRQ_DISPATCH None,In0,In1,In2,In3,In4,In5,In6,In7
+
+In0		lda #0
+		jmp Handle
+In1		lda #1
+		jmp Handle
+In2		lda #2
+		jmp Handle
+In3		lda #3
+		jmp Handle
+In4		lda #4
+		jmp Handle
+In5		lda #5
+		jmp Handle
+In6		lda #6
+		jmp Handle
+In7		lda #7
+Handle		sta Handled
+None		lda TIMER_STATUS	; clear the timer's IRQ
+		plx
+		pla
+		rti
+
+		pad $FFFA-*
+		wrd Irq,Reset,Irq
@@ -0,0 +1,26 @@
+; vim: ft=pasm sw=8 ts=8 cc=80 noet
+;
+; Routes the timer to encoder input 0 (disconnecting input 1), lets it
+; expire, and exits with the latch, which is 2 for input 0 where the
+; timer's own input 7 would latch 16.
+
+		txt
+*		equ $F100
+
+Reset		sei
+		lda #$87
+		sta $F0FB
+		lda #$10
+		sta $F018
+		stz $F019
+		lda #$07		; enable, IRQ enable, one-shot
+		sta $F01A
+Wait		lda $F0F9
+		beq Wait
+		lda $F0FF
+		sta $F0F0
+
+Irq		rti
+
+		pad $FFFA-*
+		wrd Irq,Reset,Irq
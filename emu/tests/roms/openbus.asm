@@ -0,0 +1,14 @@
+; vim: ft=pasm sw=8 ts=8 cc=80 noet
+;
+; Exits with whatever a read of an unmapped IO address returns.
+
+		txt
+*		equ $F100
+
+Reset		lda $F0E0
+		sta $F0F0
+
+Irq		rti
+
+		pad $FFFA-*
+		wrd Irq,Reset,Irq
@@ -0,0 +1,30 @@
+; vim: ft=pasm sw=8 ts=8 cc=80 noet
+;
+; Checks that 2 + 2 is EXPECT (given with -D), and reports the result
+; through the AUG $FF Report trap.
+
+		txt
+*		equ $F100
+
+Pass		byt "2 + 2 is right",0
+Fail		byt "2 + 2 is wrong",0
+
+Bad		ldx #<Fail
+		ldy #>Fail
+		lda #1
+		byt $5C,$FF,$05,$EA	; report
+
+Reset		lda #2
+		clc
+		adc #2
+		cmp #EXPECT
+		bne Bad
+		ldx #<Pass
+		ldy #>Pass
+		lda #0
+		byt $5C,$FF,$05,$EA	; report
+
+Irq		rti
+
+		pad $FFFA-*
+		wrd Irq,Reset,Irq
@@ -0,0 +1,32 @@
+; vim: ft=pasm sw=8 ts=8 cc=80 noet
+;
+; Interrupt controller registers and jump-table dispatch
+;
+; Include with `inf "irq.asm"` (and `pasm -I lib`) before the first use.
+;
+; The latch reads twice one more than the encoder input that fired, or 0
+; when none did, so it indexes a table of 2-byte vectors directly once
+; the table starts with an entry for nothing pending:
+;
+;	Irq	IRQ_DISPATCH None,In0,In1,In2,In3,In4,In5,In6,In7
+;
+; jumps to `In7` for input 7 (latch 16). Reading the latch acknowledges
+; edge-triggered inputs, so handlers needn't.
+;
+; Encoder inputs take the source of the same number until IRQ_ROUTE is
+; written: FDC0 DRQ, FDC1 DRQ, FDC0, FDC1, SER0, SER1, PPU, and TIMER.
+
+IRQ_ENABLE	equ $F0F8
+IRQ_PENDING	equ $F0F9
+IRQ_MODE	equ $F0FA
+IRQ_ROUTE	equ $F0FB	; 4 registers, a nibble per input
+IRQ_LATCH	equ $F0FF
+
+; jump through the vector of the latched input (clobbers X, and defines
+; .vectors under the enclosing label)
+IRQ_DISPATCH	mac
+		ldx IRQ_LATCH
+		jmp (.vectors,x)
+.vectors	wrd ?1
+		wrd ?2,?3,?4,?5,?6,?7,?8,?9
+		end
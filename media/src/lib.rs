@@ -0,0 +1,16 @@
+//! ROM and Floppy Geometry
+//!
+//! The sizes of ROM and disk images, shared by `pasm`, the emulator, and
+//! `xtask` so they can't drift apart.
+
+/// Where the ROM is mapped, up to the top of the address space
+pub const ROM_START: u16 = 0xF100;
+/// Size of a ROM image, or of a bank of a banked one (3.75KiB)
+pub const ROM_SIZE: usize = 0x10000 - (ROM_START as usize);
+
+pub const NUM_TRACKS: usize = 80;
+/// Sectors per track
+pub const NUM_SECTORS: usize = 16;
+pub const SECTOR_SIZE: usize = 256;
+/// Size of a floppy image (640KiB), both sides of every track
+pub const DISK_SIZE: usize = SECTOR_SIZE * NUM_SECTORS * NUM_TRACKS * 2;
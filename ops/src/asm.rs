@@ -0,0 +1,169 @@
+//! Line Assembler
+//!
+//! Assembles a single instruction in the syntax the disassembler prints,
+//! for patching code from the debugger. Expressions, directives, and
+//! macros are left to `pasm`.
+
+use crate::*;
+
+/// Assemble `line` as the instruction at `pc`. `resolve` gives the value of
+/// operands that aren't `$` hex or `%` binary numbers, such as symbols.
+pub fn assemble(
+    pc: u16,
+    line: &str,
+    mut resolve: impl FnMut(&str) -> Option<u16>,
+) -> Result<Vec<u8>, String> {
+    let line = line.trim();
+    let (mnemonic, operand) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let operand = operand
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+    let Some((name, modes)) = OPS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(mnemonic))
+    else {
+        return Err(format!("unknown instruction: `{mnemonic}`"));
+    };
+
+    let mut value = |expr: &str| {
+        let value = if let Some(hex) = expr.strip_prefix('$') {
+            u16::from_str_radix(hex, 16).ok()
+        } else if let Some(bin) = expr.strip_prefix('%') {
+            u16::from_str_radix(bin, 2).ok()
+        } else {
+            resolve(expr)
+        };
+        value.ok_or_else(|| format!("can't resolve `{expr}`"))
+    };
+    let find = |mode: u8| {
+        modes
+            .iter()
+            .find(|(m, _)| *m == mode)
+            .map(|(_, opcode)| *opcode)
+    };
+    let illegal = || format!("illegal addressing mode for {name}: `{operand}`");
+
+    // implied, with the few special cases longer than 1 byte
+    if let [(IMPL, opcode)] = modes {
+        return match *name {
+            "AUG" => Ok(vec![*opcode, 0xEA, 0xEA, 0xEA]),
+            "BRK" => Ok(vec![*opcode, 0xEA]),
+            "RTN" => Ok(vec![*opcode, byte(value(&operand)?)?]),
+            _ if operand.is_empty() => Ok(vec![*opcode]),
+            _ => Err(illegal()),
+        };
+    }
+
+    if operand.is_empty() || operand.eq_ignore_ascii_case("A") {
+        return Ok(vec![find(ACCUM).ok_or_else(illegal)?]);
+    }
+
+    if let Some(imm) = operand.strip_prefix('#') {
+        let opcode = find(IMM).ok_or_else(illegal)?;
+        if *name == "PHW" {
+            let [lo, hi] = value(imm)?.to_le_bytes();
+            return Ok(vec![opcode, lo, hi]);
+        }
+        return Ok(vec![opcode, byte(value(imm)?)?]);
+    }
+
+    // the bit number picks the opcode
+    if matches!(*name, "BBR" | "BBS" | "RMB" | "SMB") {
+        let mut args = operand.split(',');
+        let bit = args
+            .next()
+            .and_then(|bit| bit.parse::<usize>().ok())
+            .filter(|bit| *bit < 8)
+            .ok_or_else(|| "invalid bit".to_string())?;
+        let (mode, opcode) = modes[bit];
+        let bp = byte(value(args.next().ok_or_else(illegal)?)?)?;
+        let mut bytes = vec![opcode, bp];
+        if mode == B_REL {
+            let target = value(args.next().ok_or_else(illegal)?)?;
+            bytes.push(short_branch(pc.wrapping_add(3), target)?);
+        }
+        if args.next().is_some() {
+            return Err(illegal());
+        }
+        return Ok(bytes);
+    }
+
+    // branches are short when they can be
+    if let Some(opcode) = find(WREL) {
+        let target = value(&operand)?;
+        if let Some(opcode) = find(REL) {
+            if let Ok(offset) = short_branch(pc.wrapping_add(2), target) {
+                return Ok(vec![opcode, offset]);
+            }
+        }
+        let [lo, hi] = target.wrapping_sub(pc.wrapping_add(3)).to_le_bytes();
+        return Ok(vec![opcode, lo, hi]);
+    }
+
+    if let Some(inner) = operand.strip_prefix('(') {
+        // (ABS) and (ABS,X) are only JMP and JSR, which lack the base-page modes
+        let (choices, expr): (&[u8], _) = if let Some(expr) = strip_suffix(inner, ",SP),Y") {
+            (&[IND_SP], expr)
+        } else if let Some(expr) = strip_suffix(inner, ",X)") {
+            (&[IND_ABS_X, IND_X], expr)
+        } else if let Some(expr) = strip_suffix(inner, "),Y") {
+            (&[IND_Y], expr)
+        } else if let Some(expr) = strip_suffix(inner, "),Z") {
+            (&[IND_Z], expr)
+        } else if let Some(expr) = strip_suffix(inner, ")") {
+            (&[IND_ABS], expr)
+        } else {
+            return Err(illegal());
+        };
+        let (mode, opcode) = choices
+            .iter()
+            .find_map(|&mode| find(mode).map(|opcode| (mode, opcode)))
+            .ok_or_else(illegal)?;
+        let value = value(expr)?;
+        return if operand_len(mode) == 2 {
+            let [lo, hi] = value.to_le_bytes();
+            Ok(vec![opcode, lo, hi])
+        } else {
+            Ok(vec![opcode, byte(value)?])
+        };
+    }
+
+    let (expr, base_page, absolute) = if let Some(expr) = strip_suffix(&operand, ",X") {
+        (expr, B_X, ABS_X)
+    } else if let Some(expr) = strip_suffix(&operand, ",Y") {
+        (expr, B_Y, ABS_Y)
+    } else {
+        (operand.as_str(), B, ABS)
+    };
+    // a leading `|` forces absolute addressing
+    let (expr, force_abs) = match expr.strip_prefix('|') {
+        Some(expr) => (expr, true),
+        None => (expr, false),
+    };
+    let addr = value(expr)?;
+    if !force_abs && addr <= 0xFF {
+        if let Some(opcode) = find(base_page) {
+            return Ok(vec![opcode, addr as u8]);
+        }
+    }
+    let opcode = find(absolute).ok_or_else(illegal)?;
+    let [lo, hi] = addr.to_le_bytes();
+    Ok(vec![opcode, lo, hi])
+}
+
+fn byte(value: u16) -> Result<u8, String> {
+    u8::try_from(value).map_err(|_| format!("${value:04X} doesn't fit in a byte"))
+}
+
+/// The offset of a branch to `target` from `next`, the address after it
+fn short_branch(next: u16, target: u16) -> Result<u8, String> {
+    i8::try_from(target.wrapping_sub(next) as i16)
+        .map(|offset| offset as u8)
+        .map_err(|_| format!("branch to ${target:04X} is out of range"))
+}
+
+fn strip_suffix<'a>(s: &'a str, suffix: &str) -> Option<&'a str> {
+    let split = s.len().checked_sub(suffix.len())?;
+    (s.is_char_boundary(split) && s[split..].eq_ignore_ascii_case(suffix)).then(|| &s[..split])
+}
@@ -0,0 +1,124 @@
+//! Disassembler
+//!
+//! Decodes machine code into structured instructions. Operands are
+//! formatted in the assembler's syntax, so the debugger and `dasm` print
+//! the same thing `pasm` accepts.
+
+use crate::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub addr: u16,
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub mode: u8,
+    /// Bit number of BBR, BBS, RMB, and SMB
+    pub bit: Option<u8>,
+    /// Operand bytes, little-endian
+    pub operand: u32,
+    pub len: u16,
+    /// Nominal cycle count
+    pub cycles: u8,
+}
+
+impl Instruction {
+    /// Decode the instruction at `addr`. Returns `None` for opcodes missing
+    /// from the tables.
+    pub fn decode(addr: u16, mut read: impl FnMut(u16) -> u8) -> Option<Self> {
+        let opcode = read(addr);
+        let Decode {
+            mnemonic,
+            mode,
+            bit,
+            len,
+            cycles,
+        } = DECODE[opcode as usize]?;
+        let operand = (1..len).rev().fold(0, |operand, i| {
+            (operand << 8) | (read(addr.wrapping_add(i)) as u32)
+        });
+        Some(Self {
+            addr,
+            opcode,
+            mnemonic,
+            mode,
+            bit,
+            operand,
+            len,
+            cycles,
+        })
+    }
+
+    /// The address following this instruction
+    pub fn next(&self) -> u16 {
+        self.addr.wrapping_add(self.len)
+    }
+
+    /// The address the operand refers to, with branches resolved. Immediates
+    /// and implied operands have none.
+    pub fn target(&self) -> Option<u16> {
+        match self.mode {
+            IMM | ACCUM | IMPL => None,
+            REL => Some(
+                self.next()
+                    .wrapping_add_signed(self.operand as u8 as i8 as i16),
+            ),
+            WREL => Some(self.next().wrapping_add_signed(self.operand as u16 as i16)),
+            B_REL => Some(
+                self.next()
+                    .wrapping_add_signed((self.operand >> 8) as u8 as i8 as i16),
+            ),
+            _ => Some(self.operand as u16),
+        }
+    }
+
+    /// Format the operand in assembler syntax. `name` may replace an address
+    /// (base page or absolute) with a symbol.
+    pub fn operand_string(&self, mut name: impl FnMut(u16) -> Option<String>) -> String {
+        let byte = |value: u8| format!("${value:02X}");
+        let mut named = |addr: u16, base_page: bool| {
+            name(addr).unwrap_or_else(|| {
+                if base_page {
+                    format!("${addr:02X}")
+                } else {
+                    format!("${addr:04X}")
+                }
+            })
+        };
+        // a leading `|` keeps the assembler from picking a base-page mode
+        let abs = |addr: u16| if addr <= 0xFF { "|" } else { "" };
+        let operand = self.operand;
+        let bp = operand as u8 as u16;
+        let word = operand as u16;
+        match self.mode {
+            IMM if self.mnemonic == "PHW" => format!("#${:04X}", operand),
+            IMM => format!("#${:02X}", operand),
+            ACCUM => "A".to_string(),
+            IMPL if self.mnemonic == "RTN" => byte(operand as u8),
+            IMPL => String::new(),
+            B if self.bit.is_some() => format!("{},{}", self.bit.unwrap(), named(bp, true)),
+            B => named(bp, true),
+            B_X => format!("{},X", named(bp, true)),
+            B_Y => format!("{},Y", named(bp, true)),
+            IND_X => format!("({},X)", named(bp, true)),
+            IND_Y => format!("({}),Y", named(bp, true)),
+            IND_Z => format!("({}),Z", named(bp, true)),
+            // the offset is relative to the stack, not an address
+            IND_SP => format!("({},SP),Y", byte(operand as u8)),
+            ABS => format!("{}{}", abs(word), named(word, false)),
+            ABS_X => format!("{}{},X", abs(word), named(word, false)),
+            ABS_Y => format!("{}{},Y", abs(word), named(word, false)),
+            IND_ABS => format!("({})", named(word, false)),
+            IND_ABS_X => format!("({},X)", named(word, false)),
+            REL | WREL => named(self.target().unwrap(), false),
+            B_REL => {
+                let bp = named(bp, true);
+                format!(
+                    "{},{bp},{}",
+                    self.bit.unwrap(),
+                    named(self.target().unwrap(), false)
+                )
+            }
+            _ => unreachable!(),
+        }
+    }
+}
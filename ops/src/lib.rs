@@ -0,0 +1,237 @@
+//! 65CE02 Opcode Tables
+//!
+//! The mnemonics, addressing modes, and opcodes shared by the assembler
+//! and the emulator, so the two can't drift apart.
+
+pub mod asm;
+pub mod dasm;
+
+pub const IMM: u8 = 0;
+pub const ABS: u8 = 1;
+pub const B: u8 = 2;
+pub const ACCUM: u8 = 3;
+pub const IMPL: u8 = 4;
+pub const IND_X: u8 = 5; // (B,X)
+pub const IND_Y: u8 = 6; // (B),Y
+pub const IND_Z: u8 = 7; // (B),Z
+pub const IND_SP: u8 = 8; // (d,SP),Y
+pub const B_X: u8 = 9; // B,X
+pub const B_Y: u8 = 10; // B,Y
+pub const ABS_X: u8 = 11;
+pub const ABS_Y: u8 = 12;
+pub const REL: u8 = 13;
+pub const WREL: u8 = 14;
+pub const IND_ABS: u8 = 15; // (ABS)
+pub const B_REL: u8 = 16;
+pub const IND_ABS_X: u8 = 17; // (ABS,X)
+
+pub type Op = (&'static str, &'static [(u8, u8)]);
+
+#[rustfmt::skip]
+pub const OPS: &[Op] = &[
+    ("AUG", &[(IMPL, 0x5C)]), // special
+    ("BRK", &[(IMPL, 0x00)]), // special
+    ("CLC", &[(IMPL, 0x18)]),
+    ("CLD", &[(IMPL, 0xD8)]),
+    ("CLE", &[(IMPL, 0x02)]),
+    ("CLI", &[(IMPL, 0x58)]),
+    ("CLV", &[(IMPL, 0xB8)]),
+    ("DEX", &[(IMPL, 0xCA)]),
+    ("DEY", &[(IMPL, 0x88)]),
+    ("DEZ", &[(IMPL, 0x3B)]),
+    ("INX", &[(IMPL, 0xE8)]),
+    ("INY", &[(IMPL, 0xC8)]),
+    ("INZ", &[(IMPL, 0x1B)]),
+    ("NOP", &[(IMPL, 0xEA)]),
+    ("PHA", &[(IMPL, 0x48)]),
+    ("PHP", &[(IMPL, 0x08)]),
+    ("PHX", &[(IMPL, 0xDA)]),
+    ("PHY", &[(IMPL, 0x5A)]),
+    ("PHZ", &[(IMPL, 0xDB)]),
+    ("PLA", &[(IMPL, 0x68)]),
+    ("PLP", &[(IMPL, 0x28)]),
+    ("PLX", &[(IMPL, 0xFA)]),
+    ("PLY", &[(IMPL, 0x7A)]),
+    ("PLZ", &[(IMPL, 0xFB)]),
+    ("RTI", &[(IMPL, 0x40)]),
+    ("RTN", &[(IMPL, 0x62)]), // special
+    ("RTS", &[(IMPL, 0x60)]),
+    ("SEC", &[(IMPL, 0x38)]),
+    ("SED", &[(IMPL, 0xF8)]),
+    ("SEE", &[(IMPL, 0x03)]),
+    ("SEI", &[(IMPL, 0x78)]),
+    ("TAB", &[(IMPL, 0x5B)]),
+    ("TAX", &[(IMPL, 0xAA)]),
+    ("TAY", &[(IMPL, 0xA8)]),
+    ("TAZ", &[(IMPL, 0x4B)]),
+    ("TBA", &[(IMPL, 0x7B)]),
+    ("TSX", &[(IMPL, 0xBA)]),
+    ("TSY", &[(IMPL, 0x0B)]),
+    ("TXA", &[(IMPL, 0x8A)]),
+    ("TXS", &[(IMPL, 0x9A)]),
+    ("TYA", &[(IMPL, 0x98)]),
+    ("TYS", &[(IMPL, 0x2B)]),
+    ("TZA", &[(IMPL, 0x6B)]),
+
+    ("ADC", &[(IMM, 0x69), (ABS, 0x6D), (B, 0x65), (IND_X, 0x61), (IND_Y, 0x71), (IND_Z, 0x72), (B_X, 0x75), (ABS_X, 0x7D), (ABS_Y, 0x79)]),
+    ("AND", &[(IMM, 0x29), (ABS, 0x2D), (B, 0x25), (IND_X, 0x21), (IND_Y, 0x31), (IND_Z, 0x32), (B_X, 0x35), (ABS_X, 0x3D), (ABS_Y, 0x39)]),
+    ("ASL", &[(ABS, 0x0E), (B, 0x06), (ACCUM, 0x0A), (B_X, 0x16), (ABS_X, 0x1E)]),
+    ("ASR", &[(B, 0x44), (ACCUM, 0x43), (B_X, 0x54)]),
+    ("ASW", &[(ABS, 0xCB)]),
+    ("BIT", &[(IMM, 0x89), (ABS, 0x2C), (B, 0x24), (B_X, 0x34), (ABS_X, 0x3C)]),
+    ("BBR", &[(B_REL, 0x0F), (B_REL, 0x1F), (B_REL, 0x2F), (B_REL, 0x3F), (B_REL, 0x4F), (B_REL, 0x5F), (B_REL, 0x6F), (B_REL, 0x7F)]), // special
+    ("BBS", &[(B_REL, 0x8F), (B_REL, 0x9F), (B_REL, 0xAF), (B_REL, 0xBF), (B_REL, 0xCF), (B_REL, 0xDF), (B_REL, 0xEF), (B_REL, 0xFF)]), // special
+    ("BCC", &[(REL, 0x90), (WREL, 0x93)]),
+    ("BCS", &[(REL, 0xB0), (WREL, 0xB3)]),
+    ("BEQ", &[(REL, 0xF0), (WREL, 0xF3)]),
+    ("BMI", &[(REL, 0x30), (WREL, 0x33)]),
+    ("BNE", &[(REL, 0xD0), (WREL, 0xD3)]),
+    ("BPL", &[(REL, 0x10), (WREL, 0x13)]),
+    ("BRU", &[(REL, 0x80), (WREL, 0x83)]),
+    ("BSR", &[(WREL, 0x63)]),
+    ("BVC", &[(REL, 0x50), (WREL, 0x53)]),
+    ("BVS", &[(REL, 0x70), (WREL, 0x73)]),
+    ("CMP", &[(IMM, 0xC9), (ABS, 0xCD), (B, 0xC5), (IND_X, 0xC1), (IND_Y, 0xD1), (IND_Z, 0xD2), (B_X, 0xD5), (ABS_X, 0xDD), (ABS_Y, 0xD9)]),
+    ("CPX", &[(IMM, 0xE0), (ABS, 0xEC), (B, 0xE4)]),
+    ("CPY", &[(IMM, 0xC0), (ABS, 0xCC), (B, 0xC4)]),
+    ("CPZ", &[(IMM, 0xC2), (ABS, 0xDC), (B, 0xD4)]),
+    ("DEC", &[(ABS, 0xCE), (B, 0xC6), (ACCUM, 0x3A), (B_X, 0xD6), (ABS_X, 0xDE)]),
+    ("EOR", &[(IMM, 0x49), (ABS, 0x4D), (B, 0x45), (IND_X, 0x41), (IND_Y, 0x51), (IND_Z, 0x52), (B_X, 0x55), (ABS_X, 0x5D), (ABS_Y, 0x59)]),
+    ("INC", &[(ABS, 0xEE), (B, 0xE6), (ACCUM, 0x1A), (B_X, 0xF6), (ABS_X, 0xFE)]),
+    ("DEW", &[(B, 0xC3)]),
+    ("INW", &[(B, 0xE3)]),
+    ("JMP", &[(ABS, 0x4C), (IND_ABS, 0x6C), (IND_ABS_X, 0x7C)]),
+    ("JSR", &[(ABS, 0x20), (IND_ABS, 0x22), (IND_ABS_X, 0x23)]),
+    ("LDA", &[(IMM, 0xA9), (ABS, 0xAD), (B, 0xA5), (IND_X, 0xA1), (IND_Y, 0xB1), (IND_Z, 0xB2), (IND_SP, 0xE2), (B_X, 0xB5), (ABS_X, 0xBD), (ABS_Y, 0xB9)]),
+    ("LDX", &[(IMM, 0xA2), (ABS, 0xAE), (B, 0xA6), (B_Y, 0xB6), (ABS_Y, 0xBE)]),
+    ("LDY", &[(IMM, 0xA0), (ABS, 0xAC), (B, 0xA4), (B_X, 0xB4), (ABS_X, 0xBC)]),
+    ("LDZ", &[(IMM, 0xA3), (ABS, 0xAB), (ABS_X, 0xBB)]),
+    ("LSR", &[(ABS, 0x4E), (B, 0x46), (ACCUM, 0x4A), (B_X, 0x56), (ABS_X, 0x5E)]),
+    ("NEG", &[(ACCUM, 0x42)]),
+    ("ORA", &[(IMM, 0x09), (ABS, 0x0D), (B, 0x05), (IND_X, 0x01), (IND_Y, 0x11), (IND_Z, 0x12), (B_X, 0x15), (ABS_X, 0x1D), (ABS_Y, 0x19)]),
+    ("PHW", &[(IMM, 0xF4), (ABS, 0xFC)]), // special
+    ("RMB", &[(B, 0x07), (B, 0x17), (B, 0x27), (B, 0x37), (B, 0x47), (B, 0x57), (B, 0x67), (B, 0x77)]), // special
+    ("ROL", &[(ABS, 0x2E), (B, 0x26), (ACCUM, 0x2A), (B_X, 0x36), (ABS_X, 0x3E)]),
+    ("ROR", &[(ABS, 0x6E), (B, 0x66), (ACCUM, 0x6A), (B_X, 0x76), (ABS_X, 0x7E)]),
+    ("ROW", &[(ABS, 0xEB)]),
+    ("SBC", &[(IMM, 0xE9), (ABS, 0xED), (B, 0xE5), (IND_X, 0xE1), (IND_Y, 0xF1), (IND_Z, 0xF2), (B_X, 0xF5), (ABS_X, 0xFD), (ABS_Y, 0xF9)]),
+    ("SMB", &[(B, 0x87), (B, 0x97), (B, 0xA7), (B, 0xB7), (B, 0xC7), (B, 0xD7), (B, 0xE7), (B, 0xF7)]), // special
+    ("STA", &[(ABS, 0x8D), (B, 0x85), (IND_X, 0x81), (IND_Y, 0x91), (IND_Z, 0x92), (IND_SP, 0x82), (B_X, 0x95), (ABS_X, 0x9D), (ABS_Y, 0x99)]),
+    ("STX", &[(ABS, 0x8E), (B, 0x86), (B_Y, 0x96), (ABS_Y, 0x9B)]),
+    ("STY", &[(ABS, 0x8C), (B, 0x84), (B_X, 0x94), (ABS_X, 0x8B)]),
+    ("STZ", &[(ABS, 0x9C), (B, 0x64), (B_X, 0x74), (ABS_X, 0x9E)]),
+    ("TRB", &[(ABS, 0x1C), (B, 0x14)]), // xfer reset bits, M[addr] &= ~A
+    ("TSB", &[(ABS, 0x0C), (B, 0x04)]), // xfer set bits, M[addr] |= A
+];
+
+/// Nominal cycle counts per opcode, not counting the extra cycles for taken
+/// branches or page crossings
+#[rustfmt::skip]
+pub const CYCLES: [u8; 256] = [
+    7, 5, 1, 1, 4, 3, 4, 4, 3, 2, 1, 1, 5, 4, 5, 4, // 0x
+    2, 5, 5, 3, 4, 3, 4, 4, 1, 4, 1, 1, 5, 4, 5, 4, // 1x
+    5, 5, 7, 7, 3, 3, 4, 4, 3, 2, 1, 1, 4, 4, 5, 4, // 2x
+    2, 5, 5, 3, 3, 3, 4, 4, 1, 4, 1, 1, 4, 4, 5, 4, // 3x
+    5, 5, 1, 1, 4, 3, 4, 4, 3, 2, 1, 1, 3, 4, 5, 4, // 4x
+    2, 5, 5, 3, 4, 3, 4, 4, 1, 4, 3, 1, 1, 4, 5, 4, // 5x
+    4, 5, 7, 5, 3, 3, 4, 4, 3, 2, 1, 1, 5, 4, 5, 4, // 6x
+    2, 5, 5, 3, 4, 3, 4, 4, 1, 4, 3, 1, 5, 4, 5, 4, // 7x
+    2, 5, 6, 3, 3, 3, 3, 4, 1, 2, 1, 4, 4, 4, 4, 4, // 8x
+    2, 5, 5, 3, 4, 3, 4, 4, 1, 4, 1, 4, 4, 4, 4, 4, // 9x
+    2, 5, 2, 2, 3, 3, 3, 4, 1, 2, 1, 4, 4, 4, 4, 4, // Ax
+    2, 5, 5, 3, 3, 3, 3, 4, 1, 4, 1, 4, 4, 4, 4, 4, // Bx
+    2, 5, 2, 5, 3, 3, 4, 4, 1, 2, 1, 6, 4, 4, 5, 4, // Cx
+    2, 5, 5, 3, 3, 3, 4, 4, 1, 4, 3, 3, 4, 4, 5, 4, // Dx
+    2, 5, 6, 5, 3, 3, 4, 4, 1, 2, 1, 6, 4, 4, 5, 4, // Ex
+    2, 5, 5, 3, 5, 3, 4, 4, 1, 4, 3, 3, 7, 4, 5, 4, // Fx
+];
+
+/// Size in bytes of the operand of an addressing mode
+pub const fn operand_len(mode: u8) -> u16 {
+    match mode {
+        IMPL | ACCUM => 0,
+        ABS | ABS_X | ABS_Y | WREL | IND_ABS | IND_ABS_X | B_REL => 2,
+        _ => 1,
+    }
+}
+
+/// An opcode's entry in [`DECODE`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decode {
+    pub mnemonic: &'static str,
+    pub mode: u8,
+    /// Bit number of BBR, BBS, RMB, and SMB
+    pub bit: Option<u8>,
+    pub len: u16,
+    pub cycles: u8,
+}
+
+/// Every opcode's decoding, built from [`OPS`] and [`CYCLES`] at compile time
+pub const DECODE: [Option<Decode>; 256] = decode_table();
+
+const fn decode_table() -> [Option<Decode>; 256] {
+    let mut table = [None; 256];
+    let mut i = 0;
+    while i < OPS.len() {
+        let (mnemonic, modes) = OPS[i];
+        let bits = str_eq(mnemonic, "BBR")
+            || str_eq(mnemonic, "BBS")
+            || str_eq(mnemonic, "RMB")
+            || str_eq(mnemonic, "SMB");
+        let mut j = 0;
+        while j < modes.len() {
+            let (mode, opcode) = modes[j];
+            assert!(table[opcode as usize].is_none(), "duplicate opcode");
+            // the special cases are longer than their modes say
+            let len = if str_eq(mnemonic, "AUG") {
+                4
+            } else if str_eq(mnemonic, "BRK") || str_eq(mnemonic, "RTN") {
+                2
+            } else if str_eq(mnemonic, "PHW") {
+                3
+            } else {
+                1 + operand_len(mode)
+            };
+            table[opcode as usize] = Some(Decode {
+                mnemonic,
+                mode,
+                bit: if bits { Some(j as u8) } else { None },
+                len,
+                cycles: CYCLES[opcode as usize],
+            });
+            j += 1;
+        }
+        i += 1;
+    }
+    table
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Length in bytes of the instruction starting with this opcode
+pub const fn op_len(byte: u8) -> u16 {
+    match DECODE[byte as usize] {
+        Some(decode) => decode.len,
+        None => 1,
+    }
+}
+
+pub fn find_op(byte: u8) -> Option<(&'static str, u8)> {
+    DECODE[byte as usize].map(|decode| (decode.mnemonic, decode.mode))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,145 @@
+use super::*;
+
+#[test]
+fn opcodes_are_unique() {
+    let mut seen = [None; 256];
+    for (op, modes) in OPS {
+        for (_, opcode) in *modes {
+            if let Some(other) = seen[*opcode as usize] {
+                panic!("{opcode:02X} is both {other} and {op}");
+            }
+            seen[*opcode as usize] = Some(op);
+        }
+    }
+}
+
+#[test]
+fn op_len_matches_modes() {
+    assert_eq!(op_len(0xEA), 1); // NOP
+    assert_eq!(op_len(0xA9), 2); // LDA #
+    assert_eq!(op_len(0xAD), 3); // LDA ABS
+    assert_eq!(op_len(0x0F), 3); // BBR0 B,REL
+    assert_eq!(op_len(0x00), 2); // BRK
+    assert_eq!(op_len(0x5C), 4); // AUG
+    assert_eq!(op_len(0xF4), 3); // PHW #
+    assert_eq!(op_len(0xFC), 3); // PHW ABS
+}
+
+#[test]
+fn compare_and_word_ops() {
+    assert_eq!(find_op(0xE0), Some(("CPX", IMM)));
+    assert_eq!(find_op(0xCC), Some(("CPY", ABS)));
+    assert_eq!(find_op(0xD4), Some(("CPZ", B)));
+    assert_eq!(find_op(0xC3), Some(("DEW", B)));
+    assert_eq!(op_len(0xC3), 2);
+}
+
+fn decode(bytes: &[u8]) -> dasm::Instruction {
+    dasm::Instruction::decode(0xF100, |addr| bytes[(addr - 0xF100) as usize]).unwrap()
+}
+
+#[test]
+fn decode_operands() {
+    let name = |_| None;
+    assert_eq!(decode(&[0xA9, 0x12]).operand_string(name), "#$12");
+    assert_eq!(decode(&[0xF4, 0x12, 0x00]).operand_string(name), "#$0012");
+    assert_eq!(decode(&[0xAD, 0x34, 0x12]).operand_string(name), "$1234");
+    assert_eq!(decode(&[0xAD, 0x12, 0x00]).operand_string(name), "|$0012");
+    assert_eq!(decode(&[0xE2, 0x12]).operand_string(name), "($12,SP),Y");
+    assert_eq!(decode(&[0x74, 0x12]).operand_string(name), "$12,X");
+    assert_eq!(decode(&[0x96, 0x12]).operand_string(name), "$12,Y");
+    assert_eq!(
+        decode(&[0x7C, 0x34, 0x12]).operand_string(name),
+        "($1234,X)"
+    );
+    assert_eq!(
+        decode(&[0x3F, 0x12, 0xFD]).operand_string(name),
+        "3,$12,$F100"
+    );
+    assert_eq!(decode(&[0xD7, 0x12]).operand_string(name), "5,$12");
+    assert_eq!(decode(&[0xEA]).operand_string(name), "");
+}
+
+#[test]
+fn decode_branches() {
+    let bne = decode(&[0xD0, 0xFE]);
+    assert_eq!((bne.mnemonic, bne.len), ("BNE", 2));
+    assert_eq!(bne.target(), Some(0xF100));
+    let bsr = decode(&[0x63, 0x00, 0x01]);
+    assert_eq!(bsr.target(), Some(0xF203));
+    assert_eq!(
+        bsr.operand_string(|addr| (addr == 0xF203).then(|| "Foo".to_string())),
+        "Foo"
+    );
+}
+
+#[test]
+fn decode_every_opcode() {
+    for opcode in 0..=0xFF {
+        assert!(
+            dasm::Instruction::decode(0, |_| opcode).is_some(),
+            "{opcode:02X}"
+        );
+    }
+}
+
+#[test]
+fn decode_table_matches_ops() {
+    for (op, modes) in OPS {
+        for (mode, opcode) in *modes {
+            let decode = DECODE[*opcode as usize].unwrap();
+            assert_eq!((decode.mnemonic, decode.mode), (*op, *mode));
+            assert_eq!(decode.cycles, CYCLES[*opcode as usize]);
+        }
+    }
+    assert_eq!(DECODE.iter().flatten().count(), 256);
+    assert_eq!(DECODE[0x3F].unwrap().bit, Some(3)); // BBR3
+    assert_eq!(find_op(0x4B), Some(("TAZ", IMPL)));
+}
+
+#[test]
+fn decode_names_base_page() {
+    let name = |addr| (addr == 0x12).then(|| "Ptr".to_string());
+    assert_eq!(decode(&[0xB1, 0x12]).operand_string(name), "(Ptr),Y");
+    assert_eq!(decode(&[0x64, 0x12]).operand_string(name), "Ptr");
+    assert_eq!(decode(&[0xE2, 0x12]).operand_string(name), "($12,SP),Y");
+    assert_eq!(decode(&[0xA9, 0x12]).operand_string(name), "#$12");
+}
+
+#[test]
+fn assemble_round_trips_every_opcode() {
+    for opcode in (0..=0xFF).filter(|&opcode| DECODE[opcode as usize].is_some()) {
+        let bytes = match DECODE[opcode as usize].unwrap().mnemonic {
+            "AUG" | "BRK" => [opcode, 0xEA, 0xEA, 0xEA],
+            _ => [opcode, 0x34, 0x12, 0x56],
+        };
+        let inst = decode(&bytes);
+        let line = format!("{} {}", inst.mnemonic, inst.operand_string(|_| None));
+        let assembled = asm::assemble(0xF100, &line, |_| None).unwrap();
+        assert_eq!(assembled, &bytes[..inst.len as usize], "{line}");
+    }
+}
+
+#[test]
+fn assemble_picks_modes() {
+    let symbols = |name: &str| match name {
+        "Ptr" => Some(0x12),
+        "Loop" => Some(0xF100),
+        _ => None,
+    };
+    let assemble = |line| asm::assemble(0xF100, line, symbols);
+    assert_eq!(assemble("lda ( Ptr ),y"), Ok(vec![0xB1, 0x12]));
+    assert_eq!(assemble("STA Ptr"), Ok(vec![0x85, 0x12]));
+    assert_eq!(assemble("STA |Ptr"), Ok(vec![0x8D, 0x12, 0x00]));
+    assert_eq!(assemble("ASL"), Ok(vec![0x0A]));
+    assert_eq!(assemble("BNE Loop"), Ok(vec![0xD0, 0xFE]));
+    assert_eq!(assemble("BNE $F200"), Ok(vec![0xD3, 0xFD, 0x00]));
+    assert_eq!(assemble("BBR 3,Ptr,Loop"), Ok(vec![0x3F, 0x12, 0xFD]));
+    assert_eq!(assemble("LDX %101,Y"), Ok(vec![0xB6, 0x05]));
+    assert_eq!(assemble("PHW #Ptr"), Ok(vec![0xF4, 0x12, 0x00]));
+    assert!(assemble("LDA #$1234").is_err());
+    assert!(assemble("LDA Nowhere").is_err());
+    assert!(assemble("JMP ($12),Y").is_err());
+    assert!(assemble("FOO").is_err());
+    assert!(assemble("BBS 8,Ptr,Loop").is_err());
+}
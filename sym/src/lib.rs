@@ -0,0 +1,178 @@
+//! Symbol Files
+//!
+//! The format `pasm` writes symbols in and the emulator and disassembler
+//! read them from, so the two ends can't drift apart. A file starts with
+//! a header naming its version, and then has a line per symbol:
+//!
+//! ```text
+//! ; possum2-sym 1
+//! Reset:F100 section=txt
+//! Buffer:0200 section=bss
+//! Handler:1000 bank=3 source=os.asm:42
+//! ```
+//!
+//! * the name and hex value are the only required parts
+//! * `bank` is the RAM bank (hex) the address is in
+//! * `section` is `txt` or `bss`, where a label was defined
+//! * `source` is the file and line that defined it
+//!
+//! Unknown attributes are skipped so newer files stay readable, but a
+//! newer version is refused. Files without a header are the original
+//! `name:HEX` lines, which are still valid. Blank lines and `;` comments
+//! are skipped.
+
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::Path,
+    str::FromStr,
+};
+
+/// The version written in the header
+pub const VERSION: u32 = 1;
+
+const HEADER: &str = "; possum2-sym ";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Section {
+    Txt,
+    Bss,
+}
+
+impl FromStr for Section {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "txt" => Ok(Section::Txt),
+            "bss" => Ok(Section::Bss),
+            _ => Err(format!("expected `txt` or `bss`: `{s}`")),
+        }
+    }
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Section::Txt => "txt",
+            Section::Bss => "bss",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub value: u16,
+    pub bank: Option<u8>,
+    pub section: Option<Section>,
+    /// File and line the symbol was defined on
+    pub source: Option<(String, u32)>,
+}
+
+impl Symbol {
+    pub fn new(name: impl Into<String>, value: u16) -> Self {
+        Self {
+            name: name.into(),
+            value,
+            bank: None,
+            section: None,
+            source: None,
+        }
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{:04X}", self.name, self.value)?;
+        if let Some(bank) = self.bank {
+            write!(f, " bank={bank:X}")?;
+        }
+        if let Some(section) = self.section {
+            write!(f, " section={section}")?;
+        }
+        if let Some((file, line)) = &self.source {
+            write!(f, " source={file}:{line}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Symbol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split_whitespace();
+        let (name, value) = fields
+            .next()
+            .and_then(|field| field.split_once(':'))
+            .ok_or_else(|| "malformed entry".to_string())?;
+        let value = u16::from_str_radix(value, 16).map_err(|e| format!("`{value}`: {e}"))?;
+        let mut symbol = Symbol::new(name, value);
+        for field in fields {
+            let Some((key, value)) = field.split_once('=') else {
+                return Err(format!("malformed attribute `{field}`"));
+            };
+            match key {
+                "bank" => {
+                    let bank = u8::from_str_radix(value, 16).map_err(|e| format!("bank: {e}"))?;
+                    symbol.bank = Some(bank);
+                }
+                "section" => symbol.section = Some(value.parse()?),
+                "source" => {
+                    let (file, line) = value
+                        .rsplit_once(':')
+                        .and_then(|(file, line)| Some((file, line.parse().ok()?)))
+                        .ok_or_else(|| format!("expected FILE:LINE: `{value}`"))?;
+                    symbol.source = Some((file.to_string(), line));
+                }
+                _ => {}
+            }
+        }
+        Ok(symbol)
+    }
+}
+
+/// Parse the text of a symbol file. Errors name the line they're on.
+pub fn parse(text: &str) -> Result<Vec<Symbol>, String> {
+    let mut symbols = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        if let Some(version) = line.strip_prefix(HEADER) {
+            let version = version
+                .trim()
+                .parse::<u32>()
+                .map_err(|e| format!("{line_no}: version: {e}"))?;
+            if version > VERSION {
+                return Err(format!(
+                    "{line_no}: version {version} is newer than this reads ({VERSION})"
+                ));
+            }
+            continue;
+        }
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        symbols.push(line.parse().map_err(|e| format!("{line_no}: {e}"))?);
+    }
+    Ok(symbols)
+}
+
+/// Read and parse a symbol file
+pub fn read(path: &Path) -> Result<Vec<Symbol>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    parse(&text).map_err(|e| format!("{}:{e}", path.display()))
+}
+
+/// Write a symbol file, header first
+pub fn write(out: &mut impl Write, symbols: &[Symbol]) -> io::Result<()> {
+    writeln!(out, "{HEADER}{VERSION}")?;
+    for symbol in symbols {
+        writeln!(out, "{symbol}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,47 @@
+use super::*;
+
+#[test]
+fn round_trips() {
+    let mut handler = Symbol::new("Handler", 0x1000);
+    handler.bank = Some(0xA);
+    handler.section = Some(Section::Txt);
+    handler.source = Some(("os.asm".to_string(), 42));
+    let symbols = vec![Symbol::new("SER0_DATA", 0xF010), handler];
+
+    let mut out = Vec::new();
+    write(&mut out, &symbols).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    assert_eq!(
+        text,
+        "; possum2-sym 1\nSER0_DATA:F010\nHandler:1000 bank=A section=txt source=os.asm:42\n"
+    );
+    assert_eq!(parse(&text).unwrap(), symbols);
+}
+
+#[test]
+fn reads_headerless_files() {
+    let symbols = parse("Reset:F100\nloop.inner:F10A\n").unwrap();
+    assert_eq!(
+        symbols,
+        [
+            Symbol::new("Reset", 0xF100),
+            Symbol::new("loop.inner", 0xF10A)
+        ]
+    );
+}
+
+#[test]
+fn skips_unknown_attributes_but_not_versions() {
+    let symbols = parse("; possum2-sym 1\n\n; a comment\nReset:F100 size=3\n").unwrap();
+    assert_eq!(symbols, [Symbol::new("Reset", 0xF100)]);
+
+    assert_eq!(
+        parse("; possum2-sym 2\nReset:F100\n"),
+        Err("1: version 2 is newer than this reads (1)".to_string())
+    );
+    assert_eq!(
+        parse("Reset:F100\nReset\n"),
+        Err("2: malformed entry".to_string())
+    );
+    assert!(parse("Reset:F100 section=data\n").is_err());
+}
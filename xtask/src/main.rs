@@ -0,0 +1,249 @@
+//! Workspace Tasks
+//!
+//! `cargo xtask` collapses assembling, imaging, and booting into one step:
+//! * `build` assembles the ROM and any programs bound for the FD0 image,
+//!   creating a blank image if it doesn't exist yet
+//! * `run` does the same and then boots the emulator on the results, with
+//!   the SYM file of everything it assembled
+//!
+//! Sources ending in `.asm` or `.s` are assembled with the workspace's
+//! `pasm` into `target/xtask`, and anything else is used as it is.
+//! Arguments after `--` go to the emulator:
+//!
+//! ```text
+//! cargo xtask run --rom k/k.asm --fd0 build/os.img --fd0-put os.asm@0 -- --debug
+//! ```
+
+use std::{
+    env,
+    error::Error,
+    ffi::OsString,
+    fs::{self, File},
+    io::{Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+};
+
+use clap::{Parser, Subcommand};
+
+const DISK_SIZE: u64 = possum2_media::DISK_SIZE as u64;
+const SECTOR_SIZE: u64 = possum2_media::SECTOR_SIZE as u64;
+
+#[derive(Parser)]
+#[command(about = "Build and run Possum2 software from the workspace")]
+struct Args {
+    #[command(subcommand)]
+    task: Task,
+}
+
+#[derive(Subcommand)]
+enum Task {
+    /// Assemble the ROM and update the FD0 image
+    Build(Build),
+
+    /// Build, then boot the emulator
+    Run {
+        #[command(flatten)]
+        build: Build,
+
+        /// Arguments passed on to the emulator
+        #[arg(last = true)]
+        emu_args: Vec<OsString>,
+    },
+}
+
+#[derive(clap::Args)]
+struct Build {
+    /// ROM source or binary (default: the machine config's)
+    #[arg(long)]
+    rom: Option<PathBuf>,
+
+    /// FD0 image file, created blank if missing
+    #[arg(long)]
+    fd0: Option<PathBuf>,
+
+    /// Write a program into the FD0 image, starting at a 256-byte sector
+    /// counted from the start of the image (repeatable)
+    #[arg(long, value_name = "FILE@SECTOR", value_parser = parse_put, requires = "fd0")]
+    fd0_put: Vec<(PathBuf, u64)>,
+
+    /// Use release builds of the tools
+    #[arg(long)]
+    release: bool,
+}
+
+/// What a build produced
+struct Outputs {
+    rom: Option<PathBuf>,
+    syms: Vec<PathBuf>,
+}
+
+fn parse_put(s: &str) -> Result<(PathBuf, u64), String> {
+    let (path, sector) = s
+        .rsplit_once('@')
+        .ok_or_else(|| format!("expected FILE@SECTOR: `{s}`"))?;
+    let sector = sector
+        .parse()
+        .map_err(|e| format!("invalid sector `{sector}`: {e}"))?;
+    Ok((PathBuf::from(path), sector))
+}
+
+fn main() -> ExitCode {
+    match main_real() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn main_real() -> Result<ExitCode, Box<dyn Error>> {
+    let args = Args::parse();
+    match args.task {
+        Task::Build(build) => {
+            run_build(&build)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Task::Run { build, emu_args } => {
+            let outputs = run_build(&build)?;
+            let mut emu = Command::new(tool(&build, "possum2-emu"));
+            if let Some(rom) = &outputs.rom {
+                emu.arg(rom);
+            }
+            if let Some(fd0) = &build.fd0 {
+                emu.arg("--fd0").arg(fd0);
+            }
+            for sym in &outputs.syms {
+                emu.arg("--sym").arg(sym);
+            }
+            let status = emu
+                .args(&emu_args)
+                .status()
+                .map_err(|e| format!("cannot run the emulator: {e}"))?;
+            Ok(match status.code() {
+                Some(code) => ExitCode::from(code as u8),
+                None => ExitCode::FAILURE,
+            })
+        }
+    }
+}
+
+fn run_build(build: &Build) -> Result<Outputs, Box<dyn Error>> {
+    let mut cargo = Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()));
+    cargo.args([
+        "build",
+        "--quiet",
+        "--package",
+        "pasm",
+        "--package",
+        "possum2-emu",
+    ]);
+    if build.release {
+        cargo.arg("--release");
+    }
+    if !cargo.status()?.success() {
+        return Err("failed to build the tools".into());
+    }
+
+    let out_dir = target_dir().join("xtask");
+    fs::create_dir_all(&out_dir)?;
+    let mut outputs = Outputs {
+        rom: None,
+        syms: Vec::new(),
+    };
+    if let Some(rom) = &build.rom {
+        let (bin, sym) = assemble(build, &out_dir, rom, "possum2-rom")?;
+        outputs.rom = Some(bin);
+        outputs.syms.extend(sym);
+    }
+    if let Some(fd0) = &build.fd0 {
+        if !fd0.exists() {
+            if let Some(parent) = fd0.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            File::create(fd0)
+                .and_then(|file| file.set_len(DISK_SIZE))
+                .map_err(|e| format!("cannot create {}: {e}", fd0.display()))?;
+            eprintln!("created blank image {}", fd0.display());
+        }
+        let mut image = File::options()
+            .write(true)
+            .open(fd0)
+            .map_err(|e| format!("cannot open {}: {e}", fd0.display()))?;
+        for (src, sector) in &build.fd0_put {
+            let (bin, sym) = assemble(build, &out_dir, src, "raw")?;
+            let data = fs::read(&bin)?;
+            let offset = sector * SECTOR_SIZE;
+            if offset + data.len() as u64 > DISK_SIZE {
+                return Err(format!(
+                    "{} ({} bytes) runs past the end of the image from sector {sector}",
+                    src.display(),
+                    data.len()
+                )
+                .into());
+            }
+            image.seek(SeekFrom::Start(offset))?;
+            image.write_all(&data)?;
+            eprintln!(
+                "wrote {} ({} bytes) to {} at sector {sector}",
+                src.display(),
+                data.len(),
+                fd0.display()
+            );
+            outputs.syms.extend(sym);
+        }
+    }
+    Ok(outputs)
+}
+
+/// Assemble `src` for a `pasm` target if it's a source file, returning the
+/// binary and its SYM file
+fn assemble(
+    build: &Build,
+    out_dir: &Path,
+    src: &Path,
+    target: &str,
+) -> Result<(PathBuf, Option<PathBuf>), Box<dyn Error>> {
+    if !matches!(
+        src.extension().and_then(|ext| ext.to_str()),
+        Some("asm" | "s")
+    ) {
+        return Ok((src.to_path_buf(), None));
+    }
+    let stem = src.file_stem().ok_or("source has no file name")?;
+    let bin = out_dir.join(stem).with_extension("bin");
+    let sym = out_dir.join(stem).with_extension("sym");
+    let status = Command::new(tool(build, "pasm"))
+        .arg(src)
+        .arg("-o")
+        .arg(&bin)
+        .arg("-s")
+        .arg(&sym)
+        .args(["--target", target])
+        .status()
+        .map_err(|e| format!("cannot run pasm: {e}"))?;
+    if !status.success() {
+        return Err(format!("failed to assemble {}", src.display()).into());
+    }
+    Ok((bin, Some(sym)))
+}
+
+fn target_dir() -> PathBuf {
+    env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .parent()
+                .unwrap()
+                .join("target")
+        })
+}
+
+/// Path to a binary `cargo build` made
+fn tool(build: &Build, name: &str) -> PathBuf {
+    let profile = if build.release { "release" } else { "debug" };
+    target_dir()
+        .join(profile)
+        .join(format!("{name}{}", env::consts::EXE_SUFFIX))
+}